@@ -1,6 +1,3 @@
-mod openlistcore;
-mod utils;
-
 use log::{LevelFilter, info};
 use log4rs::{
     append::rolling_file::{
@@ -12,9 +9,15 @@ use log4rs::{
     config::{Appender, Config, Root},
     encode::pattern::PatternEncoder,
 };
+use openlist_desktop_service::openlistcore;
 use std::path::PathBuf;
 
 const SERVICE_NAME: &str = "OpenList Desktop Service";
+const STDIO_FLAG: &str = "--stdio";
+
+fn is_stdio_mode() -> bool {
+    std::env::args().skip(1).any(|arg| arg == STDIO_FLAG)
+}
 
 fn setup_log_file() -> Result<(), Box<dyn std::error::Error>> {
     let log_paths = [
@@ -25,10 +28,10 @@ fn setup_log_file() -> Result<(), Box<dyn std::error::Error>> {
         Some(std::env::temp_dir().join("openlist-desktop-service.log")),
     ];
     for log_path in log_paths.iter().flatten() {
-        if let Some(parent) = log_path.parent() {
-            if std::fs::create_dir_all(parent).is_err() {
-                continue;
-            }
+        if let Some(parent) = log_path.parent()
+            && std::fs::create_dir_all(parent).is_err()
+        {
+            continue;
         }
 
         let log_pattern = format!("{}.{{}}", log_path.display());
@@ -43,22 +46,17 @@ fn setup_log_file() -> Result<(), Box<dyn std::error::Error>> {
                     "[{d(%Y-%m-%d %H:%M:%S)}] [{l}] {m}{n}",
                 )))
                 .build(log_path, Box::new(compound_policy))
-            {
-                if let Ok(config) = Config::builder()
+                && let Ok(config) = Config::builder()
                     .appender(Appender::builder().build("rolling_file", Box::new(rolling_appender)))
                     .build(
                         Root::builder()
                             .appender("rolling_file")
                             .build(LevelFilter::Info),
                     )
-                {
-                    if log4rs::init_config(config).is_ok() {
-                        info!(
-                            "Rolling log file configured: {log_path:?} (max size: 10MB, keep: 3 files)"
-                        );
-                        return Ok(());
-                    }
-                }
+                && log4rs::init_config(config).is_ok()
+            {
+                info!("Rolling log file configured: {log_path:?} (max size: 10MB, keep: 3 files)");
+                return Ok(());
             }
         }
     }
@@ -70,6 +68,10 @@ fn setup_log_file() -> Result<(), Box<dyn std::error::Error>> {
 fn main() -> windows_service::Result<()> {
     let _ = setup_log_file();
     info!("Starting {SERVICE_NAME}");
+    if is_stdio_mode() {
+        openlistcore::run_stdio();
+        return Ok(());
+    }
     openlistcore::main()
 }
 
@@ -77,5 +79,9 @@ fn main() -> windows_service::Result<()> {
 fn main() {
     let _ = setup_log_file();
     info!("Starting {SERVICE_NAME}");
+    if is_stdio_mode() {
+        openlistcore::run_stdio();
+        return;
+    }
     openlistcore::main();
 }
@@ -0,0 +1,244 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+const HEADER_LEN: usize = 64;
+
+const ELF_MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const MACHO_MAGIC_32: u32 = 0xfeed_face;
+const MACHO_MAGIC_64: u32 = 0xfeed_facf;
+const FAT_MAGIC: u32 = 0xcafe_babe;
+const FAT_MAGIC_64: u32 = 0xcafe_babf;
+const MAX_FAT_ARCHS: u32 = 30;
+const PE_SIGNATURE: [u8; 4] = [b'P', b'E', 0, 0];
+
+/// Reads the executable header of `path` and returns the architectures it
+/// contains, using the same names as `std::env::consts::ARCH`. Returns `None`
+/// for formats that carry no architecture (scripts, unknown files).
+pub fn detect_binary_arch(path: &Path) -> io::Result<Option<Vec<String>>> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; HEADER_LEN];
+    let read = read_up_to(&mut file, &mut header)?;
+    let header = &header[..read];
+
+    if header.len() >= 20 && header[..4] == ELF_MAGIC {
+        return Ok(Some(vec![elf_arch(header)]));
+    }
+    if header.len() >= 8 {
+        let magic_le = u32::from_le_bytes([header[0], header[1], header[2], header[3]]);
+        let magic_be = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        if magic_le == MACHO_MAGIC_32 || magic_le == MACHO_MAGIC_64 {
+            let cpu_type = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+            return Ok(Some(vec![macho_arch(cpu_type)]));
+        }
+        if magic_be == MACHO_MAGIC_32 || magic_be == MACHO_MAGIC_64 {
+            let cpu_type = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+            return Ok(Some(vec![macho_arch(cpu_type)]));
+        }
+        if magic_be == FAT_MAGIC || magic_be == FAT_MAGIC_64 {
+            let count = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+            // Java class files share the fat magic, their next word is a version >= 45
+            if count > 0 && count <= MAX_FAT_ARCHS {
+                return fat_archs(&mut file, count, magic_be == FAT_MAGIC_64).map(Some);
+            }
+        }
+    }
+    if header.len() >= 0x40 && header[..2] == *b"MZ" {
+        let pe_offset =
+            u32::from_le_bytes([header[0x3c], header[0x3d], header[0x3e], header[0x3f]]);
+        return pe_arch(&mut file, pe_offset as u64);
+    }
+
+    Ok(None)
+}
+
+/// Whether a binary built for `archs` runs natively on this host.
+pub fn is_compatible_with_host(archs: &[String]) -> bool {
+    let host = std::env::consts::ARCH;
+    archs.iter().any(|arch| {
+        arch == host || (host == "x86_64" && arch == "x86" && cfg!(not(target_os = "macos")))
+    })
+}
+
+fn read_up_to(file: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match file.read(&mut buf[total..])? {
+            0 => break,
+            n => total += n,
+        }
+    }
+    Ok(total)
+}
+
+fn elf_arch(header: &[u8]) -> String {
+    let big_endian = header[5] == 2;
+    let machine = if big_endian {
+        u16::from_be_bytes([header[18], header[19]])
+    } else {
+        u16::from_le_bytes([header[18], header[19]])
+    };
+    let name = match machine {
+        3 => "x86",
+        8 => "mips",
+        20 => "powerpc",
+        21 => "powerpc64",
+        22 => "s390x",
+        40 => "arm",
+        62 => "x86_64",
+        183 => "aarch64",
+        243 => "riscv64",
+        258 => "loongarch64",
+        _ => return format!("elf-machine-{machine}"),
+    };
+    name.to_string()
+}
+
+fn macho_arch(cpu_type: u32) -> String {
+    let name = match cpu_type {
+        7 => "x86",
+        0x0100_0007 => "x86_64",
+        12 => "arm",
+        0x0100_000c => "aarch64",
+        18 => "powerpc",
+        0x0100_0012 => "powerpc64",
+        _ => return format!("macho-cpu-{cpu_type:#x}"),
+    };
+    name.to_string()
+}
+
+fn fat_archs(file: &mut File, count: u32, is_64: bool) -> io::Result<Vec<String>> {
+    let entry_len: usize = if is_64 { 32 } else { 20 };
+    let mut entries = vec![0u8; entry_len * count as usize];
+    file.seek(SeekFrom::Start(8))?;
+    file.read_exact(&mut entries)?;
+
+    Ok(entries
+        .chunks(entry_len)
+        .map(|entry| macho_arch(u32::from_be_bytes([entry[0], entry[1], entry[2], entry[3]])))
+        .collect())
+}
+
+fn pe_arch(file: &mut File, pe_offset: u64) -> io::Result<Option<Vec<String>>> {
+    let mut header = [0u8; 6];
+    file.seek(SeekFrom::Start(pe_offset))?;
+    if read_up_to(file, &mut header)? < header.len() || header[..4] != PE_SIGNATURE {
+        return Ok(None);
+    }
+
+    let machine = u16::from_le_bytes([header[4], header[5]]);
+    let name = match machine {
+        0x014c => "x86".to_string(),
+        0x8664 => "x86_64".to_string(),
+        0x01c0 | 0x01c4 => "arm".to_string(),
+        0xaa64 => "aarch64".to_string(),
+        _ => format!("pe-machine-{machine:#x}"),
+    };
+    Ok(Some(vec![name]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openlistcore::test_support::scratch_dir;
+
+    fn detect(name: &str, bytes: &[u8]) -> Option<Vec<String>> {
+        let path = scratch_dir("binary-arch").join(name);
+        std::fs::write(&path, bytes).unwrap();
+        detect_binary_arch(&path).unwrap()
+    }
+
+    fn elf(machine: u16, big_endian: bool) -> Vec<u8> {
+        let mut header = vec![0u8; HEADER_LEN];
+        header[..4].copy_from_slice(&ELF_MAGIC);
+        header[4] = 2;
+        header[5] = if big_endian { 2 } else { 1 };
+        let machine = if big_endian {
+            machine.to_be_bytes()
+        } else {
+            machine.to_le_bytes()
+        };
+        header[18..20].copy_from_slice(&machine);
+        header
+    }
+
+    fn pe(machine: u16) -> Vec<u8> {
+        let mut file = vec![0u8; 0x80];
+        file[..2].copy_from_slice(b"MZ");
+        file[0x3c..0x40].copy_from_slice(&0x40u32.to_le_bytes());
+        file[0x40..0x44].copy_from_slice(&PE_SIGNATURE);
+        file[0x44..0x46].copy_from_slice(&machine.to_le_bytes());
+        file
+    }
+
+    fn strings(archs: &[&str]) -> Option<Vec<String>> {
+        Some(archs.iter().map(|arch| arch.to_string()).collect())
+    }
+
+    #[test]
+    fn reads_elf_headers_of_either_byte_order() {
+        assert_eq!(detect("x86_64", &elf(62, false)), strings(&["x86_64"]));
+        assert_eq!(detect("aarch64", &elf(183, false)), strings(&["aarch64"]));
+        assert_eq!(detect("s390x", &elf(22, true)), strings(&["s390x"]));
+        assert_eq!(
+            detect("odd", &elf(999, false)),
+            strings(&["elf-machine-999"])
+        );
+    }
+
+    #[test]
+    fn reads_thin_and_fat_macho_headers() {
+        let mut thin = MACHO_MAGIC_64.to_le_bytes().to_vec();
+        thin.extend(0x0100_000cu32.to_le_bytes());
+        assert_eq!(detect("thin", &thin), strings(&["aarch64"]));
+
+        let mut fat = FAT_MAGIC.to_be_bytes().to_vec();
+        fat.extend(2u32.to_be_bytes());
+        for cpu_type in [0x0100_0007u32, 0x0100_000c] {
+            fat.extend(cpu_type.to_be_bytes());
+            fat.extend([0u8; 16]);
+        }
+        assert_eq!(detect("fat", &fat), strings(&["x86_64", "aarch64"]));
+
+        // A Java class file: same magic, then minor and major version
+        let mut class = FAT_MAGIC.to_be_bytes().to_vec();
+        class.extend([0, 0, 0, 52]);
+        assert_eq!(detect("Main.class", &class), None);
+    }
+
+    #[test]
+    fn reads_pe_headers() {
+        assert_eq!(detect("app.exe", &pe(0x8664)), strings(&["x86_64"]));
+        assert_eq!(detect("arm.exe", &pe(0xaa64)), strings(&["aarch64"]));
+
+        let mut not_pe = pe(0x8664);
+        not_pe[0x40..0x44].copy_from_slice(b"NOPE");
+        assert_eq!(detect("dos.exe", &not_pe), None);
+    }
+
+    #[test]
+    fn scripts_have_no_architecture() {
+        assert_eq!(detect("run.sh", b"#!/bin/sh\necho hi\n"), None);
+        assert_eq!(detect("empty", b""), None);
+    }
+
+    #[test]
+    fn only_host_architectures_are_compatible() {
+        let host = std::env::consts::ARCH;
+        let other = if host == "aarch64" {
+            "x86_64"
+        } else {
+            "aarch64"
+        };
+        let machine = |arch: &str| if arch == "x86_64" { 62 } else { 183 };
+
+        let native = detect("native", &elf(machine(host), false)).unwrap();
+        assert!(is_compatible_with_host(&native));
+        let foreign = detect("foreign", &elf(machine(other), false)).unwrap();
+        assert!(!is_compatible_with_host(&foreign));
+        let universal = [other.to_string(), host.to_string()];
+        assert!(is_compatible_with_host(&universal));
+    }
+}
@@ -0,0 +1,134 @@
+use super::data::ProcessConfig;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// On-disk formats of the process config file, chosen by its extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+/// Extensions looked for next to each other, in order of precedence.
+const EXTENSIONS: [&str; 4] = ["json", "toml", "yaml", "yml"];
+
+/// A TOML document has to be a table, so the processes go under a key.
+#[derive(Serialize, Deserialize)]
+struct TomlConfigs {
+    #[serde(default)]
+    processes: Vec<ProcessConfig>,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => ConfigFormat::Toml,
+            Some(ext) if ext.eq_ignore_ascii_case("yaml") || ext.eq_ignore_ascii_case("yml") => {
+                ConfigFormat::Yaml
+            }
+            _ => ConfigFormat::Json,
+        }
+    }
+
+    pub fn parse(self, bytes: &[u8]) -> Result<Vec<ProcessConfig>> {
+        Ok(match self {
+            ConfigFormat::Json => serde_json::from_slice(bytes)?,
+            ConfigFormat::Toml => {
+                toml::from_str::<TomlConfigs>(std::str::from_utf8(bytes)?)?.processes
+            }
+            ConfigFormat::Yaml => serde_yaml_ng::from_slice(bytes)?,
+        })
+    }
+
+    pub fn serialize(self, configs: &[ProcessConfig]) -> Result<Vec<u8>> {
+        Ok(match self {
+            ConfigFormat::Json => serde_json::to_vec_pretty(configs)?,
+            ConfigFormat::Toml => toml::to_string_pretty(&TomlConfigs {
+                processes: configs.to_vec(),
+            })?
+            .into_bytes(),
+            ConfigFormat::Yaml => serde_yaml_ng::to_string(configs)?.into_bytes(),
+        })
+    }
+}
+
+/// The config file named `stem` in `dir` with whichever supported extension
+/// exists, JSON first; a new file is JSON.
+pub fn locate(dir: &Path, stem: &str) -> PathBuf {
+    EXTENSIONS
+        .iter()
+        .map(|ext| dir.join(format!("{stem}.{ext}")))
+        .find(|path| path.exists())
+        .unwrap_or_else(|| dir.join(format!("{stem}.json")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sample_configs() -> Vec<ProcessConfig> {
+        let full = json!({
+            "id": "full", "name": "full", "bin_path": "/opt/openlist/openlist",
+            "args": ["server", "--data", "/var/lib/openlist"],
+            "log_file": "/var/log/openlist/{date}.log", "working_dir": "/opt/openlist",
+            "env_vars": { "OPENLIST_TOKEN": "keyring:openlist/token" },
+            "env_inheritance": { "allowlist": ["PATH", "HOME"] },
+            "auto_restart": true, "auto_start": true, "run_as_admin": false,
+            "kill_sequence": [{ "signal": "TERM", "wait_ms": 5000 }, { "signal": "KILL", "wait_ms": 0 }],
+            "readiness_check": { "type": "http", "url": "http://127.0.0.1:5244/ping" },
+            "readiness_timeout_secs": 30, "restart_on_change": ["/opt/openlist/config.json"],
+            "order": 2, "launch_wrapper": ["nice", "-n", "5"],
+            "listen_address_probe": { "type": "sockets" },
+            "sched_policy": "batch", "io_priority": { "class": "best_effort", "level": 6 },
+            "restart_alert_threshold": 3, "log_timestamps": "rfc3339",
+            "metadata": { "color": "#ff8800", "external id": "a=b; c" },
+            "created_at": 1_700_000_000u64, "updated_at": 1_700_000_100u64,
+        });
+        let minimal = json!({
+            "id": "minimal", "name": "minimal", "bin_path": "/usr/bin/true", "args": [],
+            "log_file": "", "working_dir": null, "env_vars": null,
+            "auto_restart": false, "auto_start": false, "run_as_admin": false,
+            "created_at": 0, "updated_at": 0,
+        });
+        vec![
+            serde_json::from_value(full).unwrap(),
+            serde_json::from_value(minimal).unwrap(),
+        ]
+    }
+
+    #[test]
+    fn configs_round_trip_through_every_format() {
+        let configs = sample_configs();
+        let expected = serde_json::to_value(&configs).unwrap();
+        for format in [ConfigFormat::Json, ConfigFormat::Toml, ConfigFormat::Yaml] {
+            let bytes = format.serialize(&configs).unwrap();
+            let parsed = format.parse(&bytes).unwrap();
+            assert_eq!(
+                serde_json::to_value(&parsed).unwrap(),
+                expected,
+                "{format:?}:\n{}",
+                String::from_utf8_lossy(&bytes)
+            );
+        }
+    }
+
+    #[test]
+    fn the_format_follows_the_extension() {
+        let dir = crate::openlistcore::test_support::scratch_dir("config-format");
+        assert_eq!(
+            locate(&dir, "process_configs"),
+            dir.join("process_configs.json")
+        );
+        std::fs::write(dir.join("process_configs.yml"), "[]").unwrap();
+        let located = locate(&dir, "process_configs");
+        assert_eq!(located, dir.join("process_configs.yml"));
+        assert_eq!(ConfigFormat::from_path(&located), ConfigFormat::Yaml);
+        std::fs::write(dir.join("process_configs.toml"), "").unwrap();
+        let located = locate(&dir, "process_configs");
+        assert_eq!(ConfigFormat::from_path(&located), ConfigFormat::Toml);
+        assert!(ConfigFormat::Toml.parse(b"").unwrap().is_empty());
+    }
+}
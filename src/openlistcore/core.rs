@@ -1,24 +1,76 @@
-use crate::openlistcore::process::is_process_running;
-
-use super::{data::*, process};
+use super::{
+    binary_arch,
+    config_format::{self, ConfigFormat},
+    data::*,
+    dependencies, download,
+    error::CoreError,
+    events::EventLog,
+    file_watch, health,
+    hooks::{self, HookOutcome},
+    jitter, listen_probe, log_export, log_watch, logs, metrics, monitor, process, readiness,
+    restart_limit, run_overrides, secrets, self_check, start_queue, startup_output, startup_probe,
+    stats, templates,
+    validation::{
+        self, validate_io_priority, validate_kill_sequence, validate_launch_wrapper,
+        validate_log_patterns, validate_run_as, validate_watch_paths,
+    },
+};
 use anyhow::{Context, Result, anyhow};
-use log::{error, info, warn};
+use log::{debug, error, info, warn};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
+use regex::Regex;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
 use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
     env,
     fs::{File, OpenOptions},
-    io::{BufRead, BufReader},
+    io::{BufReader, Read, Seek, SeekFrom},
     path::{Path, PathBuf},
-    sync::atomic::Ordering,
-    time::{SystemTime, UNIX_EPOCH},
+    sync::{Arc, Weak, atomic::Ordering},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use uuid::Uuid;
 
 const SERVICE_NAME: &str = "OpenList Desktop Service";
+/// Set to anything but empty, `0` or `false` to start the service in safe mode.
+const SAFE_MODE_ENV: &str = "OPENLIST_SERVICE_SAFE_MODE";
 const INVALID_PID: i32 = -1;
-const CONFIG_FILE_NAME: &str = "process_configs.json";
+/// Without its extension, which picks the format, see `config_format`.
+const CONFIG_FILE_STEM: &str = "process_configs";
+const CONFIG_LOAD_INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const CONFIG_LOAD_MAX_BACKOFF: Duration = Duration::from_secs(8);
+const SETTINGS_FILE_NAME: &str = "service_settings.json";
+const TEMPLATES_FILE_NAME: &str = "process_templates.json";
+const STATS_FILE_NAME: &str = "process_stats.json";
+/// Restarts within this long of each other are written to the stats file
+/// together, so a crash loop does not rewrite it every time.
+pub const STATS_FLUSH_DEBOUNCE: Duration = Duration::from_secs(5);
+/// Smallest accepted `max_log_line_bytes`, so the truncation marker still
+/// follows some actual content.
+const MIN_LOG_LINE_BYTES: usize = 64;
+const START_CONDITION_TIMEOUT: Duration = Duration::from_secs(30);
+const GLOBAL_PRE_START_HOOK_TIMEOUT: Duration = Duration::from_secs(30);
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(200);
+const MUTEX_WAIT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// How much of each process log `search_all_logs` reads at most.
+const SEARCH_MAX_BYTES_PER_PROCESS: u64 = 32 * 1024 * 1024;
+const SEARCH_MAX_MATCHES_PER_PROCESS: usize = 1000;
+
+/// A run shorter than this counts as a crash towards the crash-loop limit.
+const STABLE_RUN_SECS: u64 = 60;
+const CRASH_LOOP_THRESHOLD: u32 = 5;
+const RESTART_BACKOFF_BASE_SECS: u64 = 1;
+const RESTART_BACKOFF_MAX_SECS: u64 = 60;
+
+#[cfg(test)]
+pub fn get_config_dir() -> Result<PathBuf> {
+    Ok(super::test_support::config_dir())
+}
 
+#[cfg(not(test))]
 pub fn get_config_dir() -> Result<PathBuf> {
     #[cfg(target_os = "windows")]
     {
@@ -59,522 +111,7267 @@ pub fn get_config_dir() -> Result<PathBuf> {
 
 pub fn get_config_file_path() -> Result<PathBuf> {
     let config_dir = get_config_dir()?;
-    Ok(config_dir.join(CONFIG_FILE_NAME))
+    Ok(config_format::locate(&config_dir, CONFIG_FILE_STEM))
 }
 
-fn get_current_timestamp() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs()
+pub fn get_settings_file_path() -> Result<PathBuf> {
+    let config_dir = get_config_dir()?;
+    Ok(config_dir.join(SETTINGS_FILE_NAME))
 }
 
-pub static CORE_MANAGER: Lazy<Mutex<CoreManager>> = Lazy::new(|| {
-    let mut manager = CoreManager::new();
-    if let Err(e) = manager.load_config() {
-        error!("Failed to load process configurations: {e}");
-    }
-    Mutex::new(manager)
-});
+pub fn get_templates_file_path() -> Result<PathBuf> {
+    let config_dir = get_config_dir()?;
+    Ok(config_dir.join(TEMPLATES_FILE_NAME))
+}
 
-impl Default for CoreManager {
-    fn default() -> Self {
-        Self::new()
-    }
+pub fn get_stats_file_path() -> Result<PathBuf> {
+    let config_dir = get_config_dir()?;
+    Ok(config_dir.join(STATS_FILE_NAME))
 }
 
-impl CoreManager {
-    pub fn new() -> Self {
-        CoreManager {
-            process_manager: StatusInner::new(ProcessManager::default()),
+fn compare_display_order(a: &ProcessConfig, b: &ProcessConfig) -> std::cmp::Ordering {
+    a.order
+        .cmp(&b.order)
+        .then_with(|| a.name.cmp(&b.name))
+        .then_with(|| a.id.cmp(&b.id))
+}
+
+fn config_hash_path(config_path: &Path) -> PathBuf {
+    let mut hash_path = config_path.as_os_str().to_owned();
+    hash_path.push(".sha256");
+    PathBuf::from(hash_path)
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// The top-level fields that differ between two configs of one process.
+fn diff_fields(current: &ProcessConfig, on_disk: &ProcessConfig) -> Result<Vec<FieldChange>> {
+    let (Value::Object(current), Value::Object(mut on_disk)) = (
+        serde_json::to_value(current)?,
+        serde_json::to_value(on_disk)?,
+    ) else {
+        return Err(anyhow!("A process config must serialize to an object"));
+    };
+
+    let mut changes = Vec::new();
+    for (field, current) in current {
+        let on_disk = on_disk.remove(&field).unwrap_or(Value::Null);
+        if current != on_disk {
+            changes.push(FieldChange {
+                field,
+                current,
+                on_disk,
+            });
         }
     }
+    changes.extend(on_disk.into_iter().map(|(field, on_disk)| FieldChange {
+        field,
+        current: Value::Null,
+        on_disk,
+    }));
+    Ok(changes)
+}
 
-    pub fn load_config(&mut self) -> Result<()> {
-        let config_path = get_config_file_path()?;
-
-        if !config_path.exists() {
-            if let Some(parent) = config_path.parent() {
-                std::fs::create_dir_all(parent)
-                    .with_context(|| format!("Failed to create config directory: {parent:?}"))?;
-            }
-            File::create(&config_path)
-                .with_context(|| format!("Failed to create config file: {config_path:?}"))?;
-            info!("Loading process configurations from {config_path:?}");
-            return Ok(());
+/// Compares the config file against the hash written by the last save. A
+/// mismatch means something else edited or corrupted the file; it is only
+/// reported, loading still goes ahead.
+fn verify_config_hash(config_path: &Path, bytes: &[u8]) -> HashCheck {
+    let hash_path = config_hash_path(config_path);
+    let expected = match std::fs::read_to_string(&hash_path) {
+        Ok(expected) => expected,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            info!("No config hash file at {hash_path:?}, skipping integrity check");
+            return HashCheck::Unchecked;
+        }
+        Err(e) => {
+            warn!("Failed to read config hash file {hash_path:?}: {e}");
+            return HashCheck::Unchecked;
         }
+    };
 
-        info!("Loading process configurations from {config_path:?}");
+    let actual = sha256_hex(bytes);
+    if expected.trim().eq_ignore_ascii_case(&actual) {
+        info!("Config file integrity verified");
+        HashCheck::Verified
+    } else {
+        error!(
+            "Config file {config_path:?} does not match its saved hash (expected {}, got {actual}); it was modified outside the service or is corrupted",
+            expected.trim()
+        );
+        HashCheck::Mismatch
+    }
+}
 
-        let file = File::open(&config_path)
-            .with_context(|| format!("Failed to open config file: {config_path:?}"))?;
+#[derive(Debug, PartialEq, Eq)]
+enum HashCheck {
+    Verified,
+    Mismatch,
+    /// There is no saved hash, or it could not be read.
+    Unchecked,
+}
 
-        let reader = BufReader::new(file);
-        let configs: Vec<ProcessConfig> = serde_json::from_reader(reader)
-            .with_context(|| format!("Failed to parse config file: {config_path:?}"))?;
+fn get_current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
 
-        let process_manager = self.process_manager.inner.lock();
-        let mut processes = process_manager.processes.lock();
-        let mut runtime_states = process_manager.runtime_states.lock();
+/// `config` with an empty `bin_path` filled in from `default_bin_path`, as it
+/// is launched and checked.
+fn with_default_bin_path<'a>(
+    config: &'a ProcessConfig,
+    settings: &ServiceSettings,
+) -> Cow<'a, ProcessConfig> {
+    match &settings.default_bin_path {
+        Some(default_bin_path) if config.bin_path.is_empty() => Cow::Owned(ProcessConfig {
+            bin_path: default_bin_path.clone(),
+            ..config.clone()
+        }),
+        _ => Cow::Borrowed(config),
+    }
+}
 
-        for config in configs {
-            processes.insert(config.id.clone(), config.clone());
-            runtime_states.insert(config.id.clone(), ProcessRuntime::default());
-            info!(
-                "Loaded process configuration: {} ({})",
-                config.name, config.id
+fn check_binary_arch(config: &ProcessConfig) -> Result<()> {
+    let archs = match binary_arch::detect_binary_arch(Path::new(&config.bin_path)) {
+        Ok(Some(archs)) => archs,
+        Ok(None) => return Ok(()),
+        Err(e) => {
+            warn!(
+                "Could not read executable header of {}: {e}",
+                config.bin_path
             );
+            return Ok(());
         }
+    };
 
-        info!(
-            "Successfully loaded {} process configurations",
-            processes.len()
-        );
+    if binary_arch::is_compatible_with_host(&archs) {
+        return Ok(());
+    }
+
+    let message = format!(
+        "Binary {} is built for {} but this host is {}",
+        config.bin_path,
+        archs.join(", "),
+        std::env::consts::ARCH
+    );
+    if config.strict_arch_check {
+        Err(anyhow!(message))
+    } else {
+        warn!("{message}, it may fail to start or run under emulation");
         Ok(())
     }
+}
 
-    pub fn save_config(&self) -> Result<()> {
-        let config_path = get_config_file_path()?;
+/// The log file a process writes to: the path resolved at its last start,
+/// or the `log_file` template resolved for now if it never ran.
+fn active_log_file(config: &ProcessConfig, runtime: Option<&ProcessRuntime>) -> String {
+    runtime
+        .and_then(|runtime| runtime.log_path.lock().clone())
+        .unwrap_or_else(|| {
+            logs::resolve_log_path(
+                &config.log_file,
+                &config.name,
+                &config.id,
+                get_current_timestamp(),
+            )
+        })
+}
 
-        if let Some(parent) = config_path.parent() {
-            std::fs::create_dir_all(parent)
-                .with_context(|| format!("Failed to create config directory: {parent:?}"))?;
-        }
+fn log_location(config: &ProcessConfig, runtime: Option<&ProcessRuntime>) -> logs::LogLocation {
+    logs::LogLocation::new(
+        PathBuf::from(active_log_file(config, runtime)),
+        &config.log_file,
+        &config.name,
+        &config.id,
+    )
+}
 
-        let process_manager = self.process_manager.inner.lock();
-        let processes = process_manager.processes.lock();
+/// Like `active_log_file`, for `stderr_log_file`. `None` when stderr goes to
+/// the main log.
+fn active_stderr_log_file(
+    config: &ProcessConfig,
+    runtime: Option<&ProcessRuntime>,
+) -> Option<String> {
+    let template = config.stderr_log_file.as_ref()?;
+    runtime
+        .and_then(|runtime| runtime.stderr_log_path.lock().clone())
+        .or_else(|| {
+            Some(logs::resolve_log_path(
+                template,
+                &config.name,
+                &config.id,
+                get_current_timestamp(),
+            ))
+        })
+}
 
-        let configs: Vec<ProcessConfig> = processes.values().cloned().collect();
+/// Writes a log file through to disk.
+fn sync_log_file(path: &str) -> std::io::Result<()> {
+    // Windows only syncs handles opened with write access
+    OpenOptions::new()
+        .append(true)
+        .open(path)
+        .and_then(|file| file.sync_all())
+}
 
-        info!(
-            "Saving {} process configurations to {:?}",
-            configs.len(),
-            config_path
+/// Waits up to `timeout` for the output of the just stopped process `pid` to
+/// reach its logs and syncs them, so its last lines are there to read once
+/// the stop returns.
+fn flush_after_stop(config: &ProcessConfig, runtime: &ProcessRuntime, pid: u32, timeout: Duration) {
+    if !process::drain_output(pid, timeout) {
+        warn!(
+            "Output of process {} was still being copied into its logs after {}ms",
+            config.name,
+            timeout.as_millis()
         );
+    }
+    let paths = [
+        runtime.log_path.lock().clone(),
+        runtime.stderr_log_path.lock().clone(),
+    ];
+    for path in paths.into_iter().flatten() {
+        if let Err(e) = sync_log_file(&path) {
+            warn!(
+                "Failed to sync log file {path} of process {}: {e}",
+                config.name
+            );
+        }
+    }
+}
 
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&config_path)
-            .with_context(|| format!("Failed to create config file: {config_path:?}"))?;
+fn record_unexpected_exit(
+    config: &ProcessConfig,
+    runtime: &ProcessRuntime,
+    exit_code: Option<i32>,
+    now: u64,
+) {
+    let pid = runtime.running_pid.load(Ordering::Relaxed);
+    warn!(
+        "Process {} (PID: {}) exited unexpectedly with code {:?}",
+        config.name, pid, exit_code
+    );
 
-        serde_json::to_writer_pretty(file, &configs)
-            .with_context(|| format!("Failed to write config file: {config_path:?}"))?;
+    runtime.start_generation.fetch_add(1, Ordering::SeqCst);
+    runtime.is_running.store(false, Ordering::Relaxed);
+    runtime.running_pid.store(INVALID_PID, Ordering::Relaxed);
+    // Whatever is left of its output is copied over in the background
+    process::drain_output(pid as u32, Duration::ZERO);
+    *runtime.state.lock() = ProcessState::Stopped;
+    *runtime.stop_cause.lock() = Some(StopCause::Exited(exit_code));
+    if let Some(code) = exit_code {
+        runtime.last_exit_code.store(code, Ordering::Relaxed);
+    }
+    let run_secs = runtime
+        .started_at
+        .lock()
+        .take()
+        .map(|started_at| now.saturating_sub(started_at))
+        .unwrap_or(0);
 
-        info!("Successfully saved process configurations");
-        Ok(())
+    if !config.auto_restart {
+        return;
+    }
+    if !restarts_after(config, exit_code) {
+        info!(
+            "Process {} exited with code {exit_code:?}, which restart_on_exit_codes does not restart",
+            config.name
+        );
+        return;
     }
+    if run_secs >= STABLE_RUN_SECS {
+        runtime.consecutive_failures.store(0, Ordering::Relaxed);
+    }
+    let reason = match exit_code {
+        Some(code) => format!("Exited with code {code}"),
+        None => "Exited unexpectedly".to_string(),
+    };
+    schedule_restart_or_latch(&config.id, runtime, &reason, now);
+}
 
-    pub fn get_version(&self) -> Result<VersionResponse> {
-        Ok(VersionResponse {
-            service: SERVICE_NAME.to_string(),
-            version: env!("CARGO_PKG_VERSION").to_string(),
-        })
+/// Whether `restart_on_exit_codes` lets an exit with `exit_code` be
+/// auto-restarted.
+fn restarts_after(config: &ProcessConfig, exit_code: Option<i32>) -> bool {
+    let listed = |codes: &[i32]| exit_code.is_some_and(|code| codes.contains(&code));
+    match &config.restart_on_exit_codes {
+        None => true,
+        Some(RestartExitCodes::Only(codes)) => listed(codes),
+        Some(RestartExitCodes::Except(codes)) => !listed(codes),
     }
-    pub fn create_process(&mut self, request: CreateProcessRequest) -> Result<ProcessConfig> {
-        let process_manager = self.process_manager.inner.lock();
-        let mut processes = process_manager.processes.lock();
-        let mut runtime_states = process_manager.runtime_states.lock();
-        let id = Uuid::new_v4().to_string();
-        let timestamp = get_current_timestamp();
+}
 
-        let log_file = if let Some(log_file) = request.log_file {
-            log_file
-        } else {
-            let config_dir = get_config_dir()?;
-            config_dir
-                .join(format!("process_{id}.log"))
-                .to_string_lossy()
-                .to_string()
-        };
+/// Counts one more failure and either schedules the next restart with
+/// exponential backoff or, once the crash-loop limit is hit, halts
+/// auto-restart by latching the process into the failed state.
+fn schedule_restart_or_latch(id: &str, runtime: &ProcessRuntime, reason: &str, now: u64) {
+    let failures = runtime.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
 
-        let config = ProcessConfig {
-            id: id.clone(),
-            name: request.name,
-            bin_path: request.bin_path,
-            args: request.args.unwrap_or_default(),
-            log_file,
-            working_dir: request.working_dir,
-            env_vars: request.env_vars,
-            auto_restart: request.auto_restart.unwrap_or(false),
-            auto_start: request.auto_start.unwrap_or(false),
-            run_as_admin: request.run_as_admin.unwrap_or(false),
-            created_at: timestamp,
-            updated_at: timestamp,
-        };
+    if failures >= CRASH_LOOP_THRESHOLD {
+        let message = format!(
+            "Crash loop detected after {failures} consecutive failures, auto-restart halted: {reason}"
+        );
+        error!("Process {id}: {message}");
+        *runtime.state.lock() = ProcessState::Failed;
+        *runtime.last_error.lock() = Some(message);
+        *runtime.restart_at.lock() = None;
+        return;
+    }
 
-        if !Path::new(&config.bin_path).exists() {
-            return Err(anyhow!("Binary not found at: {}", config.bin_path));
-        }
-        processes.insert(id.clone(), config.clone());
-        runtime_states.insert(id.clone(), ProcessRuntime::default());
+    let delay = RESTART_BACKOFF_BASE_SECS
+        .saturating_mul(1 << (failures - 1).min(16))
+        .min(RESTART_BACKOFF_MAX_SECS);
+    info!("Process {id} will be restarted in {delay}s (failure {failures}): {reason}");
+    *runtime.last_error.lock() = Some(reason.to_string());
+    *runtime.restart_at.lock() = Some(now + delay);
+}
 
-        drop(processes);
-        drop(runtime_states);
-        drop(process_manager);
+/// Finishes a start in the background, so `manager` stays available
+/// meanwhile: runs `condition`, downloads the binary if it is missing, then
+/// relocks the manager and launches the process unless it was stopped in
+/// between. A refusing condition leaves the process stopped, a failed
+/// download marks it failed, both with the reason in `last_error`.
+fn spawn_deferred_launch(
+    manager: ManagerHandle,
+    config: ProcessConfig,
+    runtime: ProcessRuntime,
+    generation: u64,
+    condition: Option<String>,
+) {
+    std::thread::spawn(move || {
+        // `preparing` is only cleared by the start that set it, under the
+        // manager lock, as a newer start may have set it again meanwhile
+        let finish = |state: ProcessState, reason: String| {
+            let Some(manager) = manager.upgrade() else {
+                return;
+            };
+            let _core_manager = manager.lock();
+            if readiness::is_current_run(&runtime, generation) {
+                runtime.preparing.store(false, Ordering::SeqCst);
+                *runtime.state.lock() = state;
+                *runtime.stop_cause.lock() = Some(match state {
+                    ProcessState::Stopped => StopCause::ConditionRefused(reason.clone()),
+                    _ => StopCause::StartFailed(reason.clone()),
+                });
+                *runtime.last_error.lock() = Some(reason);
+            }
+        };
 
-        // Save configuration to disk
-        if let Err(e) = self.save_config() {
-            error!("Failed to save configuration after creating process: {e}");
+        if let Some(command) = &condition
+            && let Err(reason) = check_start_condition(&config, command)
+        {
+            info!("Not starting {}: {reason}", config.name);
+            finish(ProcessState::Stopped, reason);
+            return;
         }
 
-        info!(
-            "Created process configuration: {} ({})",
-            config.name, config.id
-        );
-        Ok(config)
-    }
+        let result = download::ensure_binary(&config).and_then(|()| {
+            let Some(manager) = manager.upgrade() else {
+                return Ok(());
+            };
+            let mut core_manager = manager.lock();
+            if !readiness::is_current_run(&runtime, generation) {
+                info!("Process {} was stopped before it launched", config.name);
+                return Ok(());
+            }
+            runtime.preparing.store(false, Ordering::SeqCst);
+            core_manager.launch_process(&config.id, false)
+        });
+        if let Err(e) = result {
+            error!("Failed to start process {}: {e}", config.name);
+            finish(ProcessState::Failed, e.to_string());
+        }
+    });
+}
 
-    pub fn update_process(
-        &mut self,
-        id: &str,
-        request: UpdateProcessRequest,
-    ) -> Result<ProcessConfig> {
-        let process_manager = self.process_manager.inner.lock();
-        let mut processes = process_manager.processes.lock();
+/// The name of another process of `config`'s `mutex_group` that is running,
+/// if any.
+fn mutex_group_holder(
+    config: &ProcessConfig,
+    processes: &HashMap<String, ProcessConfig>,
+    runtime_states: &HashMap<String, ProcessRuntime>,
+) -> Option<String> {
+    let group = config.mutex_group.as_ref()?;
+    processes
+        .values()
+        .filter(|other| other.id != config.id && other.mutex_group.as_ref() == Some(group))
+        .find(|other| runtime_states.get(&other.id).is_some_and(is_alive))
+        .map(|other| other.name.clone())
+}
 
-        let config = processes
-            .get_mut(id)
-            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+/// Holds a start of a process with the `wait` mutex policy back until no
+/// other process of its group runs, then launches it, unless it was stopped
+/// in between. Processes waiting for the same group launch one at a time.
+fn spawn_mutex_wait(
+    manager: ManagerHandle,
+    config: ProcessConfig,
+    runtime: ProcessRuntime,
+    generation: u64,
+) {
+    std::thread::spawn(move || {
+        loop {
+            std::thread::sleep(MUTEX_WAIT_POLL_INTERVAL);
+            let Some(manager) = manager.upgrade() else {
+                return;
+            };
+            let mut core_manager = manager.lock();
+            if !readiness::is_current_run(&runtime, generation)
+                || !runtime.preparing.load(Ordering::SeqCst)
+            {
+                return;
+            }
+            let holder = {
+                let process_manager = core_manager.process_manager.inner.lock();
+                let processes = process_manager.processes.lock();
+                let runtime_states = process_manager.runtime_states.lock();
+                mutex_group_holder(&config, &processes, &runtime_states)
+            };
+            if holder.is_some() {
+                continue;
+            }
 
-        if let Some(name) = request.name {
-            config.name = name;
-        }
-        if let Some(bin_path) = request.bin_path {
-            if !Path::new(&bin_path).exists() {
-                return Err(anyhow!("Binary not found at: {}", bin_path));
+            runtime.preparing.store(false, Ordering::SeqCst);
+            if let Err(e) = core_manager.launch_process(&config.id, false) {
+                error!("Failed to start process {}: {e}", config.name);
+                *runtime.state.lock() = ProcessState::Failed;
+                *runtime.last_error.lock() = Some(e.to_string());
             }
-            config.bin_path = bin_path;
-        }
-        if let Some(args) = request.args {
-            config.args = args;
-        }
-        if let Some(log_file) = request.log_file {
-            config.log_file = log_file;
-        }
-        if let Some(working_dir) = request.working_dir {
-            config.working_dir = Some(working_dir);
-        }
-        if let Some(env_vars) = request.env_vars {
-            config.env_vars = Some(env_vars);
+            return;
         }
-        if let Some(auto_restart) = request.auto_restart {
-            config.auto_restart = auto_restart;
+    });
+}
+
+/// Runs `command`, the process's `start_condition_command`, returning why the
+/// start is refused if it does not exit with 0.
+fn check_start_condition(config: &ProcessConfig, command: &str) -> Result<(), String> {
+    run_start_gate(
+        "Start condition",
+        command,
+        START_CONDITION_TIMEOUT,
+        config.working_dir.as_deref().map(Path::new),
+    )
+}
+
+/// Runs `command` on behalf of a start, returning why the start is refused,
+/// described as `what`, if it does not exit with 0 within `timeout`.
+fn run_start_gate(
+    what: &str,
+    command: &str,
+    timeout: Duration,
+    working_dir: Option<&Path>,
+) -> Result<(), String> {
+    let outcome = hooks::run_shell_command(command, timeout, working_dir, None);
+    Err(match outcome {
+        Ok(outcome) if outcome.success() => return Ok(()),
+        Ok(HookOutcome::Exited(Some(code))) => {
+            format!("{what} `{command}` exited with code {code}")
         }
-        if let Some(auto_start) = request.auto_start {
-            config.auto_start = auto_start;
+        Ok(HookOutcome::Exited(None)) => {
+            format!("{what} `{command}` was terminated by a signal")
         }
-        if let Some(run_as_admin) = request.run_as_admin {
-            config.run_as_admin = run_as_admin;
+        Ok(HookOutcome::TimedOut) => {
+            format!("{what} `{command}` timed out after {}s", timeout.as_secs())
         }
-        config.updated_at = get_current_timestamp();
-
-        let updated_config = config.clone();
+        Err(e) => format!("Failed to run {} `{command}`: {e}", what.to_lowercase()),
+    })
+}
 
-        drop(processes);
-        drop(process_manager);
+/// The runtime state of `id`, starting it out stopped if the entry is missing
+/// so that every configured process has a status.
+fn runtime_for<'a>(
+    runtime_states: &'a mut HashMap<String, ProcessRuntime>,
+    id: &str,
+) -> &'a ProcessRuntime {
+    runtime_states.entry(id.to_string()).or_insert_with(|| {
+        warn!("Runtime state not found for process {id}, initializing it as stopped");
+        ProcessRuntime::default()
+    })
+}
 
-        if let Err(e) = self.save_config() {
-            error!("Failed to save configuration after updating process: {e}");
-        }
+/// Whether the process of `runtime` is still alive, and not a later one that
+/// was given its pid.
+fn is_alive(runtime: &ProcessRuntime) -> bool {
+    process::is_same_process_running(
+        runtime.running_pid.load(Ordering::Relaxed),
+        runtime.pid_start_stamp.lock().as_deref(),
+    )
+}
 
-        info!(
-            "Updated process configuration: {} ({})",
-            updated_config.name, updated_config.id
-        );
-        Ok(updated_config)
+fn effective_state(runtime: &ProcessRuntime, is_running: bool) -> ProcessState {
+    // A process that died on its own may still be recorded as starting/running
+    // until the monitor notices
+    let recorded_state = *runtime.state.lock();
+    let preparing = runtime.preparing.load(Ordering::Relaxed);
+    match recorded_state {
+        _ if is_running || preparing => recorded_state,
+        ProcessState::Failed => ProcessState::Failed,
+        _ => ProcessState::Stopped,
     }
+}
 
-    pub fn delete_process(&mut self, id: &str) -> Result<()> {
-        self.stop_process(id)?;
-
-        let process_manager = self.process_manager.inner.lock();
-        let mut processes = process_manager.processes.lock();
-        let mut runtime_states = process_manager.runtime_states.lock();
-        let config = processes
-            .remove(id)
-            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
-        runtime_states.remove(id);
+fn build_process_status(
+    id: &str,
+    config: &ProcessConfig,
+    runtime: &ProcessRuntime,
+) -> ProcessStatus {
+    // Checked here rather than trusted from `is_running`, which stays set
+    // for a process that died on its own until the monitor notices
+    let is_running = is_alive(runtime);
+    let state = effective_state(runtime, is_running);
 
-        drop(processes);
-        drop(runtime_states);
-        drop(process_manager);
+    ProcessStatus {
+        id: id.to_string(),
+        name: config.name.clone(),
+        state,
+        is_running,
+        // A dead process's pid is stale, or already someone else's
+        pid: {
+            let pid = runtime.running_pid.load(Ordering::Relaxed);
+            if pid > 0 && is_running {
+                Some(pid as u32)
+            } else {
+                None
+            }
+        },
+        started_at: *runtime.started_at.lock(),
+        restart_count: runtime.restart_count.load(Ordering::Relaxed) as u32,
+        last_exit_code: {
+            let code = runtime.last_exit_code.load(Ordering::Relaxed);
+            if code != 0 { Some(code) } else { None }
+        },
+        last_error: runtime.last_error.lock().clone(),
+        next_restart_at: *runtime.restart_at.lock(),
+        listen_address: if is_running {
+            runtime.listen_address.lock().clone()
+        } else {
+            None
+        },
+        held: runtime.held.load(Ordering::Relaxed),
+        os_command_line: None,
+        config_drifted: is_running
+            && runtime
+                .spawned_command
+                .lock()
+                .as_ref()
+                .is_some_and(|spawned| *spawned != SpawnedCommand::of(config)),
+        config: config.clone(),
+    }
+}
 
-        if let Err(e) = self.save_config() {
-            error!("Failed to save configuration after deleting process: {e}");
+/// Blocks until the process just started with `start_process` is ready,
+/// returning how long that took. Gives up with `CoreError::StartTimeout`
+/// once its readiness timeout has passed. `manager` is only locked briefly
+/// for each look, so this must be called without holding it.
+pub fn wait_until_ready(manager: &Mutex<CoreManager>, id: &str) -> Result<Duration> {
+    let started = Instant::now();
+    loop {
+        let status = manager.lock().get_process(id)?;
+        if let Some(outcome) = readiness_outcome(&status, started.elapsed()) {
+            return outcome;
         }
-
-        info!(
-            "Deleted process configuration: {} ({})",
-            config.name, config.id
-        );
-        Ok(())
+        std::thread::sleep(READY_POLL_INTERVAL);
     }
+}
 
-    pub fn list_processes(&self) -> Result<Vec<ProcessStatus>> {
-        let process_manager = self.process_manager.inner.lock();
-        let processes = process_manager.processes.lock();
-        let runtime_states = process_manager.runtime_states.lock();
+/// Restarts every running process in dependency order: all of them are
+/// stopped, dependents first, then started again, dependencies first. Each
+/// start is waited on until the process is ready, within its warmup and
+/// readiness timeout, before the processes depending on it are started;
+/// those of a process that did not come back are left stopped and reported
+/// as failed. Like `wait_until_ready`, `manager` is released while waiting,
+/// so this must be called without holding it.
+pub fn restart_all_running(manager: &Mutex<CoreManager>) -> Result<BatchResult> {
+    let mut result = BatchResult::default();
+    let (running, stopped) = {
+        let mut manager = manager.lock();
+        if manager.settings.maintenance {
+            return Err(anyhow!("Service is in maintenance mode"));
+        }
+        let running = manager.running_in_start_order()?;
+        info!("Restarting {} running processes", running.len());
 
-        let mut status_list = Vec::new();
+        let mut stopped = HashSet::new();
+        for (id, _) in running.iter().rev() {
+            match manager
+                .take_restart_token(id)
+                .and_then(|()| manager.stop_process(id))
+            {
+                Ok(_) => {
+                    stopped.insert(id.clone());
+                }
+                Err(e) => result.failed.push(BatchFailure {
+                    id: id.clone(),
+                    error: e.to_string(),
+                }),
+            }
+        }
+        (running, stopped)
+    };
 
-        for (id, config) in processes.iter() {
-            if let Some(runtime) = runtime_states.get(id) {
-                let status = ProcessStatus {
+    let mut failed: HashSet<String> = result.failed.iter().map(|f| f.id.clone()).collect();
+    for (id, depends_on) in &running {
+        if !stopped.contains(id) {
+            continue;
+        }
+        let outcome = match depends_on.iter().find(|dep| failed.contains(*dep)) {
+            Some(dependency) => Err(anyhow!("Dependency {dependency} did not restart")),
+            // A start still running its start condition or downloading its
+            // binary goes on in the background once the manager is released,
+            // and counts as not ready if that outlasts the wait
+            None => {
+                let started = manager.lock().start_process(id);
+                started.and_then(|()| wait_until_ready(manager, id))
+            }
+        };
+        match outcome {
+            Ok(_) => {
+                let mut manager = manager.lock();
+                if let Some(runtime) = manager.runtime_handle(id) {
+                    manager.count_restart(id, &runtime);
+                }
+                result.succeeded.push(id.clone());
+            }
+            Err(e) => {
+                error!("Failed to restart process {id}: {e}");
+                failed.insert(id.clone());
+                result.failed.push(BatchFailure {
                     id: id.clone(),
-                    name: config.name.clone(),
-                    is_running: is_process_running(runtime.running_pid.load(Ordering::Relaxed)),
-                    pid: {
-                        let pid = runtime.running_pid.load(Ordering::Relaxed);
-                        if pid > 0 { Some(pid as u32) } else { None }
-                    },
-                    started_at: *runtime.started_at.lock(),
-                    restart_count: runtime.restart_count.load(Ordering::Relaxed) as u32,
-                    last_exit_code: {
-                        let code = runtime.last_exit_code.load(Ordering::Relaxed);
-                        if code != 0 { Some(code) } else { None }
-                    },
-                    config: config.clone(),
-                };
-                status_list.push(status);
+                    error: e.to_string(),
+                });
             }
         }
+    }
+    info!(
+        "Restart all: {} restarted, {} failed",
+        result.succeeded.len(),
+        result.failed.len()
+    );
+    Ok(result)
+}
 
-        Ok(status_list)
+/// Whether a run started `elapsed` ago is done becoming ready, and how it
+/// went, or `None` while it is still starting.
+fn readiness_outcome(status: &ProcessStatus, elapsed: Duration) -> Option<Result<Duration>> {
+    match status.state {
+        // A start clears `last_error`; the readiness watcher gives up by
+        // marking the run running with the timeout recorded there
+        ProcessState::Running if status.last_error.is_some() => {
+            return Some(Err(CoreError::StartTimeout { elapsed }.into()));
+        }
+        ProcessState::Running => return Some(Ok(elapsed)),
+        ProcessState::Starting => {}
+        _ => {
+            return Some(Err(match &status.last_error {
+                Some(reason) => anyhow!("Process {} did not start: {reason}", status.name),
+                None => anyhow!("Process {} exited before becoming ready", status.name),
+            }));
+        }
+    }
+    let limit = readiness::warmup(&status.config)
+        + readiness::readiness_timeout(&status.config)
+        + startup_probe::budget(&status.config);
+    if elapsed >= limit {
+        return Some(Err(CoreError::StartTimeout { elapsed }.into()));
     }
+    None
+}
+
+/// Applies `request` to `config`, validating as it goes. On error `config`
+/// may be partly updated, so callers apply it to a copy.
+fn apply_update(
+    config: &mut ProcessConfig,
+    request: UpdateProcessRequest,
+    default_bin_path: Option<&str>,
+) -> Result<()> {
+    let mut bin_url = config.bin_url.clone();
+    let mut bin_sha256 = config.bin_sha256.clone();
+    if let Some(url) = &request.bin_url {
+        bin_url = Some(url.clone()).filter(|u| !u.trim().is_empty());
+    }
+    if let Some(hash) = &request.bin_sha256 {
+        bin_sha256 = Some(hash.clone()).filter(|h| !h.trim().is_empty());
+    }
+    if let Some(url) = &bin_url {
+        download::validate_source(url, bin_sha256.as_deref())?;
+    }
+
+    if let Some(name) = request.name {
+        config.name = name;
+    }
+    if let Some(bin_path) = request.bin_path {
+        // An empty path goes back to the default binary
+        let bin_path = bin_path.trim().to_string();
+        let effective = match default_bin_path {
+            Some(default_bin_path) if bin_path.is_empty() => default_bin_path,
+            _ => &bin_path,
+        };
+        if effective.is_empty() {
+            return Err(anyhow!("bin_path is required without a default_bin_path"));
+        }
+        if bin_url.is_none() && !Path::new(effective).exists() {
+            return Err(anyhow!("Binary not found at: {}", effective));
+        }
+        config.bin_path = bin_path;
+    }
+    config.bin_url = bin_url;
+    config.bin_sha256 = bin_sha256;
+    if let Some(args) = request.args {
+        config.args = args;
+    }
+    if let Some(log_file) = request.log_file {
+        config.log_file = log_file;
+    }
+    if let Some(working_dir) = request.working_dir {
+        // Empty unsets it, so the process runs from its binary's directory
+        config.working_dir = Some(working_dir).filter(|dir| !dir.trim().is_empty());
+    }
+    if let Some(env_vars) = request.env_vars {
+        config.env_vars = Some(env_vars);
+    }
+    if let Some(env_inheritance) = request.env_inheritance {
+        config.env_inheritance = env_inheritance;
+    }
+    if let Some(auto_restart) = request.auto_restart {
+        config.auto_restart = auto_restart;
+    }
+    if let Some(codes) = request.restart_on_exit_codes {
+        // Excepting nothing is the same as not narrowing at all
+        config.restart_on_exit_codes =
+            (codes != RestartExitCodes::Except(Vec::new())).then_some(codes);
+    }
+    if let Some(auto_start) = request.auto_start {
+        config.auto_start = auto_start;
+    }
+    if let Some(run_as_admin) = request.run_as_admin {
+        config.run_as_admin = run_as_admin;
+    }
+    if let Some(kill_sequence) = request.kill_sequence {
+        validate_kill_sequence(&kill_sequence)?;
+        config.kill_sequence = (!kill_sequence.is_empty()).then_some(kill_sequence);
+    }
+    if let Some(strict_arch_check) = request.strict_arch_check {
+        config.strict_arch_check = strict_arch_check;
+    }
+    if let Some(readiness_check) = request.readiness_check {
+        readiness::validate(&readiness_check)?;
+        config.readiness_check = Some(readiness_check);
+    }
+    if let Some(readiness_timeout_secs) = request.readiness_timeout_secs {
+        config.readiness_timeout_secs = Some(readiness_timeout_secs);
+    }
+    if let Some(warmup_secs) = request.warmup_secs {
+        config.warmup_secs = (warmup_secs > 0).then_some(warmup_secs);
+    }
+    if let Some(on_ready_command) = request.on_ready_command {
+        config.on_ready_command = (!on_ready_command.trim().is_empty()).then_some(on_ready_command);
+    }
+    if let Some(probe) = request.startup_probe {
+        config.startup_probe = (!probe.command.trim().is_empty()).then_some(probe);
+    }
+    if let Some(health_command) = request.health_command {
+        config.health_command = (!health_command.trim().is_empty()).then_some(health_command);
+    }
+    if let Some(health_interval_secs) = request.health_interval_secs {
+        config.health_interval_secs = (health_interval_secs > 0).then_some(health_interval_secs);
+    }
+    if let Some(health_failure_threshold) = request.health_failure_threshold {
+        config.health_failure_threshold =
+            (health_failure_threshold > 0).then_some(health_failure_threshold);
+    }
+    if let Some(log_retention_days) = request.log_retention_days {
+        config.log_retention_days = (log_retention_days > 0).then_some(log_retention_days);
+    }
+    if let Some(run_as_user) = request.run_as_user {
+        config.run_as_user = (!run_as_user.trim().is_empty()).then_some(run_as_user);
+    }
+    if let Some(run_as_group) = request.run_as_group {
+        config.run_as_group = (!run_as_group.trim().is_empty()).then_some(run_as_group);
+    }
+    if let Some(restart_on_change) = request.restart_on_change {
+        validate_watch_paths(&restart_on_change)?;
+        config.restart_on_change = restart_on_change;
+    }
+    if let Some(restart_on_log_pattern) = request.restart_on_log_pattern {
+        validate_log_patterns(&restart_on_log_pattern)?;
+        config.restart_on_log_pattern = restart_on_log_pattern;
+    }
+    if let Some(start_condition_command) = request.start_condition_command {
+        config.start_condition_command =
+            (!start_condition_command.trim().is_empty()).then_some(start_condition_command);
+    }
+    if let Some(launch_wrapper) = request.launch_wrapper {
+        validate_launch_wrapper(&launch_wrapper)?;
+        config.launch_wrapper = launch_wrapper;
+    }
+    if let Some(probe) = request.listen_address_probe {
+        listen_probe::validate(&probe)?;
+        config.listen_address_probe = Some(probe);
+    }
+    if let Some(stderr_log_file) = request.stderr_log_file {
+        config.stderr_log_file = (!stderr_log_file.trim().is_empty()).then_some(stderr_log_file);
+    }
+    if let Some(allocate_pty) = request.allocate_pty {
+        config.allocate_pty = allocate_pty;
+    }
+    if let Some(new_session) = request.new_session {
+        config.new_session = new_session;
+    }
+    if let Some(log_timestamps) = request.log_timestamps {
+        config.log_timestamps = Some(log_timestamps);
+    }
+    if let Some(infer_log_level) = request.infer_log_level {
+        config.infer_log_level = infer_log_level;
+    }
+    if let Some(sched_policy) = request.sched_policy {
+        config.sched_policy = Some(sched_policy);
+    }
+    if let Some(io_priority) = request.io_priority {
+        validate_io_priority(&io_priority)?;
+        config.io_priority = Some(io_priority);
+    }
+    if let Some(threshold) = request.restart_alert_threshold {
+        config.restart_alert_threshold = (threshold > 0).then_some(threshold);
+    }
+    if let Some(limit) = request.restart_rate_limit {
+        config.restart_rate_limit = (limit > 0).then_some(limit);
+    }
+    if let Some(window_secs) = request.restart_rate_window_secs {
+        config.restart_rate_window_secs = (window_secs > 0).then_some(window_secs);
+    }
+    if let Some(max_runtime_secs) = request.max_runtime_secs {
+        config.max_runtime_secs = (max_runtime_secs > 0).then_some(max_runtime_secs);
+    }
+    if let Some(detached) = request.detached {
+        config.detached = detached;
+    }
+    if let Some(supports_sighup_reload) = request.supports_sighup_reload {
+        config.supports_sighup_reload = supports_sighup_reload;
+    }
+    if let Some(depends_on) = request.depends_on {
+        config.depends_on = depends_on;
+    }
+    if let Some(group) = request.mutex_group {
+        config.mutex_group = (!group.trim().is_empty()).then_some(group);
+    }
+    if let Some(policy) = request.mutex_policy {
+        config.mutex_policy = policy;
+    }
+    if let Some(metadata) = request.metadata {
+        config.metadata = metadata;
+    }
+    validate_run_as(config)?;
+    config.updated_at = get_current_timestamp();
+    Ok(())
+}
+
+fn safe_mode_requested(value: Option<&str>) -> bool {
+    value.is_some_and(|value| {
+        let value = value.trim();
+        !value.is_empty() && value != "0" && !value.eq_ignore_ascii_case("false")
+    })
+}
+
+/// The manager of the service, loaded on first use without waiting for
+/// anything, see `wait_for_config`, and watched by the process monitor from
+/// then on. Embedders that want to control when that happens create their
+/// own with `CoreManager::new_without_autostart` and `start_monitor`.
+pub static CORE_MANAGER: Lazy<Arc<Mutex<CoreManager>>> = Lazy::new(|| {
+    let manager = CoreManager::new_without_autostart();
+    manager.lock().load();
+    CoreManager::start_monitor(&manager);
+    manager
+});
+
+/// Waits for the process configurations to become readable, so that
+/// `CORE_MANAGER` finds them when it is first used. Failures to read the file,
+/// including a config directory that does not exist, are retried with
+/// backoff, since the directory may live on a mount that is not available
+/// yet early in boot. Call it before anything touches `CORE_MANAGER`.
+pub fn wait_for_config() {
+    let mut settings = CoreManager::new();
+    if let Err(e) = settings.load_settings() {
+        warn!("Failed to load service settings, using the default retries: {e}");
+    }
+    wait_for_config_file(
+        settings.settings.config_load_retries,
+        CONFIG_LOAD_INITIAL_BACKOFF,
+    );
+}
+
+/// Retries reading the config file up to `retries` times, returning whether
+/// it could be read in the end. Anything but an I/O error gives up straight
+/// away, loading will report it.
+fn wait_for_config_file(retries: u32, initial_backoff: Duration) -> bool {
+    let mut backoff = initial_backoff;
+    let mut attempt = 0;
+    loop {
+        let Err(e) = get_config_file_path().and_then(|path| read_config_file(&path)) else {
+            return true;
+        };
+        let transient = e.chain().any(|cause| cause.is::<std::io::Error>());
+        if !transient || attempt >= retries {
+            return false;
+        }
+        attempt += 1;
+        warn!(
+            "Failed to read process configurations (attempt {attempt} of {}), retrying in {}ms: {e:#}",
+            retries + 1,
+            backoff.as_millis()
+        );
+        std::thread::sleep(backoff);
+        backoff = (backoff * 2).min(CONFIG_LOAD_MAX_BACKOFF);
+    }
+}
+
+/// The contents of the process config file, `None` if there is none yet. A
+/// missing config directory is an error rather than an empty config, since
+/// that is also what a mount that is not there yet looks like.
+fn read_config_file(config_path: &Path) -> Result<Option<Vec<u8>>> {
+    match std::fs::read(config_path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(e)
+            if e.kind() == std::io::ErrorKind::NotFound
+                && config_path.parent().is_some_and(Path::is_dir) =>
+        {
+            Ok(None)
+        }
+        Err(e) => Err(e).with_context(|| format!("Failed to open config file: {config_path:?}")),
+    }
+}
+
+impl Default for CoreManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CoreManager {
+    /// An empty manager: nothing is read from disk or the environment and
+    /// nothing is started until asked for, see `load`. Background threads
+    /// cannot get back to it, so exits are only noticed when supervising by
+    /// hand and nothing is restarted on its own; use `new_without_autostart`
+    /// and `start_monitor` for a manager that runs by itself.
+    pub fn new() -> Self {
+        CoreManager {
+            process_manager: StatusInner::new(ProcessManager::default()),
+            settings: ServiceSettings::default(),
+            templates: BTreeMap::new(),
+            secrets: Arc::new(secrets::OsKeyring),
+            events: EventLog::default(),
+            stats_dirty_since: None,
+            safe_mode: false,
+            config_written_at: Mutex::new(None),
+            handle: Weak::new(),
+        }
+    }
+
+    /// An empty manager like `new`, shared with the background threads it
+    /// starts. Starts nothing itself: loading, auto-start and the process
+    /// monitor are up to `load`, `auto_start_processes` and `start_monitor`.
+    pub fn new_without_autostart() -> Arc<Mutex<CoreManager>> {
+        Arc::new_cyclic(|handle| {
+            let mut manager = CoreManager::new();
+            manager.handle = handle.clone();
+            Mutex::new(manager)
+        })
+    }
+
+    /// Starts the process monitor of `manager`, which polls for processes
+    /// that exited and restarts those with `auto_restart`, until `manager`
+    /// is dropped. Call it once per manager.
+    pub fn start_monitor(manager: &Arc<Mutex<CoreManager>>) {
+        monitor::spawn_monitor(Arc::downgrade(manager));
+    }
+
+    /// Loads everything the service persisted: settings, templates, process
+    /// configurations and stats, logging whatever fails to load. Enters
+    /// safe mode if `SAFE_MODE_ENV` asks for it. Starts nothing; auto-start
+    /// is up to `auto_start_processes`.
+    pub fn load(&mut self) {
+        if safe_mode_requested(env::var(SAFE_MODE_ENV).ok().as_deref()) {
+            warn!("{SAFE_MODE_ENV} is set, starting in safe mode");
+            self.safe_mode = true;
+        }
+        if let Err(e) = self.load_settings() {
+            error!("Failed to load service settings: {e}");
+        }
+        if let Err(e) = self.load_templates() {
+            error!("Failed to load process templates: {e}");
+        }
+        if let Err(e) = self.load_config() {
+            error!("Failed to load process configurations, starting without them: {e:#}");
+        }
+        if let Err(e) = self.load_stats() {
+            error!("Failed to load process stats: {e}");
+        }
+    }
+
+    pub fn load_settings(&mut self) -> Result<()> {
+        let settings_path = get_settings_file_path()?;
+
+        if !settings_path.exists() {
+            info!("No service settings at {settings_path:?}, using defaults");
+            return Ok(());
+        }
+
+        let file = File::open(&settings_path)
+            .with_context(|| format!("Failed to open settings file: {settings_path:?}"))?;
+        self.settings = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("Failed to parse settings file: {settings_path:?}"))?;
+
+        info!("Loaded service settings from {settings_path:?}");
+        Ok(())
+    }
+
+    pub fn save_settings(&self) -> Result<()> {
+        let settings_path = get_settings_file_path()?;
+
+        if let Some(parent) = settings_path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create config directory: {parent:?}"))?;
+        }
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&settings_path)
+            .with_context(|| format!("Failed to create settings file: {settings_path:?}"))?;
+
+        serde_json::to_writer_pretty(file, &self.settings)
+            .with_context(|| format!("Failed to write settings file: {settings_path:?}"))?;
+
+        info!("Successfully saved service settings");
+        Ok(())
+    }
+
+    pub fn get_settings(&self) -> ServiceSettings {
+        self.settings.clone()
+    }
+
+    pub fn update_settings(
+        &mut self,
+        request: UpdateServiceSettingsRequest,
+    ) -> Result<ServiceSettings> {
+        let mut settings = self.settings.clone();
+
+        if let Some(max_log_line_bytes) = request.max_log_line_bytes {
+            if max_log_line_bytes < MIN_LOG_LINE_BYTES {
+                return Err(anyhow!(
+                    "max_log_line_bytes must be at least {MIN_LOG_LINE_BYTES}"
+                ));
+            }
+            settings.max_log_line_bytes = max_log_line_bytes;
+        }
+
+        if let Some(default_log_lines) = request.default_log_lines {
+            settings.default_log_lines = default_log_lines;
+        }
+
+        if let Some(max_log_lines) = request.max_log_lines {
+            settings.max_log_lines = max_log_lines;
+        }
+
+        if settings.default_log_lines == 0 || settings.max_log_lines == 0 {
+            return Err(anyhow!(
+                "default_log_lines and max_log_lines must be at least 1"
+            ));
+        }
+        if settings.default_log_lines > settings.max_log_lines {
+            return Err(anyhow!(
+                "default_log_lines must not be larger than max_log_lines"
+            ));
+        }
+
+        if let Some(max_concurrent_starts) = request.max_concurrent_starts {
+            settings.max_concurrent_starts = max_concurrent_starts;
+        }
+
+        if let Some(retry_missing_binary) = request.retry_missing_binary {
+            settings.retry_missing_binary = retry_missing_binary;
+        }
+
+        if let Some(max_total_log_bytes) = request.max_total_log_bytes {
+            settings.max_total_log_bytes = max_total_log_bytes;
+        }
+
+        if let Some(config_load_retries) = request.config_load_retries {
+            settings.config_load_retries = config_load_retries;
+        }
+
+        if let Some(on_exit) = request.on_exit {
+            settings.on_exit = on_exit;
+        }
+
+        if let Some(jitter_percent) = request.jitter_percent {
+            if jitter_percent > jitter::MAX_JITTER_PERCENT {
+                return Err(anyhow!(
+                    "jitter_percent must be at most {}",
+                    jitter::MAX_JITTER_PERCENT
+                ));
+            }
+            settings.jitter_percent = jitter_percent;
+        }
+
+        if let Some(default_bin_path) = request.default_bin_path {
+            if !default_bin_path.trim().is_empty() && !Path::new(&default_bin_path).exists() {
+                return Err(anyhow!("Binary not found at: {}", default_bin_path));
+            }
+            settings.default_bin_path =
+                (!default_bin_path.trim().is_empty()).then_some(default_bin_path);
+        }
+
+        if let Some(stop_log_flush_timeout_ms) = request.stop_log_flush_timeout_ms {
+            settings.stop_log_flush_timeout_ms = stop_log_flush_timeout_ms;
+        }
+
+        if let Some(shared_monitor_threads) = request.shared_monitor_threads {
+            settings.shared_monitor_threads = shared_monitor_threads;
+        }
+
+        self.settings = settings;
+        self.save_settings()?;
+        info!("Service settings updated: {:?}", self.settings);
+        Ok(self.settings.clone())
+    }
+
+    pub fn load_config(&mut self) -> Result<()> {
+        let config_path = get_config_file_path()?;
+
+        let Some(bytes) = read_config_file(&config_path)? else {
+            info!("No process configurations at {config_path:?} yet");
+            return Ok(());
+        };
+        info!("Loading process configurations from {config_path:?}");
+        verify_config_hash(&config_path, &bytes);
+
+        let configs = ConfigFormat::from_path(&config_path)
+            .parse(&bytes)
+            .with_context(|| format!("Failed to parse config file: {config_path:?}"))?;
+
+        let process_manager = self.process_manager.inner.lock();
+        let mut processes = process_manager.processes.lock();
+        let mut runtime_states = process_manager.runtime_states.lock();
+
+        for config in configs {
+            processes.insert(config.id.clone(), config.clone());
+            // Loading again must not lose track of a process that is running
+            runtime_states.entry(config.id.clone()).or_default();
+            info!(
+                "Loaded process configuration: {} ({})",
+                config.name, config.id
+            );
+        }
+
+        info!(
+            "Successfully loaded {} process configurations",
+            processes.len()
+        );
+        Ok(())
+    }
+
+    pub fn save_config(&self) -> Result<()> {
+        let config_path = get_config_file_path()?;
+
+        if let Some(parent) = config_path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create config directory: {parent:?}"))?;
+        }
 
-    pub fn get_process(&self, id: &str) -> Result<ProcessStatus> {
         let process_manager = self.process_manager.inner.lock();
         let processes = process_manager.processes.lock();
-        let runtime_states = process_manager.runtime_states.lock();
 
-        let config = processes
-            .get(id)
-            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+        let mut configs: Vec<ProcessConfig> = processes.values().cloned().collect();
+        configs.sort_by(compare_display_order);
+
+        info!(
+            "Saving {} process configurations to {:?}",
+            configs.len(),
+            config_path
+        );
+
+        let bytes = ConfigFormat::from_path(&config_path)
+            .serialize(&configs)
+            .with_context(|| format!("Failed to serialize config file: {config_path:?}"))?;
+        std::fs::write(&config_path, &bytes)
+            .with_context(|| format!("Failed to write config file: {config_path:?}"))?;
+        *self.config_written_at.lock() = std::fs::metadata(&config_path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+
+        let hash_path = config_hash_path(&config_path);
+        if let Err(e) = std::fs::write(&hash_path, sha256_hex(&bytes)) {
+            warn!("Failed to write config hash file {hash_path:?}: {e}");
+        }
+
+        info!("Successfully saved process configurations");
+        Ok(())
+    }
+
+    /// The config file's path and modification time, and whether that was the
+    /// service's own last save rather than an edit from outside.
+    pub fn config_metadata(&self) -> Result<ConfigMetadata> {
+        let config_path = get_config_file_path()?;
+        let modified = match std::fs::metadata(&config_path) {
+            Ok(metadata) => Some(metadata.modified().with_context(|| {
+                format!("Failed to read modification time of config file: {config_path:?}")
+            })?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to read config file: {config_path:?}"));
+            }
+        };
+
+        let written_at = *self.config_written_at.lock();
+        Ok(ConfigMetadata {
+            path: config_path.to_string_lossy().into_owned(),
+            last_modified: modified.map(|modified| {
+                modified
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs()
+            }),
+            last_written_by_service: modified.is_some() && modified == written_at,
+        })
+    }
+
+    /// What loading the config file would change, without applying it. A
+    /// missing or empty file counts as one without any processes.
+    pub fn diff_config(&self) -> Result<ConfigDiff> {
+        let config_path = get_config_file_path()?;
+        let on_disk: Vec<ProcessConfig> = match std::fs::read(&config_path) {
+            Ok(bytes) if bytes.iter().all(u8::is_ascii_whitespace) => Vec::new(),
+            Ok(bytes) => ConfigFormat::from_path(&config_path)
+                .parse(&bytes)
+                .with_context(|| format!("Failed to parse config file: {config_path:?}"))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to open config file: {config_path:?}"));
+            }
+        };
+
+        let mut current: HashMap<String, ProcessConfig> = {
+            let process_manager = self.process_manager.inner.lock();
+            process_manager.processes.lock().clone()
+        };
+
+        let mut diff = ConfigDiff::default();
+        for disk_config in on_disk {
+            let Some(config) = current.remove(&disk_config.id) else {
+                diff.added.push(disk_config);
+                continue;
+            };
+            let fields = diff_fields(&config, &disk_config)?;
+            if !fields.is_empty() {
+                diff.modified.push(ProcessConfigChange {
+                    id: config.id,
+                    name: disk_config.name,
+                    fields,
+                });
+            }
+        }
+        diff.removed = current.into_values().collect();
+        diff.removed.sort_by(compare_display_order);
+        Ok(diff)
+    }
+
+    /// Brings `runtime_states` back in line with `processes`: configured
+    /// processes without a runtime state get a stopped one, and runtime
+    /// states without a process are dropped after stopping what they still
+    /// track, as nothing else could stop it afterwards.
+    pub fn reconcile_state(&mut self) -> ReconcileReport {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let mut runtime_states = process_manager.runtime_states.lock();
+
+        let mut report = ReconcileReport::default();
+        for id in processes.keys() {
+            if !runtime_states.contains_key(id) {
+                runtime_states.insert(id.clone(), ProcessRuntime::default());
+                report.added_runtime.push(id.clone());
+            }
+        }
+
+        let orphans: Vec<String> = runtime_states
+            .keys()
+            .filter(|id| !processes.contains_key(*id))
+            .cloned()
+            .collect();
+        for id in orphans {
+            let Some(runtime) = runtime_states.remove(&id) else {
+                continue;
+            };
+            // Watchers of the run bail out instead of restarting it
+            runtime.start_generation.fetch_add(1, Ordering::SeqCst);
+            let pid = runtime.running_pid.load(Ordering::Relaxed);
+            if pid > 0 && is_alive(&runtime) {
+                warn!("Stopping process {pid} left running by orphaned runtime state {id}");
+                if let Err(e) = process::kill_process(pid as u32, None) {
+                    error!("Failed to stop process {pid} of orphaned runtime state {id}: {e}");
+                }
+                process::try_reap(pid);
+                report.stopped_pids.push(pid);
+            }
+            report.removed_runtime.push(id);
+        }
+
+        report.added_runtime.sort();
+        report.removed_runtime.sort();
+        if !report.added_runtime.is_empty() || !report.removed_runtime.is_empty() {
+            warn!(
+                "Reconciled runtime state: added {:?}, removed {:?}",
+                report.added_runtime, report.removed_runtime
+            );
+        }
+        report
+    }
+
+    pub fn self_check(&self) -> Vec<CheckResult> {
+        let configs: Vec<ProcessConfig> = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            processes
+                .values()
+                .map(|config| with_default_bin_path(config, &self.settings).into_owned())
+                .collect()
+        };
+
+        match (get_config_dir(), get_config_file_path()) {
+            (Ok(config_dir), Ok(config_file)) => {
+                self_check::run_self_check(&config_dir, &config_file, &configs)
+            }
+            (Err(e), _) | (_, Err(e)) => vec![CheckResult {
+                name: "config_dir_writable".to_string(),
+                status: CheckStatus::Fail,
+                message: format!("Failed to determine config directory: {e}"),
+            }],
+        }
+    }
+
+    /// Checks every stored process configuration, individually and against
+    /// each other, without starting anything.
+    pub fn validate_all(&self) -> Vec<ValidationIssue> {
+        let mut configs: Vec<ProcessConfig> = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            processes
+                .values()
+                .map(|config| with_default_bin_path(config, &self.settings).into_owned())
+                .collect()
+        };
+        configs.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+        validation::validate_configs(&configs)
+    }
+
+    pub fn get_version(&self) -> Result<VersionResponse> {
+        Ok(VersionResponse {
+            service: SERVICE_NAME.to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        })
+    }
+    pub fn create_process(&mut self, request: CreateProcessRequest) -> Result<ProcessConfig> {
+        let process_manager = self.process_manager.inner.lock();
+        let mut processes = process_manager.processes.lock();
+        let mut runtime_states = process_manager.runtime_states.lock();
+        let id = Uuid::new_v4().to_string();
+        let timestamp = get_current_timestamp();
+
+        let log_file = if let Some(log_file) = request.log_file {
+            log_file
+        } else {
+            let config_dir = get_config_dir()?;
+            config_dir
+                .join(format!("process_{id}.log"))
+                .to_string_lossy()
+                .to_string()
+        };
+
+        let config = ProcessConfig {
+            id: id.clone(),
+            name: request.name,
+            bin_path: request
+                .bin_path
+                .filter(|p| !p.trim().is_empty())
+                .unwrap_or_default(),
+            args: request.args.unwrap_or_default(),
+            log_file,
+            working_dir: request.working_dir.filter(|dir| !dir.trim().is_empty()),
+            env_vars: request.env_vars,
+            env_inheritance: request.env_inheritance.unwrap_or_default(),
+            auto_restart: request.auto_restart.unwrap_or(false),
+            restart_on_exit_codes: request
+                .restart_on_exit_codes
+                .filter(|codes| *codes != RestartExitCodes::Except(Vec::new())),
+            auto_start: request.auto_start.unwrap_or(false),
+            run_as_admin: request.run_as_admin.unwrap_or(false),
+            kill_sequence: request.kill_sequence.filter(|steps| !steps.is_empty()),
+            strict_arch_check: request.strict_arch_check.unwrap_or(false),
+            readiness_check: request.readiness_check,
+            readiness_timeout_secs: request.readiness_timeout_secs,
+            warmup_secs: request.warmup_secs.filter(|w| *w > 0),
+            on_ready_command: request.on_ready_command.filter(|c| !c.trim().is_empty()),
+            startup_probe: request
+                .startup_probe
+                .filter(|p| !p.command.trim().is_empty()),
+            health_command: request.health_command.filter(|c| !c.trim().is_empty()),
+            health_interval_secs: request.health_interval_secs.filter(|i| *i > 0),
+            health_failure_threshold: request.health_failure_threshold.filter(|t| *t > 0),
+            log_retention_days: request.log_retention_days.filter(|days| *days > 0),
+            run_as_user: request.run_as_user.filter(|u| !u.trim().is_empty()),
+            run_as_group: request.run_as_group.filter(|g| !g.trim().is_empty()),
+            restart_on_change: request.restart_on_change.unwrap_or_default(),
+            restart_on_log_pattern: request.restart_on_log_pattern.unwrap_or_default(),
+            order: processes
+                .values()
+                .map(|c| c.order.saturating_add(1))
+                .max()
+                .unwrap_or(0),
+            bin_url: request.bin_url.filter(|u| !u.trim().is_empty()),
+            bin_sha256: request.bin_sha256.filter(|h| !h.trim().is_empty()),
+            start_condition_command: request
+                .start_condition_command
+                .filter(|c| !c.trim().is_empty()),
+            launch_wrapper: request.launch_wrapper.unwrap_or_default(),
+            listen_address_probe: request.listen_address_probe,
+            stderr_log_file: request.stderr_log_file.filter(|f| !f.trim().is_empty()),
+            allocate_pty: request.allocate_pty.unwrap_or(false),
+            new_session: request.new_session.unwrap_or(true),
+            log_timestamps: request.log_timestamps,
+            infer_log_level: request.infer_log_level.unwrap_or(false),
+            sched_policy: request.sched_policy,
+            io_priority: request.io_priority,
+            restart_alert_threshold: request.restart_alert_threshold.filter(|t| *t > 0),
+            restart_rate_limit: request.restart_rate_limit.filter(|l| *l > 0),
+            restart_rate_window_secs: request.restart_rate_window_secs.filter(|w| *w > 0),
+            max_runtime_secs: request.max_runtime_secs.filter(|m| *m > 0),
+            detached: request.detached.unwrap_or(false),
+            supports_sighup_reload: request.supports_sighup_reload.unwrap_or(false),
+            depends_on: request.depends_on.unwrap_or_default(),
+            mutex_group: request.mutex_group.filter(|g| !g.trim().is_empty()),
+            mutex_policy: request.mutex_policy.unwrap_or_default(),
+            metadata: request.metadata.unwrap_or_default(),
+            created_at: timestamp,
+            updated_at: timestamp,
+        };
+
+        let bin_path = &with_default_bin_path(&config, &self.settings).bin_path;
+        if bin_path.is_empty() {
+            return Err(anyhow!("bin_path is required without a default_bin_path"));
+        }
+        if let Some(url) = &config.bin_url {
+            download::validate_source(url, config.bin_sha256.as_deref())?;
+        } else if !Path::new(bin_path).exists() {
+            return Err(anyhow!("Binary not found at: {}", bin_path));
+        }
+        if let Some(steps) = &config.kill_sequence {
+            validate_kill_sequence(steps)?;
+        }
+        if let Some(check) = &config.readiness_check {
+            readiness::validate(check)?;
+        }
+        if let Some(probe) = &config.listen_address_probe {
+            listen_probe::validate(probe)?;
+        }
+        validate_run_as(&config)?;
+        validate_watch_paths(&config.restart_on_change)?;
+        validate_log_patterns(&config.restart_on_log_pattern)?;
+        validate_launch_wrapper(&config.launch_wrapper)?;
+        if let Some(io_priority) = &config.io_priority {
+            validate_io_priority(io_priority)?;
+        }
+        processes.insert(id.clone(), config.clone());
+        runtime_states.insert(id.clone(), ProcessRuntime::default());
+
+        drop(processes);
+        drop(runtime_states);
+        drop(process_manager);
+
+        // Save configuration to disk
+        if let Err(e) = self.save_config() {
+            error!("Failed to save configuration after creating process: {e}");
+        }
+
+        info!(
+            "Created process configuration: {} ({})",
+            config.name, config.id
+        );
+        Ok(config)
+    }
+
+    pub fn load_templates(&mut self) -> Result<()> {
+        self.templates = templates::load(&get_templates_file_path()?)?;
+        info!("Loaded {} process templates", self.templates.len());
+        Ok(())
+    }
+
+    /// Restores the restart counts of the loaded processes from the stats
+    /// file. Stats of processes that no longer exist are ignored.
+    pub fn load_stats(&mut self) -> Result<()> {
+        let stats = stats::load(&get_stats_file_path()?)?;
+        let process_manager = self.process_manager.inner.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+        for (id, runtime) in runtime_states.iter() {
+            if let Some(stats) = stats.get(id) {
+                runtime
+                    .restart_count
+                    .store(stats.restart_count as i32, Ordering::Relaxed);
+            }
+        }
+        info!("Loaded stats of {} processes", stats.len());
+        Ok(())
+    }
+
+    /// Writes the restart counts to the stats file if they changed at least
+    /// `STATS_FLUSH_DEBOUNCE` ago, or right away with `force`. On failure
+    /// they stay pending for the next flush.
+    pub fn flush_stats(&mut self, force: bool) -> Result<()> {
+        let Some(dirty_since) = self.stats_dirty_since else {
+            return Ok(());
+        };
+        if !force && dirty_since.elapsed() < STATS_FLUSH_DEBOUNCE {
+            return Ok(());
+        }
+
+        let stats = self.current_stats();
+        stats::save(&get_stats_file_path()?, &stats)?;
+        self.stats_dirty_since = None;
+        debug!("Saved stats of {} processes", stats.len());
+        Ok(())
+    }
+
+    fn current_stats(&self) -> BTreeMap<String, ProcessStats> {
+        let process_manager = self.process_manager.inner.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+        runtime_states
+            .iter()
+            .map(|(id, runtime)| {
+                let restart_count = runtime.restart_count.load(Ordering::Relaxed) as u32;
+                (id.clone(), ProcessStats { restart_count })
+            })
+            .collect()
+    }
+
+    /// The settings, process configs with their stats and the templates, as
+    /// one blob that `restore` can rebuild the service from.
+    pub fn snapshot(&self) -> ServiceSnapshot {
+        let mut processes: Vec<ProcessConfig> = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            processes.values().cloned().collect()
+        };
+        processes.sort_by(compare_display_order);
+        ServiceSnapshot {
+            created_at: get_current_timestamp(),
+            settings: self.settings.clone(),
+            processes,
+            stats: self.current_stats(),
+            templates: self.templates.clone(),
+        }
+    }
+
+    /// Replaces the settings, processes, stats and templates with those of
+    /// `snapshot` and saves them. Running processes are stopped first, as
+    /// their configs are about to be replaced; with `start` every restored
+    /// process is started afterwards. Binaries are not checked, they may
+    /// only be installed once the snapshot is restored.
+    pub fn restore(&mut self, snapshot: ServiceSnapshot, start: bool) -> Result<BatchResult> {
+        let mut seen = HashSet::new();
+        for config in &snapshot.processes {
+            if config.id.trim().is_empty() {
+                return Err(anyhow!("Process {} in the snapshot has no id", config.name));
+            }
+            if !seen.insert(config.id.as_str()) {
+                return Err(anyhow!(
+                    "Duplicate process id in the snapshot: {}",
+                    config.id
+                ));
+            }
+        }
+        let process_count = seen.len();
+
+        let running: Vec<String> = {
+            let process_manager = self.process_manager.inner.lock();
+            let runtime_states = process_manager.runtime_states.lock();
+            runtime_states
+                .iter()
+                .filter(|(_, runtime)| {
+                    is_alive(runtime) || runtime.preparing.load(Ordering::Relaxed)
+                })
+                .map(|(id, _)| id.clone())
+                .collect()
+        };
+        for id in &running {
+            self.stop_process(id)?;
+        }
+
+        {
+            let process_manager = self.process_manager.inner.lock();
+            let mut processes = process_manager.processes.lock();
+            let mut runtime_states = process_manager.runtime_states.lock();
+            processes.clear();
+            runtime_states.clear();
+            for config in snapshot.processes {
+                let runtime = ProcessRuntime::default();
+                if let Some(stats) = snapshot.stats.get(&config.id) {
+                    runtime
+                        .restart_count
+                        .store(stats.restart_count as i32, Ordering::Relaxed);
+                }
+                runtime_states.insert(config.id.clone(), runtime);
+                processes.insert(config.id.clone(), config);
+            }
+        }
+        self.settings = snapshot.settings;
+        self.templates = snapshot.templates;
+
+        self.save_settings()?;
+        templates::save(&get_templates_file_path()?, &self.templates)?;
+        self.save_config()?;
+        self.stats_dirty_since = Some(Instant::now());
+        self.flush_stats(true)?;
+        info!(
+            "Restored a snapshot taken at {} with {process_count} processes",
+            snapshot.created_at
+        );
+
+        if start {
+            self.start_all_processes()
+        } else {
+            Ok(BatchResult::default())
+        }
+    }
+
+    pub fn list_templates(&self) -> BTreeMap<String, ProcessTemplate> {
+        self.templates.clone()
+    }
+
+    /// Stores `partial` under `name` for `create_from_template`, replacing
+    /// any template of that name. Only the fields it sets are kept.
+    pub fn register_template(
+        &mut self,
+        name: &str,
+        partial: UpdateProcessRequest,
+    ) -> Result<ProcessTemplate> {
+        if name.trim().is_empty() {
+            return Err(anyhow!("Template name must not be empty"));
+        }
+        let template = templates::set_fields(partial)?;
+        let mut updated = self.templates.clone();
+        updated.insert(name.to_string(), template.clone());
+        templates::save(&get_templates_file_path()?, &updated)?;
+        self.templates = updated;
+
+        info!("Registered process template: {name}");
+        Ok(template)
+    }
+
+    pub fn delete_template(&mut self, name: &str) -> Result<()> {
+        let mut updated = self.templates.clone();
+        if updated.remove(name).is_none() {
+            return Err(anyhow!("Template not found: {}", name));
+        }
+        templates::save(&get_templates_file_path()?, &updated)?;
+        self.templates = updated;
+
+        info!("Deleted process template: {name}");
+        Ok(())
+    }
+
+    /// Creates a process from the template `name`, with `overrides` taking
+    /// precedence over the template's fields.
+    pub fn create_from_template(
+        &mut self,
+        name: &str,
+        overrides: UpdateProcessRequest,
+    ) -> Result<ProcessConfig> {
+        let template = self
+            .templates
+            .get(name)
+            .ok_or_else(|| anyhow!("Template not found: {}", name))?;
+        let request = templates::instantiate(template, overrides)?;
+        self.create_process(request)
+    }
+
+    pub fn update_process(
+        &mut self,
+        id: &str,
+        request: UpdateProcessRequest,
+    ) -> Result<ProcessConfig> {
+        let process_manager = self.process_manager.inner.lock();
+        let mut processes = process_manager.processes.lock();
+
+        let mut updated_config = processes
+            .get(id)
+            .ok_or_else(|| anyhow!("Process not found: {}", id))?
+            .clone();
+        apply_update(
+            &mut updated_config,
+            request,
+            self.settings.default_bin_path.as_deref(),
+        )?;
+        processes.insert(id.to_string(), updated_config.clone());
+
+        drop(processes);
+        drop(process_manager);
+
+        if let Err(e) = self.save_config() {
+            error!("Failed to save configuration after updating process: {e}");
+        }
+
+        info!(
+            "Updated process configuration: {} ({})",
+            updated_config.name, updated_config.id
+        );
+        Ok(updated_config)
+    }
+
+    /// Applies several updates as one: either all of them are validated,
+    /// applied and saved together, or none is. Updates to the same process
+    /// are applied in order. Returns the updated configurations.
+    pub fn update_processes(
+        &mut self,
+        updates: Vec<(String, UpdateProcessRequest)>,
+    ) -> Result<Vec<ProcessConfig>> {
+        let process_manager = self.process_manager.inner.lock();
+        let mut processes = process_manager.processes.lock();
+
+        let mut order: Vec<String> = Vec::new();
+        let mut staged: HashMap<String, ProcessConfig> = HashMap::new();
+        for (id, request) in updates {
+            let mut candidate = match staged.get(&id).or_else(|| processes.get(&id)) {
+                Some(config) => config.clone(),
+                None => return Err(anyhow!("Process not found: {}", id)),
+            };
+            apply_update(
+                &mut candidate,
+                request,
+                self.settings.default_bin_path.as_deref(),
+            )
+            .with_context(|| format!("Invalid update for process {id}"))?;
+            if !staged.contains_key(&id) {
+                order.push(id.clone());
+            }
+            staged.insert(id, candidate);
+        }
+
+        let mut previous: Vec<ProcessConfig> = Vec::with_capacity(order.len());
+        for id in &order {
+            if let Some(config) = staged.remove(id)
+                && let Some(old) = processes.insert(id.clone(), config)
+            {
+                previous.push(old);
+            }
+        }
+
+        drop(processes);
+        drop(process_manager);
+
+        if let Err(e) = self.save_config() {
+            let process_manager = self.process_manager.inner.lock();
+            let mut processes = process_manager.processes.lock();
+            for config in previous {
+                processes.insert(config.id.clone(), config);
+            }
+            return Err(e.context("Failed to save configuration, updates rolled back"));
+        }
+
+        let updated = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            order
+                .iter()
+                .filter_map(|id| processes.get(id).cloned())
+                .collect::<Vec<_>>()
+        };
+        info!("Updated {} process configurations", updated.len());
+        Ok(updated)
+    }
+
+    /// Flips `auto_restart` without a full update. Disabling it leaves a
+    /// running process alone but cancels any restart that is already pending.
+    pub fn set_auto_restart(&mut self, id: &str, enabled: bool) -> Result<()> {
+        {
+            let process_manager = self.process_manager.inner.lock();
+            let mut processes = process_manager.processes.lock();
+            let config = processes
+                .get_mut(id)
+                .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+
+            config.auto_restart = enabled;
+            config.updated_at = get_current_timestamp();
+
+            if !enabled && let Some(runtime) = process_manager.runtime_states.lock().get(id) {
+                *runtime.restart_at.lock() = None;
+            }
+        }
+
+        self.save_config()?;
+        info!("Set auto_restart={enabled} for process {id}");
+        Ok(())
+    }
+
+    /// Replaces `env_vars` and persists them. With `restart`, a running
+    /// process is restarted gracefully so that it picks them up; otherwise
+    /// they take effect on its next start. Returns whether it was restarted.
+    pub fn apply_env(
+        &mut self,
+        id: &str,
+        env_vars: HashMap<String, String>,
+        restart: bool,
+    ) -> Result<bool> {
+        {
+            let process_manager = self.process_manager.inner.lock();
+            let mut processes = process_manager.processes.lock();
+            let config = processes
+                .get_mut(id)
+                .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+
+            config.env_vars = Some(env_vars);
+            config.updated_at = get_current_timestamp();
+        }
+        self.save_config()?;
+        info!("Updated env_vars of process {id}");
+
+        if !restart || !self.get_process(id)?.is_running {
+            return Ok(false);
+        }
+        self.restart_process(id)?;
+        Ok(true)
+    }
+
+    pub fn delete_process(&mut self, id: &str) -> Result<()> {
+        self.stop_process(id)?;
+
+        let process_manager = self.process_manager.inner.lock();
+        let mut processes = process_manager.processes.lock();
+        let mut runtime_states = process_manager.runtime_states.lock();
+        let config = processes
+            .remove(id)
+            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+        runtime_states.remove(id);
+
+        drop(processes);
+        drop(runtime_states);
+        drop(process_manager);
+
+        if let Err(e) = self.save_config() {
+            error!("Failed to save configuration after deleting process: {e}");
+        }
+
+        info!(
+            "Deleted process configuration: {} ({})",
+            config.name, config.id
+        );
+        Ok(())
+    }
+
+    pub fn list_processes(&self) -> Result<Vec<ProcessStatus>> {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let mut runtime_states = process_manager.runtime_states.lock();
+
+        let mut status_list = Vec::new();
+
+        for (id, config) in processes.iter() {
+            let runtime = runtime_for(&mut runtime_states, id);
+            status_list.push(build_process_status(id, config, runtime));
+        }
+        status_list.sort_by(|a, b| compare_display_order(&a.config, &b.config));
+
+        Ok(status_list)
+    }
+
+    /// Like `list_processes`, but only the processes currently in `state`;
+    /// statuses are only built for those.
+    pub fn list_processes_by_state(&self, state: ProcessState) -> Result<Vec<ProcessStatus>> {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let mut runtime_states = process_manager.runtime_states.lock();
+
+        let mut status_list = Vec::new();
+        for (id, config) in processes.iter() {
+            let runtime = runtime_for(&mut runtime_states, id);
+            let is_running = is_alive(runtime);
+            if effective_state(runtime, is_running) == state {
+                status_list.push(build_process_status(id, config, runtime));
+            }
+        }
+        status_list.sort_by(|a, b| compare_display_order(&a.config, &b.config));
+
+        Ok(status_list)
+    }
+
+    /// Counts from the same state as `list_processes`, without building a
+    /// status for each process, so it is cheap enough for frequent polling.
+    pub fn health_summary(&self) -> HealthSummary {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+
+        let mut running = 0;
+        let mut any_failed = false;
+        for id in processes.keys() {
+            let Some(runtime) = runtime_states.get(id) else {
+                continue;
+            };
+            let is_running = is_alive(runtime);
+            running += usize::from(is_running);
+            any_failed |= effective_state(runtime, is_running) == ProcessState::Failed;
+        }
+
+        HealthSummary {
+            all_running: running == processes.len(),
+            any_failed,
+            running,
+            total: processes.len(),
+        }
+    }
+
+    /// Restart statistics across all processes. Restart counts include those
+    /// restored from the stats file; a process counts as crash-looping until
+    /// it has been running for `STABLE_RUN_SECS` again.
+    pub fn reliability_report(&self) -> ReliabilityReport {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+        let now = get_current_timestamp();
+
+        let mut report = ReliabilityReport::default();
+        let mut uptimes = Vec::new();
+        for (id, config) in processes.iter() {
+            let Some(runtime) = runtime_states.get(id) else {
+                continue;
+            };
+            let restart_count = runtime.restart_count.load(Ordering::Relaxed) as u32;
+            report.total_restarts += u64::from(restart_count);
+            if restart_count > 0
+                && report
+                    .most_restarted
+                    .as_ref()
+                    .is_none_or(|most| (restart_count, &most.id) > (most.restart_count, id))
+            {
+                report.most_restarted = Some(ProcessRestarts {
+                    id: id.clone(),
+                    name: config.name.clone(),
+                    restart_count,
+                });
+            }
+
+            let is_running = is_alive(runtime);
+            let uptime = (*runtime.started_at.lock())
+                .filter(|_| is_running)
+                .map(|started_at| now.saturating_sub(started_at));
+            uptimes.extend(uptime);
+            let stable = uptime.is_some_and(|secs| secs >= STABLE_RUN_SECS);
+            if runtime.consecutive_failures.load(Ordering::Relaxed) > 0 && !stable {
+                report.crash_looping.push(id.clone());
+            }
+        }
+        report.crash_looping.sort();
+        if !uptimes.is_empty() {
+            report.average_uptime_secs = Some(uptimes.iter().sum::<u64>() / uptimes.len() as u64);
+        }
+        report
+    }
+
+    /// Puts the given processes first, in that order, followed by all other
+    /// processes in their existing order, and persists the result.
+    pub fn reorder_processes(&mut self, ordered_ids: Vec<String>) -> Result<()> {
+        {
+            let process_manager = self.process_manager.inner.lock();
+            let mut processes = process_manager.processes.lock();
+
+            let mut seen = std::collections::HashSet::new();
+            for id in &ordered_ids {
+                if !processes.contains_key(id) {
+                    return Err(anyhow!("Process not found: {}", id));
+                }
+                if !seen.insert(id.as_str()) {
+                    return Err(anyhow!("Process listed more than once: {}", id));
+                }
+            }
+
+            let mut rest: Vec<&ProcessConfig> = processes
+                .values()
+                .filter(|c| !seen.contains(c.id.as_str()))
+                .collect();
+            rest.sort_by(|a, b| compare_display_order(a, b));
+            let full_order: Vec<String> = ordered_ids
+                .iter()
+                .cloned()
+                .chain(rest.into_iter().map(|c| c.id.clone()))
+                .collect();
+
+            for (position, id) in full_order.iter().enumerate() {
+                if let Some(config) = processes.get_mut(id) {
+                    config.order = position as u32;
+                }
+            }
+        }
+
+        self.save_config()?;
+        info!("Reordered processes");
+        Ok(())
+    }
+
+    pub fn list_processes_paged(
+        &self,
+        sort_by: ProcessSortBy,
+        order: SortOrder,
+        offset: usize,
+        limit: usize,
+    ) -> Result<ProcessPage> {
+        let mut status_list = self.list_processes()?;
+        let now = get_current_timestamp();
+        let uptime = |status: &ProcessStatus| match (status.is_running, status.started_at) {
+            (true, Some(started_at)) => now.saturating_sub(started_at),
+            _ => 0,
+        };
+
+        // Ties are always broken by id so that paging through the list is stable
+        status_list.sort_by(|a, b| {
+            let primary = match sort_by {
+                ProcessSortBy::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+                ProcessSortBy::Order => a.config.order.cmp(&b.config.order),
+                ProcessSortBy::CreatedAt => a.config.created_at.cmp(&b.config.created_at),
+                ProcessSortBy::State => a.state.cmp(&b.state),
+                ProcessSortBy::Uptime => uptime(a).cmp(&uptime(b)),
+            };
+            let primary = match order {
+                SortOrder::Asc => primary,
+                SortOrder::Desc => primary.reverse(),
+            };
+            primary.then_with(|| a.id.cmp(&b.id))
+        });
+
+        let total = status_list.len();
+        let items = status_list.into_iter().skip(offset).take(limit).collect();
+
+        Ok(ProcessPage {
+            total,
+            offset,
+            limit,
+            items,
+        })
+    }
+
+    pub fn get_process(&self, id: &str) -> Result<ProcessStatus> {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let mut runtime_states = process_manager.runtime_states.lock();
+
+        let config = processes
+            .get(id)
+            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+
+        let runtime = runtime_for(&mut runtime_states, id);
+
+        Ok(build_process_status(id, config, runtime))
+    }
+
+    /// `get_process` with `os_command_line` read from the OS, to see what
+    /// actually runs, e.g. behind an elevation wrapper.
+    pub fn get_process_with_command_line(&self, id: &str) -> Result<ProcessStatus> {
+        let mut status = self.get_process(id)?;
+        if status.is_running
+            && let Some(pid) = status.pid
+        {
+            status.os_command_line = metrics::command_line(pid);
+        }
+        Ok(status)
+    }
+
+    pub fn start_process(&mut self, id: &str) -> Result<()> {
+        info!("Starting process: {id}");
+
+        self.start_with_overrides(id, None)
+    }
+
+    /// Like `start_process`, with ad-hoc overrides for this run only, as
+    /// typed on a command line: `KEY=VALUE` sets an environment variable and
+    /// anything else is appended to the arguments. Nothing is persisted, so
+    /// later runs, auto-restarts included, use the stored config again.
+    pub fn start_process_with_overrides(&mut self, id: &str, overrides: &[String]) -> Result<()> {
+        info!("Starting process with overrides: {id}");
+
+        self.start_with_overrides(id, Some(run_overrides::parse(overrides)))
+    }
+
+    fn start_with_overrides(
+        &mut self,
+        id: &str,
+        overrides: Option<run_overrides::RunOverrides>,
+    ) -> Result<()> {
+        // An explicit start re-arms a process that auto-restart gave up on
+        self.reset_restart_state(id)?;
+        // and drops the overrides of an earlier start that never ran
+        if let Some(runtime) = self.runtime_handle(id) {
+            *runtime.run_overrides.lock() = overrides;
+        }
+        self.run_global_pre_start_hook(id)?;
+        self.launch_process(id, true)
+    }
+
+    /// Sets the shell command run before every process start, service-wide,
+    /// or removes it with `None`.
+    pub fn set_global_pre_start_hook(&mut self, command: Option<String>) -> Result<()> {
+        let command = command.filter(|c| !c.trim().is_empty());
+        match &command {
+            Some(command) => info!("Setting global pre-start hook: {command}"),
+            None => info!("Removing global pre-start hook"),
+        }
+        self.settings.global_pre_start_hook = command;
+        self.save_settings()
+    }
+
+    /// Runs the global pre-start hook, if set, before starting `id`, failing
+    /// when it does not exit with 0 within `GLOBAL_PRE_START_HOOK_TIMEOUT`.
+    fn run_global_pre_start_hook(&self, id: &str) -> Result<()> {
+        let Some(command) = &self.settings.global_pre_start_hook else {
+            return Ok(());
+        };
+        run_start_gate(
+            "Global pre-start hook",
+            command,
+            GLOBAL_PRE_START_HOOK_TIMEOUT,
+            None,
+        )
+        .map_err(|reason| {
+            if let Some(runtime) = self.runtime_handle(id) {
+                *runtime.stop_cause.lock() = Some(StopCause::StartFailed(reason.clone()));
+            }
+            anyhow!(reason)
+        })
+    }
+
+    /// Like `start_process`, but without evaluating `start_condition_command`.
+    /// A start that is still waiting for the condition is taken over.
+    pub fn start_process_ignoring_condition(&mut self, id: &str) -> Result<()> {
+        info!("Starting process without checking its start condition: {id}");
+
+        self.reset_restart_state(id)?;
+        self.run_global_pre_start_hook(id)?;
+        if let Some(runtime) = self.runtime_handle(id)
+            && runtime.preparing.swap(false, Ordering::SeqCst)
+        {
+            runtime.start_generation.fetch_add(1, Ordering::SeqCst);
+        }
+        self.launch_process(id, false)
+    }
+
+    /// Whether the process is running or a deferred start of it is under way,
+    /// for starts that treat an already running process as success. Unknown
+    /// processes are not started.
+    pub fn is_started(&self, id: &str) -> bool {
+        let process_manager = self.process_manager.inner.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+        runtime_states
+            .get(id)
+            .is_some_and(|runtime| is_alive(runtime) || runtime.preparing.load(Ordering::SeqCst))
+    }
+
+    fn reset_restart_state(&self, id: &str) -> Result<()> {
+        let process_manager = self.process_manager.inner.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+        let runtime = runtime_states
+            .get(id)
+            .ok_or_else(|| anyhow!("Runtime state not found: {}", id))?;
+
+        runtime.consecutive_failures.store(0, Ordering::Relaxed);
+        runtime.awaiting_binary.store(false, Ordering::Relaxed);
+        if runtime.held.swap(false, Ordering::Relaxed) {
+            info!("Process {id} is no longer held");
+        }
+        *runtime.restart_at.lock() = None;
+        *runtime.last_error.lock() = None;
+        let mut state = runtime.state.lock();
+        if *state == ProcessState::Failed {
+            *state = ProcessState::Stopped;
+        }
+        Ok(())
+    }
+
+    /// Spawns the process. A start that first has to pass its start condition
+    /// or download its binary is finished in the background instead, see
+    /// `spawn_deferred_launch`. A failure is recorded as the reason the
+    /// process is not running.
+    fn launch_process(&mut self, id: &str, check_condition: bool) -> Result<()> {
+        let result = self.try_launch_process(id, check_condition);
+        if let Err(e) = &result
+            && !self.is_started(id)
+            && let Some(runtime) = self.runtime_handle(id)
+        {
+            *runtime.stop_cause.lock() = Some(StopCause::StartFailed(e.to_string()));
+            runtime.run_overrides.lock().take();
+        }
+        result
+    }
+
+    fn try_launch_process(&mut self, id: &str, check_condition: bool) -> Result<()> {
+        if self.settings.maintenance {
+            return Err(anyhow!("Service is in maintenance mode"));
+        }
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+
+        let config = processes
+            .get(id)
+            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+        // As stored, for telling when it changes while the process runs
+        let spawned_command = SpawnedCommand::of(config);
+
+        let config = &*with_default_bin_path(config, &self.settings);
+        let runtime = runtime_states
+            .get(id)
+            .ok_or_else(|| anyhow!("Runtime state not found: {}", id))?;
+
+        // A pid recorded for a run that died may belong to another process by now
+        if is_alive(runtime) {
+            return Err(anyhow!("Process {} is already running", config.name));
+        }
+        // Single flight: a start that is still preparing launches the process
+        // itself, so another start joins it instead of spawning a second one
+        if runtime.preparing.load(Ordering::SeqCst) {
+            info!(
+                "Process {} is already starting, joining the start in flight",
+                config.name
+            );
+            return Ok(());
+        }
+
+        let condition = config
+            .start_condition_command
+            .clone()
+            .filter(|_| check_condition);
+        if condition.is_some() || download::needs_download(config) {
+            runtime.preparing.store(true, Ordering::SeqCst);
+            *runtime.state.lock() = ProcessState::Starting;
+            *runtime.last_error.lock() = None;
+            let generation = runtime.start_generation.load(Ordering::SeqCst);
+            spawn_deferred_launch(
+                self.handle.clone(),
+                config.clone(),
+                runtime.clone(),
+                generation,
+                condition,
+            );
+            return Ok(());
+        }
+        if let Some(holder) = mutex_group_holder(config, &processes, &runtime_states) {
+            let group = config.mutex_group.as_deref().unwrap_or_default();
+            if config.mutex_policy == MutexPolicy::Reject {
+                return Err(anyhow!(
+                    "Process {holder} of mutex group {group} is already running"
+                ));
+            }
+            info!(
+                "Process {} waits for {holder} of mutex group {group} to exit",
+                config.name
+            );
+            runtime.preparing.store(true, Ordering::SeqCst);
+            *runtime.state.lock() = ProcessState::Starting;
+            *runtime.last_error.lock() = None;
+            let generation = runtime.start_generation.load(Ordering::SeqCst);
+            spawn_mutex_wait(
+                self.handle.clone(),
+                config.clone(),
+                runtime.clone(),
+                generation,
+            );
+            return Ok(());
+        }
+        if !Path::new(&config.bin_path).exists() {
+            return Err(anyhow!("Binary not found at: {}", config.bin_path));
+        }
+        if let Some(working_dir) = &config.working_dir
+            && !Path::new(working_dir).is_dir()
+        {
+            return Err(anyhow!("Working directory not found: {working_dir}"));
+        }
+
+        check_binary_arch(config)?;
+
+        process::ensure_executable_permissions(&config.bin_path).with_context(|| {
+            format!("Failed to set execute permissions for: {}", config.bin_path)
+        })?;
+
+        let mut env_vars = config
+            .env_vars
+            .as_ref()
+            .map(|vars| secrets::resolve_env_vars(vars, self.secrets.as_ref()))
+            .transpose()?;
+        let mut args = config.args.clone();
+        if let Some(overrides) = runtime.run_overrides.lock().take() {
+            let mut names: Vec<&String> = overrides.env.keys().collect();
+            names.sort();
+            info!(
+                "Starting process {} with overrides for this run: env {names:?}, args {:?}",
+                config.name, overrides.args
+            );
+            env_vars
+                .get_or_insert_with(HashMap::new)
+                .extend(overrides.env);
+            args.extend(overrides.args);
+        }
+
+        let log_path = logs::resolve_log_path(
+            &config.log_file,
+            &config.name,
+            &config.id,
+            get_current_timestamp(),
+        );
+        let log_file = File::options()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .with_context(|| format!("Failed to open log file: {log_path}"))?;
+        let log_offset = log_file.metadata().map(|m| m.len()).unwrap_or(0);
+        let stderr_log_path = config.stderr_log_file.as_ref().map(|template| {
+            logs::resolve_log_path(template, &config.name, &config.id, get_current_timestamp())
+        });
+        let stderr_log_file = stderr_log_path
+            .as_ref()
+            .map(|path| {
+                File::options()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("Failed to open stderr log file: {path}"))
+            })
+            .transpose()?;
+        let mut startup_logs = vec![(log_path.clone(), log_offset)];
+        if let (Some(path), Some(file)) = (&stderr_log_path, &stderr_log_file) {
+            startup_logs.push((path.clone(), file.metadata().map(|m| m.len()).unwrap_or(0)));
+        }
+        // Spawn process
+        let args_strs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+        let env = process::SpawnEnv {
+            working_dir: config.working_dir.as_deref(),
+            inheritance: &config.env_inheritance,
+            vars: env_vars.as_ref(),
+            run_as_user: config.run_as_user.as_deref(),
+            run_as_group: config.run_as_group.as_deref(),
+            launch_wrapper: &config.launch_wrapper,
+            allocate_pty: config.allocate_pty,
+            new_session: config.new_session,
+            log_timestamps: config.log_timestamps,
+            sched_policy: config.sched_policy,
+            io_priority: config.io_priority,
+        };
+        let pid = process::spawn_process_with_privileges(
+            &config.bin_path,
+            &args_strs,
+            &env,
+            log_file,
+            stderr_log_file,
+            config.run_as_admin,
+        )
+        .map_err(|e| anyhow!("Failed to spawn process {}: {e}", config.bin_path))?;
+
+        runtime.is_running.store(true, Ordering::Relaxed);
+        runtime.running_pid.store(pid as i32, Ordering::Relaxed);
+        *runtime.pid_start_stamp.lock() = process::process_start_stamp(pid);
+        *runtime.started_at.lock() = Some(get_current_timestamp());
+        *runtime.state.lock() = ProcessState::Starting;
+        let generation = runtime.start_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        *runtime.log_path.lock() = Some(log_path.clone());
+        *runtime.stderr_log_path.lock() = stderr_log_path;
+        *runtime.listen_address.lock() = None;
+        *runtime.spawned_command.lock() = Some(spawned_command);
+        startup_output::spawn_capture(
+            runtime.clone(),
+            generation,
+            startup_logs.clone(),
+            self.settings.shared_monitor_threads,
+        );
+        let run_config = ProcessConfig {
+            log_file: log_path,
+            ..config.clone()
+        };
+        listen_probe::spawn_watcher(run_config.clone(), runtime.clone(), generation, log_offset);
+        if self.safe_mode {
+            *runtime.state.lock() = ProcessState::Running;
+        } else {
+            file_watch::spawn_watcher(
+                self.handle.clone(),
+                run_config.clone(),
+                runtime.clone(),
+                generation,
+            );
+            log_watch::spawn_watcher(
+                self.handle.clone(),
+                run_config.clone(),
+                runtime.clone(),
+                generation,
+                startup_logs,
+            );
+            health::spawn_watcher(
+                run_config.clone(),
+                runtime.clone(),
+                generation,
+                self.settings.jitter_percent,
+            );
+            readiness::spawn_watcher(run_config, runtime.clone(), generation);
+        }
+        monitor::watch_exit(
+            self.handle.clone(),
+            pid,
+            self.settings.shared_monitor_threads,
+        );
+        if !config.launch_wrapper.is_empty() {
+            monitor::track_wrapped_process(
+                self.handle.clone(),
+                runtime.clone(),
+                generation,
+                pid,
+                config.bin_path.clone(),
+                self.settings.shared_monitor_threads,
+            );
+        }
+
+        info!("Process {} started with PID: {}", config.name, pid);
+        Ok(())
+    }
+
+    /// The stored configuration with defaults and placeholders filled in, i.e.
+    /// what the next start (or the current run) actually uses.
+    pub fn get_effective_config(&self, id: &str) -> Result<ProcessConfig> {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+
+        let config = processes
+            .get(id)
+            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+
+        let mut effective = config.clone();
+        effective.log_file = active_log_file(config, runtime_states.get(id));
+        effective.working_dir = Some(
+            process::working_directory_for(&config.bin_path, config.working_dir.as_deref())
+                .to_string_lossy()
+                .to_string(),
+        );
+        effective.kill_sequence = process::effective_kill_sequence(config.kill_sequence.as_deref());
+        if config.readiness_check.is_some() {
+            effective.readiness_timeout_secs = Some(readiness::readiness_timeout(config).as_secs());
+        }
+        Ok(effective)
+    }
+
+    /// The first lines the current or last run of the process logged.
+    pub fn get_process_startup_output(&self, id: &str) -> Result<Vec<String>> {
+        let process_manager = self.process_manager.inner.lock();
+        if !process_manager.processes.lock().contains_key(id) {
+            return Err(anyhow!("Process not found: {}", id));
+        }
+        Ok(process_manager
+            .runtime_states
+            .lock()
+            .get(id)
+            .map(|runtime| runtime.startup_output.lock().lines.clone())
+            .unwrap_or_default())
+    }
+
+    /// Why the process is not running, or `None` if it is. What holds it down
+    /// right now, like maintenance or a hold, comes before how its last run
+    /// or start ended.
+    pub fn not_running_reason(&self, id: &str) -> Result<Option<NotRunningReason>> {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let mut runtime_states = process_manager.runtime_states.lock();
+
+        let config = processes
+            .get(id)
+            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+        let runtime = runtime_for(&mut runtime_states, id);
+
+        if is_alive(runtime) {
+            return Ok(None);
+        }
+        if runtime.preparing.load(Ordering::SeqCst) {
+            return Ok(Some(NotRunningReason::Starting));
+        }
+        if self.settings.maintenance {
+            return Ok(Some(NotRunningReason::Maintenance));
+        }
+        if runtime.held.load(Ordering::Relaxed) {
+            return Ok(Some(NotRunningReason::StoppedManually { held: true }));
+        }
+        if runtime.awaiting_binary.load(Ordering::Relaxed) {
+            return Ok(Some(NotRunningReason::AwaitingBinary));
+        }
+
+        let crash_loop = *runtime.state.lock() == ProcessState::Failed
+            && runtime.consecutive_failures.load(Ordering::Relaxed) >= CRASH_LOOP_THRESHOLD;
+        let reason = match runtime.stop_cause.lock().clone() {
+            Some(
+                StopCause::Exited(_) | StopCause::StartFailed(_) | StopCause::RuntimeExceeded(_),
+            ) if crash_loop => NotRunningReason::CrashLoop {
+                message: runtime.last_error.lock().clone().unwrap_or_default(),
+            },
+            Some(StopCause::Manual) => NotRunningReason::StoppedManually { held: false },
+            Some(StopCause::Exited(exit_code)) => NotRunningReason::Exited {
+                exit_code,
+                restart_at: *runtime.restart_at.lock(),
+            },
+            Some(StopCause::RuntimeExceeded(max_runtime_secs)) => {
+                NotRunningReason::RuntimeExceeded {
+                    max_runtime_secs,
+                    restart_at: *runtime.restart_at.lock(),
+                }
+            }
+            Some(StopCause::StartFailed(error)) => NotRunningReason::StartFailed { error },
+            Some(StopCause::ConditionRefused(message)) => {
+                NotRunningReason::ConditionNotMet { message }
+            }
+            None if self.safe_mode && config.auto_start => NotRunningReason::SafeMode,
+            None => NotRunningReason::NeverStarted,
+        };
+        Ok(Some(reason))
+    }
+
+    /// A shell command line that starts the process the way the service
+    /// would, for reproducing a start by hand. Keyring references in
+    /// `env_vars` are left unresolved so that no secret ends up in it.
+    pub fn reproduce_command(&self, id: &str) -> Result<String> {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let config = processes
+            .get(id)
+            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+        let config = &*with_default_bin_path(config, &self.settings);
+
+        let args: Vec<&str> = config.args.iter().map(String::as_str).collect();
+        let env = process::SpawnEnv {
+            working_dir: config.working_dir.as_deref(),
+            inheritance: &config.env_inheritance,
+            vars: config.env_vars.as_ref(),
+            run_as_user: config.run_as_user.as_deref(),
+            run_as_group: config.run_as_group.as_deref(),
+            launch_wrapper: &config.launch_wrapper,
+            allocate_pty: config.allocate_pty,
+            new_session: config.new_session,
+            log_timestamps: config.log_timestamps,
+            sched_policy: config.sched_policy,
+            io_priority: config.io_priority,
+        };
+        Ok(process::shell_command(
+            &config.bin_path,
+            &args,
+            &env,
+            config.run_as_admin,
+        ))
+    }
+
+    /// Stops the process gracefully and starts it again. Fails without
+    /// stopping it once the process used up its `restart_rate_limit`.
+    pub fn restart_process(&mut self, id: &str) -> Result<()> {
+        self.take_restart_token(id)?;
+        self.stop_process(id)?;
+        self.start_process(id)?;
+
+        if let Some(runtime) = self.runtime_handle(id) {
+            self.count_restart(id, &runtime);
+        }
+        Ok(())
+    }
+
+    /// Makes the running process reread its configuration: with
+    /// `supports_sighup_reload` by sending it SIGHUP, otherwise, and always on
+    /// Windows, by restarting it.
+    pub fn reload_process_config(&mut self, id: &str) -> Result<()> {
+        let (config, pid) = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            let runtime_states = process_manager.runtime_states.lock();
+            let config = processes
+                .get(id)
+                .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+            let pid = runtime_states
+                .get(id)
+                .filter(|runtime| is_alive(runtime))
+                .map_or(-1, |runtime| runtime.running_pid.load(Ordering::Relaxed));
+            (
+                with_default_bin_path(config, &self.settings).into_owned(),
+                pid,
+            )
+        };
+        if pid <= 0 {
+            return Err(anyhow!("Process {} is not running", config.name));
+        }
+        if !config.supports_sighup_reload {
+            info!(
+                "Process {} does not reload on SIGHUP, restarting it to reload its configuration",
+                config.name
+            );
+            return self.restart_process(id);
+        }
+
+        #[cfg(target_os = "windows")]
+        {
+            warn!(
+                "SIGHUP is not available on Windows, restarting process {} to reload its configuration",
+                config.name
+            );
+            self.restart_process(id)
+        }
+        #[cfg(not(target_os = "windows"))]
+        {
+            // Under sudo the pid is that of sudo, which relays the signal
+            if !config.run_as_admin && !process::runs_binary(pid as u32, &config.bin_path) {
+                return Err(anyhow!(
+                    "PID {pid} of process {} no longer runs {}",
+                    config.name,
+                    config.bin_path
+                ));
+            }
+            process::send_signal(pid as u32, "HUP")
+                .with_context(|| format!("Failed to reload process {}", config.name))?;
+            info!(
+                "Process {} ({}) asked to reload its configuration",
+                config.name, config.id
+            );
+            Ok(())
+        }
+    }
+
+    /// Takes a restart of `id` from its `restart_rate_limit`, which every
+    /// restart goes through, whatever set it off.
+    fn take_restart_token(&self, id: &str) -> Result<()> {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+        let (Some(config), Some(runtime)) = (processes.get(id), runtime_states.get(id)) else {
+            return Ok(());
+        };
+        restart_limit::take_restart(config, runtime, Instant::now()).map_err(|e| {
+            warn!("Not restarting process {id}: {e}");
+            e.into()
+        })
+    }
+
+    /// Bumps `restart_count`, emitting `restart_threshold_exceeded` on the
+    /// restart that reaches the process's alert threshold, and only that one.
+    fn count_restart(&mut self, id: &str, runtime: &ProcessRuntime) {
+        let restart_count = runtime.restart_count.fetch_add(1, Ordering::Relaxed) as u32 + 1;
+        // Written out by the monitor, never on the restart itself
+        self.stats_dirty_since.get_or_insert_with(Instant::now);
+        let config = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            processes.get(id).cloned()
+        };
+        if let Some(config) = config
+            && config.restart_alert_threshold == Some(restart_count)
+        {
+            self.events.emit(ServiceEvent::RestartThresholdExceeded {
+                id: config.id,
+                name: config.name,
+                restart_count,
+                threshold: restart_count,
+                timestamp: get_current_timestamp(),
+            });
+        }
+    }
+
+    /// Recorded events numbered `since` or later, oldest first.
+    pub fn list_events(&self, since: u64) -> Vec<EventRecord> {
+        self.events.since(since)
+    }
+
+    /// Stops the process like `stop_process` and holds it stopped: neither
+    /// auto-restart nor auto-start brings it back, only an explicit start,
+    /// which also lifts the hold.
+    pub fn stop_process_and_hold(&mut self, id: &str) -> Result<StopOutcome> {
+        let runtime = self
+            .runtime_handle(id)
+            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+        // Held before stopping, so a restart racing the stop cannot slip in
+        runtime.held.store(true, Ordering::Relaxed);
+        info!("Holding process {id} stopped");
+        self.stop_process(id)
+    }
+
+    /// Stops the process and reports whether it exited gracefully or had to
+    /// be killed.
+    pub fn stop_process(&mut self, id: &str) -> Result<StopOutcome> {
+        info!("Stopping process: {id}");
+
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+
+        let config = processes
+            .get(id)
+            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+
+        let runtime = runtime_states
+            .get(id)
+            .ok_or_else(|| anyhow!("Runtime state not found: {}", id))?;
+
+        let pid = runtime.running_pid.load(Ordering::Relaxed);
+        *runtime.restart_at.lock() = None;
+        *runtime.stop_cause.lock() = Some(StopCause::Manual);
+        runtime.awaiting_binary.store(false, Ordering::Relaxed);
+        if runtime.preparing.swap(false, Ordering::SeqCst) {
+            // The pending start still finishes preparing, but does not launch
+            runtime.start_generation.fetch_add(1, Ordering::SeqCst);
+            *runtime.state.lock() = ProcessState::Stopped;
+        }
+
+        if pid <= 0 {
+            warn!("Process {} is not running", config.name);
+            return Ok(process::already_exited());
+        }
+
+        runtime.start_generation.fetch_add(1, Ordering::SeqCst);
+        let stop_started = Instant::now();
+        let kill_result = process::kill_process(pid as u32, config.kill_sequence.as_deref());
+        process::try_reap(pid);
+        if self.settings.stop_log_flush_timeout_ms > 0 {
+            let timeout = Duration::from_millis(self.settings.stop_log_flush_timeout_ms);
+            flush_after_stop(config, runtime, pid as u32, timeout);
+        }
+
+        runtime.is_running.store(false, Ordering::Relaxed);
+        *runtime.state.lock() = ProcessState::Stopped;
+        runtime.running_pid.store(INVALID_PID, Ordering::Relaxed);
+        *runtime.started_at.lock() = None;
+
+        match kill_result {
+            Ok(outcome) => {
+                if outcome.graceful {
+                    info!(
+                        "Process {} (PID: {}) terminated gracefully in {}ms",
+                        config.name, pid, outcome.duration_ms
+                    );
+                } else {
+                    warn!(
+                        "Process {} (PID: {}) ignored graceful signals and was killed after {}ms",
+                        config.name, pid, outcome.duration_ms
+                    );
+                }
+                runtime.last_exit_code.store(0, Ordering::Relaxed);
+                Ok(outcome)
+            }
+            Err(e) => {
+                error!(
+                    "Failed to terminate process {} (PID: {}): {}",
+                    config.name, pid, e
+                );
+                runtime.last_exit_code.store(-1, Ordering::Relaxed);
+                if e.kind() == std::io::ErrorKind::TimedOut {
+                    return Err(CoreError::StopTimeout {
+                        elapsed: stop_started.elapsed(),
+                    }
+                    .into());
+                }
+                Err(anyhow!("Failed to stop process: {}", e))
+            }
+        }
+    }
+
+    /// The log file the process is currently writing to, with placeholders
+    /// resolved.
+    pub fn active_log_path(&self, id: &str) -> Result<String> {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+
+        let config = processes
+            .get(id)
+            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+        Ok(active_log_file(config, runtime_states.get(id)))
+    }
+
+    /// The current end of the process log, for a client following it to
+    /// resume from later with `log_stream::follow`.
+    pub fn get_log_offset(&self, id: &str) -> Result<LogOffsetResponse> {
+        let path = self.active_log_path(id)?;
+        let position = match std::fs::metadata(&path) {
+            Ok(metadata) => LogPosition {
+                file_id: logs::file_id(&metadata),
+                offset: metadata.len(),
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => LogPosition {
+                file_id: 0,
+                offset: 0,
+            },
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read log file: {path}"));
+            }
+        };
+        Ok(LogOffsetResponse {
+            id: id.to_string(),
+            path,
+            position,
+        })
+    }
+
+    /// Syncs the process's current log file to disk. Children write straight
+    /// to the file, so anything they have written is already visible to
+    /// `get_process_logs`; output still buffered inside the child is not.
+    pub fn flush_logs(&self, id: &str) -> Result<()> {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+
+        let config = processes
+            .get(id)
+            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+        let log_file = active_log_file(config, runtime_states.get(id));
+
+        if !Path::new(&log_file).exists() {
+            return Ok(());
+        }
+        sync_log_file(&log_file).with_context(|| format!("Failed to sync log file: {log_file}"))?;
+        debug!("Synced log file for process {}: {log_file}", config.name);
+        Ok(())
+    }
+
+    /// The last `max_bytes` of the process's current log file, exactly as
+    /// stored, without any decoding.
+    pub fn get_process_log_bytes(&self, id: &str, max_bytes: usize) -> Result<Vec<u8>> {
+        let log_file = self.active_log_path(id)?;
+        if !Path::new(&log_file).exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut file = File::open(&log_file)
+            .with_context(|| format!("Failed to open log file: {log_file}"))?;
+        let len = file.metadata()?.len();
+        let start = len.saturating_sub(max_bytes as u64);
+        file.seek(SeekFrom::Start(start))?;
+
+        let mut bytes = Vec::with_capacity((len - start) as usize);
+        file.take(max_bytes as u64)
+            .read_to_end(&mut bytes)
+            .with_context(|| format!("Failed to read log file: {log_file}"))?;
+        Ok(bytes)
+    }
+
+    /// How many lines a log request for `requested` lines gets: the default
+    /// when unspecified, at most `max_log_lines`, and whether it was cut down.
+    fn log_line_count(&self, requested: Option<usize>) -> (usize, bool) {
+        let requested = requested.unwrap_or(self.settings.default_log_lines);
+        let max = self.settings.max_log_lines;
+        (requested.min(max), requested > max)
+    }
+
+    /// The last `lines` lines of the process log. With `strip_ansi` color codes
+    /// and other escape sequences are removed from the returned text only.
+    /// With `max_bytes` only as many of the last lines as fit into that many
+    /// bytes are returned, up to `max_log_lines` when `lines` is unset.
+    pub fn get_process_logs(
+        &self,
+        id: &str,
+        lines: Option<usize>,
+        strip_ansi: bool,
+        max_bytes: Option<usize>,
+    ) -> Result<LogResponse> {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+
+        let config = processes
+            .get(id)
+            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+        let runtime = runtime_states.get(id);
+        let log_file = active_log_file(config, runtime);
+        // Only a launch records the log path of its run
+        let ever_started = runtime.is_some_and(|runtime| runtime.log_path.lock().is_some());
+
+        if !Path::new(&log_file).exists() {
+            return Ok(LogResponse {
+                id: id.to_string(),
+                name: config.name.clone(),
+                log_content: String::new(),
+                total_lines: 0,
+                fetched_lines: 0,
+                fetched_bytes: 0,
+                clamped: false,
+                log_file_exists: false,
+                ever_started,
+            });
+        }
+
+        let file = File::open(&log_file)
+            .with_context(|| format!("Failed to open log file: {log_file}"))?;
+
+        let reader = BufReader::new(file);
+        let all_lines = logs::read_lines_truncated(reader, self.settings.max_log_line_bytes)
+            .with_context(|| format!("Failed to read log file: {log_file}"))?;
+
+        let total_lines = all_lines.len();
+        let (wanted, clamped) = match (lines, max_bytes) {
+            (None, Some(_)) => (self.settings.max_log_lines, false),
+            _ => self.log_line_count(lines),
+        };
+        let start_index = total_lines.saturating_sub(wanted);
+
+        let mut fetched: Vec<String> = if strip_ansi {
+            all_lines[start_index..]
+                .iter()
+                .map(|line| logs::strip_ansi(line))
+                .collect()
+        } else {
+            all_lines[start_index..].to_vec()
+        };
+        if let Some(max_bytes) = max_bytes {
+            let fitting = logs::lines_within(&fetched, max_bytes);
+            fetched.drain(..fetched.len() - fitting);
+        }
+        let log_content = fetched.join("\n");
+
+        Ok(LogResponse {
+            id: id.to_string(),
+            name: config.name.clone(),
+            fetched_bytes: log_content.len(),
+            log_content,
+            total_lines,
+            fetched_lines: fetched.len(),
+            clamped,
+            log_file_exists: true,
+            ever_started,
+        })
+    }
+
+    /// The last `lines` lines of stdout and stderr merged into one view, each
+    /// tagged with its stream. Lines are ordered by the timestamps they start
+    /// with where those can be found; without `stderr_log_file` both streams
+    /// share one log and its lines are returned as `combined`. With
+    /// `infer_log_level` lines are tagged with a level too, and `min_level`
+    /// keeps only those at least that severe.
+    pub fn get_combined_logs(
+        &self,
+        id: &str,
+        lines: Option<usize>,
+        min_level: Option<LogLevel>,
+    ) -> Result<CombinedLogResponse> {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+
+        let config = processes
+            .get(id)
+            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+        if min_level.is_some() && !config.infer_log_level {
+            return Err(anyhow!(
+                "Filtering by level needs infer_log_level on process {id}"
+            ));
+        }
+        let mut merged = self.merged_log_lines(config, runtime_states.get(id))?;
+        if config.infer_log_level {
+            logs::infer_levels(&mut merged);
+        }
+        if let Some(min_level) = min_level {
+            merged.retain(|line| line.level.is_some_and(|level| level <= min_level));
+        }
+
+        let total_lines = merged.len();
+        let (wanted, clamped) = self.log_line_count(lines);
+        let lines_to_fetch = wanted.min(total_lines);
+        merged.drain(..total_lines - lines_to_fetch);
+
+        Ok(CombinedLogResponse {
+            id: id.to_string(),
+            name: config.name.clone(),
+            lines: merged,
+            total_lines,
+            fetched_lines: lines_to_fetch,
+            clamped,
+        })
+    }
+
+    /// The last `limit` lines at `warn` level or above among the last
+    /// `max_log_lines` lines of stdout and stderr, newest first. Levels are
+    /// inferred as with `infer_log_level`, whether or not it is set.
+    pub fn get_recent_errors(
+        &self,
+        id: &str,
+        limit: Option<usize>,
+    ) -> Result<RecentErrorsResponse> {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+
+        let config = processes
+            .get(id)
+            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+        let mut merged = self.merged_log_lines(config, runtime_states.get(id))?;
+        logs::infer_levels(&mut merged);
+        let scanned_lines = merged.len().min(self.settings.max_log_lines);
+        let (wanted, clamped) = self.log_line_count(limit);
+        let lines: Vec<TaggedLogLine> = merged
+            .into_iter()
+            .rev()
+            .take(scanned_lines)
+            .filter(|line| line.level.is_some_and(|level| level <= LogLevel::Warn))
+            .take(wanted)
+            .collect();
+
+        Ok(RecentErrorsResponse {
+            id: id.to_string(),
+            name: config.name.clone(),
+            fetched_lines: lines.len(),
+            lines,
+            scanned_lines,
+            clamped,
+        })
+    }
+
+    /// Stdout and stderr of the current run merged in order, each line tagged
+    /// with its stream but not yet with a level.
+    fn merged_log_lines(
+        &self,
+        config: &ProcessConfig,
+        runtime: Option<&ProcessRuntime>,
+    ) -> Result<Vec<TaggedLogLine>> {
+        let read_lines = |path: &str| -> Result<Vec<String>> {
+            if !Path::new(path).exists() {
+                return Ok(Vec::new());
+            }
+            let file =
+                File::open(path).with_context(|| format!("Failed to open log file: {path}"))?;
+            logs::read_lines_truncated(BufReader::new(file), self.settings.max_log_line_bytes)
+                .with_context(|| format!("Failed to read log file: {path}"))
+        };
+
+        let stdout = read_lines(&active_log_file(config, runtime))?;
+        Ok(match active_stderr_log_file(config, runtime) {
+            Some(stderr_path) => logs::merge_streams(stdout, read_lines(&stderr_path)?),
+            None => stdout
+                .into_iter()
+                .map(|line| TaggedLogLine {
+                    stream: LogStream::Combined,
+                    line,
+                    level: None,
+                })
+                .collect(),
+        })
+    }
+
+    /// Bundles the live and archived logs of a process into a `.tar.gz` at
+    /// `dest`, along with a manifest holding its config with the environment
+    /// variable values masked. `redact` masks those values in the logs too.
+    pub fn export_process_logs(&self, id: &str, dest: &Path, redact: bool) -> Result<()> {
+        let (config, location) = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            let runtime_states = process_manager.runtime_states.lock();
+            let config = processes
+                .get(id)
+                .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+            (config.clone(), log_location(config, runtime_states.get(id)))
+        };
+
+        let log_files = location
+            .files()
+            .with_context(|| format!("Failed to list log files of process: {id}"))?;
+        log_export::write_archive(dest, &config, &log_files, redact)?;
+        info!(
+            "Exported {} log files of process {} to {dest:?}",
+            log_files.len(),
+            config.name
+        );
+        Ok(())
+    }
+
+    /// Lines matching `query` in the logs of all processes, in display order.
+    /// `query` is a plain substring unless `is_regex` is set. At most
+    /// `max_matches_per_process` hits are returned per process, and only the
+    /// first `SEARCH_MAX_BYTES_PER_PROCESS` of each log are searched.
+    pub fn search_all_logs(
+        &self,
+        query: &str,
+        is_regex: bool,
+        max_matches_per_process: usize,
+    ) -> Result<Vec<LogSearchHit>> {
+        if query.is_empty() {
+            return Err(anyhow!("Search query must not be empty"));
+        }
+        let pattern = if is_regex {
+            Some(Regex::new(query).map_err(|e| anyhow!("Invalid search pattern: {e}"))?)
+        } else {
+            None
+        };
+        let is_match = |line: &str| match &pattern {
+            Some(pattern) => pattern.is_match(line),
+            None => line.contains(query),
+        };
+        let max_matches = max_matches_per_process.min(SEARCH_MAX_MATCHES_PER_PROCESS);
+
+        let targets: Vec<(String, String, String)> = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            let runtime_states = process_manager.runtime_states.lock();
+            let mut configs: Vec<&ProcessConfig> = processes.values().collect();
+            configs.sort_by(|a, b| compare_display_order(a, b));
+            configs
+                .into_iter()
+                .map(|config| {
+                    let log_file = active_log_file(config, runtime_states.get(&config.id));
+                    (config.id.clone(), config.name.clone(), log_file)
+                })
+                .collect()
+        };
+
+        let mut hits = Vec::new();
+        for (id, name, log_file) in targets {
+            if !Path::new(&log_file).exists() {
+                continue;
+            }
+            let file = File::open(&log_file)
+                .with_context(|| format!("Failed to open log file: {log_file}"))?;
+            let matches = logs::search_lines(
+                file,
+                self.settings.max_log_line_bytes,
+                SEARCH_MAX_BYTES_PER_PROCESS,
+                max_matches,
+                is_match,
+            )
+            .with_context(|| format!("Failed to read log file: {log_file}"))?;
+            hits.extend(matches.into_iter().map(|(line_number, line)| LogSearchHit {
+                id: id.clone(),
+                name: name.clone(),
+                line_number,
+                line,
+            }));
+        }
+        Ok(hits)
+    }
+
+    pub fn auto_start_processes(&mut self) -> Result<()> {
+        if self.settings.maintenance {
+            info!("Service is in maintenance mode, skipping auto-start");
+            return Ok(());
+        }
+        if self.safe_mode {
+            info!("Service is in safe mode, skipping auto-start");
+            return Ok(());
+        }
+        info!("Auto-starting configured processes...");
+
+        let process_ids: Vec<String> = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            processes
+                .iter()
+                .filter(|(_, config)| config.auto_start)
+                .map(|(id, _)| id.clone())
+                .collect()
+        };
+
+        if process_ids.is_empty() {
+            info!("No processes configured for auto-start");
+            return Ok(());
+        }
+
+        info!(
+            "Found {} processes configured for auto-start",
+            process_ids.len()
+        );
+
+        let limit = self.settings.max_concurrent_starts;
+        if limit > 0 && process_ids.len() > limit {
+            info!("Starting at most {limit} processes at a time, queueing the rest");
+            start_queue::spawn_queued_starts(
+                self.handle.clone(),
+                process_ids,
+                limit,
+                Self::auto_start_process,
+            );
+            return Ok(());
+        }
+
+        for id in process_ids {
+            if let Err(e) = self.auto_start_process(&id) {
+                error!("Failed to auto-start process {id}: {e}");
+            } else {
+                info!("Successfully auto-started process {id}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Starts one process on behalf of auto-start. A missing binary leaves the
+    /// process failed with a reason instead of just skipping it.
+    pub fn auto_start_process(&mut self, id: &str) -> Result<()> {
+        if self
+            .runtime_handle(id)
+            .is_some_and(|runtime| runtime.held.load(Ordering::Relaxed))
+        {
+            info!("Process {id} is held, not auto-starting it");
+            return Ok(());
+        }
+        // A second auto-start, e.g. after loading the config again, coalesces
+        // with the first
+        if self.is_started(id) {
+            info!("Process {id} is already running or starting, not auto-starting it");
+            return Ok(());
+        }
+        let missing_binary = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            let runtime_states = process_manager.runtime_states.lock();
+            let config = processes
+                .get(id)
+                .map(|config| with_default_bin_path(config, &self.settings));
+            match (config, runtime_states.get(id)) {
+                (Some(config), Some(runtime))
+                    if config.bin_url.is_none() && !Path::new(&config.bin_path).exists() =>
+                {
+                    let message = format!("Binary not found at: {}", config.bin_path);
+                    *runtime.state.lock() = ProcessState::Failed;
+                    *runtime.last_error.lock() = Some(message.clone());
+                    runtime
+                        .awaiting_binary
+                        .store(self.settings.retry_missing_binary, Ordering::Relaxed);
+                    Some(message)
+                }
+                _ => None,
+            }
+        };
+
+        if let Some(message) = missing_binary {
+            if self.settings.retry_missing_binary {
+                info!("Process {id} will be started once its binary appears");
+            }
+            return Err(anyhow!(message));
+        }
+        self.start_process(id)
+    }
+
+    /// Starts every process that is not running, in display order. With
+    /// `max_concurrent_starts` set and more processes than that to start,
+    /// they go through the start queue and are reported as `queued`.
+    pub fn start_all_processes(&mut self) -> Result<BatchResult> {
+        if self.settings.maintenance {
+            return Err(anyhow!("Service is in maintenance mode"));
+        }
+        let process_ids: Vec<String> = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            let runtime_states = process_manager.runtime_states.lock();
+            let mut stopped: Vec<&ProcessConfig> = processes
+                .values()
+                .filter(|config| {
+                    runtime_states
+                        .get(&config.id)
+                        .is_some_and(|runtime| !is_alive(runtime))
+                })
+                .collect();
+            stopped.sort_by(|a, b| compare_display_order(a, b));
+            stopped
+                .into_iter()
+                .map(|config| config.id.clone())
+                .collect()
+        };
+
+        let mut result = BatchResult::default();
+        let limit = self.settings.max_concurrent_starts;
+        if limit > 0 && process_ids.len() > limit {
+            info!(
+                "Starting {} processes at most {limit} at a time",
+                process_ids.len()
+            );
+            result.queued = process_ids.clone();
+            start_queue::spawn_queued_starts(
+                self.handle.clone(),
+                process_ids,
+                limit,
+                Self::start_process,
+            );
+            return Ok(result);
+        }
+
+        for id in process_ids {
+            match self.start_process(&id) {
+                Ok(_) => result.succeeded.push(id),
+                Err(e) => result.failed.push(BatchFailure {
+                    id,
+                    error: e.to_string(),
+                }),
+            }
+        }
+        info!(
+            "Start all: {} started, {} failed",
+            result.succeeded.len(),
+            result.failed.len()
+        );
+        Ok(result)
+    }
+
+    /// The running processes with what they depend on, dependencies first.
+    fn running_in_start_order(&self) -> Result<Vec<(String, Vec<String>)>> {
+        let process_manager = self.process_manager.inner.lock();
+        let processes = process_manager.processes.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+        let mut configs: Vec<&ProcessConfig> = processes.values().collect();
+        configs.sort_by(|a, b| compare_display_order(a, b));
+        Ok(dependencies::start_order(&configs)
+            .map_err(|cycle| anyhow!("Cannot order the restart: {cycle}"))?
+            .into_iter()
+            .filter(|config| runtime_states.get(&config.id).is_some_and(is_alive))
+            .map(|config| (config.id.clone(), config.depends_on.clone()))
+            .collect())
+    }
+
+    /// Called periodically by the monitor: notices processes that exited on
+    /// their own and restarts those with `auto_restart` once their backoff
+    /// has elapsed.
+    pub fn supervise_processes(&mut self) {
+        let now = get_current_timestamp();
+        let (due_restarts, overdue) = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            let runtime_states = process_manager.runtime_states.lock();
+
+            let mut due = Vec::new();
+            let mut overdue = Vec::new();
+            for (id, config) in processes.iter() {
+                let Some(runtime) = runtime_states.get(id) else {
+                    continue;
+                };
+
+                if runtime.is_running.load(Ordering::Relaxed) {
+                    let pid = runtime.running_pid.load(Ordering::Relaxed);
+                    let exit_code = process::try_reap(pid);
+                    if exit_code.is_some() || !is_alive(runtime) {
+                        // It may have exited in between the two checks
+                        let exit_code = exit_code.or_else(|| process::try_reap(pid));
+                        record_unexpected_exit(config, runtime, exit_code, now);
+                    } else if let Some(max_runtime_secs) = config.max_runtime_secs
+                        && runtime.started_at.lock().is_some_and(|started_at| {
+                            now.saturating_sub(started_at) >= max_runtime_secs
+                        })
+                    {
+                        overdue.push((id.clone(), max_runtime_secs, config.auto_restart));
+                    }
+                }
+
+                if runtime.restart_at.lock().is_some_and(|at| at <= now) {
+                    due.push(id.clone());
+                }
+            }
+            (due, overdue)
+        };
+
+        // A hung process is stopped even in maintenance mode
+        for (id, max_runtime_secs, auto_restart) in overdue {
+            self.stop_overdue_process(&id, max_runtime_secs, auto_restart, now);
+        }
+
+        // Pending restarts and awaited binaries wait for maintenance to end
+        if self.settings.maintenance {
+            return;
+        }
+        // but are dropped in safe mode, so leaving it does not set off a crash loop
+        if self.safe_mode {
+            for id in due_restarts {
+                if let Some(runtime) = self.runtime_handle(&id) {
+                    *runtime.restart_at.lock() = None;
+                    info!("Service is in safe mode, not restarting process {id}");
+                }
+            }
+            return;
+        }
+
+        for id in due_restarts {
+            self.auto_restart_process(&id);
+        }
+
+        let binaries_appeared: Vec<String> = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            let runtime_states = process_manager.runtime_states.lock();
+            runtime_states
+                .iter()
+                .filter(|(_, runtime)| runtime.awaiting_binary.load(Ordering::Relaxed))
+                .filter(|(id, _)| {
+                    processes.get(*id).is_some_and(|config| {
+                        Path::new(&with_default_bin_path(config, &self.settings).bin_path).exists()
+                    })
+                })
+                .map(|(id, _)| id.clone())
+                .collect()
+        };
+
+        for id in binaries_appeared {
+            info!("Binary for process {id} has appeared, starting it");
+            if let Err(e) = self.start_process(&id) {
+                error!("Failed to start process {id} after its binary appeared: {e}");
+            }
+        }
+    }
+
+    /// Stops a process that ran past its `max_runtime_secs`, recording that
+    /// as the reason, and schedules its restart if it has `auto_restart`.
+    fn stop_overdue_process(
+        &mut self,
+        id: &str,
+        max_runtime_secs: u64,
+        auto_restart: bool,
+        now: u64,
+    ) {
+        warn!("Process {id} exceeded its maximum runtime of {max_runtime_secs}s, stopping it");
+        if let Err(e) = self.stop_process(id) {
+            error!("Failed to stop process {id} after it exceeded its maximum runtime: {e}");
+        }
+        let Some(runtime) = self.runtime_handle(id) else {
+            return;
+        };
+
+        let reason = format!("Runtime exceeded: still running after {max_runtime_secs}s");
+        *runtime.stop_cause.lock() = Some(StopCause::RuntimeExceeded(max_runtime_secs));
+        *runtime.last_error.lock() = Some(reason.clone());
+        if !auto_restart {
+            return;
+        }
+        if max_runtime_secs >= STABLE_RUN_SECS {
+            runtime.consecutive_failures.store(0, Ordering::Relaxed);
+        }
+        schedule_restart_or_latch(id, &runtime, &reason, now);
+    }
+
+    fn auto_restart_process(&mut self, id: &str) {
+        let Some(runtime) = self.runtime_handle(id) else {
+            return;
+        };
+        *runtime.restart_at.lock() = None;
+        if self.is_started(id) {
+            info!("Process {id} was already started again, skipping its auto-restart");
+            return;
+        }
+        if runtime.held.load(Ordering::Relaxed) {
+            info!("Process {id} is held, skipping its auto-restart");
+            return;
+        }
+        // Retried once the bucket has a restart again, without counting as a
+        // failure
+        if let Err(e) = self.take_restart_token(id) {
+            let retry_after = match e.downcast_ref::<CoreError>() {
+                Some(CoreError::RestartRateLimited { retry_after }) => *retry_after,
+                _ => Duration::ZERO,
+            };
+            let retry_secs = retry_after.as_millis().div_ceil(1000).max(1) as u64;
+            *runtime.last_error.lock() = Some(e.to_string());
+            *runtime.restart_at.lock() = Some(get_current_timestamp() + retry_secs);
+            return;
+        }
+
+        info!("Auto-restarting process {id}");
+        match self
+            .run_global_pre_start_hook(id)
+            .and_then(|()| self.launch_process(id, false))
+        {
+            Ok(_) => self.count_restart(id, &runtime),
+            Err(e) => {
+                error!("Failed to auto-restart process {id}: {e}");
+                schedule_restart_or_latch(id, &runtime, &e.to_string(), get_current_timestamp());
+            }
+        }
+    }
+
+    fn runtime_handle(&self, id: &str) -> Option<ProcessRuntime> {
+        let process_manager = self.process_manager.inner.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+        runtime_states.get(id).cloned()
+    }
+
+    /// Cancels a restart that is waiting out its backoff, leaving the process
+    /// stopped. Returns whether a restart was actually pending.
+    pub fn cancel_pending_restart(&mut self, id: &str) -> Result<bool> {
+        let process_manager = self.process_manager.inner.lock();
+        let runtime_states = process_manager.runtime_states.lock();
+        let runtime = runtime_states
+            .get(id)
+            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+
+        let cancelled = runtime.restart_at.lock().take().is_some();
+        if cancelled {
+            info!("Cancelled pending auto-restart of process {id}");
+        }
+        Ok(cancelled)
+    }
+
+    /// Re-arms a single process that auto-restart gave up on, clearing its
+    /// crash-loop latch and backoff, and starts it again when `restart` is
+    /// set. Unlike `reset_and_restart_failed`, other processes are left alone.
+    pub fn clear_failed(&mut self, id: &str, restart: bool) -> Result<()> {
+        if self.runtime_handle(id).is_none() {
+            return Err(anyhow!("Process not found: {}", id));
+        }
+
+        info!("Clearing failed state of process {id}");
+        self.reset_restart_state(id)?;
+        if restart && !self.is_started(id) {
+            self.start_process(id)?;
+        }
+        Ok(())
+    }
+
+    /// Re-arms every process that auto-restart gave up on and starts it again.
+    /// Processes that are not in the failed state are left alone.
+    pub fn reset_and_restart_failed(&mut self) -> Result<BatchResult> {
+        let failed_ids: Vec<String> = {
+            let process_manager = self.process_manager.inner.lock();
+            let runtime_states = process_manager.runtime_states.lock();
+            runtime_states
+                .iter()
+                .filter(|(_, runtime)| *runtime.state.lock() == ProcessState::Failed)
+                .map(|(id, _)| id.clone())
+                .collect()
+        };
+
+        let mut result = BatchResult::default();
+        for id in failed_ids {
+            match self.start_process(&id) {
+                Ok(_) => result.succeeded.push(id),
+                Err(e) => result.failed.push(BatchFailure {
+                    id,
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        info!(
+            "Reset failed processes: {} restarted, {} failed",
+            result.succeeded.len(),
+            result.failed.len()
+        );
+        Ok(result)
+    }
+
+    /// Deletes archived log segments older than each process's
+    /// `log_retention_days`. Returns how many files were removed.
+    pub fn enforce_log_retention(&self) -> usize {
+        let targets: Vec<(logs::LogLocation, u32)> = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            let runtime_states = process_manager.runtime_states.lock();
+            processes
+                .values()
+                .filter_map(|c| {
+                    let days = c.log_retention_days?;
+                    Some((log_location(c, runtime_states.get(&c.id)), days))
+                })
+                .collect()
+        };
+
+        let mut removed_count = 0;
+        for (location, days) in targets {
+            let max_age = Duration::from_secs(u64::from(days) * 24 * 60 * 60);
+            match logs::remove_expired_segments(&location, max_age) {
+                Ok(removed) => {
+                    for path in &removed {
+                        info!("Removed expired log segment {path:?}");
+                    }
+                    removed_count += removed.len();
+                }
+                Err(e) => warn!(
+                    "Failed to enforce log retention for {}: {e}",
+                    location.active.display()
+                ),
+            }
+        }
+        removed_count
+    }
+
+    /// Evicts the oldest rotated log segments across all processes, then
+    /// empties live logs, while the logs together exceed `max_total_log_bytes`.
+    /// Returns how many files were removed or emptied.
+    pub fn enforce_total_log_size(&self) -> usize {
+        let max_total_bytes = self.settings.max_total_log_bytes;
+        if max_total_bytes == 0 {
+            return 0;
+        }
+
+        let locations: Vec<logs::LogLocation> = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            let runtime_states = process_manager.runtime_states.lock();
+            processes
+                .values()
+                .map(|c| log_location(c, runtime_states.get(&c.id)))
+                .collect()
+        };
+
+        match logs::enforce_total_size(&locations, max_total_bytes) {
+            Ok(eviction) => {
+                for path in &eviction.removed {
+                    info!("Removed log segment {path:?} to stay under max_total_log_bytes");
+                }
+                for path in &eviction.truncated {
+                    warn!("Emptied live log {path:?} to stay under max_total_log_bytes");
+                }
+                eviction.removed.len() + eviction.truncated.len()
+            }
+            Err(e) => {
+                warn!("Failed to enforce max_total_log_bytes: {e}");
+                0
+            }
+        }
+    }
+
+    /// Stops every running process and blocks all starts, manual or
+    /// automatic, until `exit_maintenance`. Survives a service restart.
+    pub fn enter_maintenance(&mut self) -> Result<BatchResult> {
+        if self.settings.maintenance {
+            return Err(anyhow!("Service is already in maintenance mode"));
+        }
+        // Persisted before stopping anything so nothing comes back up meanwhile
+        self.settings.maintenance = true;
+        self.save_settings()?;
+        info!("Entering maintenance mode");
+
+        let running_ids: Vec<String> = {
+            let process_manager = self.process_manager.inner.lock();
+            let runtime_states = process_manager.runtime_states.lock();
+            runtime_states
+                .iter()
+                .filter(|(_, runtime)| is_alive(runtime))
+                .map(|(id, _)| id.clone())
+                .collect()
+        };
+
+        let mut result = BatchResult::default();
+        for id in running_ids {
+            match self.stop_process(&id) {
+                Ok(_) => result.succeeded.push(id),
+                Err(e) => result.failed.push(BatchFailure {
+                    id,
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        self.settings.maintenance_stopped = result.succeeded.clone();
+        self.save_settings()?;
+        info!(
+            "Maintenance mode active: {} processes stopped, {} failed to stop",
+            result.succeeded.len(),
+            result.failed.len()
+        );
+        Ok(result)
+    }
+
+    /// Turns safe mode on or off. Leaving it does not start anything; run
+    /// watchers only come back with the next start of each process.
+    pub fn set_safe_mode(&mut self, enabled: bool) {
+        if self.safe_mode != enabled {
+            info!("{} safe mode", if enabled { "Entering" } else { "Leaving" });
+        }
+        self.safe_mode = enabled;
+    }
+
+    /// Lifts maintenance mode and starts the processes it stopped again.
+    pub fn exit_maintenance(&mut self) -> Result<BatchResult> {
+        if !self.settings.maintenance {
+            return Err(anyhow!("Service is not in maintenance mode"));
+        }
+        self.settings.maintenance = false;
+        let stopped = std::mem::take(&mut self.settings.maintenance_stopped);
+        self.save_settings()?;
+        info!("Leaving maintenance mode");
+
+        let mut result = BatchResult::default();
+        for id in stopped {
+            match self.start_process(&id) {
+                Ok(_) => result.succeeded.push(id),
+                Err(e) => result.failed.push(BatchFailure {
+                    id,
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        info!(
+            "Maintenance mode lifted: {} processes restarted, {} failed",
+            result.succeeded.len(),
+            result.failed.len()
+        );
+        Ok(result)
+    }
+
+    /// Called when the service exits: stops the processes its `on_exit`
+    /// policy says to and leaves the others running.
+    pub fn shutdown_all_processes(&mut self) -> Result<()> {
+        let on_exit = self.settings.on_exit;
+        let process_ids: Vec<String> = {
+            let process_manager = self.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            processes
+                .values()
+                .filter(|config| match on_exit {
+                    ExitPolicy::StopAll => true,
+                    ExitPolicy::LeaveRunning => false,
+                    ExitPolicy::StopNonDetached => !config.detached,
+                })
+                .map(|config| config.id.clone())
+                .collect()
+        };
+        info!(
+            "Shutting down with on_exit={on_exit:?}: stopping {} processes",
+            process_ids.len()
+        );
+
+        for id in process_ids {
+            if let Err(e) = self.stop_process(&id) {
+                error!("Failed to stop process {id}: {e}");
+            }
+        }
+        if let Err(e) = self.flush_stats(true) {
+            error!("Failed to save process stats: {e}");
+        }
+
+        Ok(())
+    }
+
+    /// Reports the CPU, memory and thread usage of the service process itself.
+    pub fn service_resource_usage(&self) -> ServiceResources {
+        metrics::service_resources()
+    }
+
+    /// Exposes process and service metrics in the Prometheus text format.
+    pub fn metrics_prometheus(&self) -> String {
+        let mut statuses = self.list_processes().unwrap_or_default();
+        statuses.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.id.cmp(&b.id)));
+        metrics::render(
+            env!("CARGO_PKG_VERSION"),
+            &statuses,
+            get_current_timestamp(),
+        )
+    }
+
+    pub fn get_openlist_status(&self) -> Result<serde_json::Value> {
+        let processes = self.list_processes()?;
+        Ok(serde_json::json!({
+            "processes": processes,
+            "total_processes": processes.len(),
+            "running_processes": processes.iter().filter(|p| p.is_running).count(),
+            "maintenance": self.settings.maintenance,
+            "safe_mode": self.safe_mode,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openlistcore::{
+        process::is_process_running,
+        test_support::{self, create_request, existing_binary},
+    };
+    use serde_json::json;
+
+    fn manager_with(names: &[&str]) -> CoreManager {
+        let mut manager = CoreManager::new();
+        for name in names {
+            manager
+                .create_process(create_request(name, &existing_binary(), json!({})))
+                .unwrap();
+        }
+        manager
+    }
+
+    fn names(page: &ProcessPage) -> Vec<String> {
+        page.items.iter().map(|s| s.name.clone()).collect()
+    }
+
+    #[test]
+    fn paged_listing_returns_the_requested_window() {
+        let _serial = test_support::serial();
+        let manager = manager_with(&["d", "b", "e", "a", "c"]);
+
+        let page = manager
+            .list_processes_paged(ProcessSortBy::Name, SortOrder::Asc, 1, 2)
+            .unwrap();
+        assert_eq!(page.total, 5);
+        assert_eq!((page.offset, page.limit), (1, 2));
+        assert_eq!(names(&page), ["b", "c"]);
+
+        let page = manager
+            .list_processes_paged(ProcessSortBy::Name, SortOrder::Desc, 0, 2)
+            .unwrap();
+        assert_eq!(names(&page), ["e", "d"]);
+
+        let page = manager
+            .list_processes_paged(ProcessSortBy::Name, SortOrder::Asc, 4, 10)
+            .unwrap();
+        assert_eq!(names(&page), ["e"]);
+
+        let page = manager
+            .list_processes_paged(ProcessSortBy::Name, SortOrder::Asc, 10, 10)
+            .unwrap();
+        assert_eq!(page.total, 5);
+        assert!(page.items.is_empty());
+    }
+
+    #[test]
+    fn paging_through_ties_is_stable() {
+        let _serial = test_support::serial();
+        let manager = manager_with(&["same", "Same", "same", "SAME", "same", "same", "same"]);
+
+        let full = manager
+            .list_processes_paged(ProcessSortBy::Name, SortOrder::Asc, 0, usize::MAX)
+            .unwrap();
+        let ids: Vec<String> = full.items.iter().map(|s| s.id.clone()).collect();
+        let mut sorted = ids.clone();
+        sorted.sort();
+        assert_eq!(ids, sorted, "ties are broken by id");
+
+        for order in [SortOrder::Asc, SortOrder::Desc] {
+            let mut paged = Vec::new();
+            for offset in (0..ids.len()).step_by(3) {
+                let page = manager
+                    .list_processes_paged(ProcessSortBy::Name, order, offset, 3)
+                    .unwrap();
+                paged.extend(page.items.into_iter().map(|s| s.id));
+            }
+            assert_eq!(paged, ids, "pages concatenate to the full list");
+        }
+    }
+
+    fn id_of(manager: &CoreManager, name: &str) -> String {
+        let statuses = manager.list_processes().unwrap();
+        statuses.into_iter().find(|s| s.name == name).unwrap().id
+    }
+
+    fn update(fields: serde_json::Value) -> UpdateProcessRequest {
+        serde_json::from_value(fields).unwrap()
+    }
+
+    #[test]
+    fn batch_updates_apply_in_order_or_not_at_all() {
+        let _serial = test_support::serial();
+        let mut manager = manager_with(&["a", "b"]);
+        let (a, b) = (id_of(&manager, "a"), id_of(&manager, "b"));
+
+        // Later updates to the same process build on the earlier ones
+        let updated = manager
+            .update_processes(vec![
+                (
+                    a.clone(),
+                    update(json!({ "name": "a1", "args": ["--one"] })),
+                ),
+                (b.clone(), update(json!({ "auto_restart": true }))),
+                (a.clone(), update(json!({ "name": "a2" }))),
+            ])
+            .unwrap();
+        let summary: Vec<(&str, &str)> = updated
+            .iter()
+            .map(|config| (config.id.as_str(), config.name.as_str()))
+            .collect();
+        assert_eq!(summary, [(a.as_str(), "a2"), (b.as_str(), "b")]);
+        assert_eq!(updated[0].args, ["--one"]);
+        let mut reloaded = CoreManager::new();
+        reloaded.load_config().unwrap();
+        let a_config = reloaded.get_process(&a).unwrap().config;
+        assert_eq!((a_config.name.as_str(), a_config.args.len()), ("a2", 1));
+        assert!(reloaded.get_process(&b).unwrap().config.auto_restart);
+
+        // One invalid entry, or an unknown id, and nothing changes
+        let bin_path = update(json!({ "bin_path": "/nonexistent/openlist" }));
+        for (bad, expected) in [
+            ((b.clone(), bin_path), "Invalid update for process"),
+            (
+                ("missing".to_string(), update(json!({ "name": "x" }))),
+                "Process not found: missing",
+            ),
+        ] {
+            let error = manager
+                .update_processes(vec![
+                    (a.clone(), update(json!({ "name": "a3" }))),
+                    bad,
+                    (b.clone(), update(json!({ "name": "b3" }))),
+                ])
+                .unwrap_err();
+            assert!(error.to_string().starts_with(expected), "{error}");
+            assert_eq!(manager.get_process(&a).unwrap().config.name, "a2");
+            assert_eq!(manager.get_process(&b).unwrap().config.name, "b");
+        }
+        let mut reloaded = CoreManager::new();
+        reloaded.load_config().unwrap();
+        assert_eq!(reloaded.get_process(&a).unwrap().config.name, "a2");
+    }
+
+    #[test]
+    fn batch_updates_roll_back_when_saving_fails() {
+        let _serial = test_support::serial();
+        let mut manager = manager_with(&["a", "b"]);
+        let (a, b) = (id_of(&manager, "a"), id_of(&manager, "b"));
+
+        // A directory where the config file should be makes the save fail
+        let config_path = get_config_file_path().unwrap();
+        std::fs::remove_file(&config_path).unwrap();
+        std::fs::create_dir(&config_path).unwrap();
+        let error = manager
+            .update_processes(vec![
+                (a.clone(), update(json!({ "name": "a1" }))),
+                (b.clone(), update(json!({ "auto_start": true }))),
+            ])
+            .unwrap_err();
+        assert!(error.to_string().contains("updates rolled back"), "{error}");
+
+        let a_config = manager.get_process(&a).unwrap().config;
+        let b_config = manager.get_process(&b).unwrap().config;
+        assert_eq!(a_config.name, "a");
+        assert!(!b_config.auto_start);
+
+        std::fs::remove_dir(&config_path).unwrap();
+        let updated = manager
+            .update_processes(vec![(a.clone(), update(json!({ "name": "a1" })))])
+            .unwrap();
+        assert_eq!(updated[0].name, "a1");
+    }
+
+    /// Starts `ids` and lets them crash until crash-loop detection latches
+    /// them into the failed state, skipping the backoff between restarts.
+    #[cfg(unix)]
+    fn trip_crash_loop(manager: &mut CoreManager, ids: &[String]) {
+        use crate::openlistcore::test_support::wait_for;
+
+        for id in ids {
+            manager.start_process(id).unwrap();
+        }
+
+        for _ in 0..CRASH_LOOP_THRESHOLD {
+            assert!(wait_for(Duration::from_secs(5), || {
+                ids.iter()
+                    .all(|id| !manager.get_process(id).unwrap().is_running)
+            }));
+            manager.supervise_processes();
+            for id in ids {
+                // Skip the backoff so the next supervision pass restarts it
+                if let Some(at) = manager
+                    .runtime_handle(id)
+                    .unwrap()
+                    .restart_at
+                    .lock()
+                    .as_mut()
+                {
+                    *at = 0;
+                }
+            }
+            manager.supervise_processes();
+        }
+    }
+
+    #[cfg(unix)]
+    #[cfg(unix)]
+    #[test]
+    fn maintenance_stops_what_runs_and_resumes_only_that() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let mut create = |name: &str, extra| {
+            let request = shell_request(name, "exec sleep 30", extra);
+            manager.create_process(request).unwrap().id
+        };
+        let web = create("web", json!({ "auto_restart": true }));
+        let worker = create("worker", json!({}));
+        let idle = create("idle", json!({}));
+        manager.start_process(&web).unwrap();
+        manager.start_process(&worker).unwrap();
+        let running = |manager: &CoreManager, id: &str| manager.get_process(id).unwrap().is_running;
+
+        let mut stopped = manager.enter_maintenance().unwrap().succeeded;
+        stopped.sort();
+        let mut expected = vec![web.clone(), worker.clone()];
+        expected.sort();
+        assert_eq!(stopped, expected);
+        assert!(!running(&manager, &web) && !running(&manager, &worker));
+        assert_eq!(manager.get_settings().maintenance_stopped.len(), 2);
+
+        // Neither a due restart nor a manual start brings anything up
+        let runtime = manager.runtime_handle(&web).unwrap();
+        *runtime.restart_at.lock() = Some(0);
+        manager.supervise_processes();
+        assert!(!running(&manager, &web));
+        assert_eq!(*runtime.restart_at.lock(), Some(0));
+        for id in [&web, &idle] {
+            assert!(manager.start_process(id).is_err());
+        }
+        *runtime.restart_at.lock() = None;
+
+        // The service restarting does not end maintenance
+        drop(manager);
+        let mut manager = CoreManager::new();
+        manager.load();
+        assert!(manager.get_settings().maintenance);
+        assert!(manager.start_process(&idle).is_err());
+
+        let mut resumed = manager.exit_maintenance().unwrap().succeeded;
+        resumed.sort();
+        assert_eq!(resumed, expected);
+        assert!(running(&manager, &web) && running(&manager, &worker));
+        assert!(!running(&manager, &idle));
+        let settings = manager.get_settings();
+        assert!(!settings.maintenance && settings.maintenance_stopped.is_empty());
+
+        manager.stop_process(&web).unwrap();
+        manager.stop_process(&worker).unwrap();
+    }
+
+    #[test]
+    fn crash_loops_latch_failed_until_reset() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let marker = test_support::scratch_dir("crash-loop").join("healthy");
+        // Crashes right away until the marker exists
+        let script = format!("test -e {} && exec sleep 30; exit 3", marker.display());
+        let ids: Vec<String> = ["crashy-a", "crashy-b"]
+            .iter()
+            .map(|name| {
+                let request = shell_request(name, &script, json!({ "auto_restart": true }));
+                manager.create_process(request).unwrap().id
+            })
+            .collect();
+        trip_crash_loop(&mut manager, &ids);
+
+        for id in &ids {
+            let status = manager.get_process(id).unwrap();
+            assert_eq!(status.state, ProcessState::Failed, "{id}");
+            assert!(!status.is_running);
+            assert_eq!(status.next_restart_at, None);
+            assert!(status.last_error.unwrap().contains("Crash loop detected"));
+            assert_eq!(status.restart_count, CRASH_LOOP_THRESHOLD - 1);
+        }
+
+        std::fs::write(&marker, "").unwrap();
+        let result = manager.reset_and_restart_failed().unwrap();
+        let mut restarted = result.succeeded.clone();
+        restarted.sort();
+        let mut expected = ids.clone();
+        expected.sort();
+        assert_eq!(restarted, expected);
+        assert!(result.failed.is_empty());
+
+        std::thread::sleep(Duration::from_millis(300));
+        for id in &ids {
+            let status = manager.get_process(id).unwrap();
+            assert!(status.is_running, "{id}");
+            assert_eq!(status.state, ProcessState::Running);
+            assert_eq!(status.last_error, None);
+            manager.stop_process(id).unwrap();
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn clearing_one_failed_process_leaves_the_others_latched() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let marker = test_support::scratch_dir("clear-failed").join("healthy");
+        let script = format!("test -e {} && exec sleep 30; exit 3", marker.display());
+        let ids: Vec<String> = ["latched-a", "latched-b"]
+            .iter()
+            .map(|name| {
+                let request = shell_request(name, &script, json!({ "auto_restart": true }));
+                manager.create_process(request).unwrap().id
+            })
+            .collect();
+        trip_crash_loop(&mut manager, &ids);
+        let (cleared, latched) = (&ids[0], &ids[1]);
+
+        manager.clear_failed(cleared, false).unwrap();
+        let status = manager.get_process(cleared).unwrap();
+        assert_eq!(status.state, ProcessState::Stopped);
+        assert!(!status.is_running);
+        assert_eq!(status.last_error, None);
+        assert_eq!(status.next_restart_at, None);
+        // Not restarted by supervision either, it only lost its latch
+        manager.supervise_processes();
+        assert!(!manager.get_process(cleared).unwrap().is_running);
+
+        std::fs::write(&marker, "").unwrap();
+        manager.clear_failed(cleared, true).unwrap();
+        std::thread::sleep(Duration::from_millis(300));
+        manager.supervise_processes();
+        let status = manager.get_process(cleared).unwrap();
+        assert!(status.is_running);
+        assert_eq!(status.state, ProcessState::Running);
+
+        let status = manager.get_process(latched).unwrap();
+        assert_eq!(status.state, ProcessState::Failed);
+        assert!(!status.is_running);
+        assert!(status.last_error.unwrap().contains("Crash loop detected"));
+
+        assert!(manager.clear_failed("missing", true).is_err());
+        manager.stop_process(cleared).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn processes_running_past_max_runtime_are_stopped() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let ids: Vec<String> = [("overdue", false), ("overdue-restarted", true)]
+            .iter()
+            .map(|(name, auto_restart)| {
+                let request = shell_request(
+                    name,
+                    "exec sleep 30",
+                    json!({ "max_runtime_secs": 120, "auto_restart": auto_restart }),
+                );
+                manager.create_process(request).unwrap().id
+            })
+            .collect();
+        for id in &ids {
+            manager.start_process(id).unwrap();
+        }
+
+        manager.supervise_processes();
+        for id in &ids {
+            assert!(manager.get_process(id).unwrap().is_running, "{id}");
+            // Pretend it has been running for longer than allowed
+            let runtime = manager.runtime_handle(id).unwrap();
+            *runtime.started_at.lock() = Some(get_current_timestamp() - 121);
+        }
+        manager.supervise_processes();
+
+        for id in &ids {
+            let status = manager.get_process(id).unwrap();
+            assert!(!status.is_running, "{id}");
+            assert!(status.last_error.unwrap().starts_with("Runtime exceeded"));
+        }
+        assert_eq!(
+            manager.not_running_reason(&ids[0]).unwrap(),
+            Some(NotRunningReason::RuntimeExceeded {
+                max_runtime_secs: 120,
+                restart_at: None,
+            })
+        );
+        match manager.not_running_reason(&ids[1]).unwrap() {
+            Some(NotRunningReason::RuntimeExceeded {
+                max_runtime_secs: 120,
+                restart_at: Some(_),
+            }) => {}
+            reason => panic!("unexpected reason: {reason:?}"),
+        }
+
+        manager.cancel_pending_restart(&ids[1]).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restart_on_exit_codes_decides_which_exits_are_restarted() {
+        use crate::openlistcore::test_support::{shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let cases = [
+            ("done", 2, json!({ "except": [0, 2] }), false),
+            ("crashed", 3, json!({ "except": [0, 2] }), true),
+            ("expected", 75, json!({ "only": [75] }), true),
+            ("unexpected", 1, json!({ "only": [75] }), false),
+        ];
+        let ids: Vec<String> = cases
+            .iter()
+            .map(|(name, code, codes, _)| {
+                let request = shell_request(
+                    name,
+                    &format!("exit {code}"),
+                    json!({ "auto_restart": true, "restart_on_exit_codes": codes }),
+                );
+                let id = manager.create_process(request).unwrap().id;
+                manager.start_process(&id).unwrap();
+                id
+            })
+            .collect();
+
+        assert!(wait_for(Duration::from_secs(5), || {
+            ids.iter()
+                .all(|id| !manager.get_process(id).unwrap().is_running)
+        }));
+        manager.supervise_processes();
+        for (id, (name, code, _, restarts)) in ids.iter().zip(&cases) {
+            let status = manager.get_process(id).unwrap();
+            assert_eq!(status.last_exit_code, Some(*code), "{name}");
+            assert_eq!(status.next_restart_at.is_some(), *restarts, "{name}");
+            assert_eq!(status.state, ProcessState::Stopped, "{name}");
+        }
+    }
+
+    #[test]
+    fn processes_exiting_on_their_own_report_their_exit_code() {
+        use crate::openlistcore::test_support::wait_for;
+
+        let _serial = test_support::serial();
+        #[cfg(unix)]
+        let request = test_support::shell_request("exits", "exit 3", json!({}));
+        #[cfg(windows)]
+        let request = create_request(
+            "exits",
+            r"C:\Windows\System32\cmd.exe",
+            json!({ "args": ["/c", "exit 3"] }),
+        );
+        let mut manager = CoreManager::new();
+        let id = manager.create_process(request).unwrap().id;
+        manager.start_process(&id).unwrap();
+
+        assert!(wait_for(Duration::from_secs(5), || !manager
+            .get_process(&id)
+            .unwrap()
+            .is_running));
+        manager.supervise_processes();
+        let status = manager.get_process(&id).unwrap();
+        assert_eq!(status.last_exit_code, Some(3));
+        assert_eq!(
+            *manager.runtime_handle(&id).unwrap().stop_cause.lock(),
+            Some(StopCause::Exited(Some(3)))
+        );
+        manager.delete_process(&id).unwrap();
+    }
+
+    #[test]
+    fn set_auto_restart_only_flips_the_flag_and_persists_it() {
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let request = create_request(
+            "flip",
+            &existing_binary(),
+            json!({ "args": ["--port", "5244"], "auto_start": true, "env_vars": { "A": "1" } }),
+        );
+        let before = manager.create_process(request).unwrap();
+        assert!(!before.auto_restart);
+        let runtime = manager.runtime_handle(&before.id).unwrap();
+        *runtime.restart_at.lock() = Some(u64::MAX);
+
+        manager.set_auto_restart(&before.id, true).unwrap();
+        assert_eq!(*runtime.restart_at.lock(), Some(u64::MAX));
+        manager.set_auto_restart(&before.id, false).unwrap();
+        assert_eq!(
+            *runtime.restart_at.lock(),
+            None,
+            "pending restart is cancelled"
+        );
+        manager.set_auto_restart(&before.id, true).unwrap();
+
+        let mut reloaded = CoreManager::new();
+        reloaded.load_config().unwrap();
+        let after = reloaded.get_process(&before.id).unwrap().config;
+        assert!(after.auto_restart);
+
+        let comparable = |config: &ProcessConfig| {
+            let mut value = serde_json::to_value(config).unwrap();
+            let fields = value.as_object_mut().unwrap();
+            fields.remove("auto_restart");
+            fields.remove("updated_at");
+            value
+        };
+        assert_eq!(comparable(&after), comparable(&before));
+        assert!(manager.set_auto_restart("missing", true).is_err());
+    }
+
+    #[test]
+    fn metadata_round_trips_unchanged() {
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let metadata = json!({ "color": "#ff8800", "external_id": "  42 ", "note": "" });
+        let request = create_request(
+            "tagged",
+            &existing_binary(),
+            json!({ "metadata": metadata }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+        let untouched = manager
+            .create_process(create_request("plain", &existing_binary(), json!({})))
+            .unwrap()
+            .id;
+
+        let mut reloaded = CoreManager::new();
+        reloaded.load_config().unwrap();
+        let status = reloaded.get_process(&id).unwrap();
+        assert_eq!(
+            serde_json::to_value(&status.config.metadata).unwrap(),
+            metadata
+        );
+        assert!(
+            reloaded
+                .get_process(&untouched)
+                .unwrap()
+                .config
+                .metadata
+                .is_empty()
+        );
+
+        // An update replaces the whole map, other updates leave it alone
+        let update = serde_json::from_value(json!({ "metadata": { "group": "media" } })).unwrap();
+        reloaded.update_process(&id, update).unwrap();
+        let update = serde_json::from_value(json!({ "args": ["--debug"] })).unwrap();
+        reloaded.update_process(&id, update).unwrap();
+        let mut reloaded = CoreManager::new();
+        reloaded.load_config().unwrap();
+        let listed = reloaded.list_processes().unwrap();
+        let config = &listed.iter().find(|s| s.id == id).unwrap().config;
+        assert_eq!(
+            serde_json::to_value(&config.metadata).unwrap(),
+            json!({ "group": "media" })
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn health_summary_matches_the_detailed_view() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        assert_eq!(
+            manager.health_summary(),
+            HealthSummary {
+                all_running: true,
+                any_failed: false,
+                running: 0,
+                total: 0,
+            }
+        );
+
+        let mut ids = Vec::new();
+        for name in ["up", "down", "broken"] {
+            let request = shell_request(name, "exec sleep 30", json!({}));
+            ids.push(manager.create_process(request).unwrap().id);
+        }
+        manager.start_process(&ids[0]).unwrap();
+        *manager.runtime_handle(&ids[2]).unwrap().state.lock() = ProcessState::Failed;
+
+        let summary = manager.health_summary();
+        let statuses = manager.list_processes().unwrap();
+        assert_eq!(summary.total, statuses.len());
+        assert_eq!(
+            summary.running,
+            statuses.iter().filter(|s| s.is_running).count()
+        );
+        assert_eq!(
+            summary.any_failed,
+            statuses.iter().any(|s| s.state == ProcessState::Failed)
+        );
+        assert_eq!(
+            summary,
+            HealthSummary {
+                all_running: false,
+                any_failed: true,
+                running: 1,
+                total: 3,
+            }
+        );
+
+        for id in &ids[1..] {
+            *manager.runtime_handle(id).unwrap().state.lock() = ProcessState::Stopped;
+            manager.start_process(id).unwrap();
+        }
+        let summary = manager.health_summary();
+        assert!(summary.all_running && !summary.any_failed);
+        for id in &ids {
+            manager.stop_process(id).unwrap();
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reliability_report_aggregates_restarts_across_processes() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        assert_eq!(manager.reliability_report(), ReliabilityReport::default());
+
+        let mut ids = Vec::new();
+        for name in ["steady", "flaky", "down", "fresh"] {
+            let request = shell_request(name, "exec sleep 30", json!({}));
+            ids.push(manager.create_process(request).unwrap().id);
+        }
+        let now = get_current_timestamp();
+        // (restart_count, consecutive_failures, seconds running if started)
+        let setups = [
+            (3, 1, Some(100)),
+            (5, 2, Some(20)),
+            (5, 4, None),
+            (0, 0, None),
+        ];
+        for (id, (restarts, failures, uptime)) in ids.iter().zip(setups) {
+            if uptime.is_some() {
+                manager.start_process(id).unwrap();
+            }
+            let runtime = manager.runtime_handle(id).unwrap();
+            runtime.restart_count.store(restarts, Ordering::Relaxed);
+            runtime
+                .consecutive_failures
+                .store(failures, Ordering::Relaxed);
+            if let Some(uptime) = uptime {
+                *runtime.started_at.lock() = Some(now - uptime);
+            }
+        }
+        *manager.runtime_handle(&ids[2]).unwrap().state.lock() = ProcessState::Failed;
+
+        let report = manager.reliability_report();
+        assert_eq!(report.total_restarts, 13);
+        let mut crash_looping = vec![ids[1].clone(), ids[2].clone()];
+        crash_looping.sort();
+        assert_eq!(report.crash_looping, crash_looping);
+        // "flaky" and "down" tie, the lower id wins
+        let most = report.most_restarted.unwrap();
+        assert_eq!(most.restart_count, 5);
+        assert_eq!(most.id, crash_looping[0]);
+        // Only the two running processes count, and "down" left no uptime
+        let average = report.average_uptime_secs.unwrap();
+        assert!((60..=61).contains(&average), "{average}");
+
+        for id in &ids[..2] {
+            manager.stop_process(id).unwrap();
+        }
+        let report = manager.reliability_report();
+        assert_eq!(report.average_uptime_secs, None);
+        assert_eq!(report.total_restarts, 13);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn start_overrides_apply_to_that_run_only() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let seen = scratch_dir("start-overrides").join("run");
+        let script = format!(
+            "printf '%s|%s' \"${{GREETING:-none}}\" \"$*\" > '{}'; exec sleep 30",
+            seen.display()
+        );
+        let request = shell_request(
+            "start-overrides",
+            &script,
+            json!({ "env_vars": { "GREETING": "hi" } }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+        let run = || std::fs::read_to_string(&seen).unwrap_or_default();
+
+        let overrides = ["GREETING=howdy", "sh", "--port=5245", "serve"].map(String::from);
+        manager
+            .start_process_with_overrides(&id, &overrides)
+            .unwrap();
+        // `sh` takes the place of `$0`, the other arguments follow it
+        assert!(wait_for(Duration::from_secs(5), || run()
+            == "howdy|--port=5245 serve"));
+        let config = manager.get_process(&id).unwrap().config;
+        assert_eq!(config.args.len(), 2);
+        assert_eq!(config.env_vars.unwrap()["GREETING"], "hi");
+
+        manager.stop_process(&id).unwrap();
+        std::fs::remove_file(&seen).unwrap();
+        manager.start_process(&id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || run() == "hi|"));
+        manager.stop_process(&id).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn config_drift_is_reported_until_the_process_restarts() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let request = shell_request("drift", "exec sleep 30", json!({}));
+        let id = manager.create_process(request).unwrap().id;
+        let drifted = |manager: &CoreManager| manager.get_process(&id).unwrap().config_drifted;
+
+        assert!(!drifted(&manager), "not running, nothing to drift from");
+        manager.start_process(&id).unwrap();
+        assert!(!drifted(&manager));
+
+        // Changes that do not affect the command leave it alone
+        let update = serde_json::from_value(json!({ "name": "drift-renamed" })).unwrap();
+        manager.update_process(&id, update).unwrap();
+        assert!(!drifted(&manager));
+
+        let update = serde_json::from_value(json!({ "args": ["-c", "exec sleep 31"] })).unwrap();
+        manager.update_process(&id, update).unwrap();
+        assert!(drifted(&manager));
+        let listed = manager.list_processes().unwrap();
+        assert!(listed.iter().any(|s| s.id == id && s.config_drifted));
+
+        manager.restart_process(&id).unwrap();
+        assert!(!drifted(&manager));
+
+        let update = serde_json::from_value(json!({ "env_vars": { "GREETING": "hi" } })).unwrap();
+        manager.update_process(&id, update).unwrap();
+        assert!(drifted(&manager));
+        manager.stop_process(&id).unwrap();
+        assert!(!drifted(&manager));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn applied_env_reaches_the_child_after_the_restart() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let seen = scratch_dir("apply-env").join("greeting");
+        let script = format!(
+            "printf '%s' \"$GREETING\" > '{}'; exec sleep 30",
+            seen.display()
+        );
+        let request = shell_request(
+            "apply-env",
+            &script,
+            json!({ "env_vars": { "GREETING": "hi" } }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+        let greeting = || std::fs::read_to_string(&seen).unwrap_or_default();
+
+        manager.start_process(&id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || greeting() == "hi"));
+        let pid = manager.get_process(&id).unwrap().pid;
+
+        // Without a restart the running process is left alone
+        let env = |value: &str| HashMap::from([("GREETING".to_string(), value.to_string())]);
+        assert!(!manager.apply_env(&id, env("hello"), false).unwrap());
+        assert_eq!(manager.get_process(&id).unwrap().pid, pid);
+        assert_eq!(greeting(), "hi");
+
+        assert!(manager.apply_env(&id, env("howdy"), true).unwrap());
+        assert!(wait_for(Duration::from_secs(5), || greeting() == "howdy"));
+        let status = manager.get_process(&id).unwrap();
+        assert!(status.is_running);
+        assert_ne!(status.pid, pid);
+        manager.load_config().unwrap();
+        let saved = manager.get_process(&id).unwrap().config.env_vars;
+        assert_eq!(saved, Some(env("howdy")));
+
+        // A stopped process only gets them on its next start
+        manager.stop_process(&id).unwrap();
+        assert!(!manager.apply_env(&id, env("hey"), true).unwrap());
+        assert!(!manager.get_process(&id).unwrap().is_running);
+        manager.start_process(&id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || greeting() == "hey"));
+        manager.stop_process(&id).unwrap();
+        assert!(manager.apply_env("missing", env("hi"), false).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shutdown_stops_the_processes_the_exit_policy_says_to() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        assert_eq!(manager.get_settings().on_exit, ExitPolicy::StopAll);
+        let attached = manager
+            .create_process(shell_request("attached", "exec sleep 30", json!({})))
+            .unwrap()
+            .id;
+        let detached = manager
+            .create_process(shell_request(
+                "detached",
+                "exec sleep 30",
+                json!({ "detached": true }),
+            ))
+            .unwrap()
+            .id;
+
+        let cases = [
+            ("stop_all", false, false),
+            ("leave_running", true, true),
+            ("stop_non_detached", false, true),
+        ];
+        for (on_exit, attached_running, detached_running) in cases {
+            let settings: UpdateServiceSettingsRequest =
+                serde_json::from_value(json!({ "on_exit": on_exit })).unwrap();
+            manager.update_settings(settings).unwrap();
+            for id in [&attached, &detached] {
+                if !manager.get_process(id).unwrap().is_running {
+                    manager.start_process(id).unwrap();
+                }
+            }
+
+            manager.shutdown_all_processes().unwrap();
+            let running = |id: &str| manager.get_process(id).unwrap().is_running;
+            assert_eq!(running(&attached), attached_running, "{on_exit}");
+            assert_eq!(running(&detached), detached_running, "{on_exit}");
+        }
+
+        manager.load_settings().unwrap();
+        assert_eq!(manager.get_settings().on_exit, ExitPolicy::StopNonDetached);
+        manager.stop_process(&detached).unwrap();
+    }
+
+    #[test]
+    fn a_missing_runtime_reads_back_as_stopped() {
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let id = manager
+            .create_process(create_request("drift", &existing_binary(), json!({})))
+            .unwrap()
+            .id;
+        let drop_runtime = |manager: &CoreManager| {
+            let process_manager = manager.process_manager.inner.lock();
+            process_manager.runtime_states.lock().remove(&id);
+        };
+
+        drop_runtime(&manager);
+        let status = manager.get_process(&id).unwrap();
+        assert_eq!(status.state, ProcessState::Stopped);
+        assert!(!status.is_running);
+
+        drop_runtime(&manager);
+        let listed = manager.list_processes().unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].state, ProcessState::Stopped);
+        // The runtime is back for everything else too
+        assert!(manager.runtime_handle(&id).is_some());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn cancelling_during_backoff_prevents_the_restart() {
+        use crate::openlistcore::test_support::{shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let request = shell_request("flaky", "exit 2", json!({ "auto_restart": true }));
+        let id = manager.create_process(request).unwrap().id;
+        manager.start_process(&id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || {
+            !manager.get_process(&id).unwrap().is_running
+        }));
+
+        manager.supervise_processes();
+        let status = manager.get_process(&id).unwrap();
+        let restart_at = status.next_restart_at.expect("restart is scheduled");
+        assert!(restart_at > get_current_timestamp().saturating_sub(1));
+
+        assert!(manager.cancel_pending_restart(&id).unwrap());
+        assert!(!manager.cancel_pending_restart(&id).unwrap());
+        assert_eq!(manager.get_process(&id).unwrap().next_restart_at, None);
+
+        // Well past the first backoff step
+        std::thread::sleep(Duration::from_secs(RESTART_BACKOFF_BASE_SECS + 1));
+        manager.supervise_processes();
+        let status = manager.get_process(&id).unwrap();
+        assert!(!status.is_running);
+        assert_eq!(status.state, ProcessState::Stopped);
+        assert_eq!(status.restart_count, 0);
+        assert!(manager.cancel_pending_restart("missing").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn processes_run_from_their_configured_working_dir() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let working_dir = scratch_dir("working-dir").canonicalize().unwrap();
+        // Relative to the working directory
+        let request = shell_request(
+            "working-dir",
+            "pwd -P > seen; exec sleep 30",
+            json!({ "working_dir": working_dir.to_string_lossy() }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+        let seen = working_dir.join("seen");
+
+        manager.start_process(&id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || {
+            std::fs::read_to_string(&seen)
+                .is_ok_and(|dir| dir.trim_end() == working_dir.to_str().unwrap())
+        }));
+        manager.stop_process(&id).unwrap();
+        let effective = manager.get_effective_config(&id).unwrap();
+        assert_eq!(effective.working_dir.as_deref(), working_dir.to_str());
+        let command = manager.reproduce_command(&id).unwrap();
+        assert!(command.starts_with(&format!("cd {} && ", working_dir.display())));
+
+        let missing = working_dir.join("missing");
+        let update =
+            serde_json::from_value(json!({ "working_dir": missing.to_string_lossy() })).unwrap();
+        manager.update_process(&id, update).unwrap();
+        let err = manager.start_process(&id).unwrap_err();
+        assert!(err.to_string().contains("Working directory not found"));
+        assert!(!manager.get_process(&id).unwrap().is_running);
+
+        // Unset again, it runs from the directory of its binary
+        let update = serde_json::from_value(json!({ "working_dir": "" })).unwrap();
+        manager.update_process(&id, update).unwrap();
+        assert_eq!(manager.get_process(&id).unwrap().config.working_dir, None);
+        let effective = manager.get_effective_config(&id).unwrap();
+        assert_eq!(effective.working_dir.as_deref(), Some("/bin"));
+    }
+
+    #[test]
+    fn defaults_show_in_the_effective_config_only() {
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let log_dir = test_support::scratch_dir("effective");
+        let request = create_request(
+            "web",
+            &existing_binary(),
+            json!({
+                "log_file": log_dir.join("{name}.log").to_string_lossy(),
+                "readiness_check": { "type": "tcp", "address": "127.0.0.1:5244" },
+            }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+
+        let raw = manager.get_process(&id).unwrap().config;
+        let effective = manager.get_effective_config(&id).unwrap();
+
+        assert!(raw.log_file.ends_with("{name}.log"));
+        assert_eq!(
+            effective.log_file,
+            log_dir.join("web.log").to_string_lossy()
+        );
+        assert_eq!(raw.readiness_timeout_secs, None);
+        assert_eq!(effective.readiness_timeout_secs, Some(60));
+        assert_eq!(raw.working_dir, None);
+        let bin_dir = Path::new(&raw.bin_path).parent().unwrap();
+        assert_eq!(effective.working_dir.as_deref(), bin_dir.to_str());
+        assert_eq!(raw.kill_sequence, None);
+        #[cfg(unix)]
+        {
+            let signals: Vec<String> = effective
+                .kill_sequence
+                .unwrap()
+                .into_iter()
+                .map(|step| step.signal)
+                .collect();
+            assert_eq!(signals, ["INT", "KILL"]);
+        }
+
+        // Explicit values are passed through unchanged
+        let update = serde_json::from_value(json!({ "readiness_timeout_secs": 5 })).unwrap();
+        manager.update_process(&id, update).unwrap();
+        let effective = manager.get_effective_config(&id).unwrap();
+        assert_eq!(effective.readiness_timeout_secs, Some(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restart_alert_fires_once_when_the_threshold_is_crossed() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let request = shell_request(
+            "alerting",
+            "exec sleep 30",
+            json!({ "restart_alert_threshold": 2 }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+        manager.start_process(&id).unwrap();
+
+        manager.restart_process(&id).unwrap();
+        assert!(manager.list_events(0).is_empty());
+        for _ in 0..3 {
+            manager.restart_process(&id).unwrap();
+        }
+        let events = manager.list_events(0);
+        assert_eq!(events.len(), 1, "{events:?}");
+        let ServiceEvent::RestartThresholdExceeded {
+            id: event_id,
+            restart_count,
+            threshold,
+            ..
+        } = &events[0].event;
+        assert_eq!((event_id, *restart_count, *threshold), (&id, 2, 2));
+        assert!(manager.list_events(events[0].seq + 1).is_empty());
+        assert_eq!(manager.get_process(&id).unwrap().restart_count, 4);
+
+        manager.delete_process(&id).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restart_all_stops_dependents_first_and_starts_dependencies_first() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        let trace = scratch_dir("restart-all").join("trace");
+        let script = |name: &str| {
+            format!(
+                "trap 'echo stop-{name} >> {trace}; exit 0' INT; echo start-{name} >> {trace}; \
+                 while :; do sleep 0.05; done",
+                trace = trace.display()
+            )
+        };
+        // Created first, so display order alone would restart it first
+        let app = manager
+            .lock()
+            .create_process(shell_request("app", &script("app"), json!({})))
+            .unwrap()
+            .id;
+        let db = manager
+            .lock()
+            .create_process(shell_request(
+                "db",
+                &script("db"),
+                json!({ "warmup_secs": 1 }),
+            ))
+            .unwrap()
+            .id;
+        let idle = manager
+            .lock()
+            .create_process(shell_request("idle", &script("idle"), json!({})))
+            .unwrap()
+            .id;
+        let update: UpdateProcessRequest =
+            serde_json::from_value(json!({ "depends_on": [db] })).unwrap();
+        manager.lock().update_process(&app, update).unwrap();
+
+        manager.lock().start_process(&app).unwrap();
+        manager.lock().start_process(&db).unwrap();
+        let read_trace = || std::fs::read_to_string(&trace).unwrap_or_default();
+        assert!(wait_for(Duration::from_secs(5), || read_trace()
+            .lines()
+            .count()
+            == 2));
+        std::fs::remove_file(&trace).unwrap();
+
+        let result = restart_all_running(&manager).unwrap();
+        assert_eq!(result.succeeded, [db.clone(), app.clone()]);
+        assert!(result.failed.is_empty(), "{:?}", result.failed);
+        assert!(wait_for(Duration::from_secs(5), || read_trace()
+            .lines()
+            .count()
+            == 4));
+        let events: Vec<String> = read_trace().lines().map(str::to_string).collect();
+        assert_eq!(events, ["stop-app", "stop-db", "start-db", "start-app"]);
+        // Processes that were not running stay stopped
+        assert!(!manager.lock().get_process(&idle).unwrap().is_running);
+        assert_eq!(manager.lock().get_process(&app).unwrap().restart_count, 1);
+
+        for id in [app, db, idle] {
+            manager.lock().delete_process(&id).unwrap();
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restart_all_waits_for_starts_gated_by_a_condition() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        let flag = scratch_dir("restart-all-condition").join("ready");
+        std::fs::write(&flag, "").unwrap();
+        let condition = format!("sleep 0.3; test -e {}", flag.display());
+        let db = manager
+            .lock()
+            .create_process(shell_request(
+                "db",
+                "exec sleep 30",
+                json!({ "start_condition_command": condition }),
+            ))
+            .unwrap()
+            .id;
+        let app = manager
+            .lock()
+            .create_process(shell_request(
+                "app",
+                "exec sleep 30",
+                json!({ "depends_on": [db] }),
+            ))
+            .unwrap()
+            .id;
+        manager.lock().start_process(&db).unwrap();
+        manager.lock().start_process(&app).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || manager
+            .lock()
+            .get_process(&db)
+            .unwrap()
+            .is_running));
+
+        // The condition only runs once the manager is released
+        let result = restart_all_running(&manager).unwrap();
+        assert_eq!(result.succeeded, [db.clone(), app.clone()]);
+        assert!(manager.lock().get_process(&db).unwrap().is_running);
+        assert_eq!(manager.lock().get_process(&db).unwrap().restart_count, 1);
+
+        // A refused start is not ready, so what depends on it stays stopped
+        std::fs::remove_file(&flag).unwrap();
+        let result = restart_all_running(&manager).unwrap();
+        assert!(result.succeeded.is_empty(), "{:?}", result.succeeded);
+        let failed: Vec<&str> = result.failed.iter().map(|f| f.id.as_str()).collect();
+        assert_eq!(failed, [db.as_str(), app.as_str()]);
+        assert!(result.failed[0].error.contains("did not start"));
+        let status = manager.lock().get_process(&db).unwrap();
+        assert!(!status.is_running);
+        assert_eq!(status.restart_count, 1);
+        assert!(!manager.lock().get_process(&app).unwrap().is_running);
+
+        for id in [app, db] {
+            manager.lock().delete_process(&id).unwrap();
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_os_command_line_shows_what_actually_runs() {
+        use crate::openlistcore::test_support::{shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let id = manager
+            .create_process(shell_request("cmdline", "exec sleep 30", json!({})))
+            .unwrap()
+            .id;
+        assert_eq!(
+            manager
+                .get_process_with_command_line(&id)
+                .unwrap()
+                .os_command_line,
+            None
+        );
+
+        manager.start_process(&id).unwrap();
+        // The shell replaces itself with what it was told to run
+        assert!(wait_for(Duration::from_secs(5), || {
+            manager
+                .get_process_with_command_line(&id)
+                .unwrap()
+                .os_command_line
+                == Some(vec!["sleep".to_string(), "30".to_string()])
+        }));
+        let status = manager.get_process(&id).unwrap();
+        assert_eq!(status.os_command_line, None);
+        assert_eq!(status.config.args, ["-c", "exec sleep 30"]);
+
+        manager.delete_process(&id).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rate_limited_restarts_leave_the_process_running() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let request = shell_request(
+            "throttled",
+            "exec sleep 30",
+            json!({ "restart_rate_limit": 2, "restart_rate_window_secs": 3600 }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+        manager.start_process(&id).unwrap();
+
+        manager.restart_process(&id).unwrap();
+        manager.restart_process(&id).unwrap();
+        let err = manager.restart_process(&id).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CoreError>(),
+            Some(CoreError::RestartRateLimited { .. })
+        ));
+        let status = manager.get_process(&id).unwrap();
+        assert!(status.is_running);
+        assert_eq!(status.restart_count, 2);
+
+        manager.delete_process(&id).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restart_counts_survive_a_service_restart() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let id = manager
+            .create_process(shell_request("counted", "exec sleep 30", json!({})))
+            .unwrap()
+            .id;
+        manager.start_process(&id).unwrap();
+        manager.restart_process(&id).unwrap();
+        manager.restart_process(&id).unwrap();
+
+        // Restarts in quick succession are written together, later
+        let stats_path = get_stats_file_path().unwrap();
+        manager.flush_stats(false).unwrap();
+        assert!(!stats_path.exists());
+        manager.stats_dirty_since = Some(Instant::now() - STATS_FLUSH_DEBOUNCE);
+        manager.flush_stats(false).unwrap();
+        assert!(stats_path.exists());
+        assert!(manager.stats_dirty_since.is_none());
+        manager.stop_process(&id).unwrap();
+
+        let mut restarted = CoreManager::new();
+        restarted.load_config().unwrap();
+        restarted.load_stats().unwrap();
+        assert_eq!(restarted.get_process(&id).unwrap().restart_count, 2);
+
+        // A restart after loading carries on from the persisted count
+        restarted.start_process(&id).unwrap();
+        restarted.restart_process(&id).unwrap();
+        restarted.shutdown_all_processes().unwrap();
+        let mut restarted = CoreManager::new();
+        restarted.load_config().unwrap();
+        restarted.load_stats().unwrap();
+        assert_eq!(restarted.get_process(&id).unwrap().restart_count, 3);
+        restarted.delete_process(&id).unwrap();
+    }
+
+    #[test]
+    fn new_managers_start_empty_until_loaded() {
+        let _serial = test_support::serial();
+        let mut source = CoreManager::new();
+        let id = source
+            .create_process(create_request(
+                "persisted",
+                &existing_binary(),
+                json!({ "auto_start": true }),
+            ))
+            .unwrap()
+            .id;
+        source
+            .update_settings(serde_json::from_value(json!({ "jitter_percent": 10 })).unwrap())
+            .unwrap();
+
+        let config_files = || {
+            let mut files: Vec<_> = std::fs::read_dir(test_support::config_dir())
+                .unwrap()
+                .map(|entry| {
+                    let entry = entry.unwrap();
+                    (
+                        entry.file_name(),
+                        entry.metadata().unwrap().modified().unwrap(),
+                    )
+                })
+                .collect();
+            files.sort();
+            files
+        };
+        let before = config_files();
+
+        let shared = CoreManager::new_without_autostart();
+        let mut manager = shared.lock();
+        assert!(manager.list_processes().unwrap().is_empty());
+        assert_eq!(manager.get_settings().jitter_percent, 0);
+        assert_eq!(
+            config_files(),
+            before,
+            "creating a manager touched its files"
+        );
+
+        manager.load();
+        assert_eq!(manager.get_settings().jitter_percent, 10);
+        // Not even auto_start processes are started by loading
+        let status = manager.get_process(&id).unwrap();
+        assert!(status.config.auto_start);
+        assert!(!status.is_running);
+        assert_eq!(status.started_at, None);
+
+        manager.delete_process(&id).unwrap();
+        manager
+            .update_settings(serde_json::from_value(json!({ "jitter_percent": 0 })).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn config_reads_are_retried_until_the_directory_appears() {
+        let _serial = test_support::serial();
+        let id = CoreManager::new()
+            .create_process(create_request("late mount", &existing_binary(), json!({})))
+            .unwrap()
+            .id;
+
+        // The config directory is away, like a mount that is not there yet
+        let config_dir = test_support::config_dir();
+        let unmounted = test_support::scratch_dir("late-mount").join("config");
+        std::fs::rename(&config_dir, &unmounted).unwrap();
+        let mut manager = CoreManager::new();
+        assert!(manager.load_config().is_err());
+        assert!(
+            !config_dir.exists(),
+            "loading must not create the directory"
+        );
+        assert!(!wait_for_config_file(1, Duration::from_millis(10)));
+
+        let mount = {
+            let config_dir = config_dir.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(150));
+                std::fs::rename(unmounted, config_dir).unwrap();
+            })
+        };
+        assert!(wait_for_config_file(5, Duration::from_millis(50)));
+        mount.join().unwrap();
+        manager.load_config().unwrap();
+        assert_eq!(manager.get_process(&id).unwrap().config.name, "late mount");
+
+        // An empty directory is a config without processes, and stays empty
+        std::fs::remove_dir_all(&config_dir).unwrap();
+        std::fs::create_dir_all(&config_dir).unwrap();
+        let mut manager = CoreManager::new();
+        manager.load_config().unwrap();
+        assert!(manager.list_processes().unwrap().is_empty());
+        assert_eq!(std::fs::read_dir(&config_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn safe_mode_is_requested_by_any_truthy_value() {
+        for value in ["1", "true", "yes", " on "] {
+            assert!(safe_mode_requested(Some(value)), "{value}");
+        }
+        for value in [None, Some(""), Some(" "), Some("0"), Some("FALSE")] {
+            assert!(!safe_mode_requested(value), "{value:?}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn nothing_starts_or_restarts_on_its_own_in_safe_mode() {
+        use crate::openlistcore::test_support::{shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        manager.set_safe_mode(true);
+        let auto = manager
+            .create_process(shell_request(
+                "auto",
+                "exec sleep 30",
+                json!({ "auto_start": true, "auto_restart": true }),
+            ))
+            .unwrap()
+            .id;
+        let crashing = manager
+            .create_process(shell_request(
+                "crashing",
+                "exit 3",
+                json!({ "auto_restart": true }),
+            ))
+            .unwrap()
+            .id;
+
+        manager.auto_start_processes().unwrap();
+        assert!(!manager.get_process(&auto).unwrap().is_running);
+
+        // Manual starts still work, so the config can be fixed and tried out
+        manager.start_process(&crashing).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || {
+            !manager.get_process(&crashing).unwrap().is_running
+        }));
+        manager.supervise_processes();
+        let runtime = manager.runtime_handle(&crashing).unwrap();
+        let scheduled = runtime.restart_at.lock().replace(0);
+        assert!(scheduled.is_some());
+        // The restart is dropped once due instead of carried out
+        manager.supervise_processes();
+        assert!(runtime.restart_at.lock().is_none());
+        let status = manager.get_process(&crashing).unwrap();
+        assert!(!status.is_running);
+        assert_eq!(status.restart_count, 0);
+
+        manager.set_safe_mode(false);
+        assert_eq!(
+            manager.get_openlist_status().unwrap()["safe_mode"],
+            json!(false)
+        );
+        manager.delete_process(&auto).unwrap();
+        manager.delete_process(&crashing).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn loading_the_config_again_never_starts_a_second_instance() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let starts = scratch_dir("reload-running").join("starts");
+        let request = shell_request(
+            "reload-running",
+            &format!("echo started >> {}; exec sleep 30", starts.display()),
+            json!({ "auto_start": true }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+        let start_count =
+            || std::fs::read_to_string(&starts).map_or(0, |starts| starts.lines().count());
+
+        manager.auto_start_process(&id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || start_count() == 1));
+        let pid = manager.get_process(&id).unwrap().pid;
+
+        manager.load_config().unwrap();
+        manager.auto_start_process(&id).unwrap();
+        let status = manager.get_process(&id).unwrap();
+        assert!(status.is_running);
+        assert_eq!(status.pid, pid);
+        std::thread::sleep(Duration::from_millis(200));
+        assert_eq!(start_count(), 1);
+
+        manager.stop_process(&id).unwrap();
+        manager.delete_process(&id).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reconcile_state_restores_one_runtime_per_process() {
+        use crate::openlistcore::test_support::{shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let kept = manager
+            .create_process(shell_request("kept", "sleep 30", json!({})))
+            .unwrap()
+            .id;
+        let bare = manager
+            .create_process(shell_request("bare", "sleep 30", json!({})))
+            .unwrap()
+            .id;
+        manager.start_process(&kept).unwrap();
+        let pid = manager.get_process(&kept).unwrap().pid.unwrap() as i32;
+
+        // Desync both ways: one process loses its runtime, and the running
+        // one's runtime is left behind without its process
+        {
+            let process_manager = manager.process_manager.inner.lock();
+            let mut runtime_states = process_manager.runtime_states.lock();
+            runtime_states.remove(&bare);
+            let orphan = runtime_states.remove(&kept).unwrap();
+            runtime_states.insert("orphan".to_string(), orphan);
+            runtime_states.insert(kept.clone(), ProcessRuntime::default());
+        }
+
+        let report = manager.reconcile_state();
+        assert_eq!(
+            report,
+            ReconcileReport {
+                added_runtime: vec![bare.clone()],
+                removed_runtime: vec!["orphan".to_string()],
+                stopped_pids: vec![pid],
+            }
+        );
+        assert!(wait_for(Duration::from_secs(5), || {
+            !is_process_running(pid)
+        }));
+        {
+            let process_manager = manager.process_manager.inner.lock();
+            let processes = process_manager.processes.lock();
+            let runtime_states = process_manager.runtime_states.lock();
+            let mut process_ids: Vec<_> = processes.keys().collect();
+            let mut runtime_ids: Vec<_> = runtime_states.keys().collect();
+            process_ids.sort();
+            runtime_ids.sort();
+            assert_eq!(process_ids, runtime_ids);
+        }
+
+        assert_eq!(manager.reconcile_state(), ReconcileReport::default());
+        manager.delete_process(&kept).unwrap();
+        manager.delete_process(&bare).unwrap();
+    }
+
+    #[test]
+    fn config_diff_reports_outside_edits_per_field() {
+        let _serial = test_support::serial();
+        let manager = manager_with(&["kept", "edited", "dropped"]);
+        assert!(manager.diff_config().unwrap().modified.is_empty());
+
+        let config_path = get_config_file_path().unwrap();
+        let mut on_disk: Vec<Value> =
+            serde_json::from_slice(&std::fs::read(&config_path).unwrap()).unwrap();
+        let edited = on_disk.iter_mut().find(|c| c["name"] == "edited").unwrap();
+        edited["args"] = json!(["--debug"]);
+        edited["auto_restart"] = json!(true);
+        let edited_id = edited["id"].as_str().unwrap().to_string();
+        on_disk.retain(|c| c["name"] != "dropped");
+        let mut added = on_disk[0].clone();
+        added["id"] = json!("added-id");
+        added["name"] = json!("added");
+        on_disk.push(added);
+        std::fs::write(&config_path, serde_json::to_vec(&on_disk).unwrap()).unwrap();
+
+        let diff = manager.diff_config().unwrap();
+        let names = |configs: &[ProcessConfig]| -> Vec<String> {
+            configs.iter().map(|c| c.name.clone()).collect()
+        };
+        assert_eq!(names(&diff.added), ["added"]);
+        assert_eq!(names(&diff.removed), ["dropped"]);
+        assert_eq!(diff.modified.len(), 1);
+        assert_eq!(diff.modified[0].id, edited_id);
+        assert_eq!(
+            diff.modified[0].fields,
+            [
+                FieldChange {
+                    field: "args".to_string(),
+                    current: json!([]),
+                    on_disk: json!(["--debug"]),
+                },
+                FieldChange {
+                    field: "auto_restart".to_string(),
+                    current: json!(false),
+                    on_disk: json!(true),
+                },
+            ]
+        );
+        // Only a preview: nothing in memory changed
+        assert_eq!(manager.list_processes().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn config_metadata_tells_outside_edits_from_service_writes() {
+        let _serial = test_support::serial();
+        let mut manager = manager_with(&["watched"]);
+        let config_path = get_config_file_path().unwrap();
+
+        let metadata = manager.config_metadata().unwrap();
+        assert_eq!(metadata.path, config_path.to_string_lossy());
+        assert!(metadata.last_modified.is_some());
+        assert!(metadata.last_written_by_service);
+
+        // Edited from outside, a while after the service wrote it
+        let edited_at = SystemTime::now() + Duration::from_secs(120);
+        let bytes = std::fs::read(&config_path).unwrap();
+        std::fs::write(&config_path, &bytes).unwrap();
+        File::options()
+            .write(true)
+            .open(&config_path)
+            .unwrap()
+            .set_modified(edited_at)
+            .unwrap();
+        let metadata = manager.config_metadata().unwrap();
+        assert!(!metadata.last_written_by_service);
+        assert_eq!(
+            metadata.last_modified,
+            Some(edited_at.duration_since(UNIX_EPOCH).unwrap().as_secs())
+        );
+
+        // The next save is the service's own again
+        let id = manager.list_processes().unwrap()[0].id.clone();
+        manager.set_auto_restart(&id, true).unwrap();
+        assert!(manager.config_metadata().unwrap().last_written_by_service);
+
+        // and a manager that never saved cannot vouch for the file
+        assert!(
+            !CoreManager::new()
+                .config_metadata()
+                .unwrap()
+                .last_written_by_service
+        );
+    }
+
+    #[test]
+    fn config_hash_detects_outside_edits() {
+        let _serial = test_support::serial();
+        let manager = manager_with(&["hashed"]);
+        let config_path = get_config_file_path().unwrap();
+        let hash_path = config_hash_path(&config_path);
+        let saved = std::fs::read(&config_path).unwrap();
+
+        assert_eq!(
+            verify_config_hash(&config_path, &saved),
+            HashCheck::Verified
+        );
+        let upper = std::fs::read_to_string(&hash_path).unwrap().to_uppercase();
+        std::fs::write(&hash_path, format!("{upper}\n")).unwrap();
+        assert_eq!(
+            verify_config_hash(&config_path, &saved),
+            HashCheck::Verified
+        );
+
+        let tampered = String::from_utf8(saved.clone())
+            .unwrap()
+            .replace("hashed", "edited");
+        std::fs::write(&config_path, &tampered).unwrap();
+        assert_eq!(
+            verify_config_hash(&config_path, tampered.as_bytes()),
+            HashCheck::Mismatch
+        );
+        // A mismatch is only reported, the edited file still loads
+        let mut reloaded = CoreManager::new();
+        reloaded.load_config().unwrap();
+        assert_eq!(reloaded.list_processes().unwrap()[0].name, "edited");
+
+        std::fs::remove_file(&hash_path).unwrap();
+        assert_eq!(
+            verify_config_hash(&config_path, tampered.as_bytes()),
+            HashCheck::Unchecked
+        );
+        // The next save writes a fresh hash
+        manager.save_config().unwrap();
+        let saved = std::fs::read(&config_path).unwrap();
+        assert_eq!(
+            verify_config_hash(&config_path, &saved),
+            HashCheck::Verified
+        );
+    }
+
+    #[test]
+    fn processes_are_created_from_templates_with_overrides() {
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let partial = |value| serde_json::from_value::<UpdateProcessRequest>(value).unwrap();
+
+        let template = manager
+            .register_template(
+                "backend",
+                partial(json!({
+                    "bin_path": existing_binary(),
+                    "args": ["server", "--port", "5244"],
+                    "auto_restart": true,
+                })),
+            )
+            .unwrap();
+        assert_eq!(template.len(), 3, "unset fields are not stored");
+
+        let mut reloaded = CoreManager::new();
+        reloaded.load_templates().unwrap();
+        assert_eq!(reloaded.list_templates(), manager.list_templates());
+
+        let config = reloaded
+            .create_from_template(
+                "backend",
+                partial(json!({ "name": "second", "args": ["server", "--port", "5245"] })),
+            )
+            .unwrap();
+        assert_eq!(config.name, "second");
+        assert_eq!(config.args, ["server", "--port", "5245"]);
+        assert_eq!(config.bin_path, existing_binary());
+        assert!(config.auto_restart);
+        assert!(!config.auto_start);
+        assert_eq!(reloaded.get_process(&config.id).unwrap().name, "second");
+
+        // The template itself is left as it was
+        assert_eq!(
+            reloaded.list_templates()["backend"]["args"],
+            json!(["server", "--port", "5244"])
+        );
+        let error = reloaded
+            .create_from_template("backend", partial(json!({})))
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("missing field `name`"),
+            "{error}"
+        );
+        assert!(
+            reloaded
+                .create_from_template("missing", partial(json!({ "name": "x" })))
+                .is_err()
+        );
+
+        reloaded.delete_template("backend").unwrap();
+        assert!(reloaded.delete_template("backend").is_err());
+        manager.load_templates().unwrap();
+        assert!(manager.list_templates().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn snapshots_restore_processes_stats_and_templates() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let partial = |value| serde_json::from_value::<UpdateProcessRequest>(value).unwrap();
+        let mut source = manager_with(&["web", "worker"]);
+        let db = source
+            .create_process(create_request(
+                "db",
+                &existing_binary(),
+                json!({ "args": ["--port", "5432"], "auto_start": true, "metadata": { "color": "red" } }),
+            ))
+            .unwrap();
+        for (id, restarts) in [(&db.id, 7), (&source.list_processes().unwrap()[0].id, 2)] {
+            source
+                .runtime_handle(id)
+                .unwrap()
+                .restart_count
+                .store(restarts, Ordering::Relaxed);
+        }
+        for name in ["backend", "frontend"] {
+            source
+                .register_template(
+                    name,
+                    partial(json!({ "args": [name], "auto_restart": true })),
+                )
+                .unwrap();
+        }
+        source
+            .update_settings(serde_json::from_value(json!({ "jitter_percent": 20 })).unwrap())
+            .unwrap();
+
+        let snapshot = source.snapshot();
+        let snapshot: ServiceSnapshot =
+            serde_json::from_value(serde_json::to_value(&snapshot).unwrap()).unwrap();
+        assert_eq!(snapshot.processes.len(), 3);
+
+        // What was there before is stopped and replaced
+        let mut target = CoreManager::new();
+        let stale = target
+            .create_process(shell_request("stale", "exec sleep 30", json!({})))
+            .unwrap();
+        target.start_process(&stale.id).unwrap();
+        let stale_pid = target.get_process(&stale.id).unwrap().pid.unwrap();
+        let stale_snapshot = target.snapshot();
+        target.templates.clear();
+        target.settings = ServiceSettings::default();
+
+        let result = target.restore(snapshot.clone(), false).unwrap();
+        assert!(result.succeeded.is_empty() && result.failed.is_empty());
+        assert!(!is_process_running(stale_pid as i32));
+        assert!(target.get_process(&stale.id).is_err());
+
+        let configs = |manager: &CoreManager| -> Vec<Value> {
+            let mut statuses = manager.list_processes().unwrap();
+            statuses.sort_by(|a, b| a.id.cmp(&b.id));
+            statuses
+                .iter()
+                .map(|s| json!([serde_json::to_value(&s.config).unwrap(), s.restart_count]))
+                .collect()
+        };
+        assert_eq!(configs(&target), configs(&source));
+        assert_eq!(target.get_process(&db.id).unwrap().restart_count, 7);
+        assert_eq!(target.list_templates(), source.list_templates());
+        assert_eq!(target.get_settings().jitter_percent, 20);
+
+        // and saved, so it survives a service restart
+        let mut reloaded = CoreManager::new();
+        reloaded.load_settings().unwrap();
+        reloaded.load_config().unwrap();
+        reloaded.load_templates().unwrap();
+        reloaded.load_stats().unwrap();
+        assert_eq!(configs(&reloaded), configs(&source));
+        assert_eq!(reloaded.list_templates(), source.list_templates());
+        assert_eq!(reloaded.get_settings().jitter_percent, 20);
+
+        // Processes are started again on request
+        let mut started = CoreManager::new();
+        let result = started.restore(stale_snapshot, true).unwrap();
+        assert_eq!(result.succeeded, [stale.id.as_str()]);
+        assert!(started.get_process(&stale.id).unwrap().is_running);
+        started.stop_process(&stale.id).unwrap();
+
+        let mut duplicated = snapshot;
+        duplicated.processes.push(duplicated.processes[0].clone());
+        let error = CoreManager::new().restore(duplicated, false).unwrap_err();
+        assert!(
+            error.to_string().contains("Duplicate process id"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn reordering_persists_and_changes_list_order() {
+        let _serial = test_support::serial();
+        let mut manager = manager_with(&["a", "b", "c", "d"]);
+        let id_of = |manager: &CoreManager, name: &str| {
+            manager
+                .list_processes()
+                .unwrap()
+                .into_iter()
+                .find(|s| s.name == name)
+                .unwrap()
+                .id
+        };
+        let listed = |manager: &CoreManager| -> Vec<String> {
+            manager
+                .list_processes()
+                .unwrap()
+                .into_iter()
+                .map(|s| s.name)
+                .collect()
+        };
+        assert_eq!(listed(&manager), ["a", "b", "c", "d"]);
+
+        // Unlisted processes keep their relative order after the listed ones
+        let ordered = vec![id_of(&manager, "c"), id_of(&manager, "a")];
+        manager.reorder_processes(ordered).unwrap();
+        assert_eq!(listed(&manager), ["c", "a", "b", "d"]);
+
+        let mut reloaded = CoreManager::new();
+        reloaded.load_config().unwrap();
+        assert_eq!(listed(&reloaded), ["c", "a", "b", "d"]);
+        let page = reloaded
+            .list_processes_paged(ProcessSortBy::Order, SortOrder::Desc, 0, 10)
+            .unwrap();
+        assert_eq!(names(&page), ["d", "b", "a", "c"]);
+
+        let c = id_of(&manager, "c");
+        assert!(manager.reorder_processes(vec![c.clone(), c]).is_err());
+        assert!(
+            manager
+                .reorder_processes(vec!["missing".to_string()])
+                .is_err()
+        );
+        assert_eq!(listed(&manager), ["c", "a", "b", "d"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stops_report_whether_the_process_exited_gracefully() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let polite = manager
+            .create_process(shell_request("polite", "exec sleep 30", json!({})))
+            .unwrap()
+            .id;
+        let marker = scratch_dir("stubborn").join("ready");
+        let script = format!(
+            "trap '' INT; touch {}; while :; do sleep 0.05; done",
+            marker.display()
+        );
+        let stubborn = manager
+            .create_process(shell_request("stubborn", &script, json!({})))
+            .unwrap()
+            .id;
+
+        manager.start_process(&polite).unwrap();
+        let outcome = manager.stop_process(&polite).unwrap();
+        assert!(outcome.graceful);
+        assert_eq!(outcome.signal_used.as_deref(), Some("INT"));
+        assert!(outcome.duration_ms < 1000, "{outcome:?}");
+
+        manager.start_process(&stubborn).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || marker.exists()));
+        let outcome = manager.stop_process(&stubborn).unwrap();
+        assert!(!outcome.graceful);
+        assert_eq!(outcome.signal_used.as_deref(), Some("KILL"));
+        assert!(outcome.duration_ms >= 1000, "{outcome:?}");
+        assert!(!manager.get_process(&stubborn).unwrap().is_running);
+
+        // Nothing left to signal
+        let outcome = manager.stop_process(&stubborn).unwrap();
+        assert!(outcome.graceful);
+        assert_eq!(outcome.signal_used, None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn auto_start_marks_missing_binaries_failed_until_they_appear() {
+        use crate::openlistcore::test_support::scratch_dir;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        manager.settings.retry_missing_binary = true;
+        let dir = scratch_dir("missing-binary");
+        let bin_path = dir.join("sh");
+        std::os::unix::fs::symlink("/bin/sh", &bin_path).unwrap();
+        let request = create_request(
+            "missing",
+            &bin_path.to_string_lossy(),
+            json!({
+                "args": ["-c", "exec sleep 30"],
+                "auto_start": true,
+                "log_file": dir.join("process.log"),
+            }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+        std::fs::remove_file(&bin_path).unwrap();
+
+        let error = manager.auto_start_process(&id).unwrap_err().to_string();
+        let expected = format!("Binary not found at: {}", bin_path.display());
+        assert_eq!(error, expected);
+        let status = manager.get_process(&id).unwrap();
+        assert_eq!(status.state, ProcessState::Failed);
+        assert_eq!(status.last_error.as_deref(), Some(expected.as_str()));
+        assert!(!status.is_running);
+
+        // Still missing: nothing happens
+        manager.supervise_processes();
+        assert_eq!(
+            manager.get_process(&id).unwrap().state,
+            ProcessState::Failed
+        );
+
+        std::os::unix::fs::symlink("/bin/sh", &bin_path).unwrap();
+        manager.supervise_processes();
+        let status = manager.get_process(&id).unwrap();
+        assert!(status.is_running);
+        assert_eq!(status.last_error, None);
+        manager.stop_process(&id).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn start_conditions_run_without_holding_the_manager() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        let marker = scratch_dir("condition").join("go");
+        let condition = format!("while [ ! -e {} ]; do sleep 0.05; done", marker.display());
+        let (waiting, refused) = {
+            let mut core_manager = manager.lock();
+            let mut create = |name: &str, condition: &str| {
+                let request = shell_request(
+                    name,
+                    "exec sleep 30",
+                    json!({ "start_condition_command": condition }),
+                );
+                core_manager.create_process(request).unwrap().id
+            };
+            (create("waiting", &condition), create("refused", "exit 3"))
+        };
+        let status = |id: &str| manager.lock().get_process(id).unwrap();
+
+        manager.lock().start_process(&waiting).unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        let core_manager = manager
+            .try_lock_for(Duration::from_secs(1))
+            .expect("manager is locked while the condition runs");
+        let pending = core_manager.get_process(&waiting).unwrap();
+        assert_eq!(pending.state, ProcessState::Starting);
+        assert!(!pending.is_running);
+        drop(core_manager);
+
+        std::fs::write(&marker, "").unwrap();
+        assert!(wait_for(Duration::from_secs(5), || status(&waiting).is_running));
+        assert_eq!(status(&waiting).last_error, None);
+
+        manager.lock().start_process(&refused).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || {
+            status(&refused).last_error.is_some()
+        }));
+        let refused_status = status(&refused);
+        assert_eq!(refused_status.state, ProcessState::Stopped);
+        assert!(!refused_status.is_running);
+        assert_eq!(
+            refused_status.last_error.as_deref(),
+            Some("Start condition `exit 3` exited with code 3")
+        );
+
+        // Bypassing the condition launches right away
+        manager
+            .lock()
+            .start_process_ignoring_condition(&refused)
+            .unwrap();
+        assert!(status(&refused).is_running);
+
+        let mut core_manager = manager.lock();
+        core_manager.stop_process(&waiting).unwrap();
+        core_manager.stop_process(&refused).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn processes_are_listed_while_a_slow_start_condition_is_pending() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
+        use std::sync::mpsc;
+
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        let marker = scratch_dir("slow-condition").join("go");
+        let condition = format!("while [ ! -e {} ]; do sleep 0.05; done", marker.display());
+        let request = shell_request(
+            "slow-condition",
+            "exec sleep 30",
+            json!({ "start_condition_command": condition }),
+        );
+        let id = {
+            let mut core_manager = manager.lock();
+            let id = core_manager.create_process(request).unwrap().id;
+            core_manager.start_process(&id).unwrap();
+            id
+        };
+
+        // Listed from another thread, the way the HTTP API does it
+        let (sender, receiver) = mpsc::channel();
+        let lister = manager.clone();
+        std::thread::spawn(move || {
+            let listed = lister.lock().list_processes().unwrap();
+            sender.send(listed).unwrap();
+        });
+        let listed = receiver
+            .recv_timeout(Duration::from_secs(1))
+            .expect("list_processes blocked on the pending start condition");
+        let pending = listed.iter().find(|status| status.id == id).unwrap();
+        assert_eq!(pending.state, ProcessState::Starting);
+        assert!(!pending.is_running);
+        assert!(!marker.exists());
+
+        std::fs::write(&marker, "").unwrap();
+        let status = || manager.lock().get_process(&id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || status().is_running));
+        let mut core_manager = manager.lock();
+        core_manager.stop_process(&id).unwrap();
+        core_manager.delete_process(&id).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn mutex_groups_run_one_process_at_a_time() {
+        use crate::openlistcore::test_support::{shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        let [first, rejected, waiting, other_group] = {
+            let mut core_manager = manager.lock();
+            let mut create = |name: &str, group: &str, policy: &str| {
+                let request = shell_request(
+                    name,
+                    "exec sleep 30",
+                    json!({ "mutex_group": group, "mutex_policy": policy }),
+                );
+                core_manager.create_process(request).unwrap().id
+            };
+            [
+                create("backup-nightly", "backup", "reject"),
+                create("backup-manual", "backup", "reject"),
+                create("backup-queued", "backup", "wait"),
+                create("report", "report", "reject"),
+            ]
+        };
+        let status = |id: &str| manager.lock().get_process(id).unwrap();
+
+        manager.lock().start_process(&first).unwrap();
+        let error = manager.lock().start_process(&rejected).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Process backup-nightly of mutex group backup is already running"
+        );
+        assert!(!status(&rejected).is_running);
+        manager.lock().start_process(&other_group).unwrap();
+
+        // Waits without holding the manager while the first one runs
+        manager.lock().start_process(&waiting).unwrap();
+        std::thread::sleep(Duration::from_millis(600));
+        let pending = status(&waiting);
+        assert_eq!(pending.state, ProcessState::Starting);
+        assert!(!pending.is_running);
+        // and a second start joins it
+        manager.lock().start_process(&waiting).unwrap();
+
+        manager.lock().stop_process(&first).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || status(&waiting).is_running));
+        let error = manager.lock().start_process(&first).unwrap_err();
+        assert!(error.to_string().contains("backup-queued"), "{error}");
+
+        let mut core_manager = manager.lock();
+        core_manager.stop_process(&waiting).unwrap();
+        core_manager.stop_process(&other_group).unwrap();
+        core_manager.start_process(&rejected).unwrap();
+        core_manager.stop_process(&rejected).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn overlapping_restarts_spawn_a_single_instance() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        let dir = scratch_dir("single-flight");
+        let marker = dir.join("go");
+        let pids = dir.join("pids");
+        let condition = format!("while [ ! -e {} ]; do sleep 0.05; done", marker.display());
+        let script = format!("echo $$ >> {}; exec sleep 30", pids.display());
+        let id = manager
+            .lock()
+            .create_process(shell_request(
+                "single-flight",
+                &script,
+                json!({ "auto_restart": true, "start_condition_command": condition }),
+            ))
+            .unwrap()
+            .id;
+
+        manager.lock().start_process(&id).unwrap();
+        let workers: Vec<_> = (0..4)
+            .map(|i| {
+                let id = id.clone();
+                let manager = manager.clone();
+                std::thread::spawn(move || {
+                    let mut core_manager = manager.lock();
+                    if i % 2 == 0 {
+                        // An auto-restart that came due while the start is in flight
+                        let runtime = core_manager.runtime_handle(&id).unwrap();
+                        *runtime.restart_at.lock() = Some(0);
+                        core_manager.supervise_processes();
+                    } else {
+                        core_manager.restart_process(&id).unwrap();
+                        core_manager.start_process(&id).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+        assert!(!manager.lock().get_process(&id).unwrap().is_running);
+
+        std::fs::write(&marker, "").unwrap();
+        let status = || manager.lock().get_process(&id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || status().is_running));
+        // Cancelled starts are still finishing their wait for the condition
+        std::thread::sleep(Duration::from_millis(500));
+        let spawned = std::fs::read_to_string(&pids).unwrap();
+        assert_eq!(
+            spawned.lines().collect::<Vec<_>>(),
+            vec![status().pid.unwrap().to_string()]
+        );
+
+        manager.lock().stop_process(&id).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stopping_waits_for_the_last_output_to_reach_the_log() {
+        use crate::openlistcore::test_support::{shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        // Its last words come through the timestamp relay, written by a
+        // child that outlives it briefly, as buffered output would be
+        let script = "trap '(sleep 0.3; echo last words) & exit 0' INT TERM; \
+                      echo started; while :; do sleep 0.05; done";
+        let request = shell_request(
+            "flush-on-stop",
+            script,
+            json!({ "log_timestamps": "unix_millis" }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+        let read_log = |manager: &CoreManager| {
+            manager
+                .get_process_logs(&id, Some(5), false, None)
+                .unwrap()
+                .log_content
+        };
+
+        manager.start_process(&id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || read_log(&manager)
+            .lines()
+            .any(|line| line.ends_with(" started"))));
+        let outcome = manager.stop_process(&id).unwrap();
+        assert!(outcome.graceful);
+        let log = read_log(&manager);
+        assert!(
+            log.lines().any(|line| line.ends_with(" last words")),
+            "{log}"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn flushed_logs_read_back_what_the_process_wrote() {
+        use crate::openlistcore::test_support::{shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let request = shell_request("flush", "echo first; echo second; exec sleep 30", json!({}));
+        let id = manager.create_process(request).unwrap().id;
+        // Nothing logged yet is not an error
+        manager.flush_logs(&id).unwrap();
+
+        manager.start_process(&id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || {
+            manager
+                .get_process_log_bytes(&id, 1024)
+                .is_ok_and(|bytes| bytes.ends_with(b"second\n"))
+        }));
+        manager.flush_logs(&id).unwrap();
+        // After the service's own "Spawning process" line
+        let logged = manager.get_process_log_bytes(&id, 1024).unwrap();
+        assert!(logged.starts_with(b"Spawning process: /bin/sh"));
+        assert!(logged.ends_with(b")\nfirst\nsecond\n"));
+        manager.stop_process(&id).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keyring_references_are_resolved_at_spawn_time() {
+        use crate::openlistcore::test_support::{MockKeyring, shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        manager.secrets = Arc::new(MockKeyring::with(&[("openlist", "admin", "s3cret")]));
+        let request = shell_request(
+            "keyring",
+            "echo \"token=$TOKEN\"; exec sleep 30",
+            json!({ "env_vars": { "TOKEN": "keyring:openlist/admin" } }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+        manager.start_process(&id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || {
+            manager
+                .get_process_log_bytes(&id, 1024)
+                .is_ok_and(|bytes| bytes.ends_with(b"token=s3cret\n"))
+        }));
+        manager.stop_process(&id).unwrap();
+        // Only the reference is stored
+        let config = manager.get_process(&id).unwrap().config;
+        assert_eq!(config.env_vars.unwrap()["TOKEN"], "keyring:openlist/admin");
+
+        manager.secrets = Arc::new(MockKeyring::with(&[]));
+        let err = manager.start_process(&id).unwrap_err().to_string();
+        assert!(
+            err.contains("TOKEN") && err.contains("openlist/admin"),
+            "{err}"
+        );
+        assert!(!manager.get_process(&id).unwrap().is_running);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn processes_see_a_terminal_only_with_allocate_pty() {
+        use crate::openlistcore::test_support::{shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let script = "if [ -t 0 ] && [ -t 1 ]; then echo tty; else echo no-tty; fi; exec sleep 30";
+        for (allocate_pty, expected) in [(true, &b")\ntty\n"[..]), (false, &b")\nno-tty\n"[..])] {
+            let request = shell_request(
+                &format!("pty-{allocate_pty}"),
+                script,
+                json!({ "allocate_pty": allocate_pty }),
+            );
+            let id = manager.create_process(request).unwrap().id;
+            manager.start_process(&id).unwrap();
+            assert!(
+                wait_for(Duration::from_secs(5), || {
+                    manager
+                        .get_process_log_bytes(&id, 1024)
+                        .is_ok_and(|bytes| bytes.ends_with(expected))
+                }),
+                "{:?}",
+                String::from_utf8_lossy(&manager.get_process_log_bytes(&id, 1024).unwrap())
+            );
+            manager.stop_process(&id).unwrap();
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn config_reloads_signal_processes_that_support_sighup() {
+        use crate::openlistcore::test_support::{shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let script = "trap 'echo reloaded' HUP; echo ready; while :; do sleep 0.05; done";
+        let logs_end_with = |manager: &CoreManager, id: &str, text: &str| {
+            manager
+                .get_process_logs(id, None, false, None)
+                .is_ok_and(|logs| logs.log_content.ends_with(text))
+        };
+        let mut start = |name: &str, supports_sighup_reload: bool| {
+            let request = shell_request(
+                name,
+                script,
+                json!({ "supports_sighup_reload": supports_sighup_reload }),
+            );
+            let id = manager.create_process(request).unwrap().id;
+            manager.start_process(&id).unwrap();
+            id
+        };
+        let hup = start("reload-hup", true);
+        let restart = start("reload-restart", false);
+        for id in [&hup, &restart] {
+            assert!(wait_for(Duration::from_secs(5), || {
+                logs_end_with(&manager, id, "ready")
+            }));
+        }
+
+        let pid = manager.get_process(&hup).unwrap().pid;
+        manager.reload_process_config(&hup).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || {
+            logs_end_with(&manager, &hup, "reloaded")
+        }));
+        let status = manager.get_process(&hup).unwrap();
+        assert_eq!((status.pid, status.restart_count), (pid, 0));
+
+        // Without SIGHUP support the process is restarted instead
+        let pid = manager.get_process(&restart).unwrap().pid;
+        manager.reload_process_config(&restart).unwrap();
+        let status = manager.get_process(&restart).unwrap();
+        assert_ne!(status.pid, pid);
+        assert_eq!(status.restart_count, 1);
+
+        for id in [&hup, &restart] {
+            manager.stop_process(id).unwrap();
+            assert!(manager.reload_process_config(id).is_err());
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn processes_without_bin_path_use_the_default_binary() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let mut request = shell_request("default-bin", "echo from default", json!({}));
+        request.bin_path = None;
+        assert!(manager.create_process(request.clone()).is_err());
+
+        let settings: UpdateServiceSettingsRequest =
+            serde_json::from_value(json!({ "default_bin_path": "/bin/sh" })).unwrap();
+        manager.update_settings(settings).unwrap();
+        let inherited = manager.create_process(request).unwrap();
+        assert_eq!(inherited.bin_path, "");
+        let own = manager
+            .create_process(create_request("own-bin", &existing_binary(), json!({})))
+            .unwrap();
+        assert!(
+            manager
+                .reproduce_command(&inherited.id)
+                .unwrap()
+                .contains("/bin/sh -c")
+        );
+
+        manager.start_process(&inherited.id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || {
+            manager
+                .get_process_logs(&inherited.id, None, false, None)
+                .is_ok_and(|logs| logs.log_content.ends_with("from default"))
+        }));
+
+        // Upgrading the default moves only the processes without their own
+        let upgraded = scratch_dir("default-bin-upgrade").join("sh");
+        std::os::unix::fs::symlink("/bin/sh", &upgraded).unwrap();
+        let upgraded = upgraded.to_string_lossy().to_string();
+        let settings: UpdateServiceSettingsRequest =
+            serde_json::from_value(json!({ "default_bin_path": upgraded })).unwrap();
+        manager.update_settings(settings).unwrap();
+        let inherited_command = manager.reproduce_command(&inherited.id).unwrap();
+        assert!(inherited_command.contains(&format!("{upgraded} -c")));
+        let own_command = manager.reproduce_command(&own.id).unwrap();
+        assert!(own_command.contains(&existing_binary()));
+        assert!(!own_command.contains(&upgraded));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn processes_run_outside_the_process_group_of_the_service() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        // Sending SIGINT to our own group would interrupt the test runner too,
+        // so check that it could not reach the process instead
+        let service_group = unsafe { libc::getpgrp() };
+        let service_session = unsafe { libc::getsid(0) };
+        for new_session in [true, false] {
+            let request = shell_request(
+                &format!("session-{new_session}"),
+                "exec sleep 30",
+                json!({ "new_session": new_session }),
+            );
+            let id = manager.create_process(request).unwrap().id;
+            manager.start_process(&id).unwrap();
+            let pid = manager.get_process(&id).unwrap().pid.unwrap() as libc::pid_t;
+            let (group, session) = unsafe { (libc::getpgid(pid), libc::getsid(pid)) };
+            if new_session {
+                assert_eq!((group, session), (pid, pid));
+                assert_ne!(group, service_group);
+                assert_ne!(session, service_session);
+            } else {
+                assert_eq!((group, session), (service_group, service_session));
+            }
+            manager.stop_process(&id).unwrap();
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn processes_start_with_the_configured_scheduling() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let cases = [
+            (
+                json!("batch"),
+                json!({ "class": "best_effort", "level": 7 }),
+                libc::SCHED_BATCH,
+                (2 << 13) | 7,
+            ),
+            (
+                json!("idle"),
+                json!({ "class": "idle" }),
+                libc::SCHED_IDLE,
+                3 << 13,
+            ),
+        ];
+        for (sched_policy, io_priority, expected_policy, expected_ioprio) in cases {
+            let request = shell_request(
+                &format!("sched-{sched_policy}"),
+                "exec sleep 30",
+                json!({ "sched_policy": sched_policy, "io_priority": io_priority }),
+            );
+            let id = manager.create_process(request).unwrap().id;
+            manager.start_process(&id).unwrap();
+            let pid = manager.get_process(&id).unwrap().pid.unwrap() as libc::pid_t;
+            assert_eq!(unsafe { libc::sched_getscheduler(pid) }, expected_policy);
+            let ioprio = unsafe { libc::syscall(libc::SYS_ioprio_get, 1, pid) };
+            assert_eq!(ioprio, expected_ioprio);
+            manager.stop_process(&id).unwrap();
+        }
+
+        let request = shell_request(
+            "sched-invalid",
+            "exec sleep 30",
+            json!({ "io_priority": { "class": "best_effort", "level": 8 } }),
+        );
+        assert!(manager.create_process(request).is_err());
 
-        let runtime = runtime_states
-            .get(id)
-            .ok_or_else(|| anyhow!("Runtime state not found: {}", id))?;
+        if unsafe { libc::geteuid() } != 0 {
+            let request = shell_request(
+                "sched-realtime",
+                "exec sleep 30",
+                json!({ "io_priority": { "class": "realtime" } }),
+            );
+            let id = manager.create_process(request).unwrap().id;
+            let err = manager.start_process(&id).unwrap_err().to_string();
+            assert!(err.contains("requires root"), "{err}");
+        }
+    }
 
-        let status = ProcessStatus {
-            id: id.to_string(),
-            name: config.name.clone(),
-            is_running: is_process_running(runtime.running_pid.load(Ordering::Relaxed)),
-            pid: {
-                let pid = runtime.running_pid.load(Ordering::Relaxed);
-                if pid > 0 { Some(pid as u32) } else { None }
-            },
-            started_at: *runtime.started_at.lock(),
-            restart_count: runtime.restart_count.load(Ordering::Relaxed) as u32,
-            last_exit_code: {
-                let code = runtime.last_exit_code.load(Ordering::Relaxed);
-                if code != 0 { Some(code) } else { None }
-            },
-            config: config.clone(),
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn reproduced_commands_run_the_process_like_the_service() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let out = scratch_dir("reproduce").join("out");
+        let script = "{ pwd -P; printf '%s|' \"$GREETING\" \"${HOME-unset}\"; \
+                      printf '[%s]' \"$@\"; } > \"$OUT.tmp\"; mv \"$OUT.tmp\" \"$OUT\"";
+        let args = ["it's", "", "$HOME", "a \"b\"", "semi;colon", "new\nline"];
+        let mut request = shell_request(
+            "reproduce",
+            script,
+            json!({
+                "env_vars": { "GREETING": "hi there", "OUT": out.to_string_lossy() },
+                "env_inheritance": { "allowlist": ["PATH"] },
+                "launch_wrapper": ["nice", "-n", "5"],
+                "sched_policy": "batch",
+                "io_priority": { "class": "best_effort", "level": 6 },
+            }),
+        );
+        let request_args = request.args.as_mut().unwrap();
+        request_args.push("sh".to_string());
+        request_args.extend(args.map(String::from));
+        let id = manager.create_process(request).unwrap().id;
+        let expected = format!(
+            "{}\nhi there|unset|[it's][][$HOME][a \"b\"][semi;colon][new\nline]",
+            std::fs::canonicalize("/bin").unwrap().display()
+        );
+
+        manager.start_process(&id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || out.exists()));
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), expected);
+        std::fs::remove_file(&out).unwrap();
+
+        let command = manager.reproduce_command(&id).unwrap();
+        assert!(command.starts_with("cd /bin && env -i PATH="), "{command}");
+        assert!(
+            command.contains(" chrt --batch 0 ionice -c 2 -n 6 nice -n 5 /bin/sh -c "),
+            "{command}"
+        );
+        let status = std::process::Command::new("/bin/sh")
+            .args(["-c", &command])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), expected);
+
+        let elevated = UpdateProcessRequest {
+            run_as_admin: Some(true),
+            ..serde_json::from_value(json!({})).unwrap()
         };
+        manager.update_process(&id, elevated).unwrap();
+        let command = manager.reproduce_command(&id).unwrap();
+        // sudo resets the environment unless told to keep the variables
+        assert!(
+            command.contains(" sudo --preserve-env=GREETING,OUT nice -n 5 /bin/sh "),
+            "{command}"
+        );
+        assert!(manager.reproduce_command("missing").is_err());
+    }
 
-        Ok(status)
+    #[cfg(unix)]
+    #[test]
+    fn colored_logs_are_returned_plain_on_request() {
+        use crate::openlistcore::test_support::{shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let script = r"printf '\033[1;31mred\033[0m plain\n\033]0;title\007done\n'; exec sleep 30";
+        let id = manager
+            .create_process(shell_request("ansi", script, json!({})))
+            .unwrap()
+            .id;
+        manager.start_process(&id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || {
+            manager
+                .get_process_log_bytes(&id, 1024)
+                .is_ok_and(|bytes| bytes.ends_with(b"done\n"))
+        }));
+
+        let plain = manager.get_process_logs(&id, Some(2), true, None).unwrap();
+        assert_eq!(plain.log_content, "red plain\ndone");
+        let colored = manager.get_process_logs(&id, Some(2), false, None).unwrap();
+        assert_eq!(
+            colored.log_content,
+            "\x1b[1;31mred\x1b[0m plain\n\x1b]0;title\x07done"
+        );
+        // The log file keeps the escapes
+        let logged = manager.get_process_log_bytes(&id, 1024).unwrap();
+        assert!(logged.ends_with(b"\x1b[1;31mred\x1b[0m plain\n\x1b]0;title\x07done\n"));
+        manager.stop_process(&id).unwrap();
     }
 
-    pub fn start_process(&mut self, id: &str) -> Result<()> {
-        info!("Starting process: {id}");
+    #[test]
+    fn raw_log_tails_are_returned_byte_for_byte() {
+        use crate::openlistcore::test_support::scratch_dir;
 
-        let process_manager = self.process_manager.inner.lock();
-        let processes = process_manager.processes.lock();
-        let runtime_states = process_manager.runtime_states.lock();
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let log_file = scratch_dir("raw-tail").join("process.log");
+        let request = create_request(
+            "raw-tail",
+            &existing_binary(),
+            json!({ "log_file": log_file.to_string_lossy() }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+        // No log yet is not an error
+        assert!(manager.get_process_log_bytes(&id, 1024).unwrap().is_empty());
 
-        let config = processes
-            .get(id)
-            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+        // Invalid UTF-8, then a `€` (e2 82 ac) cut off after its second byte
+        let mut content = b"first line\nbad \xff\xfe bytes\nprice: 5".to_vec();
+        content.extend_from_slice(&"€".as_bytes()[..2]);
+        std::fs::write(&log_file, &content).unwrap();
 
-        let runtime = runtime_states
-            .get(id)
-            .ok_or_else(|| anyhow!("Runtime state not found: {}", id))?;
-        let pid = runtime.running_pid.load(Ordering::Relaxed);
+        let whole = manager.get_process_log_bytes(&id, 1024).unwrap();
+        assert_eq!(whole, content);
+        let exact = manager.get_process_log_bytes(&id, content.len()).unwrap();
+        assert_eq!(exact, content);
+        // Cut at the exact byte, even inside a character, and never decoded
+        for max_bytes in [1, 2, 3, 19] {
+            let tail = manager.get_process_log_bytes(&id, max_bytes).unwrap();
+            assert_eq!(tail, &content[content.len() - max_bytes..]);
+        }
+        let tail = manager.get_process_log_bytes(&id, 19).unwrap();
+        assert_eq!(tail, b"\xff\xfe bytes\nprice: 5\xe2\x82");
+        assert!(manager.get_process_log_bytes(&id, 0).unwrap().is_empty());
+        assert!(manager.get_process_log_bytes("missing", 16).is_err());
+    }
 
-        if is_process_running(pid) {
-            return Err(anyhow!("Process {} is already running", config.name));
+    #[test]
+    fn log_requests_get_the_default_and_are_clamped_at_the_max() {
+        use crate::openlistcore::test_support::scratch_dir;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let log_file = scratch_dir("clamp").join("process.log");
+        let content: String = (1..=30).map(|i| format!("line {i}\n")).collect();
+        std::fs::write(&log_file, content).unwrap();
+        let request = create_request(
+            "clamp",
+            &existing_binary(),
+            json!({ "log_file": log_file.to_string_lossy() }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+
+        let logs = manager.get_process_logs(&id, None, false, None).unwrap();
+        assert_eq!((logs.fetched_lines, logs.clamped), (30, false));
+
+        let settings: UpdateServiceSettingsRequest =
+            serde_json::from_value(json!({ "default_log_lines": 5, "max_log_lines": 20 })).unwrap();
+        manager.update_settings(settings).unwrap();
+        let logs = manager.get_process_logs(&id, None, false, None).unwrap();
+        assert_eq!((logs.fetched_lines, logs.clamped), (5, false));
+        assert!(logs.log_content.starts_with("line 26\n"));
+        let logs = manager
+            .get_process_logs(&id, Some(20), false, None)
+            .unwrap();
+        assert_eq!((logs.fetched_lines, logs.clamped), (20, false));
+        let logs = manager
+            .get_process_logs(&id, Some(1_000_000), false, None)
+            .unwrap();
+        assert_eq!((logs.fetched_lines, logs.clamped), (20, true));
+        assert!(logs.log_content.starts_with("line 11\n"));
+        let combined = manager.get_combined_logs(&id, Some(21), None).unwrap();
+        assert_eq!((combined.fetched_lines, combined.clamped), (20, true));
+
+        for invalid in [
+            json!({ "max_log_lines": 0 }),
+            json!({ "default_log_lines": 21 }),
+        ] {
+            let settings: UpdateServiceSettingsRequest = serde_json::from_value(invalid).unwrap();
+            assert!(manager.update_settings(settings).is_err());
         }
+        assert_eq!(manager.get_settings().max_log_lines, 20);
+    }
 
-        if !Path::new(&config.bin_path).exists() {
-            return Err(anyhow!("Binary not found at: {}", config.bin_path));
+    #[test]
+    fn log_tails_fit_the_byte_budget_in_whole_lines() {
+        use crate::openlistcore::test_support::scratch_dir;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let log_file = scratch_dir("budget").join("process.log");
+        // Lines of wildly different lengths
+        let content: String = (1..=40)
+            .map(|i| format!("line {i} {}\n", "x".repeat(i * i % 97)))
+            .collect();
+        std::fs::write(&log_file, &content).unwrap();
+        let request = create_request(
+            "budget",
+            &existing_binary(),
+            json!({ "log_file": log_file.to_string_lossy() }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+        let text = content.trim_end_matches('\n');
+
+        for max_bytes in [0, 5, 50, 99, 100, 250, 1000, 100_000] {
+            let logs = manager
+                .get_process_logs(&id, None, false, Some(max_bytes))
+                .unwrap();
+            assert!(logs.log_content.len() <= max_bytes, "{max_bytes}");
+            assert_eq!(logs.fetched_bytes, logs.log_content.len());
+            assert_eq!(logs.log_content.lines().count(), logs.fetched_lines);
+            // The tail of the log, starting at the beginning of a line
+            assert!(text.ends_with(&logs.log_content), "{max_bytes}");
+            let cut = text.len() - logs.log_content.len();
+            let separator = usize::from(logs.fetched_lines > 0);
+            assert!(cut == 0 || separator == 0 || text.as_bytes()[cut - 1] == b'\n');
+            // and as many lines as fit
+            let before = &text[..cut - separator.min(cut)];
+            let one_more = before.rsplit('\n').next().unwrap().len();
+            assert!(cut == 0 || logs.log_content.len() + separator + one_more > max_bytes);
         }
 
-        process::ensure_executable_permissions(&config.bin_path).with_context(|| {
-            format!("Failed to set execute permissions for: {}", config.bin_path)
-        })?;
+        let whole = manager
+            .get_process_logs(&id, None, false, Some(100_000))
+            .unwrap();
+        assert_eq!(
+            (whole.fetched_lines, whole.log_content.as_str()),
+            (40, text)
+        );
+        // A line count still applies within the budget
+        let logs = manager
+            .get_process_logs(&id, Some(3), false, Some(100_000))
+            .unwrap();
+        assert_eq!(logs.fetched_lines, 3);
+    }
 
-        let log_file = File::options()
-            .create(true)
-            .append(true)
-            .open(&config.log_file)
-            .with_context(|| format!("Failed to open log file: {}", config.log_file))?; // Spawn process
-        let args_strs: Vec<&str> = config.args.iter().map(|s| s.as_str()).collect();
-        let pid = process::spawn_process_with_privileges(
-            &config.bin_path,
-            &args_strs,
-            log_file,
-            config.run_as_admin,
+    #[cfg(unix)]
+    #[test]
+    fn logs_tell_a_process_that_never_ran_from_one_that_ran_silently() {
+        use crate::openlistcore::test_support::{shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let never = manager
+            .create_process(shell_request("never", "exit 0", json!({})))
+            .unwrap()
+            .id;
+        let silent = manager
+            .create_process(shell_request("silent", "exit 0", json!({})))
+            .unwrap()
+            .id;
+
+        manager.start_process(&silent).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || {
+            !manager.get_process(&silent).unwrap().is_running
+        }));
+
+        let never = manager.get_process_logs(&never, None, false, None).unwrap();
+        let silent = manager
+            .get_process_logs(&silent, None, false, None)
+            .unwrap();
+        assert_eq!((never.log_content.as_str(), never.total_lines), ("", 0));
+        assert_eq!((never.log_file_exists, never.ever_started), (false, false));
+        assert_eq!((silent.log_file_exists, silent.ever_started), (true, true));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn combined_logs_interleave_the_streams_of_each_process() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        // stderr is written first, so only the timestamps put the lines in
+        // order. The date is a variable so the logged command line has none.
+        let ids: Vec<String> = ["a", "b"]
+            .into_iter()
+            .map(|tag| {
+                let script = format!(
+                    "d=2025-01-31; \
+                     echo \"$d 08:00:01 {tag} bind failed\" >&2; \
+                     echo \"    caused by: {tag} in use\" >&2; \
+                     echo \"$d 08:00:03 {tag} gave up\" >&2; \
+                     echo \"$d 08:00:00 {tag} starting\"; \
+                     echo \"$d 08:00:02 {tag} retrying\""
+                );
+                let stderr_log_file = scratch_dir(tag).join("process.err.log");
+                let request = shell_request(
+                    tag,
+                    &script,
+                    json!({ "stderr_log_file": stderr_log_file.to_string_lossy() }),
+                );
+                let id = manager.create_process(request).unwrap().id;
+                manager.start_process(&id).unwrap();
+                id
+            })
+            .collect();
+
+        for (tag, id) in ["a", "b"].into_iter().zip(&ids) {
+            assert!(wait_for(Duration::from_secs(5), || {
+                let status = manager.get_process(id).unwrap();
+                !status.is_running
+                    && manager
+                        .get_combined_logs(id, None, None)
+                        .unwrap()
+                        .lines
+                        .len()
+                        == 6
+            }));
+            let combined = manager.get_combined_logs(id, None, None).unwrap();
+            let (header, lines) = combined.lines.split_first().unwrap();
+            assert_eq!(header.stream, LogStream::Stdout);
+            assert!(header.line.starts_with("Spawning process: /bin/sh"));
+            let lines: Vec<(LogStream, String)> = lines
+                .iter()
+                .map(|line| (line.stream, line.line.clone()))
+                .collect();
+            assert_eq!(
+                lines,
+                [
+                    (
+                        LogStream::Stdout,
+                        format!("2025-01-31 08:00:00 {tag} starting")
+                    ),
+                    (
+                        LogStream::Stderr,
+                        format!("2025-01-31 08:00:01 {tag} bind failed")
+                    ),
+                    (LogStream::Stderr, format!("    caused by: {tag} in use")),
+                    (
+                        LogStream::Stdout,
+                        format!("2025-01-31 08:00:02 {tag} retrying")
+                    ),
+                    (
+                        LogStream::Stderr,
+                        format!("2025-01-31 08:00:03 {tag} gave up")
+                    ),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn combined_logs_filter_by_inferred_level() {
+        use crate::openlistcore::test_support::scratch_dir;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let dir = scratch_dir("levels");
+        let log_file = dir.join("process.log");
+        let stderr_log_file = dir.join("process.err.log");
+        std::fs::write(
+            &log_file,
+            "2025-01-31 08:00:00 INFO starting\n2025-01-31 08:00:02 WARN slow disk\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &stderr_log_file,
+            "2025-01-31 08:00:01 ERROR bind failed\n    caused by: address in use\n",
         )
-        .with_context(|| format!("Failed to spawn process: {}", config.bin_path))?;
+        .unwrap();
+        let request = create_request(
+            "levels",
+            &existing_binary(),
+            json!({
+                "log_file": log_file.to_string_lossy(),
+                "stderr_log_file": stderr_log_file.to_string_lossy(),
+            }),
+        );
+        let id = manager.create_process(request).unwrap().id;
 
-        runtime.is_running.store(true, Ordering::Relaxed);
-        runtime.running_pid.store(pid as i32, Ordering::Relaxed);
-        *runtime.started_at.lock() = Some(get_current_timestamp());
+        // Levels are only inferred when asked for
+        let plain = manager.get_combined_logs(&id, None, None).unwrap();
+        assert!(plain.lines.iter().all(|line| line.level.is_none()));
+        assert!(
+            manager
+                .get_combined_logs(&id, None, Some(LogLevel::Warn))
+                .is_err()
+        );
 
-        info!("Process {} started with PID: {}", config.name, pid);
-        Ok(())
+        let update: UpdateProcessRequest =
+            serde_json::from_value(json!({ "infer_log_level": true })).unwrap();
+        manager.update_process(&id, update).unwrap();
+        let leveled = manager.get_combined_logs(&id, None, None).unwrap();
+        let levels: Vec<_> = leveled.lines.iter().map(|line| line.level).collect();
+        assert_eq!(
+            levels,
+            [
+                Some(LogLevel::Info),
+                Some(LogLevel::Error),
+                Some(LogLevel::Error),
+                Some(LogLevel::Warn),
+            ]
+        );
+
+        let warnings = manager
+            .get_combined_logs(&id, None, Some(LogLevel::Warn))
+            .unwrap();
+        let lines: Vec<&str> = warnings.lines.iter().map(|l| l.line.as_str()).collect();
+        assert_eq!(
+            lines,
+            [
+                "2025-01-31 08:00:01 ERROR bind failed",
+                "    caused by: address in use",
+                "2025-01-31 08:00:02 WARN slow disk",
+            ]
+        );
+        assert_eq!(warnings.total_lines, 3);
     }
 
-    pub fn stop_process(&mut self, id: &str) -> Result<()> {
-        info!("Stopping process: {id}");
+    #[test]
+    fn recent_errors_are_the_latest_warnings_and_errors_newest_first() {
+        use crate::openlistcore::test_support::scratch_dir;
 
-        let process_manager = self.process_manager.inner.lock();
-        let processes = process_manager.processes.lock();
-        let runtime_states = process_manager.runtime_states.lock();
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let log_file = scratch_dir("recent-errors").join("process.log");
+        std::fs::write(
+            &log_file,
+            "INFO starting\nERROR disk full\nDEBUG retrying\nWARN slow disk\n\
+             INFO serving\nERROR bind failed\n    caused by: address in use\nINFO idle\n",
+        )
+        .unwrap();
+        // Levels are inferred even without infer_log_level
+        let request = create_request(
+            "recent-errors",
+            &existing_binary(),
+            json!({ "log_file": log_file.to_string_lossy() }),
+        );
+        let id = manager.create_process(request).unwrap().id;
 
-        let config = processes
-            .get(id)
-            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+        let errors = manager.get_recent_errors(&id, None).unwrap();
+        let lines: Vec<&str> = errors.lines.iter().map(|l| l.line.as_str()).collect();
+        assert_eq!(
+            lines,
+            [
+                "    caused by: address in use",
+                "ERROR bind failed",
+                "WARN slow disk",
+                "ERROR disk full",
+            ]
+        );
+        assert_eq!((errors.scanned_lines, errors.fetched_lines), (8, 4));
 
-        let runtime = runtime_states
-            .get(id)
-            .ok_or_else(|| anyhow!("Runtime state not found: {}", id))?;
+        let latest = manager.get_recent_errors(&id, Some(2)).unwrap();
+        let lines: Vec<&str> = latest.lines.iter().map(|l| l.line.as_str()).collect();
+        assert_eq!(
+            lines,
+            ["    caused by: address in use", "ERROR bind failed"]
+        );
+    }
 
-        let pid = runtime.running_pid.load(Ordering::Relaxed);
+    #[test]
+    fn log_search_reports_only_the_processes_with_matches() {
+        use crate::openlistcore::test_support::scratch_dir;
 
-        if pid <= 0 {
-            warn!("Process {} is not running", config.name);
-            return Ok(());
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let contents = [
+            ("quiet", "starting\nlistening on :5244\n"),
+            (
+                "noisy",
+                "starting\nERROR: disk full\nretrying\nERROR: disk full\n",
+            ),
+            ("idle", ""),
+        ];
+        let mut ids = Vec::new();
+        for (name, content) in contents {
+            let log_file = scratch_dir(name).join("process.log");
+            std::fs::write(&log_file, content).unwrap();
+            let request = create_request(
+                name,
+                &existing_binary(),
+                json!({ "log_file": log_file.to_string_lossy() }),
+            );
+            ids.push(manager.create_process(request).unwrap().id);
         }
 
-        let kill_result = process::kill_process(pid as u32);
+        let hits = manager.search_all_logs("ERROR", false, 10).unwrap();
+        assert_eq!(hits.len(), 2);
+        assert!(
+            hits.iter()
+                .all(|hit| hit.id == ids[1] && hit.name == "noisy")
+        );
+        assert_eq!(hits[0].line_number, 2);
+        assert_eq!(hits[0].line, "ERROR: disk full");
+        assert_eq!(hits[1].line_number, 4);
 
-        runtime.is_running.store(false, Ordering::Relaxed);
-        runtime.running_pid.store(INVALID_PID, Ordering::Relaxed);
-        *runtime.started_at.lock() = None;
+        assert_eq!(manager.search_all_logs("ERROR", false, 1).unwrap().len(), 1);
+        let hits = manager.search_all_logs(r"on :\d+$", true, 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(
+            (hits[0].id.as_str(), hits[0].line_number),
+            (ids[0].as_str(), 2)
+        );
+        assert!(
+            manager
+                .search_all_logs("nothing", false, 10)
+                .unwrap()
+                .is_empty()
+        );
+        assert!(manager.search_all_logs("(", true, 10).is_err());
+        assert!(manager.search_all_logs("", false, 10).is_err());
+    }
 
-        match kill_result {
-            Ok(_) => {
-                info!(
-                    "Process {} (PID: {}) terminated successfully",
-                    config.name, pid
-                );
-                runtime.last_exit_code.store(0, Ordering::Relaxed);
+    #[test]
+    fn exported_logs_hold_the_live_and_rotated_segments() {
+        use crate::openlistcore::test_support::scratch_dir;
+        use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+        use std::io::Write;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let dir = scratch_dir("export");
+        let log_file = dir.join("process.log");
+        std::fs::write(&log_file, "live: token sekrit-token\n").unwrap();
+        std::fs::write(dir.join("process.log.1"), "rotated\n").unwrap();
+        let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(b"oldest: sekrit-token\n").unwrap();
+        std::fs::write(dir.join("process.log.2.gz"), gz.finish().unwrap()).unwrap();
+        std::fs::write(dir.join("unrelated.log"), "not ours\n").unwrap();
+        let request = create_request(
+            "export",
+            &existing_binary(),
+            json!({
+                "log_file": log_file.to_string_lossy(),
+                "env_vars": { "TOKEN": "sekrit-token" },
+            }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+
+        let read_archive = |path: &Path| -> BTreeMap<String, Vec<u8>> {
+            let mut archive = tar::Archive::new(GzDecoder::new(File::open(path).unwrap()));
+            archive
+                .entries()
+                .unwrap()
+                .map(|entry| {
+                    let mut entry = entry.unwrap();
+                    let name = entry.path().unwrap().to_string_lossy().into_owned();
+                    let mut bytes = Vec::new();
+                    entry.read_to_end(&mut bytes).unwrap();
+                    (name, bytes)
+                })
+                .collect()
+        };
+
+        let plain = dir.join("plain.tar.gz");
+        manager.export_process_logs(&id, &plain, false).unwrap();
+        let entries = read_archive(&plain);
+        let names: Vec<&str> = entries.keys().map(String::as_str).collect();
+        assert_eq!(
+            names,
+            [
+                "logs/process.log",
+                "logs/process.log.1",
+                "logs/process.log.2.gz",
+                "manifest.json",
+            ]
+        );
+        assert_eq!(entries["logs/process.log"], b"live: token sekrit-token\n");
+        assert_eq!(entries["logs/process.log.1"], b"rotated\n");
+        let manifest: serde_json::Value =
+            serde_json::from_slice(&entries["manifest.json"]).unwrap();
+        assert_eq!(manifest["process"]["id"], id.as_str());
+        assert_eq!(manifest["process"]["env_vars"]["TOKEN"], "***");
+
+        let redacted = dir.join("redacted.tar.gz");
+        manager.export_process_logs(&id, &redacted, true).unwrap();
+        let entries = read_archive(&redacted);
+        assert_eq!(entries["logs/process.log"], b"live: token ***\n");
+        assert_eq!(entries["logs/process.log.2"], b"oldest: ***\n");
+        // The logs themselves are left as they were
+        assert_eq!(
+            std::fs::read_to_string(&log_file).unwrap(),
+            "live: token sekrit-token\n"
+        );
+        assert!(
+            manager
+                .export_process_logs("missing", &redacted, false)
+                .is_err()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn readiness_and_stop_timeouts_surface_as_core_errors() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        // Nothing listens here once the listener is gone
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap();
+        let marker = scratch_dir("stop-timeout").join("ready");
+        let script = format!(
+            "trap '' TERM; touch {}; while :; do sleep 0.05; done",
+            marker.display()
+        );
+        let (ready, unready, stuck) = {
+            let mut core_manager = manager.lock();
+            let mut create = |request| core_manager.create_process(request).unwrap().id;
+            (
+                create(shell_request("ready", "exec sleep 30", json!({}))),
+                create(shell_request(
+                    "unready",
+                    "exec sleep 30",
+                    json!({
+                        "readiness_check": { "type": "tcp", "address": address.to_string() },
+                        "readiness_timeout_secs": 1,
+                    }),
+                )),
+                create(shell_request(
+                    "stuck",
+                    &script,
+                    json!({ "kill_sequence": [{ "signal": "TERM", "wait_ms": 300 }] }),
+                )),
+            )
+        };
+
+        manager.lock().start_process(&ready).unwrap();
+        assert!(wait_until_ready(&manager, &ready).unwrap() < Duration::from_secs(1));
+
+        manager.lock().start_process(&unready).unwrap();
+        let error = wait_until_ready(&manager, &unready).unwrap_err();
+        match error.downcast_ref::<CoreError>() {
+            Some(CoreError::StartTimeout { elapsed }) => {
+                assert!(*elapsed < Duration::from_secs(3), "{elapsed:?}");
             }
-            Err(e) => {
-                error!(
-                    "Failed to terminate process {} (PID: {}): {}",
-                    config.name, pid, e
-                );
-                runtime.last_exit_code.store(-1, Ordering::Relaxed);
-                return Err(anyhow!("Failed to stop process: {}", e));
+            other => panic!("expected a start timeout, got {other:?}: {error}"),
+        }
+
+        manager.lock().start_process(&stuck).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || marker.exists()));
+        let pid = manager.lock().get_process(&stuck).unwrap().pid.unwrap();
+        let error = manager.lock().stop_process(&stuck).unwrap_err();
+        match error.downcast_ref::<CoreError>() {
+            Some(CoreError::StopTimeout { elapsed }) => {
+                assert!(*elapsed >= Duration::from_millis(300), "{elapsed:?}");
             }
+            other => panic!("expected a stop timeout, got {other:?}: {error}"),
         }
+        assert!(is_process_running(pid as i32));
+        unsafe { libc::kill(pid as i32, libc::SIGKILL) };
+        process::try_reap(pid as i32);
 
-        Ok(())
+        // Other failures are not timeouts
+        let error = manager.lock().start_process("missing").unwrap_err();
+        assert!(error.downcast_ref::<CoreError>().is_none());
+        let mut core_manager = manager.lock();
+        core_manager.stop_process(&ready).unwrap();
+        core_manager.stop_process(&unready).unwrap();
     }
 
-    pub fn get_process_logs(&self, id: &str, lines: Option<usize>) -> Result<LogResponse> {
-        let process_manager = self.process_manager.inner.lock();
-        let processes = process_manager.processes.lock();
+    #[cfg(unix)]
+    #[test]
+    fn held_processes_stay_down_until_started_by_hand() {
+        use crate::openlistcore::test_support::shell_request;
 
-        let config = processes
-            .get(id)
-            .ok_or_else(|| anyhow!("Process not found: {}", id))?;
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let id = manager
+            .create_process(shell_request(
+                "held",
+                "exec sleep 30",
+                json!({ "auto_start": true, "auto_restart": true }),
+            ))
+            .unwrap()
+            .id;
+        manager.start_process(&id).unwrap();
+        assert!(!manager.get_process(&id).unwrap().held);
 
-        if !Path::new(&config.log_file).exists() {
-            return Ok(LogResponse {
-                id: id.to_string(),
-                name: config.name.clone(),
-                log_content: String::new(),
-                total_lines: 0,
-                fetched_lines: 0,
-            });
-        }
+        manager.stop_process_and_hold(&id).unwrap();
+        let status = manager.get_process(&id).unwrap();
+        assert!(!status.is_running);
+        assert!(status.held);
 
-        let file = File::open(&config.log_file)
-            .with_context(|| format!("Failed to open log file: {}", config.log_file))?;
+        manager.auto_start_processes().unwrap();
+        assert!(!manager.get_process(&id).unwrap().is_running);
+        // Even a restart that is already due is not carried out
+        let runtime = manager.runtime_handle(&id).unwrap();
+        *runtime.restart_at.lock() = Some(0);
+        manager.supervise_processes();
+        assert!(runtime.restart_at.lock().is_none());
+        let status = manager.get_process(&id).unwrap();
+        assert!(!status.is_running);
+        assert_eq!(status.restart_count, 0);
 
-        let reader = BufReader::new(file);
-        let all_lines: Vec<String> = reader
-            .lines()
-            .collect::<Result<Vec<_>, _>>()
-            .with_context(|| format!("Failed to read log file: {}", config.log_file))?;
+        manager.start_process(&id).unwrap();
+        let status = manager.get_process(&id).unwrap();
+        assert!(status.is_running);
+        assert!(!status.held);
+        manager.stop_process(&id).unwrap();
+    }
 
-        let total_lines = all_lines.len();
-        let lines_to_fetch = lines.unwrap_or(100).min(total_lines);
+    #[cfg(unix)]
+    #[test]
+    fn failing_health_commands_trigger_a_restart() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
 
-        let start_index = total_lines.saturating_sub(lines_to_fetch);
+        let _serial = test_support::serial();
+        let unhealthy = scratch_dir("health-command").join("unhealthy");
+        let mut manager = CoreManager::new();
+        let id = manager
+            .create_process(shell_request(
+                "health",
+                "exec sleep 30",
+                json!({
+                    "auto_restart": true,
+                    "health_command": format!("test ! -e {}", unhealthy.display()),
+                    "health_interval_secs": 1,
+                    "health_failure_threshold": 2,
+                }),
+            ))
+            .unwrap()
+            .id;
 
-        let log_content = all_lines[start_index..].join("\n");
+        manager.start_process(&id).unwrap();
+        let pid = manager.get_process(&id).unwrap().pid;
+        // Passing checks leave the process alone
+        std::thread::sleep(Duration::from_millis(2500));
+        manager.supervise_processes();
+        let status = manager.get_process(&id).unwrap();
+        assert!(status.is_running);
+        assert_eq!((status.pid, status.next_restart_at), (pid, None));
 
-        Ok(LogResponse {
-            id: id.to_string(),
-            name: config.name.clone(),
-            log_content,
-            total_lines,
-            fetched_lines: lines_to_fetch,
-        })
+        std::fs::write(&unhealthy, "").unwrap();
+        assert!(wait_for(Duration::from_secs(10), || {
+            manager.supervise_processes();
+            manager.get_process(&id).unwrap().next_restart_at.is_some()
+        }));
+        let status = manager.get_process(&id).unwrap();
+        assert!(!status.is_running);
+
+        std::fs::remove_file(&unhealthy).unwrap();
+        let runtime = manager.runtime_handle(&id).unwrap();
+        *runtime.restart_at.lock() = Some(0);
+        manager.supervise_processes();
+        let status = manager.get_process(&id).unwrap();
+        assert!(status.is_running);
+        assert_ne!(status.pid, pid);
+        assert_eq!(status.restart_count, 1);
+        manager.stop_process(&id).unwrap();
     }
-    pub fn auto_start_processes(&mut self) -> Result<()> {
-        info!("Auto-starting configured processes...");
 
-        let process_ids: Vec<String> = {
-            let process_manager = self.process_manager.inner.lock();
-            let processes = process_manager.processes.lock();
-            processes
-                .iter()
-                .filter(|(_, config)| config.auto_start)
-                .map(|(id, _)| id.clone())
-                .collect()
-        };
+    #[cfg(unix)]
+    #[test]
+    fn the_global_pre_start_hook_gates_every_start() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
 
-        if process_ids.is_empty() {
-            info!("No processes configured for auto-start");
-            return Ok(());
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let trace = scratch_dir("global-pre-start-hook").join("trace");
+        let id = manager
+            .create_process(shell_request(
+                "gated",
+                &format!("echo start >> {}; exec sleep 30", trace.display()),
+                json!({}),
+            ))
+            .unwrap()
+            .id;
+        manager
+            .set_global_pre_start_hook(Some(format!("echo hook >> {}", trace.display())))
+            .unwrap();
+
+        let read_trace = || std::fs::read_to_string(&trace).unwrap_or_default();
+        for expected in ["hook\nstart\n", "hook\nstart\nhook\nstart\n"] {
+            manager.start_process(&id).unwrap();
+            assert!(wait_for(Duration::from_secs(5), || read_trace() == expected));
+            manager.stop_process(&id).unwrap();
         }
 
-        info!(
-            "Found {} processes configured for auto-start",
-            process_ids.len()
+        manager
+            .set_global_pre_start_hook(Some("exit 4".to_string()))
+            .unwrap();
+        let error = manager.start_process(&id).unwrap_err().to_string();
+        assert_eq!(error, "Global pre-start hook `exit 4` exited with code 4");
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(!manager.get_process(&id).unwrap().is_running);
+        assert_eq!(read_trace().matches("start").count(), 2);
+
+        manager
+            .set_global_pre_start_hook(Some(" ".to_string()))
+            .unwrap();
+        assert_eq!(manager.get_settings().global_pre_start_hook, None);
+        manager.start_process(&id).unwrap();
+        assert!(manager.get_process(&id).unwrap().is_running);
+        manager.stop_process(&id).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn processes_can_be_listed_by_state() {
+        use crate::openlistcore::test_support::{shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let closed_port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .unwrap();
+        let mut create = |name: &str, extra| {
+            manager
+                .create_process(shell_request(name, "exec sleep 30", extra))
+                .unwrap()
+                .id
+        };
+        let running = create("running", json!({}));
+        let starting = create(
+            "starting",
+            json!({ "readiness_check": { "type": "tcp", "address": closed_port.to_string() } }),
         );
+        let stopped = create("stopped", json!({}));
+        let failed = create("failed", json!({}));
 
-        for id in process_ids {
-            //check if the process is already running
-            if let Err(e) = self.start_process(&id) {
-                error!("Failed to auto-start process {id}: {e}");
-            } else {
-                info!("Successfully auto-started process {id}");
-            }
-        }
+        manager.start_process(&running).unwrap();
+        manager.start_process(&starting).unwrap();
+        *manager.runtime_handle(&failed).unwrap().state.lock() = ProcessState::Failed;
+        assert!(wait_for(Duration::from_secs(5), || {
+            manager.get_process(&running).unwrap().state == ProcessState::Running
+        }));
 
-        Ok(())
+        let ids_in = |state| -> Vec<String> {
+            let statuses = manager.list_processes_by_state(state).unwrap();
+            assert!(statuses.iter().all(|status| status.state == state));
+            statuses.into_iter().map(|status| status.id).collect()
+        };
+        assert_eq!(ids_in(ProcessState::Running), [running.as_str()]);
+        assert_eq!(ids_in(ProcessState::Starting), [starting.as_str()]);
+        assert_eq!(ids_in(ProcessState::Stopped), [stopped.as_str()]);
+        assert_eq!(ids_in(ProcessState::Failed), [failed.as_str()]);
+
+        manager.stop_process(&running).unwrap();
+        manager.stop_process(&starting).unwrap();
+        assert!(
+            manager
+                .list_processes_by_state(ProcessState::Running)
+                .unwrap()
+                .is_empty()
+        );
     }
 
-    pub fn shutdown_all_processes(&mut self) -> Result<()> {
-        let process_ids: Vec<String> = {
-            let process_manager = self.process_manager.inner.lock();
-            let processes = process_manager.processes.lock();
-            processes.keys().cloned().collect()
+    #[cfg(unix)]
+    #[test]
+    fn not_running_reasons_follow_what_keeps_the_process_down() {
+        use crate::openlistcore::test_support::{scratch_dir, shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        let marker = scratch_dir("not-running-reason").join("go");
+        let condition = format!("while [ ! -e {} ]; do sleep 0.05; done", marker.display());
+        let (idle, crashing, conditional) = {
+            let mut core_manager = manager.lock();
+            let mut create = |name: &str, script: &str, extra| {
+                core_manager
+                    .create_process(shell_request(name, script, extra))
+                    .unwrap()
+                    .id
+            };
+            (
+                create("idle", "exec sleep 30", json!({ "auto_start": true })),
+                create("crashing", "exit 3", json!({ "auto_restart": true })),
+                create(
+                    "conditional",
+                    "exec sleep 30",
+                    json!({ "start_condition_command": condition }),
+                ),
+            )
         };
+        let reason = |id: &str| manager.lock().not_running_reason(id).unwrap();
+        assert!(manager.lock().not_running_reason("missing").is_err());
 
-        for id in process_ids {
-            if let Err(e) = self.stop_process(&id) {
-                error!("Failed to stop process {id}: {e}");
-            }
-        }
+        assert_eq!(reason(&idle), Some(NotRunningReason::NeverStarted));
+        manager.lock().set_safe_mode(true);
+        assert_eq!(reason(&idle), Some(NotRunningReason::SafeMode));
+        assert_eq!(reason(&crashing), Some(NotRunningReason::NeverStarted));
+        manager.lock().set_safe_mode(false);
 
-        Ok(())
+        manager.lock().start_process(&idle).unwrap();
+        assert_eq!(reason(&idle), None);
+        manager.lock().stop_process(&idle).unwrap();
+        assert_eq!(
+            reason(&idle),
+            Some(NotRunningReason::StoppedManually { held: false })
+        );
+        manager.lock().stop_process_and_hold(&idle).unwrap();
+        assert_eq!(
+            reason(&idle),
+            Some(NotRunningReason::StoppedManually { held: true })
+        );
+
+        manager.lock().settings.maintenance = true;
+        assert_eq!(reason(&idle), Some(NotRunningReason::Maintenance));
+        manager.lock().settings.maintenance = false;
+
+        let runtime = manager.lock().runtime_handle(&crashing).unwrap();
+        runtime.awaiting_binary.store(true, Ordering::Relaxed);
+        assert_eq!(reason(&crashing), Some(NotRunningReason::AwaitingBinary));
+
+        manager.lock().start_process(&crashing).unwrap();
+        let supervised_reason = || {
+            let mut core_manager = manager.lock();
+            core_manager.supervise_processes();
+            core_manager.not_running_reason(&crashing).unwrap()
+        };
+        assert!(wait_for(Duration::from_secs(5), || {
+            matches!(supervised_reason(), Some(NotRunningReason::Exited { .. }))
+        }));
+        let restart_at = *runtime.restart_at.lock();
+        assert!(restart_at.is_some());
+        assert_eq!(
+            reason(&crashing),
+            Some(NotRunningReason::Exited {
+                exit_code: Some(3),
+                restart_at
+            })
+        );
+
+        // The next crash is the one auto-restart gives up on
+        runtime
+            .consecutive_failures
+            .store(CRASH_LOOP_THRESHOLD - 1, Ordering::Relaxed);
+        *runtime.restart_at.lock() = Some(0);
+        assert!(wait_for(Duration::from_secs(5), || {
+            matches!(
+                supervised_reason(),
+                Some(NotRunningReason::CrashLoop { .. })
+            )
+        }));
+        let Some(NotRunningReason::CrashLoop { message }) = reason(&crashing) else {
+            unreachable!()
+        };
+        assert!(message.starts_with("Crash loop detected"), "{message}");
+
+        manager
+            .lock()
+            .set_global_pre_start_hook(Some("exit 4".to_string()))
+            .unwrap();
+        assert!(manager.lock().start_process(&crashing).is_err());
+        manager.lock().set_global_pre_start_hook(None).unwrap();
+        assert_eq!(
+            reason(&crashing),
+            Some(NotRunningReason::StartFailed {
+                error: "Global pre-start hook `exit 4` exited with code 4".to_string()
+            })
+        );
+
+        manager.lock().start_process(&conditional).unwrap();
+        assert_eq!(reason(&conditional), Some(NotRunningReason::Starting));
+        std::fs::write(&marker, "").unwrap();
+        assert!(wait_for(Duration::from_secs(5), || reason(&conditional).is_none()));
+        manager.lock().stop_process(&conditional).unwrap();
+        std::fs::remove_file(&marker).unwrap();
+        manager
+            .lock()
+            .update_process(
+                &conditional,
+                serde_json::from_value(json!({ "start_condition_command": "exit 3" })).unwrap(),
+            )
+            .unwrap();
+        manager.lock().start_process(&conditional).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || {
+            reason(&conditional)
+                == Some(NotRunningReason::ConditionNotMet {
+                    message: "Start condition `exit 3` exited with code 3".to_string(),
+                })
+        }));
     }
 
-    pub fn get_openlist_status(&self) -> Result<serde_json::Value> {
-        let processes = self.list_processes()?;
-        Ok(serde_json::json!({
-            "processes": processes,
-            "total_processes": processes.len(),
-            "running_processes": processes.iter().filter(|p| p.is_running).count(),
-        }))
+    #[cfg(unix)]
+    #[test]
+    fn dead_processes_and_reused_pids_are_not_reported_running() {
+        use crate::openlistcore::test_support::{shell_request, wait_for};
+
+        let _serial = test_support::serial();
+        // Nothing supervises this manager, so its state stays stale
+        let mut manager = CoreManager::new();
+        let crashed = manager
+            .create_process(shell_request("crashed", "exec sleep 30", json!({})))
+            .unwrap()
+            .id;
+        let reused = manager
+            .create_process(shell_request("reused", "exec sleep 30", json!({})))
+            .unwrap()
+            .id;
+        manager.start_process(&crashed).unwrap();
+        manager.start_process(&reused).unwrap();
+
+        let crashed_pid = manager.get_process(&crashed).unwrap().pid.unwrap();
+        unsafe { libc::kill(crashed_pid as i32, libc::SIGKILL) };
+        assert!(wait_for(Duration::from_secs(5), || !manager
+            .get_process(&crashed)
+            .unwrap()
+            .is_running));
+        let status = manager.get_process(&crashed).unwrap();
+        assert_eq!((status.state, status.pid), (ProcessState::Stopped, None));
+
+        // As if the pid had been given to a process that started later
+        let reused_pid = manager.get_process(&reused).unwrap().pid.unwrap();
+        let runtime = manager.runtime_handle(&reused).unwrap();
+        assert!(runtime.pid_start_stamp.lock().is_some());
+        *runtime.pid_start_stamp.lock() = Some("0".to_string());
+        let status = manager.get_process(&reused).unwrap();
+        assert!(!status.is_running);
+        assert_eq!(status.pid, None);
+
+        manager.supervise_processes();
+        for (id, cause) in [
+            (&crashed, StopCause::Exited(Some(128 + libc::SIGKILL))),
+            (&reused, StopCause::Exited(None)),
+        ] {
+            let runtime = manager.runtime_handle(id).unwrap();
+            assert!(!runtime.is_running.load(Ordering::Relaxed));
+            assert_eq!(*runtime.stop_cause.lock(), Some(cause));
+        }
+        // The process behind the "reused" pid is left alone
+        assert!(is_process_running(reused_pid as i32));
+        unsafe { libc::kill(reused_pid as i32, libc::SIGKILL) };
+        process::try_reap(reused_pid as i32);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_reused_pid_does_not_block_starts_or_take_reloads() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let id = manager
+            .create_process(shell_request("reused-start", "exec sleep 30", json!({})))
+            .unwrap()
+            .id;
+        manager.start_process(&id).unwrap();
+
+        // As if the run had died and its pid been given to a later process
+        let reused_pid = manager.get_process(&id).unwrap().pid.unwrap();
+        let runtime = manager.runtime_handle(&id).unwrap();
+        *runtime.pid_start_stamp.lock() = Some("0".to_string());
+        let error = manager.reload_process_config(&id).unwrap_err();
+        assert!(error.to_string().contains("is not running"), "{error}");
+
+        manager.start_process(&id).unwrap();
+        let status = manager.get_process(&id).unwrap();
+        assert!(status.is_running);
+        assert_ne!(status.pid, Some(reused_pid));
+        // The process behind the reused pid is left alone
+        assert!(is_process_running(reused_pid as i32));
+
+        manager.stop_process(&id).unwrap();
+        unsafe { libc::kill(reused_pid as i32, libc::SIGKILL) };
+        process::try_reap(reused_pid as i32);
     }
 }
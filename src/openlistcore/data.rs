@@ -1,50 +1,534 @@
+use super::{events::EventLog, run_overrides::RunOverrides, secrets::SecretStore};
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{
-    Arc,
-    atomic::{AtomicBool, AtomicI32},
+    Arc, Weak,
+    atomic::{AtomicBool, AtomicI32, AtomicU32, AtomicU64},
 };
+use std::time::{Duration, Instant, SystemTime};
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ProcessConfig {
     pub id: String,
     pub name: String,
+    /// Empty to use `default_bin_path` of the settings.
+    #[serde(default)]
     pub bin_path: String,
     pub args: Vec<String>,
     pub log_file: String,
     pub working_dir: Option<String>,
     pub env_vars: Option<HashMap<String, String>>,
+    #[serde(default)]
+    pub env_inheritance: EnvInheritance,
     pub auto_restart: bool,
+    /// Narrows `auto_restart` down to some exit codes; unset restarts after
+    /// any exit.
+    #[serde(default)]
+    pub restart_on_exit_codes: Option<RestartExitCodes>,
     pub auto_start: bool,
     pub run_as_admin: bool,
+    #[serde(default)]
+    pub kill_sequence: Option<Vec<KillStep>>,
+    #[serde(default)]
+    pub strict_arch_check: bool,
+    #[serde(default)]
+    pub readiness_check: Option<ReadinessCheck>,
+    #[serde(default)]
+    pub readiness_timeout_secs: Option<u64>,
+    /// Seconds after spawning before the first readiness probe; the run
+    /// stays starting meanwhile.
+    #[serde(default)]
+    pub warmup_secs: Option<u64>,
+    #[serde(default)]
+    pub on_ready_command: Option<String>,
+    /// Must pass, possibly after retries, before the ready run counts as
+    /// running; exhausting its retries fails the start.
+    #[serde(default)]
+    pub startup_probe: Option<StartupProbe>,
+    /// Run periodically once the process is ready; a nonzero exit counts as
+    /// a failed health check.
+    #[serde(default)]
+    pub health_command: Option<String>,
+    #[serde(default)]
+    pub health_interval_secs: Option<u64>,
+    #[serde(default)]
+    pub health_failure_threshold: Option<u32>,
+    #[serde(default)]
+    pub log_retention_days: Option<u32>,
+    #[serde(default)]
+    pub run_as_user: Option<String>,
+    #[serde(default)]
+    pub run_as_group: Option<String>,
+    #[serde(default)]
+    pub restart_on_change: Vec<String>,
+    /// Regular expressions; a new log line matching one restarts the process.
+    #[serde(default)]
+    pub restart_on_log_pattern: Vec<String>,
+    /// Position in `list_processes`, lower first. Set by `reorder_processes`.
+    #[serde(default)]
+    pub order: u32,
+    #[serde(default)]
+    pub bin_url: Option<String>,
+    #[serde(default)]
+    pub bin_sha256: Option<String>,
+    #[serde(default)]
+    pub start_condition_command: Option<String>,
+    #[serde(default)]
+    pub launch_wrapper: Vec<String>,
+    #[serde(default)]
+    pub listen_address_probe: Option<ListenAddressProbe>,
+    #[serde(default)]
+    pub stderr_log_file: Option<String>,
+    /// Unix only: run on a pseudo-terminal, for programs that act differently
+    /// without one. Its output, stderr included, goes to `log_file`.
+    #[serde(default)]
+    pub allocate_pty: bool,
+    /// Unix only: start in a session of its own, away from the terminal and
+    /// process group of the service, so a Ctrl-C there does not reach it.
+    #[serde(default = "default_new_session")]
+    pub new_session: bool,
+    /// Prefix every line of output with the time the service received it.
+    #[serde(default)]
+    pub log_timestamps: Option<LogTimestampFormat>,
+    /// Tag log lines with a level guessed from keywords such as `ERROR`, for
+    /// filtering the logs of programs that do not write structured levels.
+    #[serde(default)]
+    pub infer_log_level: bool,
+    /// Linux only: CPU scheduling policy the process starts with.
+    #[serde(default)]
+    pub sched_policy: Option<SchedPolicy>,
+    /// Linux only: IO scheduling class and level the process starts with.
+    #[serde(default)]
+    pub io_priority: Option<IoPriority>,
+    /// Emit `restart_threshold_exceeded` once `restart_count` reaches this.
+    #[serde(default)]
+    pub restart_alert_threshold: Option<u32>,
+    /// At most this many restarts per `restart_rate_window_secs`, counting
+    /// every kind of restart: auto-restarts, file-watch and manual ones.
+    #[serde(default)]
+    pub restart_rate_limit: Option<u32>,
+    #[serde(default)]
+    pub restart_rate_window_secs: Option<u64>,
+    /// Stop the process once it has run this long, to catch hangs; an
+    /// auto-restart process is then restarted like after a crash.
+    #[serde(default)]
+    pub max_runtime_secs: Option<u64>,
+    /// Keep running when the service exits under the `stop_non_detached`
+    /// `on_exit` policy.
+    #[serde(default)]
+    pub detached: bool,
+    /// The program rereads its configuration on SIGHUP, so
+    /// `reload_process_config` signals it instead of restarting it.
+    #[serde(default)]
+    pub supports_sighup_reload: bool,
+    /// Ids of the processes this one needs running. `restart_all_running`
+    /// starts them before it and stops them after it.
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// At most one process of the same group runs at a time.
+    #[serde(default)]
+    pub mutex_group: Option<String>,
+    #[serde(default)]
+    pub mutex_policy: MutexPolicy,
+    /// Free-form data of integrators, e.g. a UI group color. Stored and
+    /// returned as is, never interpreted by the service.
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
     pub created_at: u64,
     pub updated_at: u64,
 }
 
+fn default_new_session() -> bool {
+    true
+}
+
+/// Which of the service's own environment variables a child starts with,
+/// before its `env_vars` are applied.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EnvInheritance {
+    #[default]
+    Inherit,
+    Clear,
+    Allowlist(Vec<String>),
+}
+
+/// Which exits `auto_restart` restarts a process after. An exit by signal
+/// has no code, so it is restarted under `except` but not under `only`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartExitCodes {
+    /// Restart only after exiting with one of these codes.
+    Only(Vec<i32>),
+    /// Restart after any exit but these, e.g. `[0, 2]` meaning "done".
+    Except(Vec<i32>),
+}
+
+/// How to tell that a freshly spawned process is ready to serve.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ReadinessCheck {
+    Tcp { address: String },
+    Http { url: String },
+}
+
+/// A one-time check run once a process is ready, e.g. whether its database
+/// is migrated, retried every `delay_secs` up to `retries` times.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct StartupProbe {
+    pub command: String,
+    #[serde(default = "default_startup_probe_retries")]
+    pub retries: u32,
+    #[serde(default = "default_startup_probe_delay_secs")]
+    pub delay_secs: u64,
+}
+
+fn default_startup_probe_retries() -> u32 {
+    3
+}
+
+fn default_startup_probe_delay_secs() -> u64 {
+    5
+}
+
+/// How to find out which address a started process actually listens on.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ListenAddressProbe {
+    /// Look for `pattern` in new log lines; `{address}` marks the address.
+    LogPattern { pattern: String },
+    /// Read the process's listening TCP sockets (Linux only).
+    Sockets,
+}
+
+/// What happens to the running processes when the service exits.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitPolicy {
+    #[default]
+    StopAll,
+    /// Leave every process running, e.g. across an update of the service.
+    LeaveRunning,
+    /// Stop all but the processes marked `detached`.
+    StopNonDetached,
+}
+
+/// What starting a process does while another of its `mutex_group` runs.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MutexPolicy {
+    /// Fail the start.
+    #[default]
+    Reject,
+    /// Stay starting until the group is free, then launch.
+    Wait,
+}
+
+/// How `log_timestamps` writes the time a line was received, in UTC.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogTimestampFormat {
+    /// `2025-01-31T08:15:42.123Z`
+    Rfc3339,
+    /// `2025-01-31 08:15:42.123`
+    Datetime,
+    /// Milliseconds since the Unix epoch.
+    UnixMillis,
+}
+
+/// Severity of a log line, from the most to the least severe.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+/// Linux CPU scheduling policy, see sched(7). `other` is the normal one.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SchedPolicy {
+    Other,
+    /// For CPU-bound work that should not preempt interactive processes.
+    Batch,
+    /// Only runs when nothing else wants the CPU.
+    Idle,
+}
+
+/// Linux IO scheduling class and level within it, see ioprio_set(2).
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct IoPriority {
+    pub class: IoPriorityClass,
+    /// 0 (highest) to 7 (lowest); ignored by the `idle` class.
+    #[serde(default = "default_io_priority_level")]
+    pub level: u8,
+}
+
+/// Level the kernel gives best-effort processes without an explicit one.
+pub const DEFAULT_IO_PRIORITY_LEVEL: u8 = 4;
+/// Lowest priority level of the realtime and best-effort classes.
+pub const MAX_IO_PRIORITY_LEVEL: u8 = 7;
+
+fn default_io_priority_level() -> u8 {
+    DEFAULT_IO_PRIORITY_LEVEL
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IoPriorityClass {
+    /// Served before everything else; needs root.
+    Realtime,
+    BestEffort,
+    /// Only served when no other process needs the disk.
+    Idle,
+}
+
+/// One step of a stop sequence: send `signal`, then wait up to `wait_ms` for the
+/// process to exit before moving on to the next step.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct KillStep {
+    pub signal: String,
+    pub wait_ms: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessState {
+    #[default]
+    Stopped,
+    Starting,
+    Running,
+    /// Auto-restart gave up after repeated crashes, see `last_error`.
+    Failed,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ProcessStatus {
     pub id: String,
     pub name: String,
+    pub state: ProcessState,
     pub is_running: bool,
     pub pid: Option<u32>,
     pub started_at: Option<u64>,
     pub restart_count: u32,
     pub last_exit_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub next_restart_at: Option<u64>,
+    /// Address the current run was found listening on by `listen_address_probe`.
+    pub listen_address: Option<String>,
+    /// Stopped by an operator with a hold: only an explicit start brings it back.
+    pub held: bool,
+    /// The running process's command line as read from the OS, next to the
+    /// configured `bin_path` and `args`. Only looked up on request.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_command_line: Option<Vec<String>>,
+    /// The process runs with another command or environment than `config`
+    /// now describes, so a restart is needed to apply the changes.
+    pub config_drifted: bool,
     pub config: ProcessConfig,
 }
 
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessSortBy {
+    #[default]
+    Name,
+    Order,
+    CreatedAt,
+    State,
+    Uptime,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SafeModeRequest {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct GlobalPreStartHookRequest {
+    /// `None` or a blank command removes the hook.
+    pub command: Option<String>,
+}
+
+/// Something noteworthy that happened to a process, kept for `/api/v1/events`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServiceEvent {
+    /// `restart_count` reached the process's `restart_alert_threshold`.
+    RestartThresholdExceeded {
+        id: String,
+        name: String,
+        restart_count: u32,
+        threshold: u32,
+        timestamp: u64,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct EventRecord {
+    pub seq: u64,
+    #[serde(flatten)]
+    pub event: ServiceEvent,
+}
+
+/// Runtime history of a process that is kept across service restarts.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct ProcessStats {
+    pub restart_count: u32,
+}
+
+/// Just enough to answer "is everything up?", e.g. for a tray icon.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct HealthSummary {
+    pub all_running: bool,
+    pub any_failed: bool,
+    pub running: usize,
+    pub total: usize,
+}
+
+/// Restarts across all processes, for a fleet-level view next to the
+/// per-process `restart_count`.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct ReliabilityReport {
+    pub total_restarts: u64,
+    /// Ids of processes that crashed since their last stable run and have not
+    /// had one since, including those auto-restart gave up on.
+    pub crash_looping: Vec<String>,
+    /// `None` while no process has been restarted.
+    pub most_restarted: Option<ProcessRestarts>,
+    /// Mean uptime of the running processes, `None` if none is running.
+    pub average_uptime_secs: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct ProcessRestarts {
+    pub id: String,
+    pub name: String,
+    pub restart_count: u32,
+}
+
+/// Page size used when a paged listing does not ask for one.
+pub const DEFAULT_PAGE_SIZE: usize = 50;
+
+/// Hits per process returned by a log search that does not ask for a number.
+pub const DEFAULT_SEARCH_MATCHES_PER_PROCESS: usize = 100;
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ProcessPage {
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub items: Vec<ProcessStatus>,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CreateProcessRequest {
     pub name: String,
-    pub bin_path: String,
+    /// Falls back to `default_bin_path` of the settings when unset.
+    pub bin_path: Option<String>,
     pub args: Option<Vec<String>>,
     pub log_file: Option<String>,
     pub working_dir: Option<String>,
     pub env_vars: Option<HashMap<String, String>>,
+    pub env_inheritance: Option<EnvInheritance>,
     pub auto_restart: Option<bool>,
+    pub restart_on_exit_codes: Option<RestartExitCodes>,
     pub auto_start: Option<bool>,
     pub run_as_admin: Option<bool>,
+    pub kill_sequence: Option<Vec<KillStep>>,
+    pub strict_arch_check: Option<bool>,
+    pub readiness_check: Option<ReadinessCheck>,
+    pub readiness_timeout_secs: Option<u64>,
+    pub warmup_secs: Option<u64>,
+    pub on_ready_command: Option<String>,
+    pub startup_probe: Option<StartupProbe>,
+    pub health_command: Option<String>,
+    pub health_interval_secs: Option<u64>,
+    pub health_failure_threshold: Option<u32>,
+    pub log_retention_days: Option<u32>,
+    pub run_as_user: Option<String>,
+    pub run_as_group: Option<String>,
+    pub restart_on_change: Option<Vec<String>>,
+    pub restart_on_log_pattern: Option<Vec<String>>,
+    pub bin_url: Option<String>,
+    pub bin_sha256: Option<String>,
+    pub start_condition_command: Option<String>,
+    pub launch_wrapper: Option<Vec<String>>,
+    pub listen_address_probe: Option<ListenAddressProbe>,
+    pub stderr_log_file: Option<String>,
+    pub allocate_pty: Option<bool>,
+    pub new_session: Option<bool>,
+    pub log_timestamps: Option<LogTimestampFormat>,
+    pub infer_log_level: Option<bool>,
+    pub sched_policy: Option<SchedPolicy>,
+    pub io_priority: Option<IoPriority>,
+    pub restart_alert_threshold: Option<u32>,
+    pub restart_rate_limit: Option<u32>,
+    pub restart_rate_window_secs: Option<u64>,
+    pub max_runtime_secs: Option<u64>,
+    pub detached: Option<bool>,
+    pub supports_sighup_reload: Option<bool>,
+    pub depends_on: Option<Vec<String>>,
+    pub mutex_group: Option<String>,
+    pub mutex_policy: Option<MutexPolicy>,
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ReorderProcessesRequest {
+    pub ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct SetAutoRestartRequest {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ApplyEnvRequest {
+    pub env_vars: HashMap<String, String>,
+    /// Restart the process if it is running, so the new values apply now.
+    #[serde(default)]
+    pub restart: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct StartWithOverridesRequest {
+    /// `KEY=VALUE` environment variables and extra arguments for this run.
+    pub overrides: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ExportLogsRequest {
+    /// Where to write the `.tar.gz` archive.
+    pub dest: String,
+    #[serde(default)]
+    pub redact: bool,
+}
+
+/// One entry of a batch update: the process to change and what to change.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ProcessUpdate {
+    pub id: String,
+    #[serde(flatten)]
+    pub request: UpdateProcessRequest,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct UpdateProcessesRequest {
+    pub updates: Vec<ProcessUpdate>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -55,11 +539,54 @@ pub struct UpdateProcessRequest {
     pub log_file: Option<String>,
     pub working_dir: Option<String>,
     pub env_vars: Option<HashMap<String, String>>,
+    pub env_inheritance: Option<EnvInheritance>,
     pub auto_restart: Option<bool>,
+    pub restart_on_exit_codes: Option<RestartExitCodes>,
     pub auto_start: Option<bool>,
     pub run_as_admin: Option<bool>,
+    pub kill_sequence: Option<Vec<KillStep>>,
+    pub strict_arch_check: Option<bool>,
+    pub readiness_check: Option<ReadinessCheck>,
+    pub readiness_timeout_secs: Option<u64>,
+    pub warmup_secs: Option<u64>,
+    pub on_ready_command: Option<String>,
+    pub startup_probe: Option<StartupProbe>,
+    pub health_command: Option<String>,
+    pub health_interval_secs: Option<u64>,
+    pub health_failure_threshold: Option<u32>,
+    pub log_retention_days: Option<u32>,
+    pub run_as_user: Option<String>,
+    pub run_as_group: Option<String>,
+    pub restart_on_change: Option<Vec<String>>,
+    pub restart_on_log_pattern: Option<Vec<String>>,
+    pub bin_url: Option<String>,
+    pub bin_sha256: Option<String>,
+    pub start_condition_command: Option<String>,
+    pub launch_wrapper: Option<Vec<String>>,
+    pub listen_address_probe: Option<ListenAddressProbe>,
+    pub stderr_log_file: Option<String>,
+    pub allocate_pty: Option<bool>,
+    pub new_session: Option<bool>,
+    pub log_timestamps: Option<LogTimestampFormat>,
+    pub infer_log_level: Option<bool>,
+    pub sched_policy: Option<SchedPolicy>,
+    pub io_priority: Option<IoPriority>,
+    pub restart_alert_threshold: Option<u32>,
+    pub restart_rate_limit: Option<u32>,
+    pub restart_rate_window_secs: Option<u64>,
+    pub max_runtime_secs: Option<u64>,
+    pub detached: Option<bool>,
+    pub supports_sighup_reload: Option<bool>,
+    pub depends_on: Option<Vec<String>>,
+    pub mutex_group: Option<String>,
+    pub mutex_policy: Option<MutexPolicy>,
+    pub metadata: Option<HashMap<String, String>>,
 }
 
+/// A partial process configuration to create processes from, holding only
+/// the fields it sets.
+pub type ProcessTemplate = serde_json::Map<String, serde_json::Value>;
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct StartProcessRequest {
     pub id: String,
@@ -85,6 +612,15 @@ pub struct LogResponse {
     pub log_content: String,
     pub total_lines: usize,
     pub fetched_lines: usize,
+    /// Size of `log_content`, at most the `max_bytes` asked for.
+    pub fetched_bytes: usize,
+    /// Set when more lines were asked for than `max_log_lines` allows.
+    pub clamped: bool,
+    /// Whether there is a log file at all; without one, and without
+    /// `ever_started`, the process simply never ran rather than ran silently.
+    pub log_file_exists: bool,
+    /// Whether the process was launched since the service started.
+    pub ever_started: bool,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -94,19 +630,368 @@ pub struct JsonResponse<T: Serialize> {
     pub data: Option<T>,
 }
 
+/// A place in a process log to resume following it from: `offset` bytes into
+/// the log file `file_id`, which identifies the file and so changes when the
+/// log is rotated.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct LogPosition {
+    pub file_id: u64,
+    pub offset: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct LogOffsetResponse {
+    pub id: String,
+    pub path: String,
+    /// The current end of the log, where following it would start.
+    pub position: LogPosition,
+}
+
+/// A log line found by `search_all_logs`; `line_number` counts from 1.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct LogSearchHit {
+    pub id: String,
+    pub name: String,
+    pub line_number: usize,
+    pub line: String,
+}
+
+/// Which output stream a log line came from. `Combined` lines come from a
+/// log that both streams write to.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+    Combined,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct TaggedLogLine {
+    pub stream: LogStream,
+    pub line: String,
+    /// Set for processes with `infer_log_level`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<LogLevel>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CombinedLogResponse {
+    pub id: String,
+    pub name: String,
+    pub lines: Vec<TaggedLogLine>,
+    pub total_lines: usize,
+    pub fetched_lines: usize,
+    pub clamped: bool,
+}
+
+/// The most recent warnings and errors of a process, newest first.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RecentErrorsResponse {
+    pub id: String,
+    pub name: String,
+    pub lines: Vec<TaggedLogLine>,
+    /// How many of the last log lines were looked through.
+    pub scanned_lines: usize,
+    pub fetched_lines: usize,
+    pub clamped: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct BatchFailure {
+    pub id: String,
+    pub error: String,
+}
+
+/// How a stop went: `graceful` is false when the process had to be killed
+/// outright. `signal_used` is the signal it finally exited on, if any was sent.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct StopOutcome {
+    pub graceful: bool,
+    pub signal_used: Option<String>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct BatchResult {
+    pub succeeded: Vec<String>,
+    pub failed: Vec<BatchFailure>,
+    /// Handed to the start queue, to be started in the background.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub queued: Vec<String>,
+}
+
+pub const DEFAULT_MAX_LOG_LINE_BYTES: usize = 64 * 1024;
+pub const DEFAULT_CONFIG_LOAD_RETRIES: u32 = 5;
+pub const DEFAULT_LOG_LINES: usize = 100;
+pub const DEFAULT_MAX_LOG_LINES: usize = 10_000;
+pub const DEFAULT_STOP_LOG_FLUSH_TIMEOUT_MS: u64 = 2_000;
+
+fn default_max_log_line_bytes() -> usize {
+    DEFAULT_MAX_LOG_LINE_BYTES
+}
+
+fn default_log_lines() -> usize {
+    DEFAULT_LOG_LINES
+}
+
+fn default_max_log_lines() -> usize {
+    DEFAULT_MAX_LOG_LINES
+}
+
+fn default_config_load_retries() -> u32 {
+    DEFAULT_CONFIG_LOAD_RETRIES
+}
+
+fn default_stop_log_flush_timeout_ms() -> u64 {
+    DEFAULT_STOP_LOG_FLUSH_TIMEOUT_MS
+}
+
+/// Service-wide settings, persisted separately from the process configurations.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ServiceSettings {
+    /// Lines longer than this are truncated when process logs are read.
+    #[serde(default = "default_max_log_line_bytes")]
+    pub max_log_line_bytes: usize,
+    /// Lines returned by a log request that does not ask for a number.
+    #[serde(default = "default_log_lines")]
+    pub default_log_lines: usize,
+    /// Most lines a single log request returns; larger requests are clamped.
+    #[serde(default = "default_max_log_lines")]
+    pub max_log_lines: usize,
+    /// How many auto-started processes may be starting at once, 0 for no limit.
+    #[serde(default)]
+    pub max_concurrent_starts: usize,
+    /// Start an auto-start process whose binary was missing once it appears.
+    #[serde(default)]
+    pub retry_missing_binary: bool,
+    /// Ceiling for all process logs together, 0 for no limit. Housekeeping
+    /// evicts the oldest rotated segments to stay under it.
+    #[serde(default)]
+    pub max_total_log_bytes: u64,
+    /// How often a failure to read the process configurations at startup is
+    /// retried, with backoff, before starting without them.
+    #[serde(default = "default_config_load_retries")]
+    pub config_load_retries: u32,
+    /// Set by `enter_maintenance`: nothing may start until it is cleared.
+    #[serde(default)]
+    pub maintenance: bool,
+    /// Processes that entering maintenance stopped, started again on exit.
+    #[serde(default)]
+    pub maintenance_stopped: Vec<String>,
+    /// Which processes the service stops when it exits.
+    #[serde(default)]
+    pub on_exit: ExitPolicy,
+    /// Shell command run before every process start; a failure aborts it.
+    #[serde(default)]
+    pub global_pre_start_hook: Option<String>,
+    /// Spread periodic work such as health polls by up to this many percent
+    /// of its interval either way, 0 to keep exact intervals.
+    #[serde(default)]
+    pub jitter_percent: u32,
+    /// Binary of the processes that leave their `bin_path` empty, so that
+    /// instances of the same program upgrade together.
+    #[serde(default)]
+    pub default_bin_path: Option<String>,
+    /// How long a stop waits for the last output of the process to reach
+    /// its logs before returning, 0 to return right away.
+    #[serde(default = "default_stop_log_flush_timeout_ms")]
+    pub stop_log_flush_timeout_ms: u64,
+    /// Wait for the exits of all processes and capture their startup output
+    /// on shared threads instead of threads per process. Applies to
+    /// processes started afterwards. Only these two are shared: the
+    /// readiness wait of each start and the health, log, file and listen
+    /// address watchers of each process keep threads of their own.
+    #[serde(default)]
+    pub shared_monitor_threads: bool,
+}
+
+impl Default for ServiceSettings {
+    fn default() -> Self {
+        Self {
+            max_log_line_bytes: DEFAULT_MAX_LOG_LINE_BYTES,
+            default_log_lines: DEFAULT_LOG_LINES,
+            max_log_lines: DEFAULT_MAX_LOG_LINES,
+            max_concurrent_starts: 0,
+            retry_missing_binary: false,
+            max_total_log_bytes: 0,
+            config_load_retries: DEFAULT_CONFIG_LOAD_RETRIES,
+            maintenance: false,
+            maintenance_stopped: Vec::new(),
+            on_exit: ExitPolicy::StopAll,
+            global_pre_start_hook: None,
+            jitter_percent: 0,
+            default_bin_path: None,
+            stop_log_flush_timeout_ms: DEFAULT_STOP_LOG_FLUSH_TIMEOUT_MS,
+            shared_monitor_threads: false,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct UpdateServiceSettingsRequest {
+    pub max_log_line_bytes: Option<usize>,
+    pub default_log_lines: Option<usize>,
+    pub max_log_lines: Option<usize>,
+    pub max_concurrent_starts: Option<usize>,
+    pub retry_missing_binary: Option<bool>,
+    pub max_total_log_bytes: Option<u64>,
+    pub config_load_retries: Option<u32>,
+    pub on_exit: Option<ExitPolicy>,
+    pub jitter_percent: Option<u32>,
+    /// An empty path clears it.
+    pub default_bin_path: Option<String>,
+    pub stop_log_flush_timeout_ms: Option<u64>,
+    pub shared_monitor_threads: Option<bool>,
+}
+
+/// The service's own footprint. `thread_count` is only known on Linux.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ServiceResources {
+    pub pid: u32,
+    pub cpu_usage_percent: f32,
+    pub memory_bytes: u64,
+    pub thread_count: Option<usize>,
+    pub monitor_threads: usize,
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct VersionResponse {
     pub service: String,
     pub version: String,
 }
 
-#[derive(Debug)]
+/// How the config file on disk differs from the configs in memory: `added`
+/// processes exist only on disk, `removed` ones only in memory.
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+pub struct ConfigDiff {
+    pub added: Vec<ProcessConfig>,
+    pub removed: Vec<ProcessConfig>,
+    pub modified: Vec<ProcessConfigChange>,
+}
+
+/// Where the config file is and when it last changed. `last_modified` is
+/// unset while there is no file; `last_written_by_service` is false once
+/// anything but the service itself wrote it since it was last saved.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ConfigMetadata {
+    pub path: String,
+    pub last_modified: Option<u64>,
+    pub last_written_by_service: bool,
+}
+
+/// Everything that makes up the service apart from running processes, for
+/// backups and moving to another machine, see `CoreManager::snapshot`.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ServiceSnapshot {
+    pub created_at: u64,
+    pub settings: ServiceSettings,
+    pub processes: Vec<ProcessConfig>,
+    pub stats: BTreeMap<String, ProcessStats>,
+    pub templates: BTreeMap<String, ProcessTemplate>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct RestoreSnapshotRequest {
+    pub snapshot: ServiceSnapshot,
+    /// Start every restored process once restored.
+    #[serde(default)]
+    pub start: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ProcessConfigChange {
+    pub id: String,
+    pub name: String,
+    pub fields: Vec<FieldChange>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub current: serde_json::Value,
+    pub on_disk: serde_json::Value,
+}
+
+/// What `reconcile_state` fixed: processes that had no runtime state, and
+/// runtime states left behind without a process, with the pids of those that
+/// were still running and got stopped.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct ReconcileReport {
+    pub added_runtime: Vec<String>,
+    pub removed_runtime: Vec<String>,
+    pub stopped_pids: Vec<i32>,
+}
+
+/// A problem found by `validate_all`. `process_id` is `None` for issues that
+/// involve several processes.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct ValidationIssue {
+    pub process_id: Option<String>,
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CheckResult {
+    pub name: String,
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+/// Cloning a runtime yields another handle to the same shared state, which is
+/// how background watchers observe and update a process.
+#[derive(Debug, Clone)]
 pub struct ProcessRuntime {
     pub is_running: Arc<AtomicBool>,
     pub running_pid: Arc<AtomicI32>,
     pub started_at: Arc<Mutex<Option<u64>>>,
     pub restart_count: Arc<AtomicI32>,
     pub last_exit_code: Arc<AtomicI32>,
+    pub state: Arc<Mutex<ProcessState>>,
+    /// Bumped on every start and stop so watchers of an older run can bail out.
+    pub start_generation: Arc<AtomicU64>,
+    pub last_error: Arc<Mutex<Option<String>>>,
+    /// Crashes since the last run that stayed up long enough to count as stable.
+    pub consecutive_failures: Arc<AtomicU32>,
+    /// When the monitor will next try to restart the process, if it is backing off.
+    pub restart_at: Arc<Mutex<Option<u64>>>,
+    /// `log_file` with its placeholders resolved for the current or last run.
+    pub log_path: Arc<Mutex<Option<String>>>,
+    /// `stderr_log_file` resolved the same way, if stderr is logged separately.
+    pub stderr_log_path: Arc<Mutex<Option<String>>>,
+    /// Set when auto-start found the binary missing; cleared on start or stop.
+    pub awaiting_binary: Arc<AtomicBool>,
+    /// Set while a start waits for its start condition or binary download.
+    pub preparing: Arc<AtomicBool>,
+    /// Address found by the listen address probe for the current run.
+    pub listen_address: Arc<Mutex<Option<String>>>,
+    /// Set by `stop_process_and_hold`: nothing starts the process on its own
+    /// until it is started explicitly.
+    pub held: Arc<AtomicBool>,
+    /// The first lines logged by the current or last run.
+    pub startup_output: Arc<Mutex<StartupOutput>>,
+    /// How the last run ended, or why the last start did not get one going.
+    pub stop_cause: Arc<Mutex<Option<StopCause>>>,
+    /// The `restart_rate_limit` bucket, set up by the first restart.
+    pub restart_tokens: Arc<Mutex<Option<RestartTokens>>>,
+    /// Overrides of the next run, taken when it is spawned.
+    pub run_overrides: Arc<Mutex<Option<RunOverrides>>>,
+    /// The config the current or last run was spawned with.
+    pub spawned_command: Arc<Mutex<Option<SpawnedCommand>>>,
+    /// When a line matching `restart_on_log_pattern` last restarted it.
+    pub log_pattern_restarted_at: Arc<Mutex<Option<Instant>>>,
+    /// When the process behind `running_pid` started, to tell it from a
+    /// later one reusing the pid, see `process::process_start_stamp`.
+    pub pid_start_stamp: Arc<Mutex<Option<String>>>,
 }
 
 impl Default for ProcessRuntime {
@@ -117,10 +1002,128 @@ impl Default for ProcessRuntime {
             started_at: Arc::new(Mutex::new(None)),
             restart_count: Arc::new(AtomicI32::new(0)),
             last_exit_code: Arc::new(AtomicI32::new(0)),
+            state: Arc::new(Mutex::new(ProcessState::Stopped)),
+            start_generation: Arc::new(AtomicU64::new(0)),
+            last_error: Arc::new(Mutex::new(None)),
+            consecutive_failures: Arc::new(AtomicU32::new(0)),
+            restart_at: Arc::new(Mutex::new(None)),
+            log_path: Arc::new(Mutex::new(None)),
+            stderr_log_path: Arc::new(Mutex::new(None)),
+            awaiting_binary: Arc::new(AtomicBool::new(false)),
+            preparing: Arc::new(AtomicBool::new(false)),
+            listen_address: Arc::new(Mutex::new(None)),
+            held: Arc::new(AtomicBool::new(false)),
+            startup_output: Arc::new(Mutex::new(StartupOutput::default())),
+            stop_cause: Arc::new(Mutex::new(None)),
+            restart_tokens: Arc::new(Mutex::new(None)),
+            run_overrides: Arc::new(Mutex::new(None)),
+            spawned_command: Arc::new(Mutex::new(None)),
+            log_pattern_restarted_at: Arc::new(Mutex::new(None)),
+            pid_start_stamp: Arc::new(Mutex::new(None)),
         }
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopCause {
+    /// `stop_process` was called.
+    Manual,
+    /// The process exited on its own, with this code if it was not killed by
+    /// a signal.
+    Exited(Option<i32>),
+    StartFailed(String),
+    /// `start_condition_command` refused the start, for this reason.
+    ConditionRefused(String),
+    /// Stopped by the monitor after running past `max_runtime_secs`.
+    RuntimeExceeded(u64),
+}
+
+/// Why a process is not running, see `CoreManager::not_running_reason`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum NotRunningReason {
+    /// Not started since the service started.
+    NeverStarted,
+    /// An auto-start process that safe mode kept from starting.
+    SafeMode,
+    /// The service is in maintenance mode, nothing may start.
+    Maintenance,
+    /// A start is waiting for its start condition or binary download.
+    Starting,
+    /// Auto-start found the binary missing and waits for it to appear.
+    AwaitingBinary,
+    /// Stopped through the API; `held` if it was stopped with `hold`.
+    StoppedManually {
+        held: bool,
+    },
+    /// Exited on its own; `restart_at` is set while an auto-restart is pending.
+    Exited {
+        exit_code: Option<i32>,
+        restart_at: Option<u64>,
+    },
+    /// Stopped after running longer than `max_runtime_secs`; `restart_at` is
+    /// set while an auto-restart is pending.
+    RuntimeExceeded {
+        max_runtime_secs: u64,
+        restart_at: Option<u64>,
+    },
+    /// Auto-restart gave up after repeated crashes.
+    CrashLoop {
+        message: String,
+    },
+    StartFailed {
+        error: String,
+    },
+    /// `start_condition_command` refused the last start, as `message` says.
+    ConditionNotMet {
+        message: String,
+    },
+}
+
+/// The parts of a process's config that decide what it runs and with which
+/// environment, as of a spawn, to tell when the config has changed since.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpawnedCommand {
+    pub bin_path: String,
+    pub args: Vec<String>,
+    pub launch_wrapper: Vec<String>,
+    pub working_dir: Option<String>,
+    pub env_vars: Option<HashMap<String, String>>,
+    pub env_inheritance: EnvInheritance,
+}
+
+impl SpawnedCommand {
+    pub fn of(config: &ProcessConfig) -> Self {
+        Self {
+            bin_path: config.bin_path.clone(),
+            args: config.args.clone(),
+            launch_wrapper: config.launch_wrapper.clone(),
+            working_dir: config.working_dir.clone(),
+            env_vars: config.env_vars.clone(),
+            env_inheritance: config.env_inheritance.clone(),
+        }
+    }
+}
+
+/// Output captured from the start of a run, for processes that exit right
+/// away and leave little to see at the end of their log.
+#[derive(Debug, Default, Clone)]
+pub struct StartupOutput {
+    /// The run the lines belong to; a capture for an older one is dropped.
+    pub generation: u64,
+    pub lines: Vec<String>,
+}
+
+/// A token bucket holding up to `limit` restarts, refilled evenly over
+/// `window`, see `restart_limit`.
+#[derive(Debug, Clone, Copy)]
+pub struct RestartTokens {
+    pub limit: u32,
+    pub window: Duration,
+    pub tokens: f64,
+    pub refilled_at: Instant,
+}
+
 #[derive(Default, Debug)]
 pub struct ProcessManager {
     pub processes: Arc<Mutex<HashMap<String, ProcessConfig>>>,
@@ -129,8 +1132,30 @@ pub struct ProcessManager {
 
 pub struct CoreManager {
     pub process_manager: StatusInner<ProcessManager>,
+    pub settings: ServiceSettings,
+    /// Registered process templates by name.
+    pub templates: BTreeMap<String, ProcessTemplate>,
+    /// Resolves `keyring:` references in `env_vars` when a process starts.
+    pub secrets: Arc<dyn SecretStore>,
+    pub events: EventLog,
+    /// When restart counts first changed since they were last written to the
+    /// stats file, if they have.
+    pub stats_dirty_since: Option<Instant>,
+    /// Nothing starts or restarts on its own, and started processes are not
+    /// watched for readiness or file changes. Not persisted.
+    pub safe_mode: bool,
+    /// Modification time the config file had right after the service last
+    /// saved it, to tell its own writes from edits made by something else.
+    pub config_written_at: Mutex<Option<SystemTime>>,
+    /// The manager itself, for the background threads it starts. Dangling
+    /// unless it was created with `CoreManager::new_without_autostart`.
+    pub handle: ManagerHandle,
 }
 
+/// How background threads get back to the manager that started them without
+/// keeping it alive; they give up once it is dropped.
+pub type ManagerHandle = Weak<Mutex<CoreManager>>;
+
 pub struct StatusInner<T> {
     pub inner: Mutex<T>,
 }
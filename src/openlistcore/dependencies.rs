@@ -0,0 +1,98 @@
+use super::data::ProcessConfig;
+use std::collections::{HashMap, HashSet};
+
+/// Orders `configs` so that every process comes after the processes it
+/// `depends_on`, keeping their given order where dependencies allow.
+/// Dependencies on processes missing from `configs` are ignored. Fails naming
+/// the processes of a dependency cycle.
+pub fn start_order<'a>(configs: &[&'a ProcessConfig]) -> Result<Vec<&'a ProcessConfig>, String> {
+    let by_id: HashMap<&str, &ProcessConfig> = configs
+        .iter()
+        .map(|config| (config.id.as_str(), *config))
+        .collect();
+    let mut visited = HashSet::new();
+    let mut ordered = Vec::with_capacity(configs.len());
+    for config in configs {
+        visit(config, &by_id, &mut Vec::new(), &mut visited, &mut ordered)?;
+    }
+    Ok(ordered)
+}
+
+fn visit<'a>(
+    config: &'a ProcessConfig,
+    by_id: &HashMap<&str, &'a ProcessConfig>,
+    path: &mut Vec<&'a str>,
+    visited: &mut HashSet<&'a str>,
+    ordered: &mut Vec<&'a ProcessConfig>,
+) -> Result<(), String> {
+    if visited.contains(config.id.as_str()) {
+        return Ok(());
+    }
+    if let Some(start) = path.iter().position(|id| *id == config.id) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(&config.id);
+        return Err(format!("Dependency cycle: {}", cycle.join(" -> ")));
+    }
+
+    path.push(&config.id);
+    for dependency in &config.depends_on {
+        if let Some(dependency) = by_id.get(dependency.as_str()) {
+            visit(dependency, by_id, path, visited, ordered)?;
+        }
+    }
+    path.pop();
+
+    visited.insert(&config.id);
+    ordered.push(config);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn config(id: &str, depends_on: &[&str]) -> ProcessConfig {
+        serde_json::from_value(json!({
+            "id": id,
+            "name": id,
+            "bin_path": "/bin/true",
+            "args": [],
+            "log_file": "process.log",
+            "auto_restart": false,
+            "auto_start": false,
+            "run_as_admin": false,
+            "depends_on": depends_on,
+            "created_at": 0,
+            "updated_at": 0,
+        }))
+        .unwrap()
+    }
+
+    fn ids(configs: &[&ProcessConfig]) -> Vec<String> {
+        configs.iter().map(|config| config.id.clone()).collect()
+    }
+
+    #[test]
+    fn dependencies_come_first_and_the_rest_keeps_its_order() {
+        let web = config("web", &["db", "cache"]);
+        let worker = config("worker", &["db", "missing"]);
+        let db = config("db", &[]);
+        let cache = config("cache", &["db"]);
+        let ordered = start_order(&[&web, &worker, &db, &cache]).unwrap();
+        assert_eq!(ids(&ordered), ["db", "cache", "web", "worker"]);
+    }
+
+    #[test]
+    fn cycles_are_reported() {
+        let a = config("a", &["b"]);
+        let b = config("b", &["c"]);
+        let c = config("c", &["a"]);
+        assert_eq!(
+            start_order(&[&a, &b, &c]).unwrap_err(),
+            "Dependency cycle: a -> b -> c -> a"
+        );
+        let own = config("own", &["own"]);
+        assert!(start_order(&[&own]).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+use super::{data::ProcessConfig, process};
+use anyhow::{Context, Result, anyhow};
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Largest binary `bin_url` may serve; anything bigger is rejected.
+pub const MAX_DOWNLOAD_BYTES: u64 = 512 * 1024 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// Checks that `url` is an http(s) URL and `sha256` a hex SHA-256 digest.
+pub fn validate_source(url: &str, sha256: Option<&str>) -> Result<()> {
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Err(anyhow!("bin_url must be an http:// or https:// URL: {url}"));
+    }
+    let Some(sha256) = sha256 else {
+        return Err(anyhow!("bin_url requires bin_sha256"));
+    };
+    if sha256.len() != 64 || !sha256.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(anyhow!("bin_sha256 must be 64 hex characters"));
+    }
+    Ok(())
+}
+
+/// Whether starting `config` first has to download its binary.
+pub fn needs_download(config: &ProcessConfig) -> bool {
+    config.bin_url.is_some() && !Path::new(&config.bin_path).exists()
+}
+
+/// Downloads the binary from `bin_url` to `bin_path` when the latter is
+/// missing. The file only appears at `bin_path` once its hash has matched.
+pub fn ensure_binary(config: &ProcessConfig) -> Result<()> {
+    let Some(url) = &config.bin_url else {
+        return Ok(());
+    };
+    if !needs_download(config) {
+        return Ok(());
+    }
+    validate_source(url, config.bin_sha256.as_deref())?;
+    let expected = config.bin_sha256.as_deref().unwrap_or_default();
+
+    info!(
+        "Binary for {} missing at {}, downloading from {url}",
+        config.name, config.bin_path
+    );
+
+    let target = Path::new(&config.bin_path);
+    if let Some(parent) = target.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+
+    let partial = partial_path(target);
+    let result = download_to(url, &partial).and_then(|actual| {
+        if actual.eq_ignore_ascii_case(expected) {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "SHA-256 mismatch for {url}: expected {expected}, got {actual}"
+            ))
+        }
+    });
+    if let Err(e) = result {
+        let _ = fs::remove_file(&partial);
+        warn!("Discarded download of {url}: {e}");
+        return Err(e);
+    }
+
+    fs::rename(&partial, target)
+        .with_context(|| format!("Failed to move download into place: {}", config.bin_path))?;
+    process::ensure_executable_permissions(&config.bin_path)
+        .with_context(|| format!("Failed to set execute permissions for: {}", config.bin_path))?;
+
+    info!(
+        "Downloaded and verified binary for {} at {}",
+        config.name, config.bin_path
+    );
+    Ok(())
+}
+
+fn partial_path(target: &Path) -> PathBuf {
+    let mut name = target.file_name().unwrap_or_default().to_os_string();
+    name.push(".download");
+    target.with_file_name(name)
+}
+
+/// Streams `url` into `path`, returning the hex SHA-256 of what was written.
+fn download_to(url: &str, path: &Path) -> Result<String> {
+    let tls = native_tls::TlsConnector::new().context("Failed to initialise TLS")?;
+    let agent = ureq::AgentBuilder::new()
+        .tls_connector(Arc::new(tls))
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout(DOWNLOAD_TIMEOUT)
+        .build();
+    let response = agent
+        .get(url)
+        .call()
+        .map_err(|e| anyhow!("Failed to download {url}: {e}"))?;
+
+    if let Some(length) = response
+        .header("Content-Length")
+        .and_then(|v| v.parse::<u64>().ok())
+        && length > MAX_DOWNLOAD_BYTES
+    {
+        return Err(anyhow!(
+            "Download of {url} is {length} bytes, more than the {MAX_DOWNLOAD_BYTES} byte limit"
+        ));
+    }
+
+    let mut reader = response.into_reader().take(MAX_DOWNLOAD_BYTES + 1);
+    let mut file =
+        File::create(path).with_context(|| format!("Failed to create: {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut total = 0u64;
+    let mut buf = [0u8; 64 * 1024];
+    loop {
+        let n = reader
+            .read(&mut buf)
+            .with_context(|| format!("Failed to read download of {url}"))?;
+        if n == 0 {
+            break;
+        }
+        total += n as u64;
+        if total > MAX_DOWNLOAD_BYTES {
+            return Err(anyhow!(
+                "Download of {url} exceeds the {MAX_DOWNLOAD_BYTES} byte limit"
+            ));
+        }
+        hasher.update(&buf[..n]);
+        file.write_all(&buf[..n])
+            .with_context(|| format!("Failed to write: {}", path.display()))?;
+    }
+    file.sync_all()?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::openlistcore::{
+        data::{CoreManager, ProcessState},
+        test_support::{self, create_request, scratch_dir, wait_for},
+    };
+    use parking_lot::Mutex;
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        path::Path,
+        sync::mpsc,
+        time::Duration,
+    };
+
+    const SCRIPT: &[u8] = b"#!/bin/sh\nexec sleep 30\n";
+
+    /// Serves `body` once, after reporting the request and waiting for `release`.
+    fn serve_once(body: &'static [u8]) -> (String, mpsc::Receiver<()>, mpsc::Sender<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/tool", listener.local_addr().unwrap());
+        let (requested_tx, requested_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            requested_tx.send(()).unwrap();
+            let _ = release_rx.recv();
+            let mut stream = reader.into_inner();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            )
+            .unwrap();
+            stream.write_all(body).unwrap();
+        });
+        (url, requested_rx, release_tx)
+    }
+
+    fn create_downloaded(
+        manager: &Mutex<CoreManager>,
+        name: &str,
+        url: &str,
+        sha256: String,
+    ) -> (String, String) {
+        let dir = scratch_dir(name);
+        let bin_path = dir.join("bin").join("tool").to_string_lossy().to_string();
+        let request = create_request(
+            name,
+            &bin_path,
+            json!({
+                "bin_url": url,
+                "bin_sha256": sha256,
+                "log_file": dir.join("process.log"),
+            }),
+        );
+        let id = manager.lock().create_process(request).unwrap().id;
+        (id, bin_path)
+    }
+
+    #[test]
+    fn downloads_run_without_holding_the_manager() {
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        let (url, requested, release) = serve_once(SCRIPT);
+        let (id, bin_path) = create_downloaded(
+            &manager,
+            "download",
+            &url,
+            hex::encode(Sha256::digest(SCRIPT)),
+        );
+
+        manager.lock().start_process(&id).unwrap();
+        requested.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        // The download is stalled by the server, yet the manager stays usable
+        let mut core_manager = manager
+            .try_lock_for(Duration::from_secs(1))
+            .expect("manager is locked during the download");
+        let status = core_manager.get_process(&id).unwrap();
+        assert_eq!(status.state, ProcessState::Starting);
+        assert_eq!(status.pid, None);
+        // Another start joins the one in flight
+        core_manager.start_process(&id).unwrap();
+        drop(core_manager);
+
+        release.send(()).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || {
+            manager.lock().get_process(&id).unwrap().is_running
+        }));
+        assert!(Path::new(&bin_path).exists());
+        manager.lock().stop_process(&id).unwrap();
+    }
+
+    #[test]
+    fn failed_downloads_mark_the_process_failed() {
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        let (url, _requested, release) = serve_once(SCRIPT);
+        let (id, bin_path) = create_downloaded(&manager, "bad-hash", &url, "0".repeat(64));
+
+        manager.lock().start_process(&id).unwrap();
+        release.send(()).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || {
+            manager.lock().get_process(&id).unwrap().state == ProcessState::Failed
+        }));
+
+        let status = manager.lock().get_process(&id).unwrap();
+        assert!(!status.is_running);
+        assert!(
+            status
+                .last_error
+                .is_some_and(|e| e.contains("SHA-256 mismatch"))
+        );
+        assert!(!Path::new(&bin_path).exists());
+    }
+}
@@ -0,0 +1,39 @@
+use std::{fmt, time::Duration};
+
+/// Failures a caller may want to tell apart from the rest, e.g. to answer a
+/// timeout with 504. They travel inside `anyhow::Error` like any other error
+/// and are recognised with `downcast_ref::<CoreError>()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoreError {
+    /// The process did not become ready within its readiness timeout.
+    StartTimeout { elapsed: Duration },
+    /// The process was still running after its whole kill sequence.
+    StopTimeout { elapsed: Duration },
+    /// The process used up its `restart_rate_limit`; the next restart is
+    /// allowed after `retry_after`.
+    RestartRateLimited { retry_after: Duration },
+}
+
+impl fmt::Display for CoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoreError::StartTimeout { elapsed } => write!(
+                f,
+                "Process did not become ready within {}ms",
+                elapsed.as_millis()
+            ),
+            CoreError::StopTimeout { elapsed } => write!(
+                f,
+                "Process is still running after its kill sequence ({}ms)",
+                elapsed.as_millis()
+            ),
+            CoreError::RestartRateLimited { retry_after } => write!(
+                f,
+                "Restart rate limited, next restart allowed in {}ms",
+                retry_after.as_millis()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CoreError {}
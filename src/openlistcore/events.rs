@@ -0,0 +1,71 @@
+use super::data::{EventRecord, ServiceEvent};
+use log::warn;
+use std::collections::VecDeque;
+
+/// How many events are kept for `/api/v1/events`; older ones are dropped.
+pub const EVENT_LOG_CAPACITY: usize = 256;
+
+/// The most recent events, oldest first, each numbered so that pollers can
+/// ask for only what they have not seen yet.
+#[derive(Debug, Default)]
+pub struct EventLog {
+    events: VecDeque<EventRecord>,
+    next_seq: u64,
+}
+
+impl EventLog {
+    pub fn emit(&mut self, event: ServiceEvent) {
+        warn!("Event: {event:?}");
+        if self.events.len() == EVENT_LOG_CAPACITY {
+            self.events.pop_front();
+        }
+        self.events.push_back(EventRecord {
+            seq: self.next_seq,
+            event,
+        });
+        self.next_seq += 1;
+    }
+
+    /// Events numbered `since` or later, oldest first.
+    pub fn since(&self, since: u64) -> Vec<EventRecord> {
+        self.events
+            .iter()
+            .filter(|record| record.seq >= since)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(restart_count: u32) -> ServiceEvent {
+        ServiceEvent::RestartThresholdExceeded {
+            id: "p".to_string(),
+            name: "p".to_string(),
+            restart_count,
+            threshold: 1,
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn only_the_most_recent_events_are_kept() {
+        let mut log = EventLog::default();
+        for i in 0..EVENT_LOG_CAPACITY as u32 + 10 {
+            log.emit(event(i));
+        }
+        let all = log.since(0);
+        assert_eq!(all.len(), EVENT_LOG_CAPACITY);
+        assert_eq!((all[0].seq, &all[0].event), (10, &event(10)));
+        let last = EVENT_LOG_CAPACITY as u64 + 9;
+        let newest = log.since(last);
+        assert_eq!(newest.len(), 1);
+        assert_eq!(
+            (newest[0].seq, &newest[0].event),
+            (last, &event(last as u32))
+        );
+        assert!(log.since(last + 1).is_empty());
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::HashMap,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    sync::Arc,
+    thread,
+};
+
+use log::{debug, error, info};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use super::{data::ManagerHandle, monitor::MonitorThread, process};
+
+const MAX_EVENTS: usize = 64;
+
+static WATCHER: Lazy<Mutex<Option<Arc<Watcher>>>> = Lazy::new(|| Mutex::new(None));
+
+/// What the shared watcher does once a watched process exits.
+#[derive(Debug, Clone)]
+pub enum OnExit {
+    /// Run the supervisor of the manager, which handles the exit of a
+    /// managed process.
+    Supervise(ManagerHandle),
+    /// Only collect it, for a launch wrapper that is no longer tracked.
+    Reap,
+}
+
+/// Has the thread shared by all processes act on the exit of `pid`, starting
+/// it on first use. Waits on pidfds through epoll on Linux and on a kqueue on
+/// macOS. Fails if `pid` cannot be watched, e.g. when it already exited or
+/// the kernel lacks pidfd support, leaving it to the periodic poll.
+pub fn watch(pid: u32, on_exit: OnExit) -> io::Result<()> {
+    let watcher = {
+        let mut slot = WATCHER.lock();
+        match &*slot {
+            Some(watcher) => watcher.clone(),
+            None => {
+                let watcher = Arc::new(Watcher::new()?);
+                let running = watcher.clone();
+                thread::Builder::new()
+                    .name("exit-watcher".to_string())
+                    .spawn(move || running.run())?;
+                *slot = Some(watcher.clone());
+                watcher
+            }
+        }
+    };
+    watcher.add(pid, on_exit)
+}
+
+struct Watcher {
+    /// The epoll instance on Linux, the kqueue on macOS.
+    queue: OwnedFd,
+    watched: Mutex<HashMap<u32, Watched>>,
+}
+
+struct Watched {
+    on_exit: OnExit,
+    /// Registered with the epoll instance for as long as it is open.
+    #[cfg(target_os = "linux")]
+    _pidfd: OwnedFd,
+}
+
+impl Watcher {
+    fn run(&self) {
+        let _active = MonitorThread::enter();
+        info!("Shared exit watcher started");
+        let mut exited = Vec::new();
+        loop {
+            exited.clear();
+            if let Err(e) = self.wait(&mut exited) {
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                error!("Shared exit watcher failed, relying on polling: {e}");
+                break;
+            }
+
+            let mut supervise: Vec<ManagerHandle> = Vec::new();
+            for pid in &exited {
+                let Some(watched) = self.watched.lock().remove(pid) else {
+                    continue;
+                };
+                match watched.on_exit {
+                    OnExit::Supervise(manager) => {
+                        debug!("PID {pid} exited, running supervisor");
+                        if !supervise.iter().any(|other| other.ptr_eq(&manager)) {
+                            supervise.push(manager);
+                        }
+                    }
+                    OnExit::Reap => {
+                        process::try_reap(*pid as i32);
+                    }
+                }
+            }
+            for manager in supervise.iter().filter_map(ManagerHandle::upgrade) {
+                manager.lock().supervise_processes();
+            }
+        }
+        *WATCHER.lock() = None;
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Watcher {
+    fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            queue: unsafe { OwnedFd::from_raw_fd(fd) },
+            watched: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn add(&self, pid: u32, on_exit: OnExit) -> io::Result<()> {
+        let pidfd = super::monitor::open_pidfd(pid)?;
+        let mut event = libc::epoll_event {
+            events: libc::EPOLLIN as u32,
+            u64: pid as u64,
+        };
+        // Registered before the exit can be handled, so it is not missed
+        let mut watched = self.watched.lock();
+        let rc = unsafe {
+            libc::epoll_ctl(
+                self.queue.as_raw_fd(),
+                libc::EPOLL_CTL_ADD,
+                pidfd.as_raw_fd(),
+                &mut event,
+            )
+        };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // Closing the pidfd of a PID watched before drops it from the epoll
+        watched.insert(
+            pid,
+            Watched {
+                on_exit,
+                _pidfd: pidfd,
+            },
+        );
+        Ok(())
+    }
+
+    fn wait(&self, exited: &mut Vec<u32>) -> io::Result<()> {
+        let mut ready = [libc::epoll_event { events: 0, u64: 0 }; MAX_EVENTS];
+        let n = unsafe {
+            libc::epoll_wait(
+                self.queue.as_raw_fd(),
+                ready.as_mut_ptr(),
+                MAX_EVENTS as libc::c_int,
+                -1,
+            )
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        exited.extend(ready[..n as usize].iter().map(|event| event.u64 as u32));
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+impl Watcher {
+    fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::kqueue() };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            queue: unsafe { OwnedFd::from_raw_fd(fd) },
+            watched: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn add(&self, pid: u32, on_exit: OnExit) -> io::Result<()> {
+        let change = libc::kevent {
+            ident: pid as libc::uintptr_t,
+            filter: libc::EVFILT_PROC,
+            flags: libc::EV_ADD | libc::EV_ONESHOT,
+            fflags: libc::NOTE_EXIT,
+            data: 0,
+            udata: std::ptr::null_mut(),
+        };
+        // Registered before the exit can be handled, so it is not missed
+        let mut watched = self.watched.lock();
+        let rc = unsafe {
+            libc::kevent(
+                self.queue.as_raw_fd(),
+                &change,
+                1,
+                std::ptr::null_mut(),
+                0,
+                std::ptr::null(),
+            )
+        };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        watched.insert(pid, Watched { on_exit });
+        Ok(())
+    }
+
+    fn wait(&self, exited: &mut Vec<u32>) -> io::Result<()> {
+        let mut ready: [libc::kevent; MAX_EVENTS] = unsafe { std::mem::zeroed() };
+        let n = unsafe {
+            libc::kevent(
+                self.queue.as_raw_fd(),
+                std::ptr::null(),
+                0,
+                ready.as_mut_ptr(),
+                MAX_EVENTS as libc::c_int,
+                std::ptr::null(),
+            )
+        };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        exited.extend(ready[..n as usize].iter().map(|event| event.ident as u32));
+        Ok(())
+    }
+}
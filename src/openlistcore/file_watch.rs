@@ -0,0 +1,200 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, mpsc},
+    time::{Duration, Instant},
+};
+
+use log::{error, info, warn};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use super::{
+    data::{ManagerHandle, ProcessConfig, ProcessRuntime},
+    monitor::MonitorThread,
+};
+
+/// Changes closer together than this are folded into a single restart.
+const DEBOUNCE_WINDOW: Duration = Duration::from_millis(1000);
+/// How often an idle watcher checks whether its run is still current.
+const GENERATION_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What to watch for one configured (absolute) path. Files are watched through their
+/// parent directory so that editors which save by replacing the file are
+/// still noticed.
+struct WatchTarget {
+    watch_path: PathBuf,
+    mode: RecursiveMode,
+    file: Option<PathBuf>,
+}
+
+fn watch_target(path: &str) -> WatchTarget {
+    let path = PathBuf::from(path);
+    if path.is_dir() {
+        return WatchTarget {
+            watch_path: path,
+            mode: RecursiveMode::Recursive,
+            file: None,
+        };
+    }
+    let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+    WatchTarget {
+        watch_path: parent,
+        mode: RecursiveMode::NonRecursive,
+        file: Some(path),
+    }
+}
+
+fn is_relevant(event: &notify::Event, targets: &[WatchTarget]) -> bool {
+    // Reads happen all the time, often by the process itself at startup
+    if matches!(event.kind, EventKind::Access(_) | EventKind::Any) {
+        return false;
+    }
+    event.paths.iter().any(|changed| {
+        targets.iter().any(|target| match &target.file {
+            Some(file) => changed == file,
+            None => changed.starts_with(&target.watch_path),
+        })
+    })
+}
+
+/// Watches the process's `restart_on_change` paths for the lifetime of one
+/// run and restarts it once changes have settled for `DEBOUNCE_WINDOW`.
+pub fn spawn_watcher(
+    manager: ManagerHandle,
+    config: ProcessConfig,
+    runtime: ProcessRuntime,
+    generation: u64,
+) {
+    if config.restart_on_change.is_empty() {
+        return;
+    }
+
+    let spawned = std::thread::Builder::new()
+        .name(format!("file-watch-{}", config.id))
+        .spawn(move || {
+            let _active = MonitorThread::enter();
+            watch(manager, config, runtime, generation)
+        });
+    if let Err(e) = spawned {
+        error!("Failed to start file watcher: {e}");
+    }
+}
+
+fn watch(manager: ManagerHandle, config: ProcessConfig, runtime: ProcessRuntime, generation: u64) {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher: RecommendedWatcher = match notify::recommended_watcher(tx) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            error!("Failed to create file watcher for {}: {e}", config.name);
+            return;
+        }
+    };
+
+    let targets: Vec<WatchTarget> = config
+        .restart_on_change
+        .iter()
+        .map(|path| watch_target(path))
+        .collect();
+    for target in &targets {
+        if let Err(e) = watcher.watch(&target.watch_path, target.mode) {
+            warn!(
+                "Cannot watch {:?} for process {}: {e}",
+                target.watch_path, config.name
+            );
+        }
+    }
+
+    let is_current = || runtime.start_generation.load(Ordering::SeqCst) == generation;
+    let mut pending_since: Option<Instant> = None;
+
+    while is_current() {
+        let timeout = match pending_since {
+            Some(at) => DEBOUNCE_WINDOW.saturating_sub(at.elapsed()),
+            None => GENERATION_CHECK_INTERVAL,
+        };
+        match rx.recv_timeout(timeout) {
+            Ok(Ok(event)) if is_relevant(&event, &targets) => {
+                pending_since = Some(Instant::now());
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => warn!("File watcher error for {}: {e}", config.name),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                if pending_since.is_some_and(|at| at.elapsed() >= DEBOUNCE_WINDOW) {
+                    break;
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => return,
+        }
+    }
+    drop(watcher);
+
+    if pending_since.is_none() {
+        return;
+    }
+
+    let Some(manager) = manager.upgrade() else {
+        return;
+    };
+    let mut core_manager = manager.lock();
+    // The run may have been stopped or restarted while waiting for the lock
+    if !is_current() || !runtime.is_running.load(Ordering::Relaxed) {
+        return;
+    }
+    info!(
+        "Watched files of process {} changed, restarting it",
+        config.name
+    );
+    if let Err(e) = core_manager.restart_process(&config.id) {
+        error!(
+            "Failed to restart process {} after file change: {e}",
+            config.name
+        );
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::openlistcore::data::CoreManager;
+    use crate::openlistcore::test_support::{self, scratch_dir, shell_request, wait_for};
+    use serde_json::json;
+
+    #[test]
+    fn changes_restart_the_process_once_after_settling() {
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        let watched = scratch_dir("file-watch").join("config.json");
+        std::fs::write(&watched, "{}").unwrap();
+        let request = shell_request(
+            "watched",
+            "exec sleep 30",
+            json!({ "restart_on_change": [watched.to_string_lossy()] }),
+        );
+        let id = {
+            let mut core_manager = manager.lock();
+            let id = core_manager.create_process(request).unwrap().id;
+            core_manager.start_process(&id).unwrap();
+            id
+        };
+        let status = || manager.lock().get_process(&id).unwrap();
+        let first_pid = status().pid.unwrap();
+        // Let the watcher set itself up
+        std::thread::sleep(Duration::from_millis(300));
+
+        for i in 0..3 {
+            std::fs::write(&watched, format!("{{\"edit\": {i}}}")).unwrap();
+            std::thread::sleep(Duration::from_millis(200));
+        }
+        let last_change = Instant::now() - Duration::from_millis(200);
+
+        assert!(wait_for(Duration::from_secs(5), || status().restart_count == 1));
+        assert!(last_change.elapsed() >= DEBOUNCE_WINDOW);
+        let restarted = status();
+        assert!(restarted.is_running);
+        assert_ne!(restarted.pid, Some(first_pid));
+
+        // The burst of edits counts as one change
+        std::thread::sleep(DEBOUNCE_WINDOW + Duration::from_millis(500));
+        assert_eq!(status().restart_count, 1);
+        manager.lock().stop_process(&id).unwrap();
+    }
+}
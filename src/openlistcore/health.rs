@@ -0,0 +1,133 @@
+use super::{
+    data::{ProcessConfig, ProcessRuntime, ProcessState},
+    hooks, jitter,
+    monitor::MonitorThread,
+    process::{self, is_process_running},
+    readiness::is_current_run,
+};
+use log::{error, info, warn};
+use std::{
+    path::Path,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+const DEFAULT_HEALTH_INTERVAL_SECS: u64 = 30;
+const DEFAULT_HEALTH_FAILURE_THRESHOLD: u32 = 3;
+/// How often a watcher between checks looks whether its run is still current.
+const GENERATION_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Runs `health_command` every `health_interval_secs` for the lifetime of the
+/// run identified by `generation`, once it is ready. After
+/// `health_failure_threshold` failures in a row the run is killed, so that
+/// it is treated like any other crash: restarted with backoff if
+/// `auto_restart` is set, until the crash-loop limit is hit. Each wait is
+/// spread by the service's `jitter_percent`.
+pub fn spawn_watcher(
+    config: ProcessConfig,
+    runtime: ProcessRuntime,
+    generation: u64,
+    jitter_percent: u32,
+) {
+    let Some(command) = config.health_command.clone() else {
+        return;
+    };
+
+    let spawned = std::thread::Builder::new()
+        .name(format!("health-{}", config.id))
+        .spawn(move || {
+            let _active = MonitorThread::enter();
+            watch(&config, &runtime, generation, &command, jitter_percent)
+        });
+    if let Err(e) = spawned {
+        error!("Failed to start health watcher: {e}");
+    }
+}
+
+fn watch(
+    config: &ProcessConfig,
+    runtime: &ProcessRuntime,
+    generation: u64,
+    command: &str,
+    jitter_percent: u32,
+) {
+    let interval = Duration::from_secs(
+        config
+            .health_interval_secs
+            .unwrap_or(DEFAULT_HEALTH_INTERVAL_SECS),
+    );
+    let threshold = config
+        .health_failure_threshold
+        .unwrap_or(DEFAULT_HEALTH_FAILURE_THRESHOLD);
+    let working_dir = config.working_dir.as_deref().map(Path::new);
+    let mut failures = 0;
+
+    for round in 0.. {
+        let wait = jitter::spread(interval, jitter_percent, &config.id, round);
+        if !sleep_while_current(runtime, generation, wait) {
+            return;
+        }
+        // Readiness is up to the readiness check
+        if *runtime.state.lock() == ProcessState::Starting {
+            continue;
+        }
+
+        // A check may take at most until the next one is due
+        let healthy = match hooks::run_shell_command(command, interval, working_dir, None) {
+            Ok(outcome) => outcome.success(),
+            Err(e) => {
+                error!("Failed to run health command for {}: {e}", config.name);
+                false
+            }
+        };
+        if healthy {
+            if failures > 0 {
+                info!("Process {} ({}) is healthy again", config.name, config.id);
+            }
+            failures = 0;
+            continue;
+        }
+
+        failures += 1;
+        warn!(
+            "Health command for {} ({}) failed ({failures}/{threshold})",
+            config.name, config.id
+        );
+        if failures < threshold {
+            continue;
+        }
+
+        let pid = runtime.running_pid.load(Ordering::Relaxed);
+        if !is_current_run(runtime, generation) || !is_process_running(pid) {
+            return;
+        }
+        let message = format!("Health command failed {failures} times in a row");
+        error!(
+            "Process {} ({}): {message}, killing it",
+            config.name, config.id
+        );
+        *runtime.last_error.lock() = Some(message);
+        if let Err(e) = process::kill_process(pid as u32, config.kill_sequence.as_deref()) {
+            error!("Failed to kill unhealthy process {}: {e}", config.name);
+        }
+        return;
+    }
+}
+
+/// Sleeps for `duration`, returning early with `false` once the run exited
+/// or was replaced.
+pub fn sleep_while_current(runtime: &ProcessRuntime, generation: u64, duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    loop {
+        if !is_current_run(runtime, generation)
+            || !is_process_running(runtime.running_pid.load(Ordering::Relaxed))
+        {
+            return false;
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return true;
+        }
+        std::thread::sleep(GENERATION_CHECK_INTERVAL.min(left));
+    }
+}
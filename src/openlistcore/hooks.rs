@@ -0,0 +1,83 @@
+use log::{info, warn};
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+const HOOK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookOutcome {
+    Exited(Option<i32>),
+    TimedOut,
+}
+
+impl HookOutcome {
+    pub fn success(&self) -> bool {
+        matches!(self, HookOutcome::Exited(Some(0)))
+    }
+}
+
+fn shell_command(command: &str) -> Command {
+    #[cfg(target_os = "windows")]
+    {
+        let shell = std::env::var("COMSPEC").unwrap_or_else(|_| "cmd.exe".to_string());
+        let mut cmd = Command::new(shell);
+        cmd.args(["/C", command]);
+        cmd
+    }
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        let mut cmd = Command::new("/bin/sh");
+        cmd.args(["-c", command]);
+        cmd
+    }
+}
+
+/// Runs `command` through the platform shell, killing it once `timeout` has
+/// elapsed. Output goes to `log` when given and is discarded otherwise.
+pub fn run_shell_command(
+    command: &str,
+    timeout: Duration,
+    working_dir: Option<&Path>,
+    log: Option<File>,
+) -> io::Result<HookOutcome> {
+    let mut cmd = shell_command(command);
+    if let Some(dir) = working_dir {
+        cmd.current_dir(dir);
+    }
+    match log {
+        Some(mut log) => {
+            let _ = writeln!(log, "Running command: {command}");
+            let log_for_stderr = log.try_clone()?;
+            cmd.stdout(Stdio::from(log))
+                .stderr(Stdio::from(log_for_stderr));
+        }
+        None => {
+            cmd.stdout(Stdio::null()).stderr(Stdio::null());
+        }
+    }
+    cmd.stdin(Stdio::null());
+
+    let mut child = cmd.spawn()?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            info!("Command `{command}` exited with {status}");
+            return Ok(HookOutcome::Exited(status.code()));
+        }
+        if Instant::now() >= deadline {
+            warn!(
+                "Command `{command}` timed out after {}s, killing it",
+                timeout.as_secs()
+            );
+            let _ = child.kill();
+            let _ = child.wait();
+            return Ok(HookOutcome::TimedOut);
+        }
+        std::thread::sleep(HOOK_POLL_INTERVAL);
+    }
+}
@@ -1,18 +1,26 @@
-use crate::openlistcore::core::CORE_MANAGER;
+use crate::openlistcore::core::{self, CORE_MANAGER};
 use crate::openlistcore::data::*;
+use crate::openlistcore::error::CoreError;
+use crate::openlistcore::log_stream::{self, FollowEvent};
 use anyhow::{Context, Result};
 use axum::{
     Router,
     extract::{Query, Request, State},
-    http::{Method, StatusCode},
+    http::{Method, StatusCode, header},
     middleware::{self, Next},
-    response::{IntoResponse, Json},
+    response::{
+        IntoResponse, Json,
+        sse::{Event, KeepAlive, Sse},
+    },
     routing::{delete, get, post, put},
 };
+use futures::StreamExt;
 use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use std::{
+    convert::Infallible,
     env,
+    sync::Arc,
     time::{SystemTime, UNIX_EPOCH},
 };
 use tower::ServiceBuilder;
@@ -21,6 +29,8 @@ use tower_http::cors::{Any, CorsLayer};
 const DEFAULT_HTTP_SERVER_HOST: &str = "127.0.0.1";
 const DEFAULT_HTTP_SERVER_PORT: u16 = 53211;
 
+const DEFAULT_LOG_TAIL_BYTES: usize = 64 * 1024;
+
 const DEFAULT_API_KEY: &str = "yeM6PCcZGaCpapyBKAbjTp2YAhcku6cUr";
 
 fn get_api_key() -> String {
@@ -58,6 +68,77 @@ pub struct ApiResponse<T> {
 #[derive(Debug, Deserialize)]
 pub struct LogQueryParams {
     pub lines: Option<usize>,
+    pub strip_ansi: Option<bool>,
+    /// Only combined log lines at least this severe.
+    pub level: Option<LogLevel>,
+    /// Only as many of the last lines as fit into this many bytes.
+    pub max_bytes: Option<usize>,
+}
+
+/// Where to resume following a log, as reported by `/logs/offset` or the id
+/// of the last event received.
+#[derive(Debug, Deserialize)]
+pub struct SubscribeQueryParams {
+    pub file_id: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogSearchQueryParams {
+    pub query: String,
+    pub regex: Option<bool>,
+    pub max_matches: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQueryParams {
+    pub since: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LogBytesQueryParams {
+    pub max_bytes: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartQueryParams {
+    pub ignore_condition: Option<bool>,
+    /// Treat a process that is already running as successfully started.
+    pub idempotent: Option<bool>,
+    /// Respond only once the process is ready (or timed out becoming so).
+    pub wait: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StopQueryParams {
+    /// Keep the process stopped until it is started explicitly.
+    pub hold: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClearFailedQueryParams {
+    /// Start the process again once it is cleared.
+    pub restart: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProcessQueryParams {
+    /// Also read the running process's command line from the OS.
+    pub command_line: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProcessListQueryParams {
+    /// Only list the processes currently in this state.
+    pub state: Option<ProcessState>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProcessPageQueryParams {
+    pub sort_by: Option<ProcessSortBy>,
+    pub order: Option<SortOrder>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
 }
 
 #[derive(Clone)]
@@ -121,6 +202,19 @@ fn error_response(error: String) -> Json<ApiResponse<()>> {
     })
 }
 
+/// Timeouts are answered with 504 and rate-limited restarts with 429 so
+/// clients can tell them from other failures; everything else keeps the
+/// usual 200 with `success: false`.
+fn error_status(err: &anyhow::Error) -> StatusCode {
+    match err.downcast_ref::<CoreError>() {
+        Some(CoreError::StartTimeout { .. } | CoreError::StopTimeout { .. }) => {
+            StatusCode::GATEWAY_TIMEOUT
+        }
+        Some(CoreError::RestartRateLimited { .. }) => StatusCode::TOO_MANY_REQUESTS,
+        None => StatusCode::OK,
+    }
+}
+
 async fn get_status() -> impl IntoResponse {
     info!("Handling GET /api/v1/status request");
 
@@ -164,6 +258,169 @@ async fn get_service_version() -> impl IntoResponse {
     }
 }
 
+async fn self_check_api() -> impl IntoResponse {
+    info!("Handling GET /api/v1/service/self-check request");
+
+    let core_manager = CORE_MANAGER.lock();
+    success_response(core_manager.self_check()).into_response()
+}
+
+async fn validate_all_api() -> impl IntoResponse {
+    info!("Handling GET /api/v1/service/validate request");
+
+    let core_manager = CORE_MANAGER.lock();
+    success_response(core_manager.validate_all()).into_response()
+}
+
+async fn diff_config_api() -> impl IntoResponse {
+    info!("Handling GET /api/v1/service/config-diff request");
+
+    let core_manager = CORE_MANAGER.lock();
+
+    match core_manager.diff_config() {
+        Ok(diff) => success_response(diff).into_response(),
+        Err(err) => {
+            error!("Failed to diff process configurations: {err}");
+            error_response(format!("Failed to diff process configurations: {err}")).into_response()
+        }
+    }
+}
+
+async fn config_metadata_api() -> impl IntoResponse {
+    info!("Handling GET /api/v1/service/config-metadata request");
+
+    let core_manager = CORE_MANAGER.lock();
+
+    match core_manager.config_metadata() {
+        Ok(metadata) => success_response(metadata).into_response(),
+        Err(err) => {
+            error!("Failed to read config file metadata: {err}");
+            error_response(format!("Failed to read config file metadata: {err}")).into_response()
+        }
+    }
+}
+
+async fn snapshot_api() -> impl IntoResponse {
+    info!("Handling GET /api/v1/service/snapshot request");
+
+    let core_manager = CORE_MANAGER.lock();
+    success_response(core_manager.snapshot()).into_response()
+}
+
+async fn restore_snapshot_api(Json(payload): Json<RestoreSnapshotRequest>) -> impl IntoResponse {
+    info!("Handling POST /api/v1/service/restore request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+
+    match core_manager.restore(payload.snapshot, payload.start) {
+        Ok(result) => success_response(result).into_response(),
+        Err(err) => {
+            error!("Failed to restore snapshot: {err}");
+            error_response(format!("Failed to restore snapshot: {err}")).into_response()
+        }
+    }
+}
+
+async fn reconcile_state_api() -> impl IntoResponse {
+    info!("Handling POST /api/v1/service/reconcile request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+    success_response(core_manager.reconcile_state()).into_response()
+}
+
+async fn service_resources_api() -> impl IntoResponse {
+    info!("Handling GET /api/v1/service/resources request");
+
+    let core_manager = CORE_MANAGER.lock();
+    success_response(core_manager.service_resource_usage()).into_response()
+}
+
+async fn enter_maintenance_api() -> impl IntoResponse {
+    info!("Handling POST /api/v1/service/maintenance/enter request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+
+    match core_manager.enter_maintenance() {
+        Ok(result) => success_response(result).into_response(),
+        Err(err) => {
+            error!("Failed to enter maintenance mode: {err}");
+            error_response(format!("Failed to enter maintenance mode: {err}")).into_response()
+        }
+    }
+}
+
+async fn exit_maintenance_api() -> impl IntoResponse {
+    info!("Handling POST /api/v1/service/maintenance/exit request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+
+    match core_manager.exit_maintenance() {
+        Ok(result) => success_response(result).into_response(),
+        Err(err) => {
+            error!("Failed to exit maintenance mode: {err}");
+            error_response(format!("Failed to exit maintenance mode: {err}")).into_response()
+        }
+    }
+}
+
+async fn set_safe_mode_api(Json(payload): Json<SafeModeRequest>) -> impl IntoResponse {
+    info!("Handling PUT /api/v1/service/safe-mode request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+    core_manager.set_safe_mode(payload.enabled);
+    success_response(core_manager.safe_mode).into_response()
+}
+
+async fn set_global_pre_start_hook_api(
+    Json(payload): Json<GlobalPreStartHookRequest>,
+) -> impl IntoResponse {
+    info!("Handling PUT /api/v1/service/pre-start-hook request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+    match core_manager.set_global_pre_start_hook(payload.command) {
+        Ok(()) => success_response(core_manager.get_settings()).into_response(),
+        Err(err) => {
+            error!("Failed to set the global pre-start hook: {err}");
+            error_response(format!("Failed to set the global pre-start hook: {err}"))
+                .into_response()
+        }
+    }
+}
+
+async fn get_settings_api() -> impl IntoResponse {
+    info!("Handling GET /api/v1/service/settings request");
+
+    let core_manager = CORE_MANAGER.lock();
+    success_response(core_manager.get_settings()).into_response()
+}
+
+async fn update_settings_api(
+    Json(payload): Json<UpdateServiceSettingsRequest>,
+) -> impl IntoResponse {
+    info!("Handling PUT /api/v1/service/settings request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+
+    match core_manager.update_settings(payload) {
+        Ok(settings) => success_response(settings).into_response(),
+        Err(err) => {
+            error!("Failed to update service settings: {err}");
+            error_response(format!("Failed to update service settings: {err}")).into_response()
+        }
+    }
+}
+
+async fn metrics_api() -> impl IntoResponse {
+    debug!("Handling GET /api/v1/metrics request");
+
+    let core_manager = CORE_MANAGER.lock();
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        core_manager.metrics_prometheus(),
+    )
+        .into_response()
+}
+
 async fn create_process_api(Json(payload): Json<CreateProcessRequest>) -> impl IntoResponse {
     info!("Handling POST /api/v1/processes request");
 
@@ -181,12 +438,79 @@ async fn create_process_api(Json(payload): Json<CreateProcessRequest>) -> impl I
     }
 }
 
-async fn list_processes_api() -> impl IntoResponse {
+async fn list_templates_api() -> impl IntoResponse {
+    info!("Handling GET /api/v1/templates request");
+
+    let core_manager = CORE_MANAGER.lock();
+    success_response(core_manager.list_templates()).into_response()
+}
+
+async fn register_template_api(
+    axum::extract::Path(name): axum::extract::Path<String>,
+    Json(payload): Json<UpdateProcessRequest>,
+) -> impl IntoResponse {
+    info!("Handling PUT /api/v1/templates/{name} request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+
+    match core_manager.register_template(&name, payload) {
+        Ok(template) => success_response(template).into_response(),
+        Err(err) => {
+            error!("Failed to register template {name}: {err}");
+            error_response(format!("Failed to register template: {err}")).into_response()
+        }
+    }
+}
+
+async fn delete_template_api(
+    axum::extract::Path(name): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    info!("Handling DELETE /api/v1/templates/{name} request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+
+    match core_manager.delete_template(&name) {
+        Ok(_) => success_response("Template deleted successfully").into_response(),
+        Err(err) => {
+            error!("Failed to delete template {name}: {err}");
+            error_response(format!("Failed to delete template: {err}")).into_response()
+        }
+    }
+}
+
+async fn create_from_template_api(
+    axum::extract::Path(name): axum::extract::Path<String>,
+    Json(payload): Json<UpdateProcessRequest>,
+) -> impl IntoResponse {
+    info!("Handling POST /api/v1/templates/{name}/create request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+
+    match core_manager.create_from_template(&name, payload) {
+        Ok(config) => {
+            info!(
+                "Process created successfully from template {name}: {}",
+                config.name
+            );
+            success_response(config).into_response()
+        }
+        Err(err) => {
+            error!("Failed to create process from template {name}: {err}");
+            error_response(format!("Failed to create process from template: {err}")).into_response()
+        }
+    }
+}
+
+async fn list_processes_api(Query(params): Query<ProcessListQueryParams>) -> impl IntoResponse {
     info!("Handling GET /api/v1/processes request");
 
     let core_manager = CORE_MANAGER.lock();
 
-    match core_manager.list_processes() {
+    let result = match params.state {
+        Some(state) => core_manager.list_processes_by_state(state),
+        None => core_manager.list_processes(),
+    };
+    match result {
         Ok(processes) => {
             debug!("Processes retrieved successfully");
             success_response(processes).into_response()
@@ -198,13 +522,123 @@ async fn list_processes_api() -> impl IntoResponse {
     }
 }
 
+async fn list_processes_paged_api(
+    Query(params): Query<ProcessPageQueryParams>,
+) -> impl IntoResponse {
+    info!("Handling GET /api/v1/processes/paged request");
+
+    let core_manager = CORE_MANAGER.lock();
+
+    match core_manager.list_processes_paged(
+        params.sort_by.unwrap_or_default(),
+        params.order.unwrap_or_default(),
+        params.offset.unwrap_or(0),
+        params.limit.unwrap_or(DEFAULT_PAGE_SIZE),
+    ) {
+        Ok(page) => {
+            debug!("Process page retrieved successfully");
+            success_response(page).into_response()
+        }
+        Err(err) => {
+            error!("Failed to list processes: {err}");
+            error_response(format!("Failed to list processes: {err}")).into_response()
+        }
+    }
+}
+
+async fn reorder_processes_api(Json(payload): Json<ReorderProcessesRequest>) -> impl IntoResponse {
+    info!("Handling POST /api/v1/processes/reorder request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+
+    match core_manager.reorder_processes(payload.ids) {
+        Ok(_) => success_response("Processes reordered successfully").into_response(),
+        Err(err) => {
+            error!("Failed to reorder processes: {err}");
+            error_response(format!("Failed to reorder processes: {err}")).into_response()
+        }
+    }
+}
+
+async fn list_events_api(Query(params): Query<EventsQueryParams>) -> impl IntoResponse {
+    debug!("Handling GET /api/v1/events request");
+
+    let core_manager = CORE_MANAGER.lock();
+    success_response(core_manager.list_events(params.since.unwrap_or(0))).into_response()
+}
+
+async fn health_summary_api() -> impl IntoResponse {
+    debug!("Handling GET /api/v1/processes/health request");
+
+    let core_manager = CORE_MANAGER.lock();
+    success_response(core_manager.health_summary()).into_response()
+}
+
+async fn reliability_report_api() -> impl IntoResponse {
+    debug!("Handling GET /api/v1/processes/reliability request");
+
+    let core_manager = CORE_MANAGER.lock();
+    success_response(core_manager.reliability_report()).into_response()
+}
+
+async fn reset_failed_processes_api() -> impl IntoResponse {
+    info!("Handling POST /api/v1/processes/reset-failed request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+
+    match core_manager.reset_and_restart_failed() {
+        Ok(result) => success_response(result).into_response(),
+        Err(err) => {
+            error!("Failed to reset failed processes: {err}");
+            error_response(format!("Failed to reset failed processes: {err}")).into_response()
+        }
+    }
+}
+
+async fn start_all_processes_api() -> impl IntoResponse {
+    info!("Handling POST /api/v1/processes/start-all request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+
+    match core_manager.start_all_processes() {
+        Ok(result) => success_response(result).into_response(),
+        Err(err) => {
+            error!("Failed to start all processes: {err}");
+            error_response(format!("Failed to start all processes: {err}")).into_response()
+        }
+    }
+}
+
+async fn restart_all_running_api() -> impl IntoResponse {
+    info!("Handling POST /api/v1/processes/restart-all request");
+
+    // Waits for each process to become ready, so it runs off the async workers
+    let restarted = tokio::task::spawn_blocking(|| core::restart_all_running(&CORE_MANAGER))
+        .await
+        .unwrap_or_else(|e| Err(anyhow::anyhow!("Restarting processes failed: {e}")));
+
+    match restarted {
+        Ok(result) => success_response(result).into_response(),
+        Err(err) => {
+            error!("Failed to restart all processes: {err}");
+            error_response(format!("Failed to restart all processes: {err}")).into_response()
+        }
+    }
+}
+
 async fn get_process_api(
     axum::extract::Path(id): axum::extract::Path<String>,
+    Query(params): Query<ProcessQueryParams>,
 ) -> impl IntoResponse {
     info!("Handling GET /api/v1/processes/{id} request");
     let core_manager = CORE_MANAGER.lock();
 
-    match core_manager.get_process(&id) {
+    let result = if params.command_line.unwrap_or(false) {
+        core_manager.get_process_with_command_line(&id)
+    } else {
+        core_manager.get_process(&id)
+    };
+    match result {
         Ok(process) => {
             debug!("Process retrieved successfully: {}", process.name);
             success_response(process).into_response()
@@ -216,6 +650,66 @@ async fn get_process_api(
     }
 }
 
+async fn get_effective_config_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    info!("Handling GET /api/v1/processes/{id}/effective-config request");
+    let core_manager = CORE_MANAGER.lock();
+
+    match core_manager.get_effective_config(&id) {
+        Ok(config) => success_response(config).into_response(),
+        Err(err) => {
+            error!("Failed to get effective config of process {id}: {err}");
+            error_response(format!("Failed to get effective config: {err}")).into_response()
+        }
+    }
+}
+
+async fn reproduce_command_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    info!("Handling GET /api/v1/processes/{id}/reproduce-command request");
+    let core_manager = CORE_MANAGER.lock();
+
+    match core_manager.reproduce_command(&id) {
+        Ok(command) => success_response(command).into_response(),
+        Err(err) => {
+            error!("Failed to build the start command of process {id}: {err}");
+            error_response(format!("Failed to build the start command: {err}")).into_response()
+        }
+    }
+}
+
+async fn get_startup_output_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    info!("Handling GET /api/v1/processes/{id}/startup-output request");
+    let core_manager = CORE_MANAGER.lock();
+
+    match core_manager.get_process_startup_output(&id) {
+        Ok(lines) => success_response(lines).into_response(),
+        Err(err) => {
+            error!("Failed to get startup output of process {id}: {err}");
+            error_response(format!("Failed to get startup output: {err}")).into_response()
+        }
+    }
+}
+
+async fn not_running_reason_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    info!("Handling GET /api/v1/processes/{id}/not-running-reason request");
+    let core_manager = CORE_MANAGER.lock();
+
+    match core_manager.not_running_reason(&id) {
+        Ok(reason) => success_response(reason).into_response(),
+        Err(err) => {
+            error!("Failed to get why process {id} is not running: {err}");
+            error_response(format!("Failed to get not-running reason: {err}")).into_response()
+        }
+    }
+}
+
 async fn update_process_api(
     axum::extract::Path(id): axum::extract::Path<String>,
     Json(payload): Json<UpdateProcessRequest>,
@@ -236,6 +730,25 @@ async fn update_process_api(
     }
 }
 
+async fn update_processes_api(Json(payload): Json<UpdateProcessesRequest>) -> impl IntoResponse {
+    info!("Handling PUT /api/v1/processes request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+    let updates = payload
+        .updates
+        .into_iter()
+        .map(|update| (update.id, update.request))
+        .collect();
+
+    match core_manager.update_processes(updates) {
+        Ok(configs) => success_response(configs).into_response(),
+        Err(err) => {
+            error!("Failed to update processes: {err:#}");
+            error_response(format!("Failed to update processes: {err:#}")).into_response()
+        }
+    }
+}
+
 async fn delete_process_api(
     axum::extract::Path(id): axum::extract::Path<String>,
 ) -> impl IntoResponse {
@@ -255,15 +768,153 @@ async fn delete_process_api(
     }
 }
 
+async fn set_auto_restart_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Json(payload): Json<SetAutoRestartRequest>,
+) -> impl IntoResponse {
+    info!("Handling PUT /api/v1/processes/{id}/auto-restart request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+
+    match core_manager.set_auto_restart(&id, payload.enabled) {
+        Ok(_) => success_response("Auto-restart updated successfully").into_response(),
+        Err(err) => {
+            error!("Failed to set auto-restart for process {id}: {err}");
+            error_response(format!("Failed to set auto-restart: {err}")).into_response()
+        }
+    }
+}
+
+async fn apply_env_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Json(payload): Json<ApplyEnvRequest>,
+) -> impl IntoResponse {
+    info!("Handling PUT /api/v1/processes/{id}/env request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+
+    match core_manager.apply_env(&id, payload.env_vars, payload.restart) {
+        Ok(restarted) => success_response(restarted).into_response(),
+        Err(err) => {
+            error!("Failed to apply env_vars to process {id}: {err}");
+            (
+                error_status(&err),
+                error_response(format!("Failed to apply env_vars: {err}")),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn cancel_pending_restart_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    info!("Handling POST /api/v1/processes/{id}/cancel-restart request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+
+    match core_manager.cancel_pending_restart(&id) {
+        Ok(cancelled) => success_response(cancelled).into_response(),
+        Err(err) => {
+            error!("Failed to cancel pending restart of process {id}: {err}");
+            error_response(format!("Failed to cancel pending restart: {err}")).into_response()
+        }
+    }
+}
+
+async fn clear_failed_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Query(params): Query<ClearFailedQueryParams>,
+) -> impl IntoResponse {
+    info!("Handling POST /api/v1/processes/{id}/clear-failed request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+
+    match core_manager.clear_failed(&id, params.restart.unwrap_or(false)) {
+        Ok(()) => success_response("Failed state cleared").into_response(),
+        Err(err) => {
+            error!("Failed to clear failed state of process {id}: {err}");
+            (
+                error_status(&err),
+                error_response(format!("Failed to clear failed state: {err}")),
+            )
+                .into_response()
+        }
+    }
+}
+
+async fn reload_process_config_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    info!("Handling POST /api/v1/processes/{id}/reload request");
+
+    let mut core_manager = CORE_MANAGER.lock();
+
+    match core_manager.reload_process_config(&id) {
+        Ok(()) => success_response("Process configuration reloaded").into_response(),
+        Err(err) => {
+            error!("Failed to reload the configuration of process {id}: {err}");
+            (
+                error_status(&err),
+                error_response(format!("Failed to reload process configuration: {err}")),
+            )
+                .into_response()
+        }
+    }
+}
+
 async fn start_process_api(
     axum::extract::Path(id): axum::extract::Path<String>,
+    Query(params): Query<StartQueryParams>,
 ) -> impl IntoResponse {
     info!("Handling POST /api/v1/processes/{id}/start request");
 
+    let result = {
+        let mut core_manager = CORE_MANAGER.lock();
+        if params.idempotent.unwrap_or(false) && core_manager.is_started(&id) {
+            info!("Process {id} is already running, nothing to start");
+            Ok(())
+        } else if params.ignore_condition.unwrap_or(false) {
+            core_manager.start_process_ignoring_condition(&id)
+        } else {
+            core_manager.start_process(&id)
+        }
+    };
+    if let Err(err) = result {
+        error!("Failed to start process {id}: {err}");
+        return error_response(format!("Failed to start process: {err}")).into_response();
+    }
+
+    if params.wait.unwrap_or(false) {
+        let wait_id = id.clone();
+        let ready =
+            tokio::task::spawn_blocking(move || core::wait_until_ready(&CORE_MANAGER, &wait_id))
+                .await
+                .unwrap_or_else(|e| Err(anyhow::anyhow!("Waiting for readiness failed: {e}")));
+        if let Err(err) = ready {
+            error!("Process {id} did not become ready: {err}");
+            return (
+                error_status(&err),
+                error_response(format!("Process did not become ready: {err}")),
+            )
+                .into_response();
+        }
+    }
+
+    info!("Process started successfully: {id}");
+    success_response("Process started successfully").into_response()
+}
+
+async fn start_process_with_overrides_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Json(payload): Json<StartWithOverridesRequest>,
+) -> impl IntoResponse {
+    info!("Handling POST /api/v1/processes/{id}/start-with-overrides request");
+
     let mut core_manager = CORE_MANAGER.lock();
 
-    match core_manager.start_process(&id) {
-        Ok(_) => {
+    match core_manager.start_process_with_overrides(&id, &payload.overrides) {
+        Ok(()) => {
             info!("Process started successfully: {id}");
             success_response("Process started successfully").into_response()
         }
@@ -276,19 +927,29 @@ async fn start_process_api(
 
 async fn stop_process_api(
     axum::extract::Path(id): axum::extract::Path<String>,
+    Query(params): Query<StopQueryParams>,
 ) -> impl IntoResponse {
     info!("Handling POST /api/v1/processes/{id}/stop request");
 
     let mut core_manager = CORE_MANAGER.lock();
 
-    match core_manager.stop_process(&id) {
-        Ok(_) => {
+    let result = if params.hold.unwrap_or(false) {
+        core_manager.stop_process_and_hold(&id)
+    } else {
+        core_manager.stop_process(&id)
+    };
+    match result {
+        Ok(outcome) => {
             info!("Process stopped successfully: {id}");
-            success_response("Process stopped successfully").into_response()
+            success_response(outcome).into_response()
         }
         Err(err) => {
             error!("Failed to stop process {id}: {err}");
-            error_response(format!("Failed to stop process: {err}")).into_response()
+            (
+                error_status(&err),
+                error_response(format!("Failed to stop process: {err}")),
+            )
+                .into_response()
         }
     }
 }
@@ -301,7 +962,12 @@ async fn get_process_logs_api(
 
     let core_manager = CORE_MANAGER.lock();
 
-    match core_manager.get_process_logs(&id, params.lines) {
+    match core_manager.get_process_logs(
+        &id,
+        params.lines,
+        params.strip_ansi.unwrap_or(false),
+        params.max_bytes,
+    ) {
         Ok(logs) => {
             debug!("Process logs retrieved successfully: {}", logs.name);
             success_response(logs).into_response()
@@ -313,6 +979,177 @@ async fn get_process_logs_api(
     }
 }
 
+async fn get_process_log_bytes_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Query(params): Query<LogBytesQueryParams>,
+) -> impl IntoResponse {
+    info!("Handling GET /api/v1/processes/{id}/logs/raw request");
+
+    let core_manager = CORE_MANAGER.lock();
+    let max_bytes = params.max_bytes.unwrap_or(DEFAULT_LOG_TAIL_BYTES);
+
+    match core_manager.get_process_log_bytes(&id, max_bytes) {
+        Ok(bytes) => ([(header::CONTENT_TYPE, "application/octet-stream")], bytes).into_response(),
+        Err(err) => {
+            error!("Failed to get raw logs for process {id}: {err}");
+            error_response(format!("Failed to get process logs: {err}")).into_response()
+        }
+    }
+}
+
+async fn get_combined_logs_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Query(params): Query<LogQueryParams>,
+) -> impl IntoResponse {
+    info!("Handling GET /api/v1/processes/{id}/logs/combined request");
+
+    let core_manager = CORE_MANAGER.lock();
+
+    match core_manager.get_combined_logs(&id, params.lines, params.level) {
+        Ok(logs) => {
+            debug!(
+                "Combined process logs retrieved successfully: {}",
+                logs.name
+            );
+            success_response(logs).into_response()
+        }
+        Err(err) => {
+            error!("Failed to get combined logs for process {id}: {err}");
+            error_response(format!("Failed to get process logs: {err}")).into_response()
+        }
+    }
+}
+
+async fn get_recent_errors_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Query(params): Query<LogQueryParams>,
+) -> impl IntoResponse {
+    info!("Handling GET /api/v1/processes/{id}/logs/errors request");
+
+    let core_manager = CORE_MANAGER.lock();
+
+    match core_manager.get_recent_errors(&id, params.lines) {
+        Ok(errors) => {
+            debug!("Recent errors retrieved successfully: {}", errors.name);
+            success_response(errors).into_response()
+        }
+        Err(err) => {
+            error!("Failed to get recent errors for process {id}: {err}");
+            error_response(format!("Failed to get process logs: {err}")).into_response()
+        }
+    }
+}
+
+async fn search_all_logs_api(Query(params): Query<LogSearchQueryParams>) -> impl IntoResponse {
+    info!("Handling GET /api/v1/logs/search request");
+
+    let core_manager = CORE_MANAGER.lock();
+
+    match core_manager.search_all_logs(
+        &params.query,
+        params.regex.unwrap_or(false),
+        params
+            .max_matches
+            .unwrap_or(DEFAULT_SEARCH_MATCHES_PER_PROCESS),
+    ) {
+        Ok(hits) => {
+            debug!("Log search found {} matching lines", hits.len());
+            success_response(hits).into_response()
+        }
+        Err(err) => {
+            error!("Failed to search process logs: {err}");
+            error_response(format!("Failed to search process logs: {err}")).into_response()
+        }
+    }
+}
+
+/// One SSE event per log line, with the position after it as the event id,
+/// or a `rotated` event when following starts over. Events cannot carry
+/// carriage returns, e.g. from progress output, so those are dropped.
+fn log_event(event: FollowEvent) -> Event {
+    match event {
+        FollowEvent::Line(line, position) => Event::default()
+            .id(format!("{}:{}", position.file_id, position.offset))
+            .data(line.replace('\r', "")),
+        FollowEvent::Rotated => Event::default().event("rotated").data(""),
+    }
+}
+
+async fn subscribe_logs_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Query(params): Query<SubscribeQueryParams>,
+) -> impl IntoResponse {
+    info!("Handling GET /api/v1/processes/{id}/logs/subscribe request");
+
+    if let Err(err) = CORE_MANAGER.lock().active_log_path(&id) {
+        error!("Failed to subscribe to logs of process {id}: {err}");
+        return error_response(format!("Failed to subscribe to process logs: {err}"))
+            .into_response();
+    }
+    let resume = match (params.file_id, params.offset) {
+        (Some(file_id), Some(offset)) => Some(LogPosition { file_id, offset }),
+        (None, None) => None,
+        _ => {
+            return error_response("file_id and offset must be given together".to_string())
+                .into_response();
+        }
+    };
+
+    let events = log_stream::follow(Arc::downgrade(&CORE_MANAGER), id, resume)
+        .map(|event| Ok::<_, Infallible>(log_event(event)));
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+async fn get_log_offset_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> impl IntoResponse {
+    info!("Handling GET /api/v1/processes/{id}/logs/offset request");
+
+    let core_manager = CORE_MANAGER.lock();
+
+    match core_manager.get_log_offset(&id) {
+        Ok(offset) => success_response(offset).into_response(),
+        Err(err) => {
+            error!("Failed to get log offset of process {id}: {err}");
+            error_response(format!("Failed to get log offset: {err}")).into_response()
+        }
+    }
+}
+
+async fn flush_logs_api(axum::extract::Path(id): axum::extract::Path<String>) -> impl IntoResponse {
+    info!("Handling POST /api/v1/processes/{id}/logs/flush request");
+
+    let core_manager = CORE_MANAGER.lock();
+
+    match core_manager.flush_logs(&id) {
+        Ok(_) => success_response("Process logs flushed successfully").into_response(),
+        Err(err) => {
+            error!("Failed to flush logs for process {id}: {err}");
+            error_response(format!("Failed to flush process logs: {err}")).into_response()
+        }
+    }
+}
+
+async fn export_process_logs_api(
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Json(payload): Json<ExportLogsRequest>,
+) -> impl IntoResponse {
+    info!("Handling POST /api/v1/processes/{id}/logs/export request");
+
+    let core_manager = CORE_MANAGER.lock();
+
+    match core_manager.export_process_logs(&id, std::path::Path::new(&payload.dest), payload.redact)
+    {
+        Ok(_) => success_response("Process logs exported successfully").into_response(),
+        Err(err) => {
+            error!("Failed to export logs for process {id}: {err}");
+            error_response(format!("Failed to export process logs: {err}")).into_response()
+        }
+    }
+}
+
 async fn stop_service_api() -> impl IntoResponse {
     info!("Handling POST /api/v1/service/stop request - stopping service");
 
@@ -359,15 +1196,118 @@ fn create_router(app_state: AppState) -> Router {
     let protected_routes = Router::new()
         .route("/api/v1/status", get(get_status))
         .route("/api/v1/version", get(get_service_version))
+        .route("/api/v1/metrics", get(metrics_api))
         .route("/api/v1/service/stop", post(stop_service_api))
+        .route("/api/v1/service/self-check", get(self_check_api))
+        .route("/api/v1/service/validate", get(validate_all_api))
+        .route("/api/v1/service/config-diff", get(diff_config_api))
+        .route("/api/v1/service/config-metadata", get(config_metadata_api))
+        .route("/api/v1/service/snapshot", get(snapshot_api))
+        .route("/api/v1/service/restore", post(restore_snapshot_api))
+        .route("/api/v1/service/reconcile", post(reconcile_state_api))
+        .route("/api/v1/service/resources", get(service_resources_api))
+        .route(
+            "/api/v1/service/maintenance/enter",
+            post(enter_maintenance_api),
+        )
+        .route(
+            "/api/v1/service/maintenance/exit",
+            post(exit_maintenance_api),
+        )
+        .route("/api/v1/service/safe-mode", put(set_safe_mode_api))
+        .route(
+            "/api/v1/service/pre-start-hook",
+            put(set_global_pre_start_hook_api),
+        )
+        .route("/api/v1/service/settings", get(get_settings_api))
+        .route("/api/v1/service/settings", put(update_settings_api))
+        .route("/api/v1/templates", get(list_templates_api))
+        .route("/api/v1/templates/:name", put(register_template_api))
+        .route("/api/v1/templates/:name", delete(delete_template_api))
+        .route(
+            "/api/v1/templates/:name/create",
+            post(create_from_template_api),
+        )
+        .route("/api/v1/logs/search", get(search_all_logs_api))
+        .route("/api/v1/events", get(list_events_api))
         .route("/api/v1/processes", get(list_processes_api))
         .route("/api/v1/processes", post(create_process_api))
+        .route("/api/v1/processes", put(update_processes_api))
+        .route("/api/v1/processes/paged", get(list_processes_paged_api))
+        .route("/api/v1/processes/health", get(health_summary_api))
+        .route("/api/v1/processes/reliability", get(reliability_report_api))
+        .route("/api/v1/processes/reorder", post(reorder_processes_api))
+        .route(
+            "/api/v1/processes/reset-failed",
+            post(reset_failed_processes_api),
+        )
+        .route("/api/v1/processes/start-all", post(start_all_processes_api))
+        .route(
+            "/api/v1/processes/restart-all",
+            post(restart_all_running_api),
+        )
         .route("/api/v1/processes/:id", get(get_process_api))
         .route("/api/v1/processes/:id", put(update_process_api))
         .route("/api/v1/processes/:id", delete(delete_process_api))
+        .route(
+            "/api/v1/processes/:id/effective-config",
+            get(get_effective_config_api),
+        )
+        .route(
+            "/api/v1/processes/:id/reproduce-command",
+            get(reproduce_command_api),
+        )
+        .route(
+            "/api/v1/processes/:id/startup-output",
+            get(get_startup_output_api),
+        )
+        .route(
+            "/api/v1/processes/:id/not-running-reason",
+            get(not_running_reason_api),
+        )
+        .route(
+            "/api/v1/processes/:id/auto-restart",
+            put(set_auto_restart_api),
+        )
+        .route("/api/v1/processes/:id/env", put(apply_env_api))
+        .route(
+            "/api/v1/processes/:id/cancel-restart",
+            post(cancel_pending_restart_api),
+        )
+        .route("/api/v1/processes/:id/clear-failed", post(clear_failed_api))
+        .route(
+            "/api/v1/processes/:id/reload",
+            post(reload_process_config_api),
+        )
         .route("/api/v1/processes/:id/start", post(start_process_api))
+        .route(
+            "/api/v1/processes/:id/start-with-overrides",
+            post(start_process_with_overrides_api),
+        )
         .route("/api/v1/processes/:id/stop", post(stop_process_api))
         .route("/api/v1/processes/:id/logs", get(get_process_logs_api))
+        .route(
+            "/api/v1/processes/:id/logs/raw",
+            get(get_process_log_bytes_api),
+        )
+        .route(
+            "/api/v1/processes/:id/logs/combined",
+            get(get_combined_logs_api),
+        )
+        .route(
+            "/api/v1/processes/:id/logs/errors",
+            get(get_recent_errors_api),
+        )
+        .route("/api/v1/processes/:id/logs/flush", post(flush_logs_api))
+        .route(
+            "/api/v1/processes/:id/logs/export",
+            post(export_process_logs_api),
+        )
+        .route(
+            "/api/v1/processes/:id/logs/subscribe",
+            get(subscribe_logs_api),
+        )
+        .route("/api/v1/processes/:id/logs/offset", get(get_log_offset_api))
         .layer(middleware::from_fn_with_state(
             app_state.clone(),
             auth_middleware,
@@ -415,22 +1355,89 @@ pub async fn run_ipc_server() -> Result<()> {
     info!("  GET  /health - Health check");
     info!("  GET  /api/v1/status - Get service status");
     info!("  GET  /api/v1/version - Get version information");
+    info!("  GET  /api/v1/metrics - Prometheus metrics");
 
     info!("");
     info!("Service management endpoints:");
     info!("  POST /api/v1/service/stop - Stop the entire service");
     info!("  POST /api/v1/service/restart - Restart the entire service");
+    info!("  GET  /api/v1/service/self-check - Probe the environment for problems");
+    info!("  GET  /api/v1/service/validate - Validate all process configurations");
+    info!("  GET  /api/v1/service/config-diff - Compare the config file with the loaded configs");
+    info!(
+        "  GET  /api/v1/service/config-metadata - When the config file changed and whether by the service"
+    );
+    info!("  GET  /api/v1/service/snapshot - Settings, processes, stats and templates as one blob");
+    info!(
+        "  POST /api/v1/service/restore - Replace everything with a snapshot, optionally starting it"
+    );
+    info!("  POST /api/v1/service/reconcile - Repair runtime state out of sync with the configs");
+    info!("  GET  /api/v1/service/resources - Service process CPU, memory and threads");
+    info!("  POST /api/v1/service/maintenance/enter - Stop everything and block starts");
+    info!("  POST /api/v1/service/maintenance/exit - Lift maintenance and restart what it stopped");
+    info!("  PUT  /api/v1/service/safe-mode - Turn safe mode on or off");
+    info!("  PUT  /api/v1/service/pre-start-hook - Set the command run before every start");
+    info!("  GET  /api/v1/service/settings - Get service settings");
+    info!("  PUT  /api/v1/service/settings - Update service settings");
+
+    info!("");
+    info!("Process template endpoints:");
+    info!("  GET    /api/v1/templates - List process templates");
+    info!("  PUT    /api/v1/templates/:name - Register or replace a template");
+    info!("  DELETE /api/v1/templates/:name - Delete a template");
+    info!("  POST   /api/v1/templates/:name/create - Create a process from a template");
 
     info!("");
     info!("Process management endpoints:");
-    info!("  GET    /api/v1/processes - List all processes");
+    info!("  GET    /api/v1/processes?state= - List all processes, optionally in one state");
     info!("  POST   /api/v1/processes - Create new process");
+    info!("  PUT    /api/v1/processes - Update several processes at once");
+    info!(
+        "  GET    /api/v1/processes/paged?sort_by=&order=&offset=&limit= - List processes sorted and paginated"
+    );
+    info!("  GET    /api/v1/processes/health - Count running and failed processes");
+    info!("  GET    /api/v1/processes/reliability - Restart statistics across processes");
+    info!("  POST   /api/v1/processes/reorder - Set the display order of processes");
+    info!(
+        "  POST   /api/v1/processes/reset-failed - Restart all processes halted by crash-loop detection"
+    );
+    info!("  POST   /api/v1/processes/start-all - Start every stopped process");
+    info!(
+        "  POST   /api/v1/processes/restart-all - Restart every running process in dependency order"
+    );
     info!("  GET    /api/v1/processes/:id - Get process details");
     info!("  PUT    /api/v1/processes/:id - Update process");
     info!("  DELETE /api/v1/processes/:id - Delete process");
-    info!("  POST   /api/v1/processes/:id/start - Start process");
-    info!("  POST   /api/v1/processes/:id/stop - Stop process");
+    info!("  GET    /api/v1/processes/:id/effective-config - Get the config with defaults applied");
+    info!("  GET    /api/v1/processes/:id/reproduce-command - Shell command equivalent to a start");
+    info!("  GET    /api/v1/processes/:id/startup-output - First lines logged by the last run");
+    info!("  GET    /api/v1/processes/:id/not-running-reason - Why the process is not running");
+    info!("  PUT    /api/v1/processes/:id/auto-restart - Enable or disable auto-restart");
+    info!("  PUT    /api/v1/processes/:id/env - Replace env_vars, optionally restarting");
+    info!("  POST   /api/v1/processes/:id/cancel-restart - Cancel a pending auto-restart");
+    info!(
+        "  POST   /api/v1/processes/:id/clear-failed?restart= - Clear the crash-loop latch of one process"
+    );
+    info!("  POST   /api/v1/processes/:id/reload - Reload config via SIGHUP or a restart");
+    info!("  POST   /api/v1/processes/:id/start?wait= - Start process, optionally until ready");
+    info!(
+        "  POST   /api/v1/processes/:id/start-with-overrides - Start process with KEY=VALUE env and extra args for one run"
+    );
+    info!("  POST   /api/v1/processes/:id/stop?hold= - Stop process, optionally holding it down");
+    info!("  GET    /api/v1/logs/search?query= - Search the logs of all processes");
+    info!("  GET    /api/v1/events?since= - Recent events, such as restart alerts");
     info!("  GET    /api/v1/processes/:id/logs - Get process logs");
+    info!("  GET    /api/v1/processes/:id/logs/raw - Tail of the log file as raw bytes");
+    info!(
+        "  GET    /api/v1/processes/:id/logs/combined - Stdout and stderr merged, tagged by stream"
+    );
+    info!("  GET    /api/v1/processes/:id/logs/errors - Most recent warnings and errors");
+    info!("  POST   /api/v1/processes/:id/logs/flush - Sync process logs to disk");
+    info!("  POST   /api/v1/processes/:id/logs/export - Archive the logs of a process");
+    info!(
+        "  GET    /api/v1/processes/:id/logs/subscribe?file_id=&offset= - Stream new log lines (SSE)"
+    );
+    info!("  GET    /api/v1/processes/:id/logs/offset - End of the log, to resume following from");
     info!("");
     info!("Usage examples:");
     info!("  curl -H \"Authorization: {api_key}\" http://{addr}/api/v1/processes",);
@@ -443,3 +1450,59 @@ pub async fn run_ipc_server() -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::openlistcore::test_support::{self, shell_request};
+    use std::time::Duration;
+
+    #[test]
+    fn subscribers_get_new_lines_in_order() {
+        let _serial = test_support::serial();
+        let script = "sleep 0.5; printf 'one\\r\\ntwo\\rhalf\\n'; \
+                      printf 'three\\n'; exec sleep 30";
+        let id = {
+            let mut core_manager = CORE_MANAGER.lock();
+            let id = core_manager
+                .create_process(shell_request("subscribe", script, serde_json::json!({})))
+                .unwrap()
+                .id;
+            core_manager.start_process(&id).unwrap();
+            id
+        };
+
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let received = runtime.block_on(async {
+            let params = SubscribeQueryParams {
+                file_id: None,
+                offset: None,
+            };
+            let response = subscribe_logs_api(axum::extract::Path(id.clone()), Query(params))
+                .await
+                .into_response();
+            assert_eq!(response.status(), StatusCode::OK);
+            let mut body = response.into_body().into_data_stream();
+            let mut received = String::new();
+            while received.matches("data:").count() < 3 {
+                let chunk = tokio::time::timeout(Duration::from_secs(5), body.next())
+                    .await
+                    .expect("no log event within 5s")
+                    .unwrap()
+                    .unwrap();
+                received.push_str(&String::from_utf8_lossy(&chunk));
+            }
+            received
+        });
+
+        // Every event also carries its position as the id
+        assert_eq!(received.matches("id: ").count(), 3);
+        let data: String = received
+            .lines()
+            .filter(|line| !line.starts_with("id: "))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        assert_eq!(data, "data: one\n\ndata: twohalf\n\ndata: three\n\n");
+        CORE_MANAGER.lock().stop_process(&id).unwrap();
+    }
+}
@@ -0,0 +1,67 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    time::Duration,
+};
+
+/// Largest `jitter_percent` accepted, so an interval never shrinks below half.
+pub const MAX_JITTER_PERCENT: u32 = 50;
+
+/// Offsets `interval` by up to `percent`% either way, so that processes
+/// sharing an interval do not all fire at once. The offset is derived from
+/// `key`, such as a process id, and `round`, counting the firings, so it
+/// differs between processes and from one firing to the next while staying
+/// reproducible.
+pub fn spread(interval: Duration, percent: u32, key: &str, round: u64) -> Duration {
+    if percent == 0 {
+        return interval;
+    }
+    let mut hasher = DefaultHasher::new();
+    (key, round).hash(&mut hasher);
+    // Uniform in -1.0..=1.0
+    let unit = (hasher.finish() % 2001) as f64 / 1000.0 - 1.0;
+    let factor = 1.0 + unit * f64::from(percent.min(MAX_JITTER_PERCENT)) / 100.0;
+    interval.mul_f64(factor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn processes_with_the_same_interval_fire_at_different_times() {
+        let interval = Duration::from_secs(30);
+        let mut fires = [Duration::ZERO; 2];
+        for round in 0..20 {
+            let web = spread(interval, 10, "web", round);
+            let worker = spread(interval, 10, "worker", round);
+            assert_ne!(web, worker, "round {round}");
+            for delay in [web, worker] {
+                assert!(
+                    (Duration::from_secs(27)..=Duration::from_secs(33)).contains(&delay),
+                    "{delay:?}"
+                );
+            }
+            fires[0] += web;
+            fires[1] += worker;
+            assert_ne!(fires[0], fires[1], "round {round}");
+        }
+
+        // Without jitter they stay in lockstep
+        assert_eq!(spread(interval, 0, "web", 3), interval);
+        assert_eq!(
+            spread(interval, 10, "web", 3),
+            spread(interval, 10, "web", 3)
+        );
+    }
+
+    #[test]
+    fn jitter_is_capped() {
+        for round in 0..50 {
+            let delay = spread(Duration::from_secs(10), 500, "capped", round);
+            assert!(
+                (Duration::from_secs(5)..=Duration::from_secs(15)).contains(&delay),
+                "{delay:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use super::{
+    data::{ListenAddressProbe, ProcessConfig, ProcessRuntime},
+    logs::LineSplitter,
+    monitor::MonitorThread,
+    process::is_process_running,
+    readiness::{is_current_run, readiness_timeout},
+};
+use anyhow::{Result, anyhow};
+use log::{info, warn};
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+const PROBE_INTERVAL: Duration = Duration::from_millis(500);
+const PLACEHOLDER: &str = "{address}";
+/// Addresses are logged near the start of a line, so longer lines are cut.
+const MAX_SCANNED_LINE_BYTES: usize = 4096;
+const READ_CHUNK_BYTES: usize = 64 * 1024;
+
+pub fn validate(probe: &ListenAddressProbe) -> Result<()> {
+    match probe {
+        ListenAddressProbe::LogPattern { pattern } => {
+            if !pattern.contains(PLACEHOLDER) {
+                return Err(anyhow!(
+                    "Listen address pattern must contain {PLACEHOLDER}: {pattern}"
+                ));
+            }
+        }
+        ListenAddressProbe::Sockets => {
+            if !cfg!(target_os = "linux") {
+                return Err(anyhow!(
+                    "Probing listening sockets is only supported on Linux"
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Looks in the background for the address the run identified by
+/// `generation` listens on and records it in `runtime.listen_address`. Log
+/// patterns only match output written after `log_offset`, i.e. by this run.
+pub fn spawn_watcher(
+    config: ProcessConfig,
+    runtime: ProcessRuntime,
+    generation: u64,
+    log_offset: u64,
+) {
+    let Some(probe) = config.listen_address_probe.clone() else {
+        return;
+    };
+
+    std::thread::spawn(move || {
+        let _active = MonitorThread::enter();
+        let mut scan = LogScan {
+            offset: log_offset,
+            splitter: LineSplitter::new(MAX_SCANNED_LINE_BYTES),
+        };
+        let timeout = readiness_timeout(&config);
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if !is_current_run(&runtime, generation) {
+                return;
+            }
+            let pid = runtime.running_pid.load(Ordering::Relaxed);
+            if !is_process_running(pid) {
+                return;
+            }
+
+            let found = match &probe {
+                ListenAddressProbe::LogPattern { pattern } => scan.find(&config.log_file, pattern),
+                ListenAddressProbe::Sockets => listening_address(pid),
+            };
+            if let Some(address) = found {
+                info!(
+                    "Process {} ({}) is listening on {address}",
+                    config.name, config.id
+                );
+                *runtime.listen_address.lock() = Some(address);
+                return;
+            }
+
+            if Instant::now() >= deadline {
+                warn!(
+                    "Could not detect the listen address of process {} ({}) within {}s",
+                    config.name,
+                    config.id,
+                    timeout.as_secs()
+                );
+                return;
+            }
+            std::thread::sleep(PROBE_INTERVAL);
+        }
+    });
+}
+
+struct LogScan {
+    offset: u64,
+    splitter: LineSplitter,
+}
+
+impl LogScan {
+    /// Reads whatever was logged since the last call and returns the address
+    /// from the first line matching `pattern`.
+    fn find(&mut self, path: &str, pattern: &str) -> Option<String> {
+        let mut file = File::open(path).ok()?;
+        let len = file.metadata().ok()?.len();
+        if len < self.offset {
+            // Rotated or truncated since the last look
+            self.offset = 0;
+            self.splitter.reset();
+        }
+        file.seek(SeekFrom::Start(self.offset)).ok()?;
+
+        let mut buf = vec![0u8; READ_CHUNK_BYTES];
+        loop {
+            let n = file.read(&mut buf).ok()?;
+            if n == 0 {
+                return None;
+            }
+            self.offset += n as u64;
+            if let Some(address) = self
+                .splitter
+                .push(&buf[..n])
+                .iter()
+                .find_map(|line| match_pattern(line, pattern))
+            {
+                return Some(address);
+            }
+        }
+    }
+}
+
+/// Extracts the text standing in for `{address}` in `pattern` from `line`.
+/// Without anything after the placeholder, the address runs to the next
+/// whitespace.
+fn match_pattern(line: &str, pattern: &str) -> Option<String> {
+    let (prefix, suffix) = pattern.split_once(PLACEHOLDER)?;
+    let start = line.find(prefix)? + prefix.len();
+    let rest = &line[start..];
+    let end = if suffix.is_empty() {
+        rest.find(char::is_whitespace).unwrap_or(rest.len())
+    } else {
+        rest.find(suffix)?
+    };
+    let address = rest[..end].trim();
+    (!address.is_empty()).then(|| address.to_string())
+}
+
+/// The lowest-port TCP socket `pid` is listening on, read from `/proc`.
+#[cfg(target_os = "linux")]
+fn listening_address(pid: i32) -> Option<String> {
+    use std::{
+        collections::HashSet,
+        net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    };
+
+    // "socket:[12345]" -> 12345
+    let inodes: HashSet<String> = std::fs::read_dir(format!("/proc/{pid}/fd"))
+        .ok()?
+        .flatten()
+        .filter_map(|entry| std::fs::read_link(entry.path()).ok())
+        .filter_map(|target| {
+            let target = target.to_str()?;
+            let inode = target.strip_prefix("socket:[")?.strip_suffix(']')?;
+            Some(inode.to_string())
+        })
+        .collect();
+    if inodes.is_empty() {
+        return None;
+    }
+
+    // Addresses are printed as the raw in-memory words, so each 32-bit group
+    // is converted back with native byte order
+    let parse_words = |hex: &str| -> Option<Vec<u8>> {
+        (0..hex.len())
+            .step_by(8)
+            .map(|i| {
+                let word = u32::from_str_radix(hex.get(i..i + 8)?, 16).ok()?;
+                Some(word.to_ne_bytes())
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|words| words.concat())
+    };
+
+    let mut listening = Vec::new();
+    for table in ["tcp", "tcp6"] {
+        let Ok(contents) = std::fs::read_to_string(format!("/proc/{pid}/net/{table}")) else {
+            continue;
+        };
+        for line in contents.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            // sl local_address rem_address st ... inode is the 10th column
+            let (Some(local), Some(state), Some(inode)) =
+                (fields.get(1), fields.get(3), fields.get(9))
+            else {
+                continue;
+            };
+            const TCP_LISTEN: &str = "0A";
+            if *state != TCP_LISTEN || !inodes.contains(*inode) {
+                continue;
+            }
+            let Some((ip, port)) = local.split_once(':') else {
+                continue;
+            };
+            let (Some(ip), Ok(port)) = (parse_words(ip), u16::from_str_radix(port, 16)) else {
+                continue;
+            };
+            let ip = match <[u8; 4]>::try_from(ip.as_slice()) {
+                Ok(v4) => Ipv4Addr::from(v4).into(),
+                Err(_) => match <[u8; 16]>::try_from(ip.as_slice()) {
+                    Ok(v6) => Ipv6Addr::from(v6).into(),
+                    Err(_) => continue,
+                },
+            };
+            listening.push(SocketAddr::new(ip, port));
+        }
+    }
+
+    listening.sort_by_key(|addr| (addr.port(), addr.is_ipv6()));
+    listening.first().map(SocketAddr::to_string)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn listening_address(_pid: i32) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openlistcore::{
+        data::CoreManager,
+        test_support::{self, scratch_dir, shell_request, wait_for},
+    };
+    use serde_json::json;
+
+    #[test]
+    fn patterns_extract_the_address() {
+        let pattern = "listening on {address}";
+        assert_eq!(
+            match_pattern("INFO listening on :5244 (http)", pattern).as_deref(),
+            Some(":5244")
+        );
+        assert_eq!(
+            match_pattern("server at [::1]:80, ready", "server at {address},").as_deref(),
+            Some("[::1]:80")
+        );
+        assert_eq!(match_pattern("listening on ", pattern), None);
+        assert_eq!(match_pattern("starting up", pattern), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn logged_addresses_are_recorded_and_reset_on_the_next_start() {
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        // Each run logs a new port, a little after starting, then exits soon.
+        // The words are split so the logged command line does not match.
+        let runs = scratch_dir("listen-probe").join("runs");
+        let script = format!(
+            "n=$(($(cat {runs} 2>/dev/null || echo 0) + 1)); echo $n > {runs}; \
+             sleep 0.3; echo listening \"on :808$n\"; exec sleep 1.5",
+            runs = runs.display()
+        );
+        let request = shell_request(
+            "listen-probe",
+            &script,
+            json!({
+                "listen_address_probe": {
+                    "type": "log_pattern",
+                    "pattern": "listening on {address}"
+                }
+            }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+        let address = |manager: &CoreManager| manager.get_process(&id).unwrap().listen_address;
+
+        manager.start_process(&id).unwrap();
+        assert_eq!(address(&manager), None);
+        assert!(wait_for(Duration::from_secs(5), || {
+            address(&manager).as_deref() == Some(":8081")
+        }));
+        assert!(wait_for(Duration::from_secs(5), || {
+            !manager.get_process(&id).unwrap().is_running
+        }));
+
+        // The next run does not report the previous run's address
+        manager.start_process(&id).unwrap();
+        assert_eq!(address(&manager), None);
+        assert!(wait_for(Duration::from_secs(5), || {
+            address(&manager).as_deref() == Some(":8082")
+        }));
+        manager.stop_process(&id).unwrap();
+    }
+}
@@ -0,0 +1,129 @@
+use super::data::ProcessConfig;
+use anyhow::{Context, Result};
+use flate2::{Compression, read::GzDecoder, write::GzEncoder};
+use serde_json::json;
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const REDACTED: &str = "***";
+/// Shorter environment variable values are flags and the like rather than
+/// secrets, and masking them would garble unrelated log text.
+const MIN_REDACTED_LEN: usize = 6;
+
+/// `config` with the values of its environment variables masked, as those
+/// commonly hold tokens and passwords.
+fn redacted_config(config: &ProcessConfig) -> ProcessConfig {
+    let mut config = config.clone();
+    if let Some(env_vars) = config.env_vars.as_mut() {
+        for value in env_vars.values_mut() {
+            *value = REDACTED.to_string();
+        }
+    }
+    config
+}
+
+/// Writes a gzipped tarball to `dest` with a `manifest.json` describing the
+/// process and each of `log_files` under `logs/`. With `redact`, the values
+/// of the process's environment variables are masked in the logs as well;
+/// compressed segments are then stored decompressed.
+pub fn write_archive(
+    dest: &Path,
+    config: &ProcessConfig,
+    log_files: &[PathBuf],
+    redact: bool,
+) -> Result<()> {
+    let mut secrets: Vec<&str> = match (&config.env_vars, redact) {
+        (Some(env_vars), true) => env_vars
+            .values()
+            .map(String::as_str)
+            .filter(|value| value.chars().count() >= MIN_REDACTED_LEN)
+            .collect(),
+        _ => Vec::new(),
+    };
+    // A secret that contains another one has to be masked first
+    secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+
+    let entries: Vec<(&PathBuf, String, bool)> = log_files
+        .iter()
+        .filter_map(|path| {
+            let file_name = path.file_name()?.to_string_lossy().into_owned();
+            let decompress = redact && file_name.ends_with(".gz");
+            let name = match file_name.strip_suffix(".gz") {
+                Some(stem) if decompress => format!("logs/{stem}"),
+                _ => format!("logs/{file_name}"),
+            };
+            Some((path, name, decompress))
+        })
+        .collect();
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let manifest = json!({
+        "process": redacted_config(config),
+        "exported_at": now,
+        "redacted": redact,
+        "files": entries.iter().map(|(_, name, _)| name).collect::<Vec<_>>(),
+    });
+
+    let file = File::create(dest).with_context(|| format!("Failed to create archive: {dest:?}"))?;
+    let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    append_bytes(
+        &mut archive,
+        "manifest.json",
+        &serde_json::to_vec_pretty(&manifest)?,
+        now,
+    )?;
+
+    for (path, name, decompress) in entries {
+        if !redact {
+            archive
+                .append_path_with_name(path, &name)
+                .with_context(|| format!("Failed to add log file: {path:?}"))?;
+            continue;
+        }
+
+        let mut file =
+            File::open(path).with_context(|| format!("Failed to open log file: {path:?}"))?;
+        let mut bytes = Vec::new();
+        if decompress {
+            GzDecoder::new(file).read_to_end(&mut bytes)
+        } else {
+            file.read_to_end(&mut bytes)
+        }
+        .with_context(|| format!("Failed to read log file: {path:?}"))?;
+
+        let mut text = String::from_utf8_lossy(&bytes).into_owned();
+        for secret in &secrets {
+            text = text.replace(secret, REDACTED);
+        }
+        append_bytes(&mut archive, &name, text.as_bytes(), now)?;
+    }
+
+    archive
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .with_context(|| format!("Failed to write archive: {dest:?}"))?;
+    Ok(())
+}
+
+fn append_bytes(
+    archive: &mut tar::Builder<GzEncoder<File>>,
+    name: &str,
+    bytes: &[u8],
+    mtime: u64,
+) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    archive
+        .append_data(&mut header, name, bytes)
+        .with_context(|| format!("Failed to add {name} to the archive"))
+}
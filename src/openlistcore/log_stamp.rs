@@ -0,0 +1,200 @@
+use super::{data::LogTimestampFormat, logs::utc_date};
+use log::warn;
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Wall-clock time read once when a run starts and advanced with the
+/// monotonic clock from there, so the timestamps of a run never go backwards
+/// when the system clock is adjusted.
+#[derive(Debug, Clone, Copy)]
+pub struct LogClock {
+    since_epoch: Duration,
+    started: Instant,
+}
+
+impl LogClock {
+    pub fn start() -> Self {
+        Self {
+            since_epoch: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            started: Instant::now(),
+        }
+    }
+
+    fn now(&self) -> Duration {
+        self.since_epoch + self.started.elapsed()
+    }
+}
+
+pub fn format_timestamp(format: LogTimestampFormat, since_epoch: Duration) -> String {
+    let secs = since_epoch.as_secs();
+    let millis = since_epoch.subsec_millis();
+    let (hours, minutes, seconds) = (secs % 86_400 / 3600, secs % 3600 / 60, secs % 60);
+    match format {
+        LogTimestampFormat::Rfc3339 => format!(
+            "{}T{hours:02}:{minutes:02}:{seconds:02}.{millis:03}Z",
+            utc_date(secs)
+        ),
+        LogTimestampFormat::Datetime => format!(
+            "{} {hours:02}:{minutes:02}:{seconds:02}.{millis:03}",
+            utc_date(secs)
+        ),
+        LogTimestampFormat::UnixMillis => since_epoch.as_millis().to_string(),
+    }
+}
+
+/// A log that output of the process is copied into, with each line prefixed
+/// by the time its first byte was received when `log_timestamps` is set.
+pub struct StampedLog {
+    log: File,
+    stamp: Option<(LogTimestampFormat, LogClock)>,
+    at_line_start: bool,
+}
+
+impl StampedLog {
+    pub fn new(log: File, format: Option<LogTimestampFormat>, clock: LogClock) -> Self {
+        Self {
+            log,
+            stamp: format.map(|format| (format, clock)),
+            at_line_start: true,
+        }
+    }
+
+    pub fn write_chunk(&mut self, mut data: &[u8]) -> io::Result<()> {
+        let Some((format, clock)) = self.stamp else {
+            return self.log.write_all(data);
+        };
+        let mut stamped = Vec::with_capacity(data.len() + 32);
+        while !data.is_empty() {
+            if self.at_line_start {
+                stamped.extend_from_slice(format_timestamp(format, clock.now()).as_bytes());
+                stamped.push(b' ');
+                self.at_line_start = false;
+            }
+            let end = match data.iter().position(|&b| b == b'\n') {
+                Some(newline) => {
+                    self.at_line_start = true;
+                    newline + 1
+                }
+                None => data.len(),
+            };
+            stamped.extend_from_slice(&data[..end]);
+            data = &data[end..];
+        }
+        self.log.write_all(&stamped)
+    }
+}
+
+/// Copies `output` of the process into `log` in the background until the
+/// process closes it.
+pub fn forward(mut output: impl Read + Send + 'static, mut log: StampedLog) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        loop {
+            match output.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => {
+                    if let Err(e) = log.write_chunk(&buf[..read]) {
+                        warn!("Failed to write process output to the log: {e}");
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => break,
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_are_formatted_in_utc() {
+        // 2024-02-29 23:59:58.042 UTC
+        let at = Duration::from_millis(1_709_251_198_042);
+        assert_eq!(
+            format_timestamp(LogTimestampFormat::Rfc3339, at),
+            "2024-02-29T23:59:58.042Z"
+        );
+        assert_eq!(
+            format_timestamp(LogTimestampFormat::Datetime, at),
+            "2024-02-29 23:59:58.042"
+        );
+        assert_eq!(
+            format_timestamp(LogTimestampFormat::UnixMillis, at),
+            "1709251198042"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn every_line_is_stamped_when_received() {
+        use crate::openlistcore::{
+            data::CoreManager,
+            test_support::{self, shell_request, wait_for},
+        };
+        use serde_json::json;
+
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let script =
+            "echo one; sleep 0.2; echo two >&2; sleep 0.2; printf 'three\\nfour\\n'; exec sleep 30";
+        let id = manager
+            .create_process(shell_request(
+                "stamped",
+                script,
+                json!({ "log_timestamps": "unix_millis" }),
+            ))
+            .unwrap()
+            .id;
+        let since_epoch = || SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+
+        let started = since_epoch().as_millis();
+        manager.start_process(&id).unwrap();
+        let read_log = |manager: &CoreManager| {
+            manager
+                .get_process_logs(&id, None, false, None)
+                .unwrap()
+                .log_content
+        };
+        assert!(wait_for(Duration::from_secs(5), || {
+            read_log(&manager)
+                .lines()
+                .any(|line| line.ends_with(" four"))
+        }));
+        let finished = since_epoch().as_millis();
+        let log = read_log(&manager);
+        manager.stop_process(&id).unwrap();
+
+        // After the service's own line naming the command
+        let stamped: Vec<(u128, &str)> = log
+            .lines()
+            .skip_while(|line| !line.starts_with("Spawning process"))
+            .skip(1)
+            .map(|line| {
+                let (stamp, text) = line.split_once(' ').unwrap();
+                (stamp.parse().unwrap(), text)
+            })
+            .collect();
+        let texts: Vec<&str> = stamped.iter().map(|(_, text)| *text).collect();
+        assert_eq!(texts, ["one", "two", "three", "four"], "{log}");
+        let stamps: Vec<u128> = stamped.iter().map(|(stamp, _)| *stamp).collect();
+        assert!(
+            stamps.windows(2).all(|pair| pair[0] <= pair[1]),
+            "{stamps:?}"
+        );
+        // The sleeps in between show up in the stamps
+        assert!(
+            stamps[1] - stamps[0] >= 150 && stamps[2] - stamps[1] >= 150,
+            "{stamps:?}"
+        );
+        assert!(started <= stamps[0] && stamps[3] <= finished, "{stamps:?}");
+    }
+}
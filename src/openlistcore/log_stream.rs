@@ -0,0 +1,252 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    time::Duration,
+};
+
+use futures::Stream;
+use log::debug;
+
+use super::{
+    data::{DEFAULT_MAX_LOG_LINE_BYTES, LogPosition, ManagerHandle},
+    logs,
+    logs::LineSplitter,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+const READ_CHUNK_BYTES: usize = 64 * 1024;
+
+pub enum FollowEvent {
+    /// A new line, with the position right after it to resume from.
+    Line(String, LogPosition),
+    /// The log was rotated, truncated or replaced, so following starts over
+    /// at the beginning of the new file.
+    Rotated,
+}
+
+/// Follows a process's log from its current end, or from `resume` when that
+/// is still in the current file, and yields each new line. Lines are read
+/// from the file only as fast as the consumer takes them, so a slow
+/// subscriber falls behind on its own without ever blocking the process.
+/// Ends when the process is deleted, or `manager` is.
+pub fn follow(
+    manager: ManagerHandle,
+    id: String,
+    resume: Option<LogPosition>,
+) -> impl Stream<Item = FollowEvent> {
+    // Without a manager the first poll ends the stream anyway
+    let max_line_bytes = manager
+        .upgrade()
+        .map_or(DEFAULT_MAX_LOG_LINE_BYTES, |manager| {
+            manager.lock().get_settings().max_log_line_bytes
+        });
+    let tail = Tail {
+        manager,
+        id,
+        path: None,
+        file_id: None,
+        resume,
+        offset: 0,
+        splitter: LineSplitter::new(max_line_bytes),
+        ready: VecDeque::new(),
+    };
+
+    futures::stream::unfold(tail, |mut tail| async move {
+        loop {
+            if let Some(event) = tail.ready.pop_front() {
+                return Some((event, tail));
+            }
+            match tail.poll() {
+                Ok(true) => continue,
+                Ok(false) => tokio::time::sleep(POLL_INTERVAL).await,
+                Err(e) => {
+                    debug!("Stopped following logs of process {}: {e}", tail.id);
+                    return None;
+                }
+            }
+        }
+    })
+}
+
+struct Tail {
+    manager: ManagerHandle,
+    id: String,
+    path: Option<String>,
+    /// `None` while there is no log file.
+    file_id: Option<u64>,
+    /// Where the subscriber left off, until the first poll.
+    resume: Option<LogPosition>,
+    offset: u64,
+    splitter: LineSplitter,
+    ready: VecDeque<FollowEvent>,
+}
+
+impl Tail {
+    /// Reads the next chunk of the log, if any. Returns whether anything was
+    /// read and fails once the process no longer exists.
+    fn poll(&mut self) -> io::Result<bool> {
+        let manager = self
+            .manager
+            .upgrade()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Manager is gone"))?;
+        let path = manager
+            .lock()
+            .active_log_path(&self.id)
+            .map_err(|e| io::Error::new(io::ErrorKind::NotFound, e.to_string()))?;
+        let metadata = std::fs::metadata(&path).ok();
+        let len = metadata.as_ref().map_or(0, |m| m.len());
+        let file_id = metadata.as_ref().map(logs::file_id);
+
+        match &self.path {
+            None => match self.resume.take() {
+                Some(resume) if file_id == Some(resume.file_id) && resume.offset <= len => {
+                    self.offset = resume.offset;
+                }
+                // Whatever was left of the log the subscriber knew is gone
+                Some(_) => self.start_over(),
+                // Only lines written after subscribing are streamed
+                None => self.offset = len,
+            },
+            // A new run may log to a freshly resolved path, and a rotated log
+            // is moved away and replaced by a new file
+            Some(current)
+                if *current != path || (self.file_id.is_some() && self.file_id != file_id) =>
+            {
+                self.start_over()
+            }
+            // Truncated in place
+            _ if len < self.offset => self.start_over(),
+            _ => {}
+        }
+        self.path = Some(path.clone());
+        self.file_id = file_id;
+
+        let Some(file_id) = file_id.filter(|_| len > self.offset) else {
+            return Ok(!self.ready.is_empty());
+        };
+
+        let mut file = File::open(&path)?;
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut buf = vec![0u8; READ_CHUNK_BYTES];
+        let n = file.read(&mut buf)?;
+        let ends = buf[..n]
+            .iter()
+            .enumerate()
+            .filter(|&(_, &b)| b == b'\n')
+            .map(|(i, _)| self.offset + i as u64 + 1);
+        // The splitter yields exactly one line per newline
+        let lines = self.splitter.push(&buf[..n]);
+        self.ready.extend(
+            lines
+                .into_iter()
+                .zip(ends)
+                .map(|(line, offset)| FollowEvent::Line(line, LogPosition { file_id, offset })),
+        );
+        self.offset += n as u64;
+        Ok(n > 0)
+    }
+
+    fn start_over(&mut self) {
+        self.offset = 0;
+        self.splitter.reset();
+        self.ready.push_back(FollowEvent::Rotated);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::openlistcore::data::CoreManager;
+    use crate::openlistcore::test_support::{self, create_request, existing_binary, scratch_dir};
+    use futures::StreamExt;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    /// The next `count` events of `stream`, lines as their text.
+    fn next_events(
+        runtime: &tokio::runtime::Runtime,
+        stream: &mut (impl Stream<Item = FollowEvent> + Unpin),
+        count: usize,
+    ) -> Vec<(String, Option<LogPosition>)> {
+        runtime.block_on(async {
+            let mut events = Vec::new();
+            while events.len() < count {
+                let event = tokio::time::timeout(Duration::from_secs(5), stream.next())
+                    .await
+                    .expect("no log event within 5s")
+                    .unwrap();
+                events.push(match event {
+                    FollowEvent::Line(line, position) => (line, Some(position)),
+                    FollowEvent::Rotated => ("rotated".to_string(), None),
+                });
+            }
+            events
+        })
+    }
+
+    #[test]
+    fn following_resumes_from_an_offset_and_starts_over_after_rotation() {
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        let log_file = scratch_dir("follow-resume").join("process.log");
+        std::fs::write(&log_file, "one\ntwo\n").unwrap();
+        let id = manager
+            .lock()
+            .create_process(create_request(
+                "follow-resume",
+                &existing_binary(),
+                json!({ "log_file": log_file.to_string_lossy() }),
+            ))
+            .unwrap()
+            .id;
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+
+        let consumed = manager.lock().get_log_offset(&id).unwrap().position;
+        assert_eq!(consumed.offset, 8);
+        // Written while the client was away
+        std::fs::write(&log_file, "one\ntwo\nthree\n").unwrap();
+        let mut resumed = Box::pin(follow(Arc::downgrade(&manager), id.clone(), Some(consumed)));
+        let events = next_events(&runtime, &mut resumed, 1);
+        let after_three = LogPosition {
+            file_id: consumed.file_id,
+            offset: 14,
+        };
+        assert_eq!(events, [("three".to_string(), Some(after_three))]);
+
+        // Rotated: the log is moved away and a new one takes its place
+        std::fs::rename(&log_file, log_file.with_extension("log.1")).unwrap();
+        std::fs::write(&log_file, "four\n").unwrap();
+        let new_file_id = manager.lock().get_log_offset(&id).unwrap().position.file_id;
+        assert_ne!(new_file_id, consumed.file_id);
+        let after_four = LogPosition {
+            file_id: new_file_id,
+            offset: 5,
+        };
+        let events = next_events(&runtime, &mut resumed, 2);
+        assert_eq!(
+            events,
+            [
+                ("rotated".to_string(), None),
+                ("four".to_string(), Some(after_four)),
+            ]
+        );
+
+        // A position in the rotated log no longer applies after reconnecting
+        let mut reconnected = Box::pin(follow(
+            Arc::downgrade(&manager),
+            id.clone(),
+            Some(after_three),
+        ));
+        let events = next_events(&runtime, &mut reconnected, 2);
+        assert_eq!(
+            events,
+            [
+                ("rotated".to_string(), None),
+                ("four".to_string(), Some(after_four)),
+            ]
+        );
+
+        manager.lock().delete_process(&id).unwrap();
+    }
+}
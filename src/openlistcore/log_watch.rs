@@ -0,0 +1,248 @@
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use log::{error, info};
+use regex::RegexSet;
+
+use super::{
+    data::{ManagerHandle, ProcessConfig, ProcessRuntime},
+    logs::LineSplitter,
+    monitor::MonitorThread,
+    process::is_process_running,
+    readiness::is_current_run,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Restarts triggered by log lines are at least this far apart, so a process
+/// that logs a matching line every time it starts is not restarted in a loop.
+const RESTART_COOLDOWN: Duration = Duration::from_secs(60);
+const MAX_LINE_BYTES: usize = 64 * 1024;
+const READ_CHUNK_BYTES: usize = 64 * 1024;
+
+/// Reads the logs of the run identified by `generation` as they are written
+/// and restarts the process once a line matches one of its
+/// `restart_on_log_pattern`s, for processes that log a fatal error but stay
+/// alive. `logs` are the run's log files with the offsets it starts writing
+/// at. A match within `RESTART_COOLDOWN` of the last such restart is acted
+/// on once the cooldown is over.
+pub fn spawn_watcher(
+    manager: ManagerHandle,
+    config: ProcessConfig,
+    runtime: ProcessRuntime,
+    generation: u64,
+    logs: Vec<(String, u64)>,
+) {
+    if config.restart_on_log_pattern.is_empty() {
+        return;
+    }
+    let patterns = match RegexSet::new(&config.restart_on_log_pattern) {
+        Ok(patterns) => patterns,
+        Err(e) => {
+            error!(
+                "Invalid restart_on_log_pattern of process {}: {e}",
+                config.name
+            );
+            return;
+        }
+    };
+
+    let spawned = std::thread::Builder::new()
+        .name(format!("log-watch-{}", config.id))
+        .spawn(move || {
+            let _active = MonitorThread::enter();
+            watch(&manager, &config, &runtime, generation, &patterns, logs)
+        });
+    if let Err(e) = spawned {
+        error!("Failed to start log pattern watcher: {e}");
+    }
+}
+
+fn watch(
+    manager: &ManagerHandle,
+    config: &ProcessConfig,
+    runtime: &ProcessRuntime,
+    generation: u64,
+    patterns: &RegexSet,
+    logs: Vec<(String, u64)>,
+) {
+    let mut tails: Vec<LogTail> = logs
+        .into_iter()
+        .enumerate()
+        .map(|(i, (path, offset))| LogTail {
+            path,
+            offset,
+            // The service's own line naming the command opens the run's log
+            skip_lines: usize::from(i == 0),
+            splitter: LineSplitter::new(MAX_LINE_BYTES),
+        })
+        .collect();
+    let mut matched: Option<String> = None;
+
+    while is_current_run(runtime, generation)
+        && is_process_running(runtime.running_pid.load(Ordering::Relaxed))
+    {
+        if matched.is_none() {
+            matched = tails.iter_mut().find_map(|tail| tail.find(patterns));
+            if let Some(line) = &matched
+                && cooling_down(runtime)
+            {
+                info!(
+                    "Process {} logged {line:?}, restarting it once the restart cooldown is over",
+                    config.name
+                );
+            }
+        }
+        if let Some(line) = &matched
+            && !cooling_down(runtime)
+        {
+            restart(manager, config, runtime, generation, line);
+            return;
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+fn cooling_down(runtime: &ProcessRuntime) -> bool {
+    runtime
+        .log_pattern_restarted_at
+        .lock()
+        .is_some_and(|at| at.elapsed() < RESTART_COOLDOWN)
+}
+
+fn restart(
+    manager: &ManagerHandle,
+    config: &ProcessConfig,
+    runtime: &ProcessRuntime,
+    generation: u64,
+    line: &str,
+) {
+    let Some(manager) = manager.upgrade() else {
+        return;
+    };
+    let mut core_manager = manager.lock();
+    // The run may have been stopped or restarted while waiting for the lock
+    if !is_current_run(runtime, generation) || !runtime.is_running.load(Ordering::Relaxed) {
+        return;
+    }
+    info!(
+        "Process {} logged {line:?}, which matches its restart_on_log_pattern, restarting it",
+        config.name
+    );
+    *runtime.log_pattern_restarted_at.lock() = Some(Instant::now());
+    match core_manager.restart_process(&config.id) {
+        Ok(()) => *runtime.last_error.lock() = Some(format!("Restarted after logging: {line}")),
+        Err(e) => error!(
+            "Failed to restart process {} after it logged {line:?}: {e}",
+            config.name
+        ),
+    }
+}
+
+struct LogTail {
+    path: String,
+    offset: u64,
+    /// Lines at the start that are not the process's output.
+    skip_lines: usize,
+    splitter: LineSplitter,
+}
+
+impl LogTail {
+    /// The first line logged since the last call that matches `patterns`.
+    fn find(&mut self, patterns: &RegexSet) -> Option<String> {
+        let mut file = File::open(&self.path).ok()?;
+        if file.metadata().ok()?.len() < self.offset {
+            // Rotated or truncated since the last look
+            self.offset = 0;
+            self.skip_lines = 0;
+            self.splitter.reset();
+        }
+        file.seek(SeekFrom::Start(self.offset)).ok()?;
+
+        let mut buf = vec![0u8; READ_CHUNK_BYTES];
+        loop {
+            let n = file.read(&mut buf).ok()?;
+            if n == 0 {
+                return None;
+            }
+            self.offset += n as u64;
+            let lines = self.splitter.push(&buf[..n]);
+            let skipped = self.skip_lines.min(lines.len());
+            self.skip_lines -= skipped;
+            if let Some(line) = lines
+                .into_iter()
+                .skip(skipped)
+                .find(|line| patterns.is_match(line))
+            {
+                return Some(line);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::openlistcore::data::CoreManager;
+    use crate::openlistcore::test_support::{self, shell_request, wait_for};
+    use serde_json::json;
+
+    #[test]
+    fn a_matching_line_restarts_the_process_once_per_cooldown() {
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        // Logs the fatal error on every start, then hangs. The service's
+        // line naming the command matches too, but is not the process's.
+        let request = shell_request(
+            "wedged",
+            "echo starting; sleep 0.3; echo 'FATAL: deadlock detected'; exec sleep 30",
+            json!({ "restart_on_log_pattern": ["FATAL: ", "panicked at"] }),
+        );
+        let id = {
+            let mut core_manager = manager.lock();
+            let id = core_manager.create_process(request).unwrap().id;
+            core_manager.start_process(&id).unwrap();
+            id
+        };
+        let status = || manager.lock().get_process(&id).unwrap();
+        let first_pid = status().pid.unwrap();
+        assert_eq!(status().restart_count, 0);
+
+        assert!(wait_for(Duration::from_secs(5), || status().restart_count == 1));
+        let restarted = status();
+        assert!(restarted.is_running);
+        assert_ne!(restarted.pid, Some(first_pid));
+        assert_eq!(
+            restarted.last_error.as_deref(),
+            Some("Restarted after logging: FATAL: deadlock detected")
+        );
+
+        // The new run logs the same line, but within the cooldown
+        std::thread::sleep(Duration::from_secs(1));
+        assert_eq!(status().restart_count, 1);
+        assert_eq!(status().pid, restarted.pid);
+
+        let mut core_manager = manager.lock();
+        core_manager.stop_process(&id).unwrap();
+        core_manager.delete_process(&id).unwrap();
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        let request = shell_request(
+            "bad-pattern",
+            "exec sleep 30",
+            json!({ "restart_on_log_pattern": ["fatal: (unclosed"] }),
+        );
+        let err = manager.lock().create_process(request).unwrap_err();
+        assert!(
+            err.to_string().contains("Invalid restart_on_log_pattern"),
+            "{err}"
+        );
+    }
+}
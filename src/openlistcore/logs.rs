@@ -0,0 +1,1028 @@
+use super::data::{LogLevel, LogStream, TaggedLogLine};
+use std::{
+    cmp::Ordering,
+    fs::{self, OpenOptions},
+    io::{self, BufRead, Read},
+    iter::Peekable,
+    path::{Path, PathBuf},
+    str::Chars,
+    time::{Duration, SystemTime},
+};
+
+pub const TRUNCATED_MARKER: &str = "…[truncated]";
+
+/// Reads all lines like `BufRead::lines`, but never holds more than
+/// `max_line_bytes` of a single line in memory. Longer lines are cut at a
+/// character boundary and suffixed with `TRUNCATED_MARKER`; the remainder is
+/// skipped without being buffered.
+pub fn read_lines_truncated<R: BufRead>(
+    mut reader: R,
+    max_line_bytes: usize,
+) -> io::Result<Vec<String>> {
+    let mut lines = Vec::new();
+    let mut line: Vec<u8> = Vec::new();
+    let mut truncated = false;
+
+    loop {
+        let available = match reader.fill_buf() {
+            Ok(buf) => buf,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        if available.is_empty() {
+            if !line.is_empty() || truncated {
+                lines.push(finish_line(&mut line, truncated));
+            }
+            return Ok(lines);
+        }
+
+        let (chunk, found_newline) = match available.iter().position(|&b| b == b'\n') {
+            Some(pos) => (&available[..pos], true),
+            None => (available, false),
+        };
+
+        let room = max_line_bytes.saturating_sub(line.len());
+        if chunk.len() > room {
+            truncated = true;
+        }
+        line.extend_from_slice(&chunk[..chunk.len().min(room)]);
+
+        let consumed = chunk.len() + usize::from(found_newline);
+        reader.consume(consumed);
+
+        if found_newline {
+            lines.push(finish_line(&mut line, truncated));
+            truncated = false;
+        }
+    }
+}
+
+/// Identifies a log file apart from its path, so that a rotated log is told
+/// from the one that replaced it: the inode on Unix, the creation time
+/// elsewhere.
+pub fn file_id(metadata: &fs::Metadata) -> u64 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        metadata.ino()
+    }
+    #[cfg(not(unix))]
+    {
+        metadata
+            .created()
+            .ok()
+            .and_then(|created| created.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_nanos() as u64)
+    }
+}
+
+/// Splits data that arrives in chunks, such as a log file being followed,
+/// into lines truncated the same way as `read_lines_truncated`. A trailing
+/// partial line is held back until its newline arrives.
+pub struct LineSplitter {
+    line: Vec<u8>,
+    truncated: bool,
+    max_line_bytes: usize,
+}
+
+impl LineSplitter {
+    pub fn new(max_line_bytes: usize) -> Self {
+        Self {
+            line: Vec::new(),
+            truncated: false,
+            max_line_bytes,
+        }
+    }
+
+    pub fn push(&mut self, mut data: &[u8]) -> Vec<String> {
+        let mut lines = Vec::new();
+        while !data.is_empty() {
+            let (chunk, found_newline) = match data.iter().position(|&b| b == b'\n') {
+                Some(pos) => (&data[..pos], true),
+                None => (data, false),
+            };
+
+            let room = self.max_line_bytes.saturating_sub(self.line.len());
+            if chunk.len() > room {
+                self.truncated = true;
+            }
+            self.line.extend_from_slice(&chunk[..chunk.len().min(room)]);
+            data = &data[chunk.len() + usize::from(found_newline)..];
+
+            if found_newline {
+                lines.push(finish_line(&mut self.line, self.truncated));
+                self.truncated = false;
+            }
+        }
+        lines
+    }
+
+    /// Drops a held-back partial line, e.g. when the file was replaced.
+    pub fn reset(&mut self) {
+        self.line.clear();
+        self.truncated = false;
+    }
+
+    /// The held-back partial line, for when no more data will arrive.
+    pub fn finish(&mut self) -> Option<String> {
+        if self.line.is_empty() && !self.truncated {
+            return None;
+        }
+        let line = finish_line(&mut self.line, self.truncated);
+        self.truncated = false;
+        Some(line)
+    }
+}
+
+/// The lines of `reader` that `is_match` accepts, with their line numbers and
+/// truncated like `read_lines_truncated`. Reading stops after `max_bytes` or
+/// once `max_matches` lines matched, whichever comes first.
+pub fn search_lines<R: Read>(
+    reader: R,
+    max_line_bytes: usize,
+    max_bytes: u64,
+    max_matches: usize,
+    is_match: impl Fn(&str) -> bool,
+) -> io::Result<Vec<(usize, String)>> {
+    let mut reader = reader.take(max_bytes);
+    let mut splitter = LineSplitter::new(max_line_bytes);
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut line_number = 0;
+    let mut hits = Vec::new();
+
+    while hits.len() < max_matches {
+        let read = match reader.read(&mut buf) {
+            Ok(read) => read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let lines = if read == 0 {
+            splitter.finish().into_iter().collect()
+        } else {
+            splitter.push(&buf[..read])
+        };
+        for line in lines {
+            line_number += 1;
+            if hits.len() < max_matches && is_match(&line) {
+                hits.push((line_number, line));
+            }
+        }
+        if read == 0 {
+            break;
+        }
+    }
+    Ok(hits)
+}
+
+fn finish_line(line: &mut Vec<u8>, truncated: bool) -> String {
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    if !truncated {
+        let text = String::from_utf8_lossy(line).into_owned();
+        line.clear();
+        return text;
+    }
+
+    // Don't leave half of a multi-byte character in front of the marker
+    let end = match std::str::from_utf8(line) {
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        _ => line.len(),
+    };
+    let mut text = String::from_utf8_lossy(&line[..end]).into_owned();
+    text.push_str(TRUNCATED_MARKER);
+
+    line.clear();
+    text
+}
+
+/// Expands the `{name}`, `{id}` and `{date}` placeholders of a `log_file`
+/// template. `{date}` is the UTC date of `now` as `YYYY-MM-DD`, so a template
+/// using it starts a new file on the first start of each day.
+pub fn resolve_log_path(template: &str, name: &str, id: &str, now: u64) -> String {
+    if !template.contains('{') {
+        return template.to_string();
+    }
+    resolve_name_and_id(template, name, id).replace(DATE_PLACEHOLDER, &utc_date(now))
+}
+
+const DATE_PLACEHOLDER: &str = "{date}";
+
+fn resolve_name_and_id(template: &str, name: &str, id: &str) -> String {
+    template
+        .replace("{name}", &sanitize_file_name(name))
+        .replace("{id}", id)
+}
+
+/// Where one process keeps its logs: the file it writes to now and, for a
+/// `log_file` with `{date}` in its file name, the files of other days.
+pub struct LogLocation {
+    pub active: PathBuf,
+    /// The file name template with everything but `{date}` resolved, split
+    /// around the date.
+    dated: Option<(String, String)>,
+}
+
+impl LogLocation {
+    pub fn new(active: PathBuf, template: &str, name: &str, id: &str) -> Self {
+        let template = resolve_name_and_id(template, name, id);
+        let dated = Path::new(&template)
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy())
+            .and_then(|file_name| {
+                let (prefix, suffix) = file_name.split_once(DATE_PLACEHOLDER)?;
+                Some((prefix.to_string(), suffix.to_string()))
+            });
+        Self { active, dated }
+    }
+
+    /// Whether `file_name` is the log of some day other than the live one.
+    fn is_other_day(&self, file_name: &str) -> bool {
+        let Some((prefix, suffix)) = &self.dated else {
+            return false;
+        };
+        if self
+            .active
+            .file_name()
+            .is_some_and(|active| active == file_name)
+        {
+            return false;
+        }
+        file_name
+            .strip_prefix(prefix.as_str())
+            .and_then(|rest| rest.strip_suffix(suffix.as_str()))
+            .is_some_and(is_date)
+    }
+
+    /// The live log, if it exists yet, followed by its archived logs.
+    pub fn files(&self) -> io::Result<Vec<PathBuf>> {
+        let mut files: Vec<PathBuf> = Some(self.active.clone())
+            .filter(|active| active.is_file())
+            .into_iter()
+            .collect();
+        files.extend(self.archived()?.into_iter().map(|segment| segment.path));
+        Ok(files)
+    }
+
+    /// Every log file of the process except the live one: rotated segments of
+    /// the live log and the files of other days along with their segments.
+    fn archived(&self) -> io::Result<Vec<Segment>> {
+        let mut archived = Vec::new();
+        let mut bases = vec![self.active.clone()];
+        if self.dated.is_some() {
+            for file in log_dir_files(&self.active)? {
+                if self.is_other_day(&file.file_name) {
+                    bases.push(file.segment.path.clone());
+                    archived.push(file.segment);
+                }
+            }
+        }
+        for base in bases {
+            archived.extend(rotated_segments(&base)?);
+        }
+        Ok(archived)
+    }
+}
+
+/// `YYYY-MM-DD`, as produced by `utc_date`.
+fn is_date(text: &str) -> bool {
+    text.len() == 10
+        && text.bytes().enumerate().all(|(i, b)| match i {
+            4 | 7 => b == b'-',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+/// Keeps a process name from escaping into other directories or using
+/// characters that are invalid in file names.
+fn sanitize_file_name(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+pub fn utc_date(unix_secs: u64) -> String {
+    // Civil-from-days, see http://howardhinnant.github.io/date_algorithms.html
+    let days = (unix_secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+/// Whether `file_name` is an archived segment of `base`: `<base>.N` or
+/// `<base>.N.gz`.
+fn is_rotated_segment(file_name: &str, base: &str) -> bool {
+    let Some(suffix) = file_name
+        .strip_prefix(base)
+        .and_then(|rest| rest.strip_prefix('.'))
+    else {
+        return false;
+    };
+    let index = suffix.strip_suffix(".gz").unwrap_or(suffix);
+    !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Deletes the archived logs of `location` (rotated segments and the files of
+/// earlier days) whose modification time is older than `max_age`. The live
+/// log itself is never touched. Returns the removed paths.
+pub fn remove_expired_segments(
+    location: &LogLocation,
+    max_age: Duration,
+) -> io::Result<Vec<PathBuf>> {
+    let Some(cutoff) = SystemTime::now().checked_sub(max_age) else {
+        return Ok(Vec::new());
+    };
+
+    let mut removed = Vec::new();
+    for segment in location.archived()? {
+        if segment.modified >= cutoff {
+            continue;
+        }
+        fs::remove_file(&segment.path)?;
+        removed.push(segment.path);
+    }
+    Ok(removed)
+}
+
+/// What `enforce_total_size` did to get under the limit.
+#[derive(Debug, Default)]
+pub struct Eviction {
+    pub removed: Vec<PathBuf>,
+    pub truncated: Vec<PathBuf>,
+}
+
+/// Deletes archived logs of all `locations`, oldest first, until the live
+/// logs and their archives together take up at most `max_total_bytes`. When
+/// that is not enough, live logs are emptied, least recently written first.
+/// They are never removed, and processes append to them, so writing just
+/// carries on at the start of the emptied file.
+pub fn enforce_total_size(locations: &[LogLocation], max_total_bytes: u64) -> io::Result<Eviction> {
+    let mut live_files: Vec<&PathBuf> = locations.iter().map(|l| &l.active).collect();
+    live_files.sort();
+    live_files.dedup();
+
+    let mut live: Vec<(&PathBuf, Segment)> = live_files
+        .iter()
+        .filter_map(|path| {
+            let metadata = fs::metadata(path).ok()?;
+            let segment = Segment {
+                path: (*path).clone(),
+                len: metadata.len(),
+                modified: metadata.modified().ok()?,
+            };
+            Some((*path, segment))
+        })
+        .collect();
+    let mut total: u64 = live.iter().map(|(_, segment)| segment.len).sum();
+    let mut segments = Vec::new();
+    for location in locations {
+        segments.extend(location.archived()?);
+    }
+    // Another process's live log is never evicted as one of our days
+    segments.retain(|segment| !live_files.contains(&&segment.path));
+    segments.sort_by(|a, b| a.path.cmp(&b.path));
+    segments.dedup_by(|a, b| a.path == b.path);
+    total += segments.iter().map(|segment| segment.len).sum::<u64>();
+
+    segments.sort_by_key(|segment| segment.modified);
+    let mut eviction = Eviction::default();
+    for segment in segments {
+        if total <= max_total_bytes {
+            return Ok(eviction);
+        }
+        fs::remove_file(&segment.path)?;
+        total = total.saturating_sub(segment.len);
+        eviction.removed.push(segment.path);
+    }
+
+    live.sort_by_key(|(_, segment)| segment.modified);
+    for (path, segment) in live {
+        if total <= max_total_bytes {
+            break;
+        }
+        if segment.len == 0 {
+            continue;
+        }
+        OpenOptions::new().write(true).open(path)?.set_len(0)?;
+        total = total.saturating_sub(segment.len);
+        eviction.truncated.push(segment.path);
+    }
+    Ok(eviction)
+}
+
+struct Segment {
+    path: PathBuf,
+    len: u64,
+    modified: SystemTime,
+}
+
+/// The archived segments of `log_file` that exist next to it.
+fn rotated_segments(log_file: &Path) -> io::Result<Vec<Segment>> {
+    let Some(base) = log_file.file_name() else {
+        return Ok(Vec::new());
+    };
+    let base = base.to_string_lossy();
+    Ok(log_dir_files(log_file)?
+        .into_iter()
+        .filter(|file| is_rotated_segment(&file.file_name, &base))
+        .map(|file| file.segment)
+        .collect())
+}
+
+struct DirFile {
+    file_name: String,
+    segment: Segment,
+}
+
+/// The regular files in the directory holding `log_file`.
+fn log_dir_files(log_file: &Path) -> io::Result<Vec<DirFile>> {
+    let Some(dir) = log_file.parent() else {
+        return Ok(Vec::new());
+    };
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        files.push(DirFile {
+            file_name: entry.file_name().to_string_lossy().into_owned(),
+            segment: Segment {
+                path: entry.path(),
+                len: metadata.len(),
+                modified: metadata.modified()?,
+            },
+        });
+    }
+    Ok(files)
+}
+
+/// Interleaves a process's stdout and stderr lines. Lines are ordered by a
+/// `YYYY-MM-DD HH:MM:SS` timestamp near their start; lines without one (such
+/// as the rest of a stack trace) stay with the line before them. Where the
+/// order cannot be told, stdout comes first. Each stream keeps its own order.
+pub fn merge_streams(stdout: Vec<String>, stderr: Vec<String>) -> Vec<TaggedLogLine> {
+    let keyed = |lines: Vec<String>| {
+        let mut last = None;
+        lines
+            .into_iter()
+            .map(|line| {
+                if let Some(timestamp) = line_timestamp(&line) {
+                    last = Some(timestamp);
+                }
+                (last.clone(), line)
+            })
+            .collect::<Vec<_>>()
+    };
+    let stdout = keyed(stdout);
+    let stderr = keyed(stderr);
+
+    let mut merged = Vec::with_capacity(stdout.len() + stderr.len());
+    let mut stdout = stdout.into_iter().peekable();
+    let mut stderr = stderr.into_iter().peekable();
+    loop {
+        let take_stdout = match (stdout.peek(), stderr.peek()) {
+            (None, None) => break,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (Some((Some(out), _)), Some((Some(err), _))) => out.cmp(err) != Ordering::Greater,
+            (Some(_), Some(_)) => true,
+        };
+        let (stream, next) = if take_stdout {
+            (LogStream::Stdout, stdout.next())
+        } else {
+            (LogStream::Stderr, stderr.next())
+        };
+        let Some((_, line)) = next else {
+            break;
+        };
+        merged.push(TaggedLogLine {
+            stream,
+            line,
+            level: None,
+        });
+    }
+    merged
+}
+
+/// How far into a line a timestamp is looked for, to skip level prefixes
+/// like `INFO[` without scanning whole messages.
+const TIMESTAMP_SEARCH_BYTES: usize = 64;
+
+/// Finds a `YYYY-MM-DD HH:MM:SS[.fff]` timestamp (`/` and `T` separators are
+/// accepted too) near the start of `line` and returns its digits as a key that
+/// sorts chronologically.
+fn line_timestamp(line: &str) -> Option<String> {
+    let bytes = line.as_bytes();
+    let search = bytes.len().min(TIMESTAMP_SEARCH_BYTES);
+    (0..search).find_map(|start| parse_timestamp(&bytes[start..]))
+}
+
+fn parse_timestamp(bytes: &[u8]) -> Option<String> {
+    // Digits, and the separator allowed after each group
+    const LAYOUT: [(usize, &[u8]); 6] = [
+        (4, b"-/"),
+        (2, b"-/"),
+        (2, b" T"),
+        (2, b":"),
+        (2, b":"),
+        (2, b""),
+    ];
+    let mut key = String::with_capacity(23);
+    let mut pos = 0;
+    for (digits, separators) in LAYOUT {
+        let group = bytes.get(pos..pos + digits)?;
+        if !group.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        key.extend(group.iter().map(|&b| b as char));
+        pos += digits;
+        if !separators.is_empty() {
+            if !separators.contains(bytes.get(pos)?) {
+                return None;
+            }
+            pos += 1;
+        }
+    }
+
+    // Fractional seconds, padded so that precisions compare correctly
+    let mut fraction = String::new();
+    if matches!(bytes.get(pos), Some(b'.' | b',')) {
+        fraction.extend(
+            bytes[pos + 1..]
+                .iter()
+                .take_while(|b| b.is_ascii_digit())
+                .take(9)
+                .map(|&b| b as char),
+        );
+    }
+    key.push_str(&format!("{fraction:0<9}"));
+    Some(key)
+}
+
+/// How far into a line a level keyword is looked for, past a timestamp and
+/// the like, so that words in the message itself are not taken for one.
+const LEVEL_SEARCH_BYTES: usize = 48;
+
+/// Guesses the level of `line` from the first level keyword near its start,
+/// such as `ERROR`, `[warn]` or `level=info`, in any case.
+pub fn infer_level(line: &str) -> Option<LogLevel> {
+    let mut end = line.len().min(LEVEL_SEARCH_BYTES);
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    line[..end]
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .find_map(|word| match word.to_ascii_lowercase().as_str() {
+            "fatal" | "panic" | "crit" | "critical" | "error" | "err" => Some(LogLevel::Error),
+            "warn" | "warning" => Some(LogLevel::Warn),
+            "info" | "notice" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            "trace" => Some(LogLevel::Trace),
+            _ => None,
+        })
+}
+
+/// Sets the level of each line with `infer_level`. Lines without a keyword,
+/// such as the rest of a stack trace, take the level of the line before them
+/// in the same stream.
+pub fn infer_levels(lines: &mut [TaggedLogLine]) {
+    let mut previous: Vec<(LogStream, LogLevel)> = Vec::new();
+    for line in lines {
+        line.level = infer_level(&strip_ansi(&line.line)).or_else(|| {
+            previous
+                .iter()
+                .find(|(stream, _)| *stream == line.stream)
+                .map(|(_, level)| *level)
+        });
+        if let Some(level) = line.level {
+            previous.retain(|(stream, _)| *stream != line.stream);
+            previous.push((line.stream, level));
+        }
+    }
+}
+
+/// How many of the last of `lines` fit into `max_bytes` once joined with
+/// newlines. Lines are only ever taken whole.
+pub fn lines_within(lines: &[String], max_bytes: usize) -> usize {
+    let mut used = 0;
+    let mut count = 0;
+    for line in lines.iter().rev() {
+        let needed = line.len() + usize::from(count > 0);
+        if used + needed > max_bytes {
+            break;
+        }
+        used += needed;
+        count += 1;
+    }
+    count
+}
+
+/// Removes ANSI escape sequences such as color codes from `line`, keeping
+/// the text around them: CSI sequences (`ESC [ ... m`), OSC sequences such as
+/// terminal titles and hyperlinks, and the shorter escapes.
+pub fn strip_ansi(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                Some('[') => skip_csi(&mut chars),
+                Some(']') => skip_osc(&mut chars),
+                // Character set selections and the like, e.g. `ESC ( B`
+                Some('\x20'..='\x2f') => {
+                    while chars.next_if(|c| matches!(c, '\x20'..='\x2f')).is_some() {}
+                    chars.next();
+                }
+                _ => {}
+            },
+            // The single-character form of `ESC [`
+            '\u{9b}' => skip_csi(&mut chars),
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+/// Skips parameters and intermediates up to and including the final byte.
+fn skip_csi(chars: &mut Peekable<Chars>) {
+    for c in chars.by_ref() {
+        if matches!(c, '\x40'..='\x7e') {
+            break;
+        }
+    }
+}
+
+/// Skips up to and including the terminating BEL or `ESC \\`.
+fn skip_osc(chars: &mut Peekable<Chars>) {
+    while let Some(c) = chars.next() {
+        match c {
+            '\x07' => break,
+            '\x1b' => {
+                chars.next_if_eq(&'\\');
+                break;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufReader, Read};
+
+    /// An endless-looking line: `len` bytes of `x` without a newline, produced
+    /// on the fly so the test itself never holds it in memory.
+    struct LongLine {
+        remaining: usize,
+    }
+
+    impl Read for LongLine {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.remaining);
+            buf[..n].fill(b'x');
+            self.remaining -= n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn pathological_lines_are_truncated_without_buffering_them() {
+        let huge = LongLine {
+            remaining: 64 * 1024 * 1024,
+        };
+        let input = b"before\n".chain(huge).chain(&b"\nafter\r\n"[..]);
+        let lines = read_lines_truncated(BufReader::with_capacity(4096, input), 16).unwrap();
+
+        assert_eq!(
+            lines,
+            [
+                "before",
+                &format!("{}{TRUNCATED_MARKER}", "x".repeat(16)),
+                "after"
+            ]
+        );
+    }
+
+    #[test]
+    fn truncation_does_not_split_characters() {
+        // Three-byte characters, cut after the 7th byte
+        let input = "€€€€\nok\n".as_bytes();
+        let lines = read_lines_truncated(input, 7).unwrap();
+        assert_eq!(lines, [format!("€€{TRUNCATED_MARKER}"), "ok".to_string()]);
+
+        let lines = read_lines_truncated(&b"exactly8"[..], 8).unwrap();
+        assert_eq!(lines, ["exactly8"]);
+    }
+
+    #[test]
+    fn splitter_truncates_lines_spread_over_many_chunks() {
+        let mut splitter = LineSplitter::new(10);
+        let mut lines = splitter.push(b"short\nthis line ");
+        for _ in 0..10_000 {
+            lines.extend(splitter.push(b"keeps going "));
+        }
+        assert_eq!(lines, ["short"]);
+        lines.extend(splitter.push(b"and ends\npartial"));
+        assert_eq!(
+            lines,
+            ["short".to_string(), format!("this line {TRUNCATED_MARKER}")]
+        );
+
+        splitter.reset();
+        assert_eq!(splitter.push(b"fresh\r\n"), ["fresh"]);
+    }
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Creates `name` in `dir` with `len` bytes, last modified `age` ago.
+    fn backdated(dir: &Path, name: &str, len: usize, age: Duration) -> PathBuf {
+        let path = dir.join(name);
+        let file = fs::File::create(&path).unwrap();
+        file.set_len(len as u64).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+        path
+    }
+
+    fn remaining(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn retention_removes_only_expired_rotated_segments() {
+        let dir = crate::openlistcore::test_support::scratch_dir("retention");
+        let live = backdated(&dir, "app.log", 10, DAY * 30);
+        backdated(&dir, "app.log.1", 10, DAY);
+        backdated(&dir, "app.log.2.gz", 10, DAY * 8);
+        backdated(&dir, "app.log.3", 10, DAY * 9);
+        backdated(&dir, "app.log.old", 10, DAY * 9);
+        backdated(&dir, "other.log.1", 10, DAY * 9);
+
+        let location = LogLocation::new(live, &dir.join("app.log").to_string_lossy(), "app", "1");
+        let mut removed = remove_expired_segments(&location, DAY * 7).unwrap();
+        removed.sort();
+
+        assert_eq!(removed, [dir.join("app.log.2.gz"), dir.join("app.log.3")]);
+        assert_eq!(
+            remaining(&dir),
+            ["app.log", "app.log.1", "app.log.old", "other.log.1"]
+        );
+    }
+
+    #[test]
+    fn retention_covers_earlier_days_of_dated_templates() {
+        let dir = crate::openlistcore::test_support::scratch_dir("dated-retention");
+        let template = dir.join("{name}-{date}.log").to_string_lossy().into_owned();
+        // The live file of a long-running process may itself be old
+        let live = backdated(&dir, "web-2024-06-01.log", 10, DAY * 30);
+        backdated(&dir, "web-2024-05-20.log", 10, DAY * 12);
+        backdated(&dir, "web-2024-05-20.log.1", 10, DAY * 12);
+        backdated(&dir, "web-2024-05-30.log", 10, DAY * 2);
+        backdated(&dir, "web-backup.log", 10, DAY * 12);
+        backdated(&dir, "api-2024-05-20.log", 10, DAY * 12);
+
+        let location = LogLocation::new(live, &template, "web", "1");
+        let mut removed = remove_expired_segments(&location, DAY * 7).unwrap();
+        removed.sort();
+
+        assert_eq!(
+            removed,
+            [
+                dir.join("web-2024-05-20.log"),
+                dir.join("web-2024-05-20.log.1")
+            ]
+        );
+        assert_eq!(
+            remaining(&dir),
+            [
+                "api-2024-05-20.log",
+                "web-2024-05-30.log",
+                "web-2024-06-01.log",
+                "web-backup.log"
+            ]
+        );
+    }
+
+    #[test]
+    fn placeholders_resolve_per_start() {
+        // 2024-06-01 12:00:00 UTC
+        let noon = 1_717_243_200;
+        assert_eq!(
+            resolve_log_path("/logs/{name}-{date}.log", "openlist", "abc", noon),
+            "/logs/openlist-2024-06-01.log"
+        );
+        assert_eq!(
+            resolve_log_path("{id}/{name}.{id}.log", "web", "abc", noon),
+            "abc/web.abc.log"
+        );
+        assert_eq!(
+            resolve_log_path(
+                "/logs/{name}-{date}.log",
+                "openlist",
+                "abc",
+                noon + 12 * 3600
+            ),
+            "/logs/openlist-2024-06-02.log"
+        );
+        assert_eq!(
+            resolve_log_path("/logs/plain.log", "openlist", "abc", noon),
+            "/logs/plain.log"
+        );
+        assert_eq!(
+            resolve_log_path("/logs/{unknown}.log", "openlist", "abc", noon),
+            "/logs/{unknown}.log"
+        );
+    }
+
+    #[test]
+    fn names_cannot_escape_the_log_directory() {
+        assert_eq!(
+            resolve_log_path("/logs/{name}.log", "../etc/pass:wd", "abc", 0),
+            "/logs/.._etc_pass_wd.log"
+        );
+        assert_eq!(
+            resolve_log_path("/logs/{name}.log", "tab\there", "abc", 0),
+            "/logs/tab_here.log"
+        );
+    }
+
+    #[test]
+    fn utc_dates_handle_leap_years_and_boundaries() {
+        assert_eq!(utc_date(0), "1970-01-01");
+        assert_eq!(utc_date(86_399), "1970-01-01");
+        assert_eq!(utc_date(86_400), "1970-01-02");
+        assert_eq!(utc_date(951_782_400), "2000-02-29");
+        assert_eq!(utc_date(1_709_164_800), "2024-02-29");
+        assert_eq!(utc_date(1_735_689_599), "2024-12-31");
+        assert_eq!(utc_date(4_102_444_800), "2100-01-01");
+    }
+
+    #[test]
+    fn total_size_evicts_oldest_archives_then_empties_live_logs() {
+        use std::io::Write;
+
+        let dir = crate::openlistcore::test_support::scratch_dir("total-size");
+        let a = backdated(&dir, "a.log", 100, DAY);
+        backdated(&dir, "a.log.1", 50, DAY * 3);
+        backdated(&dir, "a.log.2", 50, DAY * 5);
+        let b = backdated(&dir, "b.log", 100, DAY * 2);
+        backdated(&dir, "b.log.1", 50, DAY * 4);
+        let location = |live: &PathBuf, name: &str| {
+            LogLocation::new(live.clone(), &dir.join(name).to_string_lossy(), name, "1")
+        };
+        let locations = [location(&a, "a.log"), location(&b, "b.log")];
+        let mut writer = OpenOptions::new().append(true).open(&b).unwrap();
+
+        // 350 bytes in all: archives go first, oldest first
+        let eviction = enforce_total_size(&locations, 260).unwrap();
+        assert_eq!(eviction.removed, [dir.join("a.log.2"), dir.join("b.log.1")]);
+        assert!(eviction.truncated.is_empty());
+        assert_eq!(remaining(&dir), ["a.log", "a.log.1", "b.log"]);
+
+        // Then the least recently written live log is emptied, not removed
+        let eviction = enforce_total_size(&locations, 120).unwrap();
+        assert_eq!(eviction.removed, [dir.join("a.log.1")]);
+        assert_eq!(eviction.truncated, std::slice::from_ref(&b));
+        assert_eq!(remaining(&dir), ["a.log", "b.log"]);
+        assert_eq!(fs::metadata(&a).unwrap().len(), 100);
+        assert_eq!(fs::metadata(&b).unwrap().len(), 0);
+
+        // Appending writers carry on at the start of the emptied file
+        writer.write_all(b"after\n").unwrap();
+        assert_eq!(fs::read(&b).unwrap(), b"after\n");
+
+        let eviction = enforce_total_size(&locations, 120).unwrap();
+        assert!(eviction.removed.is_empty() && eviction.truncated.is_empty());
+    }
+
+    #[test]
+    fn ansi_sequences_are_stripped_and_text_is_kept() {
+        let cases = [
+            ("\x1b[31mred\x1b[0m plain", "red plain"),
+            ("\x1b[1;38;5;208mbold orange\x1b[m", "bold orange"),
+            ("\x1b[38;2;255;0;0mtrue\x1b[39m color", "true color"),
+            ("\x1b]0;title\x07after title", "after title"),
+            (
+                "\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x1b\\",
+                "link",
+            ),
+            ("\x1b(Bcharset\x1b7saved\x1b8", "charsetsaved"),
+            ("\u{9b}32mcsi\u{9b}0m", "csi"),
+            (
+                "2024-01-02 03:04:05 [INFO] 你好 plain",
+                "2024-01-02 03:04:05 [INFO] 你好 plain",
+            ),
+            ("cut off \x1b[3", "cut off "),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(strip_ansi(input), expected, "{input:?}");
+        }
+    }
+
+    #[test]
+    fn search_stops_at_the_match_and_byte_limits() {
+        let log = "ok\nerror: one\nok\nerror: two\nerror: three";
+        let hits = |max_bytes: u64, max_matches: usize| {
+            search_lines(log.as_bytes(), 1024, max_bytes, max_matches, |line| {
+                line.contains("error")
+            })
+            .unwrap()
+        };
+
+        assert_eq!(
+            hits(u64::MAX, 10),
+            vec![
+                (2, "error: one".to_string()),
+                (4, "error: two".to_string()),
+                (5, "error: three".to_string()),
+            ]
+        );
+        assert_eq!(hits(u64::MAX, 2).len(), 2);
+        // Only the first two lines fit in 14 bytes
+        assert_eq!(hits(14, 10), vec![(2, "error: one".to_string())]);
+    }
+
+    #[test]
+    fn levels_are_inferred_from_keywords_near_the_start() {
+        let cases = [
+            (
+                "2025-01-31 08:15:42 ERROR failed to bind",
+                Some(LogLevel::Error),
+            ),
+            ("FATAL[0000] cannot start", Some(LogLevel::Error)),
+            ("[warn] disk almost full", Some(LogLevel::Warn)),
+            (
+                "time=\"2025-01-31T08:15:42Z\" level=warning msg=slow",
+                Some(LogLevel::Warn),
+            ),
+            ("\x1b[36mINFO\x1b[0m[0000] listening", Some(LogLevel::Info)),
+            ("DEBUG cache hit", Some(LogLevel::Debug)),
+            ("trace: entering handler", Some(LogLevel::Trace)),
+            // Only whole words count, and only near the start
+            ("information about errors", None),
+            (
+                "a message that keeps going for quite a while before it says error",
+                None,
+            ),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(infer_level(&strip_ansi(line)), expected, "{line:?}");
+        }
+    }
+
+    #[test]
+    fn continuation_lines_take_the_level_of_their_stream() {
+        let tagged = |stream, line: &str| TaggedLogLine {
+            stream,
+            line: line.to_string(),
+            level: None,
+        };
+        let mut lines = [
+            tagged(LogStream::Stdout, "unleveled banner"),
+            tagged(LogStream::Stderr, "ERROR panic in worker"),
+            tagged(LogStream::Stdout, "INFO serving"),
+            tagged(LogStream::Stderr, "    at worker.rs:10"),
+            tagged(LogStream::Stdout, "still serving"),
+        ];
+        infer_levels(&mut lines);
+        let levels: Vec<_> = lines.iter().map(|line| line.level).collect();
+        assert_eq!(
+            levels,
+            [
+                None,
+                Some(LogLevel::Error),
+                Some(LogLevel::Info),
+                Some(LogLevel::Error),
+                Some(LogLevel::Info),
+            ]
+        );
+    }
+}
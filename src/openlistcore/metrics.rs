@@ -0,0 +1,436 @@
+use std::fmt::Write;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+
+use super::{
+    data::{ProcessState, ProcessStatus, ServiceResources},
+    monitor,
+};
+
+/// Kept across scrapes so CPU usage is measured over the interval between them.
+static SYSTEM: Lazy<Mutex<System>> = Lazy::new(|| Mutex::new(System::new()));
+
+struct ResourceUsage {
+    cpu_percent: f32,
+    memory_bytes: u64,
+}
+
+fn sample_resources(pids: &[u32]) -> Vec<Option<ResourceUsage>> {
+    let sys_pids: Vec<Pid> = pids.iter().map(|&pid| Pid::from_u32(pid)).collect();
+    let mut system = SYSTEM.lock();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&sys_pids),
+        true,
+        ProcessRefreshKind::nothing().with_cpu().with_memory(),
+    );
+
+    sys_pids
+        .iter()
+        .map(|pid| {
+            system.process(*pid).map(|process| ResourceUsage {
+                cpu_percent: process.cpu_usage(),
+                memory_bytes: process.memory(),
+            })
+        })
+        .collect()
+}
+
+/// Samples the service's own process. CPU usage is measured since the
+/// previous sample, so the first call reports 0.
+pub fn service_resources() -> ServiceResources {
+    let pid = std::process::id();
+    let sys_pid = Pid::from_u32(pid);
+    let mut system = SYSTEM.lock();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[sys_pid]),
+        true,
+        ProcessRefreshKind::nothing()
+            .with_cpu()
+            .with_memory()
+            .with_tasks(),
+    );
+
+    let process = system.process(sys_pid);
+    ServiceResources {
+        pid,
+        cpu_usage_percent: process.map_or(0.0, |p| p.cpu_usage()),
+        memory_bytes: process.map_or(0, |p| p.memory()),
+        // sysinfo lists every thread but the main one as a task.
+        thread_count: process.and_then(|p| p.tasks()).map(|tasks| tasks.len() + 1),
+        monitor_threads: monitor::active_monitor_threads(),
+    }
+}
+
+/// The command line of `pid` as the OS reports it, which is what actually
+/// runs after wrappers like sudo replaced or exec'd themselves. `None` when
+/// the process is gone or the OS does not let the service read it.
+pub fn command_line(pid: u32) -> Option<Vec<String>> {
+    let sys_pid = Pid::from_u32(pid);
+    let mut system = SYSTEM.lock();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[sys_pid]),
+        true,
+        ProcessRefreshKind::nothing().with_cmd(UpdateKind::Always),
+    );
+
+    let cmd = system.process(sys_pid)?.cmd();
+    (!cmd.is_empty()).then(|| {
+        cmd.iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    })
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+/// Renders the given process statuses in the Prometheus text exposition format.
+pub fn render(version: &str, statuses: &[ProcessStatus], now: u64) -> String {
+    let labels: Vec<String> = statuses
+        .iter()
+        .map(|s| {
+            format!(
+                "id=\"{}\",name=\"{}\"",
+                escape_label(&s.id),
+                escape_label(&s.name)
+            )
+        })
+        .collect();
+    let running_pids: Vec<u32> = statuses
+        .iter()
+        .filter(|s| s.is_running)
+        .filter_map(|s| s.pid)
+        .collect();
+    let mut usage = sample_resources(&running_pids).into_iter();
+
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "openlist_service_info",
+        "gauge",
+        "Service version information.",
+    );
+    let _ = writeln!(
+        out,
+        "openlist_service_info{{version=\"{}\"}} 1",
+        escape_label(version)
+    );
+
+    header(
+        &mut out,
+        "openlist_processes",
+        "gauge",
+        "Number of configured processes.",
+    );
+    let _ = writeln!(out, "openlist_processes {}", statuses.len());
+
+    header(
+        &mut out,
+        "openlist_processes_running",
+        "gauge",
+        "Number of running processes.",
+    );
+    let running = statuses.iter().filter(|s| s.is_running).count();
+    let _ = writeln!(out, "openlist_processes_running {running}");
+
+    header(
+        &mut out,
+        "openlist_processes_failed",
+        "gauge",
+        "Number of processes halted by crash-loop detection.",
+    );
+    let failed = statuses
+        .iter()
+        .filter(|s| s.state == ProcessState::Failed)
+        .count();
+    let _ = writeln!(out, "openlist_processes_failed {failed}");
+
+    header(
+        &mut out,
+        "openlist_restarts_total",
+        "counter",
+        "Restarts across all processes since the service started, whether automatic, manual or on file change.",
+    );
+    let restarts: u64 = statuses.iter().map(|s| u64::from(s.restart_count)).sum();
+    let _ = writeln!(out, "openlist_restarts_total {restarts}");
+
+    header(
+        &mut out,
+        "openlist_process_up",
+        "gauge",
+        "Whether the process is running.",
+    );
+    for (status, labels) in statuses.iter().zip(&labels) {
+        let _ = writeln!(
+            out,
+            "openlist_process_up{{{labels}}} {}",
+            u8::from(status.is_running)
+        );
+    }
+
+    header(
+        &mut out,
+        "openlist_process_restarts_total",
+        "counter",
+        "Restarts of the process since the service started, whether automatic, manual or on file change.",
+    );
+    for (status, labels) in statuses.iter().zip(&labels) {
+        let _ = writeln!(
+            out,
+            "openlist_process_restarts_total{{{labels}}} {}",
+            status.restart_count
+        );
+    }
+
+    header(
+        &mut out,
+        "openlist_process_uptime_seconds",
+        "gauge",
+        "Seconds since the process was started, 0 when it is not running.",
+    );
+    for (status, labels) in statuses.iter().zip(&labels) {
+        let uptime = match status.started_at {
+            Some(started_at) if status.is_running => now.saturating_sub(started_at),
+            _ => 0,
+        };
+        let _ = writeln!(out, "openlist_process_uptime_seconds{{{labels}}} {uptime}");
+    }
+
+    let mut cpu = String::new();
+    let mut memory = String::new();
+    for (status, labels) in statuses.iter().zip(&labels) {
+        if !status.is_running || status.pid.is_none() {
+            continue;
+        }
+        if let Some(Some(sample)) = usage.next() {
+            let _ = writeln!(
+                cpu,
+                "openlist_process_cpu_usage_percent{{{labels}}} {}",
+                sample.cpu_percent
+            );
+            let _ = writeln!(
+                memory,
+                "openlist_process_memory_bytes{{{labels}}} {}",
+                sample.memory_bytes
+            );
+        }
+    }
+
+    header(
+        &mut out,
+        "openlist_process_cpu_usage_percent",
+        "gauge",
+        "CPU usage of the process since the previous scrape.",
+    );
+    out.push_str(&cpu);
+    header(
+        &mut out,
+        "openlist_process_memory_bytes",
+        "gauge",
+        "Resident memory of the process.",
+    );
+    out.push_str(&memory);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openlistcore::{
+        data::CoreManager,
+        test_support::{self, create_request, existing_binary},
+    };
+    use serde_json::json;
+    use std::collections::{BTreeMap, HashSet};
+
+    struct Sample {
+        name: String,
+        labels: BTreeMap<String, String>,
+        value: f64,
+    }
+
+    /// Parses the exposition format strictly enough to catch malformed output:
+    /// every sample needs a preceding HELP and TYPE for its metric.
+    fn parse(text: &str) -> Vec<Sample> {
+        let mut described = HashSet::new();
+        let mut samples = Vec::new();
+        for line in text.lines() {
+            if let Some(rest) = line.strip_prefix("# HELP ") {
+                let (name, help) = rest.split_once(' ').unwrap();
+                assert!(!help.is_empty(), "{name} has no help text");
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix("# TYPE ") {
+                let (name, kind) = rest.split_once(' ').unwrap();
+                assert!(["gauge", "counter"].contains(&kind), "{line}");
+                described.insert(name.to_string());
+                continue;
+            }
+
+            let (series, value) = line.rsplit_once(' ').unwrap();
+            let (name, labels) = match series.split_once('{') {
+                Some((name, labels)) => (name, labels.strip_suffix('}').unwrap()),
+                None => (series, ""),
+            };
+            assert!(described.contains(name), "{name} is not described");
+            let labels = parse_labels(labels);
+            samples.push(Sample {
+                name: name.to_string(),
+                labels,
+                value: value.parse().unwrap(),
+            });
+        }
+        samples
+    }
+
+    fn parse_labels(mut text: &str) -> BTreeMap<String, String> {
+        let mut labels = BTreeMap::new();
+        while !text.is_empty() {
+            let (name, rest) = text.split_once("=\"").unwrap();
+            let mut value = String::new();
+            let mut chars = rest.char_indices();
+            let end = loop {
+                match chars.next().unwrap() {
+                    (_, '\\') => match chars.next().unwrap().1 {
+                        'n' => value.push('\n'),
+                        c => value.push(c),
+                    },
+                    (i, '"') => break i,
+                    (_, c) => value.push(c),
+                }
+            };
+            labels.insert(name.to_string(), value);
+            text = rest[end + 1..].trim_start_matches(',');
+        }
+        labels
+    }
+
+    #[test]
+    fn metrics_are_well_formed_and_labelled() {
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let odd_name = "quote\" back\\slash\nnewline";
+        for name in ["plain", odd_name] {
+            manager
+                .create_process(create_request(name, &existing_binary(), json!({})))
+                .unwrap();
+        }
+        let mut statuses = manager.list_processes().unwrap();
+        statuses.sort_by(|a, b| a.name.cmp(&b.name));
+        statuses[0].restart_count = 2;
+        statuses[1].restart_count = 3;
+
+        let samples = parse(&render("1.2.3", &statuses, 1_000));
+        let value = |name: &str, process: Option<&str>| {
+            samples
+                .iter()
+                .find(|s| {
+                    s.name == name
+                        && process
+                            .is_none_or(|p| s.labels.get("name").map(String::as_str) == Some(p))
+                })
+                .map(|s| s.value)
+        };
+
+        assert_eq!(value("openlist_service_info", None), Some(1.0));
+        assert_eq!(value("openlist_processes", None), Some(2.0));
+        assert_eq!(value("openlist_processes_running", None), Some(0.0));
+        assert_eq!(value("openlist_processes_failed", None), Some(0.0));
+        assert_eq!(value("openlist_restarts_total", None), Some(5.0));
+        assert_eq!(
+            value("openlist_process_restarts_total", Some("plain")),
+            Some(2.0)
+        );
+        assert_eq!(
+            value("openlist_process_restarts_total", Some(odd_name)),
+            Some(3.0)
+        );
+        assert_eq!(value("openlist_process_up", Some(odd_name)), Some(0.0));
+        assert_eq!(
+            value("openlist_process_uptime_seconds", Some("plain")),
+            Some(0.0)
+        );
+        // Resource usage is only reported for running processes
+        assert_eq!(value("openlist_process_memory_bytes", Some("plain")), None);
+
+        let info = samples
+            .iter()
+            .find(|s| s.name == "openlist_service_info")
+            .unwrap();
+        assert_eq!(info.labels["version"], "1.2.3");
+        for sample in samples
+            .iter()
+            .filter(|s| s.name.starts_with("openlist_process_"))
+        {
+            let ids: Vec<&str> = statuses.iter().map(|s| s.id.as_str()).collect();
+            assert!(ids.contains(&sample.labels["id"].as_str()));
+            assert_eq!(sample.labels.len(), 2);
+        }
+    }
+
+    #[test]
+    fn service_resources_describe_this_process() {
+        use std::{
+            sync::{Arc, Barrier},
+            time::{Duration, Instant},
+        };
+
+        const EXTRA_THREADS: usize = 3;
+        let barrier = Arc::new(Barrier::new(EXTRA_THREADS + 1));
+        let threads: Vec<_> = (0..EXTRA_THREADS)
+            .map(|_| {
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    let _active = monitor::MonitorThread::enter();
+                    barrier.wait();
+                    barrier.wait();
+                })
+            })
+            .collect();
+        barrier.wait();
+
+        let busy = || {
+            let busy_until = Instant::now() + Duration::from_millis(300);
+            let mut spins = 0u64;
+            while Instant::now() < busy_until {
+                spins = std::hint::black_box(spins.wrapping_add(1));
+            }
+        };
+        // sysinfo only reports CPU usage once it has seen some CPU time used
+        busy();
+        service_resources();
+        busy();
+        let resources = service_resources();
+
+        assert_eq!(resources.pid, std::process::id());
+        assert!(resources.memory_bytes > 1024 * 1024, "{resources:?}");
+        let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+        assert!(resources.cpu_usage_percent > 0.0, "{resources:?}");
+        assert!(
+            resources.cpu_usage_percent <= 100.0 * cpus as f32 + 1.0,
+            "{resources:?}"
+        );
+        if cfg!(target_os = "linux") {
+            assert!(resources.thread_count.unwrap() > EXTRA_THREADS);
+        }
+        assert!(resources.monitor_threads >= EXTRA_THREADS);
+
+        barrier.wait();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
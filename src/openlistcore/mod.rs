@@ -1,10 +1,67 @@
+mod binary_arch;
+mod config_format;
 pub mod core;
 mod data;
+mod dependencies;
+mod download;
+mod error;
+mod events;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod exit_watcher;
+mod file_watch;
+mod health;
+mod hooks;
 mod http_api;
+mod jitter;
+mod listen_probe;
+mod log_export;
+mod log_stamp;
+mod log_stream;
+mod log_watch;
+mod logs;
+mod metrics;
+mod monitor;
 mod process;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod pty;
+mod readiness;
+mod restart_limit;
+mod rpc;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+mod run_as;
+mod run_overrides;
+#[cfg(target_os = "linux")]
+mod sched;
+mod secrets;
+mod self_check;
+mod start_queue;
+mod startup_output;
+mod startup_probe;
+mod stats;
+mod templates;
+#[cfg(test)]
+mod test_support;
+mod validation;
 
+// The manager and the types of its API, for library consumers; the service
+// itself goes through `core::CORE_MANAGER`
+pub use self::data::{
+    ApplyEnvRequest, BatchFailure, BatchResult, CheckResult, CheckStatus, CombinedLogResponse,
+    ConfigDiff, ConfigMetadata, CoreManager, CreateProcessRequest, EnvInheritance, EventRecord,
+    ExitPolicy, ExportLogsRequest, FieldChange, GlobalPreStartHookRequest, HealthSummary,
+    IoPriority, IoPriorityClass, JsonResponse, KillStep, ListenAddressProbe, LogLevel,
+    LogOffsetResponse, LogPosition, LogRequest, LogResponse, LogSearchHit, LogStream,
+    LogTimestampFormat, MutexPolicy, NotRunningReason, ProcessConfig, ProcessConfigChange,
+    ProcessPage, ProcessRestarts, ProcessSortBy, ProcessState, ProcessStats, ProcessStatus,
+    ProcessTemplate, ProcessUpdate, ReadinessCheck, RecentErrorsResponse, ReconcileReport,
+    ReliabilityReport, ReorderProcessesRequest, RestartExitCodes, RestoreSnapshotRequest,
+    SafeModeRequest, SchedPolicy, ServiceEvent, ServiceResources, ServiceSettings, ServiceSnapshot,
+    SetAutoRestartRequest, SortOrder, StartProcessRequest, StartWithOverridesRequest, StartupProbe,
+    StopOutcome, StopProcessRequest, TaggedLogLine, UpdateProcessRequest, UpdateProcessesRequest,
+    UpdateServiceSettingsRequest, ValidationIssue, VersionResponse,
+};
 use self::http_api::run_ipc_server;
-use log::{error, info};
+use log::{error, info, warn};
 use tokio::runtime::Runtime;
 
 #[cfg(any(target_os = "macos", target_os = "linux"))]
@@ -40,6 +97,49 @@ async fn auto_start_core() {
     }
 }
 
+fn run_startup_self_check() {
+    use self::core::CORE_MANAGER;
+    use self::data::CheckStatus;
+
+    let results = CORE_MANAGER.lock().self_check();
+    for check in results {
+        match check.status {
+            CheckStatus::Pass => info!("Self-check [{}] passed: {}", check.name, check.message),
+            CheckStatus::Warn => warn!("Self-check [{}] warning: {}", check.name, check.message),
+            CheckStatus::Fail => error!("Self-check [{}] failed: {}", check.name, check.message),
+        }
+    }
+}
+
+/// Stops the processes the `on_exit` policy says to before the service exits.
+fn shutdown_processes() {
+    if let Err(e) = self::core::CORE_MANAGER.lock().shutdown_all_processes() {
+        error!("Failed to stop processes on exit: {e}");
+    }
+}
+
+/// Resolves once the service is asked to terminate, after the processes
+/// have been dealt with according to `on_exit`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+async fn wait_for_termination() {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let (Ok(mut terminate), Ok(mut interrupt)) = (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) else {
+        error!("Failed to install the termination signal handlers");
+        return std::future::pending().await;
+    };
+    tokio::select! {
+        _ = terminate.recv() => info!("Received SIGTERM, shutting down"),
+        _ = interrupt.recv() => info!("Received SIGINT, shutting down"),
+    }
+    if let Err(e) = tokio::task::spawn_blocking(shutdown_processes).await {
+        error!("Failed to stop processes on exit: {e}");
+    }
+}
+
 pub async fn run_service() -> anyhow::Result<()> {
     #[cfg(windows)]
     let status_handle = service_control_handler::register(
@@ -47,7 +147,10 @@ pub async fn run_service() -> anyhow::Result<()> {
         move |event| -> ServiceControlHandlerResult {
             match event {
                 ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
-                ServiceControl::Stop => std::process::exit(0),
+                ServiceControl::Stop => {
+                    shutdown_processes();
+                    std::process::exit(0)
+                }
                 _ => ServiceControlHandlerResult::NotImplemented,
             }
         },
@@ -65,11 +168,24 @@ pub async fn run_service() -> anyhow::Result<()> {
 
     info!("Starting Service - HTTP API mode");
 
+    if let Err(e) = tokio::task::spawn_blocking(self::core::wait_for_config).await {
+        error!("Failed to wait for the process configurations: {e}");
+    }
+    run_startup_self_check();
+
     tokio::spawn(async {
         tokio::time::sleep(std::time::Duration::from_secs(1)).await;
         auto_start_core().await;
     });
 
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    tokio::select! {
+        result = run_ipc_server() => if let Err(err) = result {
+            error!("HTTP API server error: {err}");
+        },
+        _ = wait_for_termination() => {}
+    }
+    #[cfg(windows)]
     if let Err(err) = run_ipc_server().await {
         error!("HTTP API server error: {err}");
     }
@@ -77,6 +193,26 @@ pub async fn run_service() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Runs the manager as a sidecar speaking JSON-RPC over stdin/stdout instead of
+/// opening the HTTP API.
+pub fn run_stdio() {
+    info!("Starting Service - JSON-RPC stdio mode");
+
+    self::core::wait_for_config();
+    run_startup_self_check();
+
+    {
+        let mut core_manager = self::core::CORE_MANAGER.lock();
+        if let Err(e) = core_manager.auto_start_processes() {
+            error!("Failed to auto-start processes: {e}");
+        }
+    }
+
+    if let Err(err) = rpc::run_stdio_server() {
+        error!("JSON-RPC stdio server error: {err}");
+    }
+}
+
 #[cfg(target_os = "windows")]
 pub fn stop_service() -> Result<()> {
     let status_handle = service_control_handler::register("openlist_desktop_service", |_| {
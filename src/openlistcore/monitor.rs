@@ -0,0 +1,479 @@
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, error, info, warn};
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use super::exit_watcher::{self, OnExit};
+use super::{
+    data::{ManagerHandle, ProcessRuntime},
+    process::{self, is_process_running},
+    readiness::is_current_run,
+};
+
+const DEFAULT_MONITOR_INTERVAL: Duration = Duration::from_secs(2);
+/// Milliseconds between two polls of the process monitor.
+const MONITOR_INTERVAL_ENV: &str = "OPENLIST_SERVICE_MONITOR_INTERVAL_MS";
+const HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(60 * 60);
+const WRAPPED_PID_TIMEOUT: Duration = Duration::from_secs(5);
+const WRAPPED_PID_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+static MONITOR_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// Held for its lifetime by every background thread that watches managed
+/// processes, so their number can be reported.
+pub struct MonitorThread(());
+
+impl MonitorThread {
+    pub fn enter() -> Self {
+        MONITOR_THREADS.fetch_add(1, Ordering::Relaxed);
+        Self(())
+    }
+}
+
+impl Drop for MonitorThread {
+    fn drop(&mut self) {
+        MONITOR_THREADS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+pub fn active_monitor_threads() -> usize {
+    MONITOR_THREADS.load(Ordering::Relaxed)
+}
+
+/// Starts the background thread that polls the processes of `manager` for
+/// unexpected exits and drives auto-restart, at the interval
+/// `MONITOR_INTERVAL_ENV` asks for. Every call starts another thread, so
+/// each manager should only get one.
+pub fn spawn_monitor(manager: ManagerHandle) {
+    let interval = monitor_interval(std::env::var(MONITOR_INTERVAL_ENV).ok().as_deref());
+    spawn_monitor_thread(manager, interval);
+}
+
+/// The monitor thread itself, which runs until `manager` is dropped. Log
+/// retention is enforced on the same thread at a much lower rate.
+fn spawn_monitor_thread(manager: ManagerHandle, interval: Duration) {
+    let spawned = thread::Builder::new()
+        .name("process-monitor".to_string())
+        .spawn(move || {
+            let _active = MonitorThread::enter();
+            info!("Process monitor started (interval: {interval:?})");
+            let mut last_housekeeping: Option<Instant> = None;
+            loop {
+                thread::sleep(interval);
+                let Some(manager) = manager.upgrade() else {
+                    info!("Process monitor stopped, its manager is gone");
+                    return;
+                };
+                {
+                    let mut core_manager = manager.lock();
+                    core_manager.supervise_processes();
+                    if let Err(e) = core_manager.flush_stats(false) {
+                        error!("Failed to save process stats: {e}");
+                    }
+                }
+
+                if last_housekeeping.is_none_or(|at| at.elapsed() >= HOUSEKEEPING_INTERVAL) {
+                    last_housekeeping = Some(Instant::now());
+                    let core_manager = manager.lock();
+                    core_manager.enforce_log_retention();
+                    core_manager.enforce_total_log_size();
+                }
+            }
+        });
+
+    if let Err(e) = spawned {
+        error!("Failed to start process monitor: {e}");
+    }
+}
+
+/// The poll interval `MONITOR_INTERVAL_ENV` asks for, or the default when it
+/// is unset or not a positive number of milliseconds.
+fn monitor_interval(value: Option<&str>) -> Duration {
+    let Some(value) = value else {
+        return DEFAULT_MONITOR_INTERVAL;
+    };
+    match value.trim().parse::<u64>() {
+        Ok(ms) if ms > 0 => Duration::from_millis(ms),
+        _ => {
+            warn!(
+                "Ignoring invalid {MONITOR_INTERVAL_ENV} {value:?}, polling every {DEFAULT_MONITOR_INTERVAL:?}"
+            );
+            DEFAULT_MONITOR_INTERVAL
+        }
+    }
+}
+
+/// Wakes the supervisor of `manager` as soon as `pid` exits instead of at the
+/// next poll.
+/// With `shared`, the exit watcher shared by all processes waits for it;
+/// otherwise a thread of its own waits on a pidfd, on Linux only. Elsewhere,
+/// or on kernels without pidfd support, the periodic poll above notices the
+/// exit instead.
+pub fn watch_exit(manager: ManagerHandle, pid: u32, shared: bool) {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    if shared {
+        if let Err(e) = exit_watcher::watch(pid, OnExit::Supervise(manager)) {
+            debug!("Cannot watch PID {pid} for its exit, relying on polling: {e}");
+        }
+        return;
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let pidfd = match open_pidfd(pid) {
+            Ok(pidfd) => pidfd,
+            Err(e) => {
+                debug!("pidfd unavailable for PID {pid}, relying on polling: {e}");
+                return;
+            }
+        };
+
+        let spawned = thread::Builder::new()
+            .name(format!("exit-watch-{pid}"))
+            .spawn(move || {
+                let _active = MonitorThread::enter();
+                if let Err(e) = wait_readable(&pidfd) {
+                    debug!("Waiting on pidfd of PID {pid} failed, relying on polling: {e}");
+                    return;
+                }
+                debug!("PID {pid} exited, running supervisor");
+                if let Some(manager) = manager.upgrade() {
+                    manager.lock().supervise_processes();
+                }
+            });
+        if let Err(e) = spawned {
+            error!("Failed to start exit watcher for PID {pid}: {e}");
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (manager, shared);
+    #[cfg(not(target_os = "linux"))]
+    debug!("No exit notification for PID {pid} on this platform, relying on polling");
+}
+
+/// Switches the run identified by `generation` from the launch wrapper's PID
+/// to that of the process the wrapper starts for `bin_path`, once it shows
+/// up, so stopping and liveness checks hit the real process. The wrapper is
+/// then reaped in the background when it exits, by the shared exit watcher
+/// with `shared`. Keeps the wrapper's PID if no such process appears, e.g.
+/// where descendants cannot be listed.
+pub fn track_wrapped_process(
+    manager: ManagerHandle,
+    runtime: ProcessRuntime,
+    generation: u64,
+    wrapper_pid: u32,
+    bin_path: String,
+    shared: bool,
+) {
+    let spawned = thread::Builder::new()
+        .name(format!("wrapper-watch-{wrapper_pid}"))
+        .spawn(move || {
+            let _active = MonitorThread::enter();
+            let deadline = Instant::now() + WRAPPED_PID_TIMEOUT;
+            while is_current_run(&runtime, generation) && is_process_running(wrapper_pid as i32) {
+                match process::find_wrapped_pid(wrapper_pid, &bin_path) {
+                    // The wrapper exec'd the process itself
+                    Some(pid) if pid == wrapper_pid => return,
+                    Some(pid) => {
+                        let switched = runtime
+                            .running_pid
+                            .compare_exchange(
+                                wrapper_pid as i32,
+                                pid as i32,
+                                Ordering::SeqCst,
+                                Ordering::SeqCst,
+                            )
+                            .is_ok();
+                        if switched {
+                            *runtime.pid_start_stamp.lock() = process::process_start_stamp(pid);
+                            info!("Tracking PID {pid} started by launch wrapper PID {wrapper_pid}");
+                            watch_exit(manager, pid, shared);
+                            reap_wrapper(wrapper_pid, shared);
+                        }
+                        return;
+                    }
+                    None if Instant::now() >= deadline => break,
+                    None => thread::sleep(WRAPPED_PID_POLL_INTERVAL),
+                }
+            }
+            debug!("Keeping launch wrapper PID {wrapper_pid}, no wrapped process found");
+        });
+    if let Err(e) = spawned {
+        error!("Failed to start wrapper watcher for PID {wrapper_pid}: {e}");
+    }
+}
+
+fn reap_wrapper(wrapper_pid: u32, shared: bool) {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    if shared && exit_watcher::watch(wrapper_pid, OnExit::Reap).is_ok() {
+        return;
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    let _ = shared;
+    process::reap(wrapper_pid);
+}
+
+#[cfg(target_os = "linux")]
+pub fn open_pidfd(pid: u32) -> std::io::Result<std::os::fd::OwnedFd> {
+    use std::os::fd::FromRawFd;
+
+    let fd = unsafe { libc::syscall(libc::SYS_pidfd_open, pid as libc::pid_t, 0) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(unsafe { std::os::fd::OwnedFd::from_raw_fd(fd as libc::c_int) })
+}
+
+/// Blocks until the pidfd becomes readable, which happens when the process exits.
+#[cfg(target_os = "linux")]
+fn wait_readable(pidfd: &std::os::fd::OwnedFd) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let mut poll_fd = libc::pollfd {
+        fd: pidfd.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    loop {
+        if unsafe { libc::poll(&mut poll_fd, 1, -1) } >= 0 {
+            return Ok(());
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::openlistcore::{
+        data::{CoreManager, ProcessState},
+        process::is_process_running,
+        test_support::{self, shell_request, wait_for},
+    };
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn the_monitor_restarts_processes_that_exited() {
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        let request = shell_request("flaky", "exec sleep 0.2", json!({ "auto_restart": true }));
+        let id = {
+            let mut core_manager = manager.lock();
+            let id = core_manager.create_process(request).unwrap().id;
+            core_manager.start_process(&id).unwrap();
+            id
+        };
+        let status = || manager.lock().get_process(&id).unwrap();
+
+        // The exit watcher schedules the restart, but only the poll carries
+        // it out
+        assert!(wait_for(Duration::from_secs(5), || status()
+            .next_restart_at
+            .is_some()));
+        let due = status().next_restart_at.unwrap();
+        let now = || {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        };
+        assert!(wait_for(Duration::from_secs(5), || now() > due));
+        assert_eq!(status().restart_count, 0);
+
+        let interval = monitor_interval(Some("50"));
+        assert_eq!(interval, Duration::from_millis(50));
+        spawn_monitor_thread(Arc::downgrade(&manager), interval);
+        assert!(wait_for(Duration::from_secs(2), || status().restart_count >= 1));
+        manager.lock().stop_process(&id).unwrap();
+    }
+
+    #[test]
+    fn a_manager_of_its_own_restarts_processes_once_monitored() {
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        CoreManager::start_monitor(&manager);
+        let request = shell_request(
+            "own-flaky",
+            "exec sleep 0.2",
+            json!({ "auto_restart": true }),
+        );
+        let id = {
+            let mut core_manager = manager.lock();
+            let id = core_manager.create_process(request).unwrap().id;
+            core_manager.start_process(&id).unwrap();
+            id
+        };
+
+        // Backoff plus up to two default poll intervals
+        assert!(wait_for(Duration::from_secs(10), || manager
+            .lock()
+            .get_process(&id)
+            .unwrap()
+            .restart_count
+            >= 1));
+        let mut core_manager = manager.lock();
+        core_manager.set_auto_restart(&id, false).unwrap();
+        let _ = core_manager.stop_process(&id);
+        core_manager.delete_process(&id).unwrap();
+    }
+
+    #[test]
+    fn exits_are_noticed_without_waiting_for_the_poll() {
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        let request = shell_request("short-lived", "exec sleep 0.5", json!({}));
+        let id = {
+            let mut core_manager = manager.lock();
+            let id = core_manager.create_process(request).unwrap().id;
+            core_manager.start_process(&id).unwrap();
+            id
+        };
+        let status = || manager.lock().get_process(&id).unwrap();
+        let pid = status().pid.unwrap() as i32;
+        assert!(status().started_at.is_some());
+
+        assert!(wait_for(Duration::from_secs(5), || !is_process_running(
+            pid
+        )));
+        let exited = Instant::now();
+        // Nothing but the exit watcher runs the supervisor in tests
+        assert!(wait_for(Duration::from_secs(1), || status()
+            .started_at
+            .is_none()));
+        assert!(
+            exited.elapsed() < Duration::from_millis(100),
+            "noticed after {:?}",
+            exited.elapsed()
+        );
+        assert_eq!(status().pid, None);
+    }
+
+    #[test]
+    fn shared_monitor_threads_bound_the_thread_count() {
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        manager.lock().settings.shared_monitor_threads = true;
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let before = active_monitor_threads();
+        let ids: Vec<String> = (0..20)
+            .map(|i| {
+                let request = shell_request(
+                    &format!("shared-watch-{i}"),
+                    "exec sleep 30",
+                    json!({
+                        "readiness_check": { "type": "tcp", "address": address },
+                        "health_command": "true",
+                        "health_interval_secs": 1,
+                    }),
+                );
+                let mut core_manager = manager.lock();
+                let id = core_manager.create_process(request).unwrap().id;
+                core_manager.start_process(&id).unwrap();
+                id
+            })
+            .collect();
+        let status = |id: &str| manager.lock().get_process(id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || ids
+            .iter()
+            .all(|id| status(id).state == ProcessState::Running)));
+        // Besides the shared exit watcher and startup output capture, unless
+        // an earlier test started them, only the health watchers are left
+        // once the readiness waits are over
+        assert!(
+            wait_for(Duration::from_secs(2), || active_monitor_threads()
+                <= before + 2 + ids.len()),
+            "{before} monitor threads before, {} after",
+            active_monitor_threads()
+        );
+
+        let status = |id: &str| manager.lock().get_process(id).unwrap();
+        let pid = status(&ids[7]).pid.unwrap() as i32;
+        unsafe { libc::kill(pid, libc::SIGKILL) };
+        // Nothing but the exit watcher runs the supervisor in tests
+        assert!(wait_for(Duration::from_secs(1), || status(&ids[7])
+            .started_at
+            .is_none()));
+        assert!(status(&ids[8]).is_running);
+        // Startup output is still captured, by the shared poller
+        assert!(wait_for(Duration::from_secs(1), || manager
+            .lock()
+            .get_process_startup_output(&ids[3])
+            .unwrap()
+            .first()
+            .is_some_and(|line| line.starts_with("Spawning process"))));
+
+        let mut core_manager = manager.lock();
+        core_manager.settings.shared_monitor_threads = false;
+        for id in &ids {
+            let _ = core_manager.stop_process(id);
+            core_manager.delete_process(id).unwrap();
+        }
+    }
+
+    #[test]
+    fn the_process_behind_a_launch_wrapper_is_tracked() {
+        use crate::openlistcore::test_support::{create_request, scratch_dir};
+
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        let log = scratch_dir("wrapped").join("process.log");
+        let request = create_request(
+            "wrapped",
+            "/bin/sleep",
+            json!({
+                "args": ["30"],
+                "launch_wrapper": ["/bin/sh", "-c", "echo wrapped; \"$@\"; exit $?", "wrapper"],
+                "log_file": log,
+            }),
+        );
+        let id = {
+            let mut core_manager = manager.lock();
+            let id = core_manager.create_process(request).unwrap().id;
+            core_manager.start_process(&id).unwrap();
+            id
+        };
+        let status = || manager.lock().get_process(&id).unwrap();
+        let exe = |pid: u32| std::fs::read_link(format!("/proc/{pid}/exe")).ok();
+        let sleep = std::fs::canonicalize("/bin/sleep").ok();
+
+        // The switch may already have happened by the time the start returns
+        assert!(wait_for(Duration::from_secs(5), || status()
+            .pid
+            .is_some_and(|pid| exe(pid) == sleep)));
+        let pid = status().pid.unwrap();
+        assert!(status().is_running);
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).unwrap();
+        let wrapper_pid: u32 = stat
+            .rsplit_once(')')
+            .unwrap()
+            .1
+            .split_whitespace()
+            .nth(1)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(exe(wrapper_pid), std::fs::canonicalize("/bin/sh").ok());
+        assert!(
+            std::fs::read_to_string(&log)
+                .unwrap()
+                .contains("\nwrapped\n")
+        );
+
+        let outcome = manager.lock().stop_process(&id).unwrap();
+        assert!(outcome.graceful);
+        assert!(!is_process_running(pid as i32));
+        // The wrapper exits with it and is reaped rather than left a zombie
+        assert!(wait_for(Duration::from_secs(5), || !std::path::Path::new(
+            &format!("/proc/{wrapper_pid}")
+        )
+        .exists()));
+    }
+}
@@ -1,10 +1,38 @@
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::os::unix::process::CommandExt;
 use std::{
+    collections::HashMap,
     io::{self, Write},
     path::Path,
     process::{Command, Stdio},
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
+use super::data::{
+    EnvInheritance, IoPriority, IoPriorityClass, KillStep, LogTimestampFormat, SchedPolicy,
+    StopOutcome,
+};
+use super::log_stamp::{self, LogClock, StampedLog};
+#[cfg(target_os = "linux")]
+use super::sched;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use super::{pty, run_as};
 use log::{error, info, warn};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+/// Threads still copying the output of a process into its logs, by PID.
+static OUTPUT_RELAYS: Lazy<Mutex<HashMap<u32, Vec<JoinHandle<()>>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Exit codes of children that exited, by PID, until `try_reap` takes them.
+/// Windows cannot reap on demand, only whoever holds the child learns them.
+#[cfg(target_os = "windows")]
+static EXIT_CODES: Lazy<Mutex<HashMap<u32, i32>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
 
 #[cfg(target_os = "windows")]
 pub fn is_process_running(pid: i32) -> bool {
@@ -33,14 +61,167 @@ pub fn is_process_running(pid: i32) -> bool {
     if pid <= 0 {
         return false;
     }
-    let check_process = Command::new("ps").args(["-p", &pid.to_string()]).output();
+    let check_process = Command::new("ps")
+        .args(["-o", "stat=", "-p", &pid.to_string()])
+        .output();
 
     match check_process {
-        Ok(output) => output.status.success(),
+        // A zombie has already exited and is only waiting to be reaped
+        Ok(output) => {
+            output.status.success() && !String::from_utf8_lossy(&output.stdout).starts_with('Z')
+        }
         Err(_) => false,
     }
 }
 
+/// When `pid` started, as far as the OS tells, so that a later process given
+/// the same pid is not taken for it. `None` where that is not known.
+#[cfg(target_os = "linux")]
+pub fn process_start_stamp(pid: u32) -> Option<String> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // `starttime`, the 22nd field; the name before it may contain spaces
+    stat.rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .nth(19)
+        .map(str::to_string)
+}
+
+#[cfg(target_os = "macos")]
+pub fn process_start_stamp(pid: u32) -> Option<String> {
+    let output = Command::new("ps")
+        .args(["-o", "lstart=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    let stamp = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !stamp.is_empty()).then_some(stamp)
+}
+
+#[cfg(target_os = "windows")]
+pub fn process_start_stamp(_pid: u32) -> Option<String> {
+    None
+}
+
+/// Whether `pid` is alive and, when `start_stamp` was recorded for it, still
+/// the same process rather than a later one that reuses the pid.
+pub fn is_same_process_running(pid: i32, start_stamp: Option<&str>) -> bool {
+    is_process_running(pid)
+        && start_stamp.is_none_or(|stamp| process_start_stamp(pid as u32).as_deref() == Some(stamp))
+}
+
+/// Collects the exit status of a child that has already terminated, so it does
+/// not linger as a zombie. Returns the exit code (128 + signal when killed by a
+/// signal), or `None` if `pid` is still running or is not our child.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn try_reap(pid: i32) -> Option<i32> {
+    if pid <= 0 {
+        return None;
+    }
+    let mut status = 0;
+    let result = unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) };
+    if result != pid {
+        return None;
+    }
+    if libc::WIFEXITED(status) {
+        Some(libc::WEXITSTATUS(status))
+    } else if libc::WIFSIGNALED(status) {
+        Some(128 + libc::WTERMSIG(status))
+    } else {
+        None
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn try_reap(pid: i32) -> Option<i32> {
+    u32::try_from(pid)
+        .ok()
+        .and_then(|pid| EXIT_CODES.lock().remove(&pid))
+}
+
+/// Waits for our child `pid` to exit and collects it.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn reap(pid: u32) {
+    let mut status = 0;
+    unsafe { libc::waitpid(pid as libc::pid_t, &mut status, 0) };
+}
+
+#[cfg(target_os = "windows")]
+pub fn reap(_pid: u32) {}
+
+/// The descendant of `wrapper_pid` running `bin_path`, i.e. the process a
+/// launch wrapper started: either the executable itself or a script run by an
+/// interpreter. `wrapper_pid` itself is returned for wrappers that exec.
+/// `None` until such a process shows up.
+#[cfg(target_os = "linux")]
+pub fn find_wrapped_pid(wrapper_pid: u32, bin_path: &str) -> Option<u32> {
+    let target = std::fs::canonicalize(bin_path).ok()?;
+    let runs_target = |pid: u32| runs_canonical_binary(pid, &target);
+
+    // "pid (comm) state ppid ...", where comm may itself contain spaces
+    let parent_of = |pid: u32| -> Option<u32> {
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+        stat.rsplit_once(')')?
+            .1
+            .split_whitespace()
+            .nth(1)?
+            .parse()
+            .ok()
+    };
+    let parents: Vec<(u32, u32)> = std::fs::read_dir("/proc")
+        .ok()?
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .filter_map(|pid| Some((pid, parent_of(pid)?)))
+        .collect();
+
+    let mut generation = vec![wrapper_pid];
+    while !generation.is_empty() {
+        if let Some(&pid) = generation.iter().find(|&&pid| runs_target(pid)) {
+            return Some(pid);
+        }
+        generation = parents
+            .iter()
+            .filter(|(_, parent)| generation.contains(parent))
+            .map(|&(pid, _)| pid)
+            .collect();
+    }
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn find_wrapped_pid(_wrapper_pid: u32, _bin_path: &str) -> Option<u32> {
+    None
+}
+
+/// Whether `pid` runs `bin_path`, either the executable itself or a script
+/// run by an interpreter, so that a reused pid is not taken for the process.
+#[cfg(target_os = "linux")]
+pub fn runs_binary(pid: u32, bin_path: &str) -> bool {
+    std::fs::canonicalize(bin_path).is_ok_and(|target| runs_canonical_binary(pid, &target))
+}
+
+/// Without /proc there is nothing to tell a reused pid by.
+#[cfg(not(target_os = "linux"))]
+pub fn runs_binary(_pid: u32, _bin_path: &str) -> bool {
+    true
+}
+
+#[cfg(target_os = "linux")]
+fn runs_canonical_binary(pid: u32, target: &Path) -> bool {
+    let exe = std::fs::read_link(format!("/proc/{pid}/exe")).ok();
+    if exe.is_some_and(|exe| exe == target) {
+        return true;
+    }
+    // An interpreter has the script among its first arguments
+    std::fs::read(format!("/proc/{pid}/cmdline")).is_ok_and(|cmdline| {
+        cmdline
+            .split(|&b| b == 0)
+            .take(3)
+            .filter_map(|arg| std::fs::canonicalize(String::from_utf8_lossy(arg).as_ref()).ok())
+            .any(|arg| arg == target)
+    })
+}
+
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 pub fn ensure_executable_permissions(binary_path: &str) -> io::Result<()> {
     use std::os::unix::fs::PermissionsExt;
@@ -78,26 +259,330 @@ pub fn ensure_executable_permissions(_binary_path: &str) -> io::Result<()> {
     Ok(())
 }
 
-fn get_working_directory(command: &str) -> &Path {
+/// The directory a child is started in: its configured `working_dir`, else
+/// the directory of its binary, or the service's own working directory for a
+/// relative path.
+pub fn working_directory_for<'a>(command: &'a str, working_dir: Option<&'a str>) -> &'a Path {
+    if let Some(working_dir) = working_dir {
+        return Path::new(working_dir);
+    }
     let command_path = Path::new(command);
-    if command_path.is_absolute() {
-        if let Some(parent) = command_path.parent() {
-            info!(
-                "Using working directory from command path: {}",
-                parent.display()
-            );
-            return parent;
+    if command_path.is_absolute()
+        && let Some(parent) = command_path.parent()
+    {
+        return parent;
+    }
+    Path::new(".")
+}
+
+fn get_working_directory<'a>(command: &'a str, configured: Option<&'a str>) -> &'a Path {
+    let working_dir = working_directory_for(command, configured);
+    if configured.is_some() {
+        info!(
+            "Using configured working directory: {}",
+            working_dir.display()
+        );
+    } else if working_dir == Path::new(".") {
+        warn!("Could not determine working directory from command path, using current directory");
+    } else {
+        info!(
+            "Using working directory from command path: {}",
+            working_dir.display()
+        );
+    }
+    working_dir
+}
+
+/// The stop sequence `kill_process` will actually use for `configured`.
+/// `None` where sequences are not supported.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn effective_kill_sequence(configured: Option<&[KillStep]>) -> Option<Vec<KillStep>> {
+    match configured {
+        Some(steps) if !steps.is_empty() => Some(steps.to_vec()),
+        _ => Some(default_kill_sequence()),
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn effective_kill_sequence(_configured: Option<&[KillStep]>) -> Option<Vec<KillStep>> {
+    None
+}
+
+/// Environment handed to a spawned child: what it inherits from the service
+/// plus the configured `env_vars` on top, the user/group it runs as, and the
+/// wrapper command it is launched through.
+pub struct SpawnEnv<'a> {
+    /// The configured `working_dir`; unset runs the process from the
+    /// directory of its binary.
+    pub working_dir: Option<&'a str>,
+    pub inheritance: &'a EnvInheritance,
+    pub vars: Option<&'a HashMap<String, String>>,
+    pub run_as_user: Option<&'a str>,
+    pub run_as_group: Option<&'a str>,
+    pub launch_wrapper: &'a [String],
+    /// Run the process on a pseudo-terminal instead of plain pipes.
+    pub allocate_pty: bool,
+    /// Unix only: start the process in a session of its own.
+    pub new_session: bool,
+    pub log_timestamps: Option<LogTimestampFormat>,
+    pub sched_policy: Option<SchedPolicy>,
+    pub io_priority: Option<IoPriority>,
+}
+
+fn apply_environment(command: &mut Command, env: &SpawnEnv) {
+    match env.inheritance {
+        EnvInheritance::Inherit => {}
+        EnvInheritance::Clear => {
+            command.env_clear();
+        }
+        EnvInheritance::Allowlist(names) => {
+            command.env_clear();
+            for name in names {
+                if let Some(value) = std::env::var_os(name) {
+                    command.env(name, value);
+                }
+            }
         }
     }
+    if let Some(vars) = env.vars {
+        command.envs(vars);
+    }
+}
 
-    warn!("Could not determine working directory from command path, using current directory");
-    Path::new(".")
+/// `word` quoted for a POSIX shell, left bare when nothing in it is special.
+fn shell_quote(word: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !word.is_empty() && word.chars().all(is_plain) {
+        return word.to_string();
+    }
+    format!("'{}'", word.replace('\'', r"'\''"))
+}
+
+/// The variables `env` sets on top of what the child inherits, in the order
+/// a later one overrides an earlier one, plus whether it starts from an
+/// empty environment.
+fn environment_assignments(env: &SpawnEnv) -> (bool, Vec<(String, String)>) {
+    let mut vars: Vec<(String, String)> = env
+        .vars
+        .map(|vars| vars.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+        .unwrap_or_default();
+    vars.sort();
+    match env.inheritance {
+        EnvInheritance::Inherit => (false, vars),
+        EnvInheritance::Clear => (true, vars),
+        EnvInheritance::Allowlist(names) => {
+            let inherited = names.iter().filter_map(|name| {
+                let value = std::env::var(name).ok()?;
+                Some((name.clone(), value))
+            });
+            (true, inherited.chain(vars).collect())
+        }
+    }
+}
+
+/// The sudo option that keeps the `env_vars` of `env` for the command it
+/// runs, as sudo otherwise resets its environment. Only the names are
+/// passed, so values such as keyring secrets stay off the command line.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn sudo_preserve_env(env: &SpawnEnv) -> Option<String> {
+    let vars = env.vars.filter(|vars| !vars.is_empty())?;
+    let mut names: Vec<&str> = vars.keys().map(String::as_str).collect();
+    names.sort();
+    Some(format!("--preserve-env={}", names.join(",")))
+}
+
+/// A POSIX shell command line that runs `command` the way
+/// `spawn_process_with_privileges` would: in the same directory, with the
+/// same environment, identity, scheduling, elevation and launch wrapper.
+/// Output is left on the terminal rather than sent to the logs.
+pub fn shell_command(command: &str, args: &[&str], env: &SpawnEnv, run_as_admin: bool) -> String {
+    let working_dir = match working_directory_for(command, env.working_dir) {
+        dir if dir == Path::new(".") => std::env::current_dir().unwrap_or_default(),
+        dir => dir.to_path_buf(),
+    };
+    let mut words: Vec<String> = Vec::new();
+
+    // Privileges are dropped last, after the environment has been set up
+    if env.run_as_user.is_some() || env.run_as_group.is_some() {
+        words.push("sudo".to_string());
+        if let Some(user) = env.run_as_user {
+            words.extend(["-u".to_string(), user.to_string()]);
+        }
+        if let Some(group) = env.run_as_group {
+            words.extend(["-g".to_string(), group.to_string()]);
+        }
+    }
+    let (clear, vars) = environment_assignments(env);
+    if clear || !vars.is_empty() {
+        words.push("env".to_string());
+        if clear {
+            words.push("-i".to_string());
+        }
+        words.extend(
+            vars.into_iter()
+                .map(|(name, value)| format!("{name}={value}")),
+        );
+    }
+    match env.sched_policy {
+        Some(SchedPolicy::Other) => words.extend(["chrt", "--other", "0"].map(String::from)),
+        Some(SchedPolicy::Batch) => words.extend(["chrt", "--batch", "0"].map(String::from)),
+        Some(SchedPolicy::Idle) => words.extend(["chrt", "--idle", "0"].map(String::from)),
+        None => {}
+    }
+    if let Some(io_priority) = env.io_priority {
+        words.extend(["ionice".to_string(), "-c".to_string()]);
+        match io_priority.class {
+            IoPriorityClass::Realtime => words.extend(["1", "-n"].map(String::from)),
+            IoPriorityClass::BestEffort => words.extend(["2", "-n"].map(String::from)),
+            IoPriorityClass::Idle => words.push("3".to_string()),
+        }
+        if io_priority.class != IoPriorityClass::Idle {
+            words.push(io_priority.level.to_string());
+        }
+    }
+    if run_as_admin {
+        words.push("sudo".to_string());
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        words.extend(sudo_preserve_env(env));
+    }
+    words.extend(env.launch_wrapper.iter().cloned());
+    words.push(command.to_string());
+    words.extend(args.iter().map(|arg| arg.to_string()));
+
+    let words: Vec<String> = words.iter().map(|word| shell_quote(word)).collect();
+    format!(
+        "cd {} && {}",
+        shell_quote(&working_dir.to_string_lossy()),
+        words.join(" ")
+    )
+}
+
+/// What the service still has to copy into the logs once the child runs.
+enum OutputRelay {
+    /// The child writes to its logs itself.
+    Direct,
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    Terminal(std::fs::File, StampedLog),
+    /// stdout and stderr are pipes, so that `log_timestamps` can be applied.
+    Pipes(StampedLog, StampedLog),
+}
+
+/// Points stdout and stderr at the logs, or with `allocate_pty` at a new
+/// terminal. Output that has to pass through the service, a terminal's or
+/// one with `log_timestamps`, is copied over by `relay_output`.
+fn attach_output(
+    command: &mut Command,
+    env: &SpawnEnv,
+    log: std::fs::File,
+    stderr_log: std::fs::File,
+) -> io::Result<OutputRelay> {
+    let clock = LogClock::start();
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    if env.allocate_pty {
+        info!("Attaching process to a pseudo-terminal");
+        return Ok(OutputRelay::Terminal(
+            pty::attach(command)?,
+            StampedLog::new(log, env.log_timestamps, clock),
+        ));
+    }
+    if env.log_timestamps.is_some() {
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        return Ok(OutputRelay::Pipes(
+            StampedLog::new(log, env.log_timestamps, clock),
+            StampedLog::new(stderr_log, env.log_timestamps, clock),
+        ));
+    }
+    command
+        .stdout(Stdio::from(log))
+        .stderr(Stdio::from(stderr_log));
+    Ok(OutputRelay::Direct)
+}
+
+fn relay_output(child: &mut std::process::Child, relay: OutputRelay) {
+    let relays = match relay {
+        OutputRelay::Direct => return,
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        OutputRelay::Terminal(master, log) => vec![pty::forward_output(master, log)],
+        OutputRelay::Pipes(log, stderr_log) => {
+            let stdout = child
+                .stdout
+                .take()
+                .map(|stdout| log_stamp::forward(stdout, log));
+            let stderr = child
+                .stderr
+                .take()
+                .map(|stderr| log_stamp::forward(stderr, stderr_log));
+            stdout.into_iter().chain(stderr).collect()
+        }
+    };
+    OUTPUT_RELAYS.lock().insert(child.id(), relays);
+}
+
+/// Waits up to `timeout` until everything the process `pid` wrote has been
+/// copied into its logs, returning whether it was. Output the child writes
+/// straight to its logs needs no waiting. Past the timeout, e.g. while a
+/// grandchild keeps the output open, copying carries on in the background.
+pub fn drain_output(pid: u32, timeout: Duration) -> bool {
+    let Some(relays) = OUTPUT_RELAYS.lock().remove(&pid) else {
+        return true;
+    };
+    let deadline = Instant::now() + timeout;
+    while !relays.iter().all(JoinHandle::is_finished) {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    true
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn apply_run_as(command: &mut Command, env: &SpawnEnv) -> io::Result<()> {
+    if let Some(identity) = run_as::resolve(env.run_as_user, env.run_as_group)? {
+        info!("Dropping privileges to {}", identity.description);
+        run_as::apply(command, &identity);
+    }
+    Ok(())
+}
+
+/// Starts the process as the leader of a new session, so it neither shares
+/// the controlling terminal nor the process group of the service.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn apply_session(command: &mut Command, env: &SpawnEnv) {
+    // A pseudo-terminal already gets a session of its own
+    if !env.new_session || env.allocate_pty {
+        return;
+    }
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn apply_scheduling(command: &mut Command, env: &SpawnEnv) -> io::Result<()> {
+    if env.sched_policy.is_none() && env.io_priority.is_none() {
+        return Ok(());
+    }
+    sched::check_privileges(env.io_priority)?;
+    info!(
+        "Setting scheduling policy {:?} and IO priority {:?}",
+        env.sched_policy, env.io_priority
+    );
+    sched::apply(command, env.sched_policy, env.io_priority);
+    Ok(())
 }
 
 pub fn spawn_process_with_privileges(
     command: &str,
     args: &[&str],
+    env: &SpawnEnv,
     mut log: std::fs::File,
+    stderr_log: Option<std::fs::File>,
     run_as_admin: bool,
 ) -> io::Result<u32> {
     let _ = writeln!(
@@ -116,14 +601,57 @@ pub fn spawn_process_with_privileges(
         run_as_admin
     );
 
-    let working_dir = get_working_directory(command);
+    let working_dir = get_working_directory(command, env.working_dir);
     info!("Setting working directory to: {}", working_dir.display());
 
-    let log_for_stderr = log.try_clone()?;
+    // The wrapper runs the real command, e.g. `firejail --private <command> <args>`
+    let wrapped_args: Vec<&str>;
+    let (command, args) = match env.launch_wrapper.split_first() {
+        Some((wrapper, wrapper_args)) => {
+            info!(
+                "Launching through wrapper: {}",
+                env.launch_wrapper.join(" ")
+            );
+            wrapped_args = wrapper_args
+                .iter()
+                .map(String::as_str)
+                .chain(std::iter::once(command))
+                .chain(args.iter().copied())
+                .collect();
+            (wrapper.as_str(), wrapped_args.as_slice())
+        }
+        None => (command, args),
+    };
+
+    let log_for_stderr = match stderr_log {
+        Some(file) => file,
+        None => log.try_clone()?,
+    };
+    #[cfg(not(target_os = "linux"))]
+    if env.sched_policy.is_some() || env.io_priority.is_some() {
+        return Err(io::Error::other(
+            "sched_policy and io_priority are only supported on Linux",
+        ));
+    }
     #[cfg(target_os = "windows")]
     {
+        if env.run_as_user.is_some() || env.run_as_group.is_some() {
+            return Err(io::Error::other(
+                "run_as_user and run_as_group are only supported on Linux and macOS",
+            ));
+        }
+        if env.allocate_pty {
+            return Err(io::Error::other(
+                "allocate_pty is only supported on Linux and macOS",
+            ));
+        }
         if run_as_admin {
             info!("Running process with administrator privileges on Windows");
+            // Start-Process -Verb RunAs starts the process from a fresh
+            // environment rather than the one set up here
+            if env.vars.is_some_and(|vars| !vars.is_empty()) {
+                warn!("env_vars are not passed to processes started elevated on Windows");
+            }
             let escaped_args = args
                 .iter()
                 .map(|arg| format!("'{}'", arg.replace("'", "''")))
@@ -182,12 +710,11 @@ pub fn spawn_process_with_privileges(
             }
         } else {
             info!("Running process without administrator privileges on Windows");
-            let child = Command::new(command)
-                .args(args)
-                .current_dir(working_dir)
-                .stdout(Stdio::from(log))
-                .stderr(Stdio::from(log_for_stderr))
-                .spawn()?;
+            let mut child_command = Command::new(command);
+            apply_environment(&mut child_command, env);
+            let relay = attach_output(&mut child_command, env, log, log_for_stderr)?;
+            let mut child = child_command.args(args).current_dir(working_dir).spawn()?;
+            relay_output(&mut child, relay);
 
             let pid = child.id();
             info!(
@@ -196,6 +723,17 @@ pub fn spawn_process_with_privileges(
                 working_dir.display()
             );
 
+            // Left over from an earlier process with this pid whose exit was
+            // never picked up
+            EXIT_CODES.lock().remove(&pid);
+            std::thread::spawn(move || {
+                if let Ok(status) = child.wait()
+                    && let Some(code) = status.code()
+                {
+                    EXIT_CODES.lock().insert(pid, code);
+                }
+            });
+
             Ok(pid)
         }
     }
@@ -203,6 +741,7 @@ pub fn spawn_process_with_privileges(
     {
         let mut command_to_run = command.to_string();
         let mut args_to_run = args.to_vec();
+        let preserve_env = sudo_preserve_env(env);
 
         if run_as_admin {
             info!("Running process with root privileges on Linux using sudo");
@@ -213,6 +752,9 @@ pub fn spawn_process_with_privileges(
                 .is_ok_and(|o| o.status.success())
             {
                 args_to_run.insert(0, command);
+                if let Some(preserve_env) = &preserve_env {
+                    args_to_run.insert(0, preserve_env);
+                }
                 command_to_run = "sudo".to_string();
             } else {
                 warn!("sudo not available, running without elevated privileges");
@@ -220,12 +762,28 @@ pub fn spawn_process_with_privileges(
         } else {
             info!("Running process without elevated privileges on Linux");
         }
-        let child = Command::new(&command_to_run)
+        let mut child_command = Command::new(&command_to_run);
+        apply_environment(&mut child_command, env);
+        apply_session(&mut child_command, env);
+        apply_run_as(&mut child_command, env)?;
+        apply_scheduling(&mut child_command, env)?;
+        let relay = attach_output(&mut child_command, env, log, log_for_stderr)?;
+        let mut child = child_command
             .args(&args_to_run)
             .current_dir(working_dir)
-            .stdout(Stdio::from(log))
-            .stderr(Stdio::from(log_for_stderr))
-            .spawn()?;
+            .spawn()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::PermissionDenied
+                    if env.sched_policy.is_some() || env.io_priority.is_some() =>
+                {
+                    io::Error::new(
+                        e.kind(),
+                        format!("{e}; the service may lack the privileges for its sched_policy or io_priority"),
+                    )
+                }
+                _ => e,
+            })?;
+        relay_output(&mut child, relay);
 
         let pid = child.id();
         info!(
@@ -240,6 +798,7 @@ pub fn spawn_process_with_privileges(
     {
         let mut command_to_run = command.to_string();
         let mut args_to_run = args.to_vec();
+        let preserve_env = sudo_preserve_env(env);
 
         if run_as_admin {
             info!("Running process with administrator privileges on macOS using sudo");
@@ -250,6 +809,9 @@ pub fn spawn_process_with_privileges(
                 .is_ok_and(|o| o.status.success())
             {
                 args_to_run.insert(0, command);
+                if let Some(preserve_env) = &preserve_env {
+                    args_to_run.insert(0, preserve_env);
+                }
                 command_to_run = "sudo".to_string();
             } else {
                 warn!("sudo not available, running without elevated privileges");
@@ -258,12 +820,16 @@ pub fn spawn_process_with_privileges(
             info!("Running process without elevated privileges on macOS");
         }
 
-        let child = Command::new(&command_to_run)
+        let mut child_command = Command::new(&command_to_run);
+        apply_environment(&mut child_command, env);
+        apply_session(&mut child_command, env);
+        apply_run_as(&mut child_command, env)?;
+        let relay = attach_output(&mut child_command, env, log, log_for_stderr)?;
+        let mut child = child_command
             .args(&args_to_run)
             .current_dir(working_dir)
-            .stdout(Stdio::from(log))
-            .stderr(Stdio::from(log_for_stderr))
             .spawn()?;
+        relay_output(&mut child, relay);
 
         let pid = child.id();
         info!(
@@ -272,32 +838,32 @@ pub fn spawn_process_with_privileges(
             working_dir.display()
         );
 
-        std::thread::spawn(move || {
-            let _ = child.wait_with_output();
-        });
-
         Ok(pid)
     }
 }
 
 #[cfg(target_os = "windows")]
-pub fn kill_process(pid: u32) -> io::Result<()> {
+pub fn kill_process(pid: u32, kill_sequence: Option<&[KillStep]>) -> io::Result<StopOutcome> {
     info!("Attempting to terminate process PID {pid} with administrator privileges");
+    if kill_sequence.is_some_and(|steps| !steps.is_empty()) {
+        warn!("Custom kill sequences are not supported on Windows, using taskkill /F");
+    }
     let check_output = Command::new("tasklist")
         .args(["/FI", &format!("PID eq {pid}")])
         .output()?;
 
     if !check_output.status.success() {
         info!("Process PID {pid} does not exist, skipping termination");
-        return Ok(());
+        return Ok(already_exited());
     }
 
     let output_str = String::from_utf8_lossy(&check_output.stdout);
     if !output_str.contains(&pid.to_string()) {
         info!("Process PID {pid} does not exist, skipping termination");
-        return Ok(());
+        return Ok(already_exited());
     }
 
+    let started = Instant::now();
     let ps_command = format!(
         "Start-Process -FilePath 'taskkill' -ArgumentList @('/F', '/PID', '{pid}') -Verb RunAs -WindowStyle Hidden -Wait"
     );
@@ -309,7 +875,12 @@ pub fn kill_process(pid: u32) -> io::Result<()> {
 
     if output.status.success() {
         info!("Successfully terminated process PID {pid} with administrator privileges");
-        Ok(())
+        // taskkill /F ends the process without giving it a chance to clean up.
+        Ok(StopOutcome {
+            graceful: false,
+            signal_used: Some("KILL".to_string()),
+            duration_ms: started.elapsed().as_millis() as u64,
+        })
     } else {
         error!("Failed to terminate process PID {pid} with administrator privileges:");
         Err(io::Error::other(format!(
@@ -319,65 +890,274 @@ pub fn kill_process(pid: u32) -> io::Result<()> {
 }
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
-pub fn kill_process(pid: u32) -> io::Result<()> {
+pub fn kill_process(pid: u32, kill_sequence: Option<&[KillStep]>) -> io::Result<StopOutcome> {
     info!("Attempting to terminate process PID {pid} with elevated privileges");
 
-    let check_process = Command::new("ps").args(["-p", &pid.to_string()]).output()?;
-    if !check_process.status.success() {
+    if !is_process_running(pid as i32) {
         info!("Process PID {pid} does not exist, skipping termination");
-        return Ok(());
+        return Ok(already_exited());
     }
 
-    info!("Sending SIGINT signal to process PID {pid} with sudo");
-    let kill_int_args = &["-2", &pid.to_string()];
-    let output = Command::new("sudo")
-        .arg("kill")
-        .args(kill_int_args)
-        .output()?;
+    let started = Instant::now();
+    let default_sequence = default_kill_sequence();
+    let steps = match kill_sequence {
+        Some(steps) if !steps.is_empty() => steps,
+        _ => default_sequence.as_slice(),
+    };
 
-    if output.status.success() {
-        info!("Successfully sent SIGINT signal to process PID {pid} with sudo");
-        std::thread::sleep(std::time::Duration::from_millis(1000));
+    for (index, step) in steps.iter().enumerate() {
+        let signal = normalize_signal(&step.signal)
+            .ok_or_else(|| io::Error::other(format!("Unknown signal: {}", step.signal)))?;
+
+        info!(
+            "Sending SIG{signal} signal to process PID {pid} (step {}/{})",
+            index + 1,
+            steps.len()
+        );
+        let output = kill_command(&signal, pid).output()?;
 
-        let check_process = Command::new("ps").args(["-p", &pid.to_string()]).output()?;
+        if output.status.success() {
+            info!("Successfully sent SIG{signal} signal to process PID {pid}");
+        } else {
+            warn!(
+                "Failed to send SIG{signal} to process PID {pid}: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
 
-        if !check_process.status.success() {
-            return Ok(());
+        if wait_for_exit(pid, Duration::from_millis(step.wait_ms)) {
+            info!("Process PID {pid} terminated after SIG{signal}");
+            return Ok(StopOutcome {
+                graceful: signal != "KILL" && signal != "9",
+                signal_used: Some(signal),
+                duration_ms: started.elapsed().as_millis() as u64,
+            });
         }
 
-        warn!(
-            "Process {pid} did not terminate after receiving SIGINT, attempting to send SIGKILL with sudo"
-        );
-    } else {
-        warn!(
-            "Failed to send SIGINT to process PID {pid} with sudo, attempting to send SIGKILL with sudo"
-        );
+        warn!("Process {pid} did not terminate after receiving SIG{signal}");
     }
 
-    info!("Sending SIGKILL signal to process PID {pid} with sudo");
-    let kill_kill_args = &["-9", &pid.to_string()];
-    let output = Command::new("sudo")
-        .arg("kill")
-        .args(kill_kill_args)
-        .output()?;
+    error!(
+        "Failed to terminate process PID {pid} after {} kill steps",
+        steps.len()
+    );
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!(
+            "Process {pid} is still running after {} kill steps",
+            steps.len()
+        ),
+    ))
+}
 
-    let stderr = if !output.stderr.is_empty() {
-        String::from_utf8_lossy(&output.stderr).to_string()
+/// Sends `signal`, e.g. `HUP`, to `pid` without waiting for anything.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn send_signal(pid: u32, signal: &str) -> io::Result<()> {
+    let signal = normalize_signal(signal)
+        .ok_or_else(|| io::Error::other(format!("Unknown signal: {signal}")))?;
+    let output = kill_command(&signal, pid).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "Failed to send SIG{signal} to process PID {pid}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    info!("Sent SIG{signal} to process PID {pid}");
+    Ok(())
+}
+
+/// `kill -<signal> <pid>`, through sudo unless the service already runs as root.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn kill_command(signal: &str, pid: u32) -> Command {
+    let mut command = if unsafe { libc::geteuid() } == 0 {
+        Command::new("kill")
     } else {
-        String::from("")
+        let mut sudo = Command::new("sudo");
+        sudo.arg("kill");
+        sudo
     };
+    command.args([format!("-{signal}"), pid.to_string()]);
+    command
+}
 
-    if output.status.success() {
-        info!("Successfully terminated process PID {pid} using SIGKILL with sudo");
-        Ok(())
-    } else {
-        error!(
-            "Failed to terminate process PID {pid} using SIGKILL with sudo: {}",
-            stderr.trim()
+/// The outcome for a process that was already gone when asked to stop.
+pub fn already_exited() -> StopOutcome {
+    StopOutcome {
+        graceful: true,
+        signal_used: None,
+        duration_ms: 0,
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn default_kill_sequence() -> Vec<KillStep> {
+    vec![
+        KillStep {
+            signal: "INT".to_string(),
+            wait_ms: 1000,
+        },
+        KillStep {
+            signal: "KILL".to_string(),
+            wait_ms: 1000,
+        },
+    ]
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn wait_for_exit(pid: u32, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if !is_process_running(pid as i32) {
+            return true;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return false;
+        }
+        std::thread::sleep(EXIT_POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+/// Accepts `TERM`, `SIGTERM`, `sigterm` or a signal number like `15` and returns the
+/// form understood by `kill -<signal>`.
+pub fn normalize_signal(signal: &str) -> Option<String> {
+    const SIGNAL_NAMES: &[&str] = &[
+        "HUP", "INT", "QUIT", "ABRT", "KILL", "USR1", "USR2", "PIPE", "ALRM", "TERM", "CONT",
+        "STOP", "TSTP",
+    ];
+
+    let signal = signal.trim();
+    if let Ok(number) = signal.parse::<u8>() {
+        return (1..=64).contains(&number).then(|| number.to_string());
+    }
+
+    let upper = signal.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    SIGNAL_NAMES.contains(&name).then(|| name.to_string())
+}
+
+#[cfg(all(test, any(target_os = "linux", target_os = "macos")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kill_sequence_steps_run_in_order_until_the_process_exits() {
+        use crate::openlistcore::test_support::scratch_dir;
+
+        let trace = scratch_dir("kill-order").join("signals");
+        let script = format!(
+            "trap 'echo INT >> {trace}' INT; trap 'echo TERM >> {trace}' TERM; \
+             echo ready >> {trace}; while :; do sleep 0.05; done",
+            trace = trace.display()
+        );
+        let mut child = Command::new("sh").args(["-c", &script]).spawn().unwrap();
+        let ready_by = Instant::now() + Duration::from_secs(5);
+        while !std::fs::read_to_string(&trace).is_ok_and(|s| s.contains("ready")) {
+            assert!(Instant::now() < ready_by, "script never became ready");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+
+        let steps = [
+            KillStep {
+                signal: "INT".to_string(),
+                wait_ms: 300,
+            },
+            KillStep {
+                signal: "SIGTERM".to_string(),
+                wait_ms: 300,
+            },
+            KillStep {
+                signal: "9".to_string(),
+                wait_ms: 2000,
+            },
+        ];
+        let pid = child.id();
+        let reaper = std::thread::spawn(move || child.wait());
+        let outcome = kill_process(pid, Some(&steps)).unwrap();
+        reaper.join().unwrap().unwrap();
+
+        assert!(!outcome.graceful);
+        assert_eq!(outcome.signal_used.as_deref(), Some("9"));
+        assert!(outcome.duration_ms >= 600, "{outcome:?}");
+        let signals = std::fs::read_to_string(&trace).unwrap();
+        assert_eq!(
+            signals.lines().collect::<Vec<_>>(),
+            ["ready", "INT", "TERM"]
+        );
+    }
+
+    /// The environment a child spawned with `inheritance` and `vars` sees.
+    fn child_environment(
+        inheritance: EnvInheritance,
+        vars: &[(&str, &str)],
+    ) -> HashMap<String, String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let env = SpawnEnv {
+            working_dir: None,
+            inheritance: &inheritance,
+            vars: Some(&vars),
+            run_as_user: None,
+            run_as_group: None,
+            launch_wrapper: &[],
+            allocate_pty: false,
+            new_session: false,
+            log_timestamps: None,
+            sched_policy: None,
+            io_priority: None,
+        };
+        let mut command = Command::new("/usr/bin/env");
+        apply_environment(&mut command, &env);
+        let output = command.output().unwrap();
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn children_inherit_the_service_environment_by_default() {
+        let seen = child_environment(EnvInheritance::Inherit, &[("OPENLIST_TEST", "1")]);
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(seen.get("PATH"), Some(&path));
+        assert_eq!(seen.get("OPENLIST_TEST").map(String::as_str), Some("1"));
+        assert!(seen.len() >= std::env::vars_os().count());
+    }
+
+    #[test]
+    fn cleared_children_only_see_their_env_vars() {
+        let seen = child_environment(EnvInheritance::Clear, &[("OPENLIST_TEST", "1")]);
+        assert_eq!(
+            seen,
+            HashMap::from([("OPENLIST_TEST".to_string(), "1".to_string())])
+        );
+    }
+
+    #[test]
+    fn allowlisted_children_see_listed_and_configured_vars() {
+        let allowlist = EnvInheritance::Allowlist(vec![
+            "PATH".to_string(),
+            "OPENLIST_SURELY_UNSET".to_string(),
+        ]);
+        let seen = child_environment(allowlist, &[("OPENLIST_TEST", "1")]);
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(
+            seen,
+            HashMap::from([
+                ("PATH".to_string(), path),
+                ("OPENLIST_TEST".to_string(), "1".to_string()),
+            ])
+        );
+
+        // env_vars win over inherited values
+        let allowlist = EnvInheritance::Allowlist(vec!["PATH".to_string()]);
+        let seen = child_environment(allowlist, &[("PATH", "/override")]);
+        assert_eq!(
+            seen,
+            HashMap::from([("PATH".to_string(), "/override".to_string())])
         );
-        Err(io::Error::other(format!(
-            "Kill command with sudo failed: {}",
-            stderr.trim()
-        )))
     }
 }
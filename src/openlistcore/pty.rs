@@ -0,0 +1,92 @@
+use super::log_stamp::StampedLog;
+use log::warn;
+use std::{
+    fs::File,
+    io::{self, Read},
+    mem,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::process::CommandExt,
+    },
+    process::{Command, Stdio},
+    ptr,
+    thread::JoinHandle,
+};
+
+/// Runs `command` on a new pseudo-terminal: the terminal becomes its
+/// controlling terminal and its stdin, stdout and stderr. Returns the master
+/// side, which yields everything the process writes.
+pub fn attach(command: &mut Command) -> io::Result<File> {
+    let mut master = -1;
+    let mut slave = -1;
+    let rc = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            ptr::null_mut(),
+            ptr::null(),
+            ptr::null(),
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let master = unsafe { File::from_raw_fd(master) };
+    let slave = unsafe { OwnedFd::from_raw_fd(slave) };
+    // Other children must not inherit the master
+    if unsafe { libc::fcntl(master.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // Keep line endings as written instead of turning them into "\r\n"
+    let mut termios: libc::termios = unsafe { mem::zeroed() };
+    if unsafe { libc::tcgetattr(slave.as_raw_fd(), &mut termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    termios.c_oflag &= !libc::ONLCR;
+    if unsafe { libc::tcsetattr(slave.as_raw_fd(), libc::TCSANOW, &termios) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    command
+        .stdin(Stdio::from(slave.try_clone()?))
+        .stdout(Stdio::from(slave.try_clone()?))
+        .stderr(Stdio::from(slave));
+    unsafe {
+        command.pre_exec(|| {
+            // A terminal can only become the controlling terminal of a
+            // session leader
+            if libc::setsid() == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+    Ok(master)
+}
+
+/// Copies the terminal output of the process into `log` in the background
+/// until the terminal is closed, i.e. the process and its children exited.
+pub fn forward_output(mut master: File, mut log: StampedLog) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut buf = [0u8; 8192];
+        loop {
+            match master.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => {
+                    if let Err(e) = log.write_chunk(&buf[..read]) {
+                        warn!("Failed to write terminal output to the log: {e}");
+                        break;
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                // Reading the master fails with EIO once the last holder of
+                // the terminal has closed it
+                Err(_) => break,
+            }
+        }
+    })
+}
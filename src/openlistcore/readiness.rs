@@ -0,0 +1,401 @@
+use super::{
+    data::{ProcessConfig, ProcessRuntime, ProcessState, ReadinessCheck},
+    hooks,
+    monitor::MonitorThread,
+    process::is_process_running,
+    startup_probe::{self, ProbeOutcome},
+};
+use anyhow::{Result, anyhow};
+use log::{error, info, warn};
+use std::{
+    fs::File,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    path::Path,
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+const PROBE_INTERVAL: Duration = Duration::from_millis(500);
+const DEFAULT_READINESS_TIMEOUT_SECS: u64 = 60;
+const ON_READY_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Waits in the background for the run identified by `generation` to become
+/// ready and pass its `startup_probe`, then marks it running and fires
+/// `on_ready_command` exactly once. A run that never becomes ready is marked
+/// running too, with the timeout recorded as its `last_error`, and the
+/// command is skipped. A run failing its probe is stopped and marked failed.
+pub fn spawn_watcher(config: ProcessConfig, runtime: ProcessRuntime, generation: u64) {
+    std::thread::spawn(move || {
+        let _active = MonitorThread::enter();
+        let timed_out = match wait_until_ready(&config, &runtime, generation) {
+            Readiness::Ready => None,
+            Readiness::TimedOut(timeout) => Some(timeout),
+            Readiness::Abandoned => return,
+        };
+
+        if let Some(probe) = &config.startup_probe {
+            match startup_probe::run(&config, probe, &runtime, generation) {
+                ProbeOutcome::Passed => {}
+                ProbeOutcome::Failed(message) => {
+                    startup_probe::fail_start(&config, &runtime, generation, &message);
+                    return;
+                }
+                ProbeOutcome::Abandoned => return,
+            }
+        }
+
+        if let Some(timeout) = timed_out {
+            *runtime.last_error.lock() = Some(format!(
+                "Did not become ready within {}s",
+                timeout.as_secs()
+            ));
+            *runtime.state.lock() = ProcessState::Running;
+            return;
+        }
+
+        *runtime.state.lock() = ProcessState::Running;
+        info!("Process {} ({}) is ready", config.name, config.id);
+
+        if let Some(command) = &config.on_ready_command {
+            run_on_ready_command(&config, command);
+        }
+    });
+}
+
+/// How long a run may stay in the starting state before it is given up on.
+pub fn readiness_timeout(config: &ProcessConfig) -> Duration {
+    Duration::from_secs(
+        config
+            .readiness_timeout_secs
+            .unwrap_or(DEFAULT_READINESS_TIMEOUT_SECS),
+    )
+}
+
+/// How long after spawning a run stays starting before it is probed.
+pub fn warmup(config: &ProcessConfig) -> Duration {
+    Duration::from_secs(config.warmup_secs.unwrap_or(0))
+}
+
+pub fn is_current_run(runtime: &ProcessRuntime, generation: u64) -> bool {
+    runtime.start_generation.load(Ordering::SeqCst) == generation
+}
+
+enum Readiness {
+    Ready,
+    TimedOut(Duration),
+    /// The run exited or was replaced before it became ready.
+    Abandoned,
+}
+
+fn wait_until_ready(
+    config: &ProcessConfig,
+    runtime: &ProcessRuntime,
+    generation: u64,
+) -> Readiness {
+    let warmup_end = Instant::now() + warmup(config);
+    if config.warmup_secs.is_some() {
+        info!(
+            "Process {} ({}) is warming up for {}s",
+            config.name,
+            config.id,
+            warmup(config).as_secs()
+        );
+    }
+    while Instant::now() < warmup_end {
+        if is_abandoned(config, runtime, generation) {
+            return Readiness::Abandoned;
+        }
+        std::thread::sleep(
+            PROBE_INTERVAL.min(warmup_end.saturating_duration_since(Instant::now())),
+        );
+    }
+
+    let Some(check) = &config.readiness_check else {
+        return if is_current_run(runtime, generation) {
+            Readiness::Ready
+        } else {
+            Readiness::Abandoned
+        };
+    };
+
+    // The readiness timeout only starts once the warmup is over
+    let timeout = readiness_timeout(config);
+    let deadline = Instant::now() + timeout;
+    loop {
+        if is_abandoned(config, runtime, generation) {
+            return Readiness::Abandoned;
+        }
+        if probe(check) {
+            return Readiness::Ready;
+        }
+        if Instant::now() >= deadline {
+            warn!(
+                "Process {} ({}) did not become ready within {}s",
+                config.name,
+                config.id,
+                timeout.as_secs()
+            );
+            return Readiness::TimedOut(timeout);
+        }
+        std::thread::sleep(PROBE_INTERVAL);
+    }
+}
+
+/// Whether the run exited or was replaced, so waiting for it is pointless.
+fn is_abandoned(config: &ProcessConfig, runtime: &ProcessRuntime, generation: u64) -> bool {
+    if !is_current_run(runtime, generation) {
+        return true;
+    }
+    if !is_process_running(runtime.running_pid.load(Ordering::Relaxed)) {
+        warn!(
+            "Process {} ({}) exited before becoming ready",
+            config.name, config.id
+        );
+        return true;
+    }
+    false
+}
+
+fn run_on_ready_command(config: &ProcessConfig, command: &str) {
+    let log = File::options()
+        .create(true)
+        .append(true)
+        .open(&config.log_file)
+        .ok();
+    let working_dir = config.working_dir.as_deref().map(Path::new);
+
+    match hooks::run_shell_command(command, ON_READY_COMMAND_TIMEOUT, working_dir, log) {
+        Ok(outcome) if outcome.success() => {
+            info!("On-ready command for {} succeeded", config.name);
+        }
+        Ok(outcome) => {
+            warn!("On-ready command for {} failed: {outcome:?}", config.name);
+        }
+        Err(e) => {
+            error!("Failed to run on-ready command for {}: {e}", config.name);
+        }
+    }
+}
+
+pub fn validate(check: &ReadinessCheck) -> Result<()> {
+    match check {
+        ReadinessCheck::Tcp { address } => {
+            resolve(address)?;
+        }
+        ReadinessCheck::Http { url } => {
+            parse_http_url(url)?;
+        }
+    }
+    Ok(())
+}
+
+/// The port the check connects to, used to spot processes that would collide.
+pub fn port(check: &ReadinessCheck) -> Option<u16> {
+    let address = match check {
+        ReadinessCheck::Tcp { address } => address.clone(),
+        ReadinessCheck::Http { url } => parse_http_url(url).ok()?.0,
+    };
+    address.rsplit_once(':')?.1.parse().ok()
+}
+
+/// Runs a single attempt of `check`, returning whether the process answered.
+pub fn probe(check: &ReadinessCheck) -> bool {
+    match check {
+        ReadinessCheck::Tcp { address } => probe_tcp(address),
+        ReadinessCheck::Http { url } => probe_http(url),
+    }
+}
+
+fn resolve(address: &str) -> Result<SocketAddr> {
+    address
+        .to_socket_addrs()
+        .map_err(|e| anyhow!("Invalid address {address}: {e}"))?
+        .next()
+        .ok_or_else(|| anyhow!("Address {address} did not resolve"))
+}
+
+fn probe_tcp(address: &str) -> bool {
+    resolve(address)
+        .ok()
+        .is_some_and(|addr| TcpStream::connect_timeout(&addr, PROBE_TIMEOUT).is_ok())
+}
+
+/// Splits `http://host:port/path` into the connect address, host header and path.
+fn parse_http_url(url: &str) -> Result<(String, String, String)> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| anyhow!("Only http:// URLs are supported for readiness checks: {url}"))?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return Err(anyhow!("Missing host in readiness URL: {url}"));
+    }
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{authority}:80")
+    };
+    Ok((address, authority.to_string(), path.to_string()))
+}
+
+fn probe_http(url: &str) -> bool {
+    let Ok((address, host, path)) = parse_http_url(url) else {
+        return false;
+    };
+    let Ok(addr) = resolve(&address) else {
+        return false;
+    };
+    let Ok(mut stream) = TcpStream::connect_timeout(&addr, PROBE_TIMEOUT) else {
+        return false;
+    };
+    let _ = stream.set_read_timeout(Some(PROBE_TIMEOUT));
+    let _ = stream.set_write_timeout(Some(PROBE_TIMEOUT));
+
+    let request = format!("GET {path} HTTP/1.0\r\nHost: {host}\r\nConnection: close\r\n\r\n");
+    if stream.write_all(request.as_bytes()).is_err() {
+        return false;
+    }
+
+    let mut status_line = [0u8; 32];
+    let Ok(read) = stream.read(&mut status_line) else {
+        return false;
+    };
+    // "HTTP/1.1 200 OK" -> 200
+    String::from_utf8_lossy(&status_line[..read])
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse::<u16>().ok())
+        .is_some_and(|code| (200..400).contains(&code))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::openlistcore::{
+        data::{CoreManager, ProcessState},
+        test_support::{self, scratch_dir, shell_request, wait_for},
+    };
+    use serde_json::json;
+    use std::{net::TcpListener, time::Duration};
+
+    #[test]
+    fn on_ready_command_runs_once_per_start() {
+        let _serial = test_support::serial();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let marker = scratch_dir("on-ready").join("ready");
+        let mut manager = CoreManager::new();
+        let request = shell_request(
+            "on-ready",
+            "exec sleep 30",
+            json!({
+                "readiness_check": { "type": "tcp", "address": address },
+                "on_ready_command": format!("echo ready >> {}", marker.display()),
+            }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+        let ready_count = || {
+            std::fs::read_to_string(&marker)
+                .map(|s| s.lines().count())
+                .unwrap_or(0)
+        };
+
+        for start in 1..=2 {
+            manager.start_process(&id).unwrap();
+            assert!(wait_for(Duration::from_secs(5), || ready_count() == start));
+            assert_eq!(
+                manager.get_process(&id).unwrap().state,
+                ProcessState::Running
+            );
+            // Further probes of the same run must not fire the command again
+            std::thread::sleep(Duration::from_millis(1200));
+            assert_eq!(ready_count(), start);
+            manager.stop_process(&id).unwrap();
+        }
+    }
+
+    #[test]
+    fn readiness_timeout_is_recorded_and_leaves_starting() {
+        let _serial = test_support::serial();
+        let closed_port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let marker = scratch_dir("never-ready").join("ready");
+        let mut manager = CoreManager::new();
+        let request = shell_request(
+            "never-ready",
+            "exec sleep 30",
+            json!({
+                "readiness_check": { "type": "tcp", "address": closed_port.to_string() },
+                "readiness_timeout_secs": 1,
+                "on_ready_command": format!("echo ready >> {}", marker.display()),
+            }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+
+        manager.start_process(&id).unwrap();
+        assert_eq!(
+            manager.get_process(&id).unwrap().state,
+            ProcessState::Starting
+        );
+        assert!(wait_for(Duration::from_secs(5), || {
+            manager.get_process(&id).unwrap().state != ProcessState::Starting
+        }));
+
+        let status = manager.get_process(&id).unwrap();
+        assert_eq!(status.state, ProcessState::Running);
+        assert!(status.is_running);
+        assert_eq!(
+            status.last_error.as_deref(),
+            Some("Did not become ready within 1s")
+        );
+        assert!(!marker.exists());
+        manager.stop_process(&id).unwrap();
+    }
+
+    #[test]
+    fn probes_wait_for_the_warmup() {
+        let _serial = test_support::serial();
+        let address = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut manager = CoreManager::new();
+        let request = shell_request(
+            "warmup",
+            "exec sleep 30",
+            json!({
+                "readiness_check": { "type": "tcp", "address": address.to_string() },
+                "readiness_timeout_secs": 1,
+                "warmup_secs": 2,
+            }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+
+        manager.start_process(&id).unwrap();
+        let pid = manager.get_process(&id).unwrap().pid;
+        // Unhealthy for longer than the readiness timeout, but only during
+        // the warmup
+        let opener = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(1500));
+            TcpListener::bind(address).unwrap()
+        });
+        std::thread::sleep(Duration::from_millis(1200));
+        let status = manager.get_process(&id).unwrap();
+        assert_eq!(status.state, ProcessState::Starting);
+        assert_eq!(status.last_error, None);
+
+        assert!(wait_for(Duration::from_secs(5), || {
+            manager.get_process(&id).unwrap().state == ProcessState::Running
+        }));
+        let status = manager.get_process(&id).unwrap();
+        assert_eq!(status.last_error, None);
+        assert_eq!((status.pid, status.restart_count), (pid, 0));
+        manager.stop_process(&id).unwrap();
+        drop(opener.join().unwrap());
+    }
+}
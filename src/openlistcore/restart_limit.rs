@@ -0,0 +1,132 @@
+use super::{
+    data::{ProcessConfig, ProcessRuntime, RestartTokens},
+    error::CoreError,
+};
+use std::time::{Duration, Instant};
+
+const DEFAULT_RESTART_RATE_WINDOW_SECS: u64 = 60;
+
+/// Takes one restart from the process's `restart_rate_limit` bucket, which
+/// starts out full and refills one restart every `restart_rate_window_secs /
+/// restart_rate_limit`. Fails with `CoreError::RestartRateLimited` while it is
+/// empty. A changed limit starts over with a full bucket.
+pub fn take_restart(
+    config: &ProcessConfig,
+    runtime: &ProcessRuntime,
+    now: Instant,
+) -> Result<(), CoreError> {
+    let mut bucket = runtime.restart_tokens.lock();
+    let Some(limit) = config.restart_rate_limit else {
+        *bucket = None;
+        return Ok(());
+    };
+    let window = Duration::from_secs(
+        config
+            .restart_rate_window_secs
+            .unwrap_or(DEFAULT_RESTART_RATE_WINDOW_SECS),
+    );
+
+    let tokens = match bucket.as_mut() {
+        Some(tokens) if tokens.limit == limit && tokens.window == window => tokens,
+        _ => bucket.insert(RestartTokens {
+            limit,
+            window,
+            tokens: f64::from(limit),
+            refilled_at: now,
+        }),
+    };
+    tokens
+        .take(now)
+        .map_err(|retry_after| CoreError::RestartRateLimited { retry_after })
+}
+
+impl RestartTokens {
+    /// Takes a token, or returns how long until the next one is refilled.
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        let per_token = self.window.as_secs_f64() / f64::from(self.limit);
+        let refilled = now
+            .saturating_duration_since(self.refilled_at)
+            .as_secs_f64()
+            / per_token;
+        self.tokens = (self.tokens + refilled).min(f64::from(self.limit));
+        self.refilled_at = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+        Err(Duration::from_secs_f64((1.0 - self.tokens) * per_token))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn limited_config(limit: u32, window_secs: u64) -> ProcessConfig {
+        serde_json::from_value(json!({
+            "id": "limited",
+            "name": "limited",
+            "bin_path": "/bin/true",
+            "args": [],
+            "log_file": "process.log",
+            "auto_restart": false,
+            "auto_start": false,
+            "run_as_admin": false,
+            "restart_rate_limit": limit,
+            "restart_rate_window_secs": window_secs,
+            "created_at": 0,
+            "updated_at": 0,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn restarts_are_rejected_until_the_bucket_refills() {
+        // One restart every 20s
+        let config = limited_config(3, 60);
+        let runtime = ProcessRuntime::default();
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        for _ in 0..3 {
+            take_restart(&config, &runtime, start).unwrap();
+        }
+        assert_eq!(
+            take_restart(&config, &runtime, at(5)),
+            Err(CoreError::RestartRateLimited {
+                retry_after: Duration::from_secs(15)
+            })
+        );
+        assert!(take_restart(&config, &runtime, at(19)).is_err());
+        take_restart(&config, &runtime, at(20)).unwrap();
+        assert!(take_restart(&config, &runtime, at(21)).is_err());
+
+        // Refilled up to the limit, and no further
+        for _ in 0..3 {
+            take_restart(&config, &runtime, at(1000)).unwrap();
+        }
+        assert!(take_restart(&config, &runtime, at(1000)).is_err());
+    }
+
+    #[test]
+    fn a_changed_limit_starts_with_a_full_bucket() {
+        let runtime = ProcessRuntime::default();
+        let now = Instant::now();
+        take_restart(&limited_config(1, 60), &runtime, now).unwrap();
+        assert!(take_restart(&limited_config(1, 60), &runtime, now).is_err());
+
+        for _ in 0..2 {
+            take_restart(&limited_config(2, 60), &runtime, now).unwrap();
+        }
+        assert!(take_restart(&limited_config(2, 60), &runtime, now).is_err());
+
+        let unlimited = ProcessConfig {
+            restart_rate_limit: None,
+            ..limited_config(2, 60)
+        };
+        take_restart(&unlimited, &runtime, now).unwrap();
+        assert!(runtime.restart_tokens.lock().is_none());
+    }
+}
@@ -0,0 +1,631 @@
+use super::{core, data::*, error::CoreError};
+use anyhow::Result;
+use log::{error, info};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use serde_json::{Value, json};
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+const JSONRPC_VERSION: &str = "2.0";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const OPERATION_FAILED: i64 = -32000;
+const TIMED_OUT: i64 = -32001;
+const RATE_LIMITED: i64 = -32002;
+
+#[derive(Debug, Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+    id: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IdParams {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct GetProcessParams {
+    id: String,
+    #[serde(default)]
+    command_line: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateParams {
+    id: String,
+    #[serde(flatten)]
+    request: UpdateProcessRequest,
+}
+
+/// `template` names the template; the other fields are its contents or, when
+/// creating from it, the overrides.
+#[derive(Debug, Deserialize)]
+struct TemplateParams {
+    template: String,
+    #[serde(flatten)]
+    request: UpdateProcessRequest,
+}
+
+#[derive(Debug, Deserialize)]
+struct TemplateNameParams {
+    template: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportLogsParams {
+    id: String,
+    #[serde(flatten)]
+    request: ExportLogsRequest,
+}
+
+#[derive(Debug, Deserialize)]
+struct AutoRestartParams {
+    id: String,
+    enabled: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApplyEnvParams {
+    id: String,
+    #[serde(flatten)]
+    request: ApplyEnvRequest,
+}
+
+#[derive(Debug, Deserialize)]
+struct StartWithOverridesParams {
+    id: String,
+    #[serde(flatten)]
+    request: StartWithOverridesRequest,
+}
+
+#[derive(Debug, Deserialize)]
+struct StopParams {
+    id: String,
+    #[serde(default)]
+    hold: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct StartParams {
+    id: String,
+    #[serde(default)]
+    ignore_condition: bool,
+    #[serde(default)]
+    idempotent: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClearFailedParams {
+    id: String,
+    #[serde(default)]
+    restart: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct LogParams {
+    id: String,
+    lines: Option<usize>,
+    #[serde(default)]
+    strip_ansi: bool,
+    level: Option<LogLevel>,
+    max_bytes: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LogSearchParams {
+    query: String,
+    #[serde(default)]
+    regex: bool,
+    max_matches: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StateParams {
+    state: ProcessState,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventsParams {
+    since: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LogBytesParams {
+    id: String,
+    max_bytes: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct PageParams {
+    sort_by: Option<ProcessSortBy>,
+    order: Option<SortOrder>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params)
+        .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid params: {e}")))
+}
+
+fn to_result<T: Serialize>(result: Result<T>) -> Result<Value, RpcError> {
+    match result {
+        Ok(value) => serde_json::to_value(value)
+            .map_err(|e| RpcError::new(OPERATION_FAILED, format!("Failed to encode result: {e}"))),
+        Err(e) => match e.downcast_ref::<CoreError>() {
+            Some(CoreError::StartTimeout { .. } | CoreError::StopTimeout { .. }) => {
+                Err(RpcError::new(TIMED_OUT, e.to_string()))
+            }
+            Some(CoreError::RestartRateLimited { .. }) => {
+                Err(RpcError::new(RATE_LIMITED, e.to_string()))
+            }
+            None => Err(RpcError::new(OPERATION_FAILED, e.to_string())),
+        },
+    }
+}
+
+fn call(manager: &Mutex<CoreManager>, method: &str, params: Value) -> Result<Value, RpcError> {
+    // Waits for each process to become ready, which needs the manager released
+    if method == "restart_all_running" {
+        return to_result(core::restart_all_running(manager));
+    }
+    let mut manager = manager.lock();
+    match method {
+        "get_status" => to_result(manager.get_openlist_status()),
+        "get_version" => to_result(manager.get_version()),
+        "self_check" => to_result(Ok(manager.self_check())),
+        "validate_all" => to_result(Ok(manager.validate_all())),
+        "diff_config" => to_result(manager.diff_config()),
+        "config_metadata" => to_result(manager.config_metadata()),
+        "snapshot" => to_result(Ok(manager.snapshot())),
+        "restore" => {
+            let request: RestoreSnapshotRequest = parse_params(params)?;
+            to_result(manager.restore(request.snapshot, request.start))
+        }
+        "reconcile_state" => to_result(Ok(manager.reconcile_state())),
+        "service_resource_usage" => to_result(Ok(manager.service_resource_usage())),
+        "enter_maintenance" => to_result(manager.enter_maintenance()),
+        "exit_maintenance" => to_result(manager.exit_maintenance()),
+        "set_safe_mode" => {
+            let request: SafeModeRequest = parse_params(params)?;
+            manager.set_safe_mode(request.enabled);
+            to_result(Ok(manager.safe_mode))
+        }
+        "set_global_pre_start_hook" => {
+            let request: GlobalPreStartHookRequest = parse_params(params)?;
+            to_result(
+                manager
+                    .set_global_pre_start_hook(request.command)
+                    .map(|()| manager.get_settings()),
+            )
+        }
+        "get_settings" => to_result(Ok(manager.get_settings())),
+        "update_settings" => {
+            let request: UpdateServiceSettingsRequest = parse_params(params)?;
+            to_result(manager.update_settings(request))
+        }
+        "metrics_prometheus" => to_result(Ok(manager.metrics_prometheus())),
+        "list_processes" => to_result(manager.list_processes()),
+        "list_processes_by_state" => {
+            let p: StateParams = parse_params(params)?;
+            to_result(manager.list_processes_by_state(p.state))
+        }
+        "health_summary" => to_result(Ok(manager.health_summary())),
+        "reliability_report" => to_result(Ok(manager.reliability_report())),
+        "list_events" => {
+            let p: EventsParams = parse_params(params)?;
+            to_result(Ok(manager.list_events(p.since.unwrap_or(0))))
+        }
+        "list_processes_paged" => {
+            let p: PageParams = parse_params(params)?;
+            to_result(manager.list_processes_paged(
+                p.sort_by.unwrap_or_default(),
+                p.order.unwrap_or_default(),
+                p.offset.unwrap_or(0),
+                p.limit.unwrap_or(DEFAULT_PAGE_SIZE),
+            ))
+        }
+        "reorder_processes" => {
+            let request: ReorderProcessesRequest = parse_params(params)?;
+            to_result(manager.reorder_processes(request.ids))
+        }
+        "get_process" => {
+            let p: GetProcessParams = parse_params(params)?;
+            if p.command_line {
+                to_result(manager.get_process_with_command_line(&p.id))
+            } else {
+                to_result(manager.get_process(&p.id))
+            }
+        }
+        "get_effective_config" => {
+            let p: IdParams = parse_params(params)?;
+            to_result(manager.get_effective_config(&p.id))
+        }
+        "reproduce_command" => {
+            let p: IdParams = parse_params(params)?;
+            to_result(manager.reproduce_command(&p.id))
+        }
+        "get_process_startup_output" => {
+            let p: IdParams = parse_params(params)?;
+            to_result(manager.get_process_startup_output(&p.id))
+        }
+        "not_running_reason" => {
+            let p: IdParams = parse_params(params)?;
+            to_result(manager.not_running_reason(&p.id))
+        }
+        "create_process" => {
+            let request: CreateProcessRequest = parse_params(params)?;
+            to_result(manager.create_process(request))
+        }
+        "list_templates" => to_result(Ok(manager.list_templates())),
+        "register_template" => {
+            let p: TemplateParams = parse_params(params)?;
+            to_result(manager.register_template(&p.template, p.request))
+        }
+        "delete_template" => {
+            let p: TemplateNameParams = parse_params(params)?;
+            to_result(manager.delete_template(&p.template))
+        }
+        "create_from_template" => {
+            let p: TemplateParams = parse_params(params)?;
+            to_result(manager.create_from_template(&p.template, p.request))
+        }
+        "update_processes" => {
+            let request: UpdateProcessesRequest = parse_params(params)?;
+            let updates = request
+                .updates
+                .into_iter()
+                .map(|update| (update.id, update.request))
+                .collect();
+            to_result(manager.update_processes(updates))
+        }
+        "update_process" => {
+            let p: UpdateParams = parse_params(params)?;
+            to_result(manager.update_process(&p.id, p.request))
+        }
+        "set_auto_restart" => {
+            let p: AutoRestartParams = parse_params(params)?;
+            to_result(manager.set_auto_restart(&p.id, p.enabled))
+        }
+        "apply_env" => {
+            let p: ApplyEnvParams = parse_params(params)?;
+            to_result(manager.apply_env(&p.id, p.request.env_vars, p.request.restart))
+        }
+        "delete_process" => {
+            let p: IdParams = parse_params(params)?;
+            to_result(manager.delete_process(&p.id))
+        }
+        "start_process" => {
+            let p: StartParams = parse_params(params)?;
+            if p.idempotent && manager.is_started(&p.id) {
+                to_result(Ok(()))
+            } else if p.ignore_condition {
+                to_result(manager.start_process_ignoring_condition(&p.id))
+            } else {
+                to_result(manager.start_process(&p.id))
+            }
+        }
+        "start_process_with_overrides" => {
+            let p: StartWithOverridesParams = parse_params(params)?;
+            to_result(manager.start_process_with_overrides(&p.id, &p.request.overrides))
+        }
+        "stop_process" => {
+            let p: StopParams = parse_params(params)?;
+            if p.hold {
+                to_result(manager.stop_process_and_hold(&p.id))
+            } else {
+                to_result(manager.stop_process(&p.id))
+            }
+        }
+        "cancel_pending_restart" => {
+            let p: IdParams = parse_params(params)?;
+            to_result(manager.cancel_pending_restart(&p.id))
+        }
+        "get_log_offset" => {
+            let p: IdParams = parse_params(params)?;
+            to_result(manager.get_log_offset(&p.id))
+        }
+        "reload_process_config" => {
+            let p: IdParams = parse_params(params)?;
+            to_result(manager.reload_process_config(&p.id))
+        }
+        "clear_failed" => {
+            let p: ClearFailedParams = parse_params(params)?;
+            to_result(manager.clear_failed(&p.id, p.restart))
+        }
+        "reset_and_restart_failed" => to_result(manager.reset_and_restart_failed()),
+        "start_all_processes" => to_result(manager.start_all_processes()),
+        "get_process_logs" => {
+            let p: LogParams = parse_params(params)?;
+            to_result(manager.get_process_logs(&p.id, p.lines, p.strip_ansi, p.max_bytes))
+        }
+        "get_combined_logs" => {
+            let p: LogParams = parse_params(params)?;
+            to_result(manager.get_combined_logs(&p.id, p.lines, p.level))
+        }
+        "get_recent_errors" => {
+            let p: LogParams = parse_params(params)?;
+            to_result(manager.get_recent_errors(&p.id, p.lines))
+        }
+        "search_all_logs" => {
+            let p: LogSearchParams = parse_params(params)?;
+            to_result(manager.search_all_logs(
+                &p.query,
+                p.regex,
+                p.max_matches.unwrap_or(DEFAULT_SEARCH_MATCHES_PER_PROCESS),
+            ))
+        }
+        "export_process_logs" => {
+            let p: ExportLogsParams = parse_params(params)?;
+            to_result(manager.export_process_logs(
+                &p.id,
+                Path::new(&p.request.dest),
+                p.request.redact,
+            ))
+        }
+        "get_process_log_bytes" => {
+            let p: LogBytesParams = parse_params(params)?;
+            to_result(manager.get_process_log_bytes(&p.id, p.max_bytes))
+        }
+        "flush_logs" => {
+            let p: IdParams = parse_params(params)?;
+            to_result(manager.flush_logs(&p.id))
+        }
+        "shutdown_all_processes" => to_result(manager.shutdown_all_processes()),
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Method not found: {method}"),
+        )),
+    }
+}
+
+fn error_object(id: Value, error: RpcError) -> Value {
+    json!({
+        "jsonrpc": JSONRPC_VERSION,
+        "id": id,
+        "error": { "code": error.code, "message": error.message },
+    })
+}
+
+/// Handles a single decoded request object. Returns `None` for notifications
+/// (requests without an id), which must not be answered.
+fn dispatch_one(manager: &Mutex<CoreManager>, request: Value) -> Option<Value> {
+    let request: RpcRequest = match serde_json::from_value(request) {
+        Ok(request) => request,
+        Err(e) => {
+            return Some(error_object(
+                Value::Null,
+                RpcError::new(INVALID_REQUEST, format!("Invalid request: {e}")),
+            ));
+        }
+    };
+
+    let id = request.id.clone();
+    let outcome = if request.jsonrpc != JSONRPC_VERSION {
+        Err(RpcError::new(
+            INVALID_REQUEST,
+            format!("Unsupported jsonrpc version: {}", request.jsonrpc),
+        ))
+    } else {
+        call(manager, &request.method, request.params)
+    };
+
+    let id = id?;
+    Some(match outcome {
+        Ok(result) => json!({ "jsonrpc": JSONRPC_VERSION, "id": id, "result": result }),
+        Err(e) => {
+            error!("JSON-RPC method {} failed: {}", request.method, e.message);
+            error_object(id, e)
+        }
+    })
+}
+
+/// Dispatches one line of input, which may hold a single request or a batch.
+pub fn dispatch(manager: &Mutex<CoreManager>, line: &str) -> Option<Value> {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(e) => {
+            return Some(error_object(
+                Value::Null,
+                RpcError::new(PARSE_ERROR, format!("Parse error: {e}")),
+            ));
+        }
+    };
+
+    match request {
+        Value::Array(batch) if batch.is_empty() => Some(error_object(
+            Value::Null,
+            RpcError::new(INVALID_REQUEST, "Empty batch"),
+        )),
+        Value::Array(batch) => {
+            let responses: Vec<Value> = batch
+                .into_iter()
+                .filter_map(|request| dispatch_one(manager, request))
+                .collect();
+            (!responses.is_empty()).then_some(Value::Array(responses))
+        }
+        request => dispatch_one(manager, request),
+    }
+}
+
+pub fn run_stdio_server() -> Result<()> {
+    use super::core::CORE_MANAGER;
+
+    info!("JSON-RPC server reading requests from stdin");
+
+    let stdin = io::stdin();
+    let mut stdout = io::stdout().lock();
+
+    for line in stdin.lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = dispatch(&CORE_MANAGER, &line);
+
+        if let Some(response) = response {
+            writeln!(stdout, "{response}")?;
+            stdout.flush()?;
+        }
+    }
+
+    info!("stdin closed, stopping JSON-RPC server");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openlistcore::test_support::{self, existing_binary};
+
+    fn request(id: u64, method: &str, params: Value) -> String {
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }).to_string()
+    }
+
+    #[test]
+    fn requests_are_answered_with_results_or_error_codes() {
+        let _serial = test_support::serial();
+        let manager = Mutex::new(CoreManager::new());
+
+        let created = dispatch(
+            &manager,
+            &request(
+                1,
+                "create_process",
+                json!({ "name": "rpc", "bin_path": existing_binary() }),
+            ),
+        )
+        .unwrap();
+        assert_eq!(created["id"], 1);
+        let id = created["result"]["id"].as_str().unwrap().to_string();
+
+        let fetched = dispatch(&manager, &request(2, "get_process", json!({ "id": id })));
+        let fetched = fetched.unwrap();
+        assert_eq!(fetched["result"]["name"], "rpc");
+        assert_eq!(fetched["result"]["state"], "stopped");
+
+        let missing = dispatch(
+            &manager,
+            &request(3, "get_process", json!({ "id": "nope" })),
+        );
+        assert_eq!(missing.unwrap()["error"]["code"], OPERATION_FAILED);
+        let bad_params = dispatch(&manager, &request(4, "get_process", json!({})));
+        assert_eq!(bad_params.unwrap()["error"]["code"], INVALID_PARAMS);
+        let unknown = dispatch(&manager, &request(5, "no_such_method", Value::Null));
+        assert_eq!(unknown.unwrap()["error"]["code"], METHOD_NOT_FOUND);
+        let garbage = dispatch(&manager, "{not json").unwrap();
+        assert_eq!(garbage["error"]["code"], PARSE_ERROR);
+        assert_eq!(garbage["id"], Value::Null);
+    }
+
+    #[test]
+    fn batches_skip_notifications() {
+        let _serial = test_support::serial();
+        let manager = Mutex::new(CoreManager::new());
+
+        let batch = format!(
+            "[{}, {}, {}]",
+            request(1, "get_settings", Value::Null),
+            json!({ "jsonrpc": "2.0", "method": "get_settings" }),
+            json!({ "jsonrpc": "1.0", "id": 3, "method": "get_settings" }),
+        );
+        let responses = dispatch(&manager, &batch).unwrap();
+        let responses = responses.as_array().unwrap();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["id"], 1);
+        assert!(responses[0]["result"].is_object());
+        assert_eq!(responses[1]["error"]["code"], INVALID_REQUEST);
+
+        let notification = json!({ "jsonrpc": "2.0", "method": "get_settings" }).to_string();
+        assert!(dispatch(&manager, &notification).is_none());
+        let empty = dispatch(&manager, "[]").unwrap();
+        assert_eq!(empty["error"]["code"], INVALID_REQUEST);
+    }
+
+    #[test]
+    fn paged_listing_defaults_to_the_default_page_size() {
+        let _serial = test_support::serial();
+        let manager = Mutex::new(CoreManager::new());
+        for i in 0..DEFAULT_PAGE_SIZE + 5 {
+            let request =
+                test_support::create_request(&format!("p{i:03}"), &existing_binary(), json!({}));
+            manager.lock().create_process(request).unwrap();
+        }
+
+        let page = dispatch(&manager, &request(1, "list_processes_paged", json!({})));
+        let page = &page.unwrap()["result"];
+        assert_eq!(page["total"], DEFAULT_PAGE_SIZE + 5);
+        assert_eq!(page["limit"], DEFAULT_PAGE_SIZE);
+        assert_eq!(page["items"].as_array().unwrap().len(), DEFAULT_PAGE_SIZE);
+
+        let rest = dispatch(
+            &manager,
+            &request(
+                2,
+                "list_processes_paged",
+                json!({ "offset": DEFAULT_PAGE_SIZE }),
+            ),
+        );
+        let rest = &rest.unwrap()["result"]["items"];
+        assert_eq!(rest.as_array().unwrap().len(), 5);
+        assert_eq!(rest[0]["name"], format!("p{DEFAULT_PAGE_SIZE:03}"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn idempotent_starts_accept_a_running_process() {
+        use crate::openlistcore::test_support::shell_request;
+
+        let _serial = test_support::serial();
+        let manager = Mutex::new(CoreManager::new());
+        let id = manager
+            .lock()
+            .create_process(shell_request("ensure", "exec sleep 30", json!({})))
+            .unwrap()
+            .id;
+        let start = |manager: &Mutex<CoreManager>, params: Value| {
+            dispatch(manager, &request(1, "start_process", params)).unwrap()
+        };
+
+        // Starts a stopped process like a plain start
+        let first = start(&manager, json!({ "id": id, "idempotent": true }));
+        assert_eq!(first["result"], Value::Null);
+        let pid = manager.lock().get_process(&id).unwrap().pid;
+        assert!(pid.is_some());
+
+        let strict = start(&manager, json!({ "id": id }));
+        assert_eq!(strict["error"]["code"], OPERATION_FAILED);
+        let again = start(&manager, json!({ "id": id, "idempotent": true }));
+        assert!(again.get("error").is_none(), "{again}");
+        assert_eq!(manager.lock().get_process(&id).unwrap().pid, pid);
+
+        let missing = start(&manager, json!({ "id": "nope", "idempotent": true }));
+        assert_eq!(missing["error"]["code"], OPERATION_FAILED);
+        manager.lock().stop_process(&id).unwrap();
+    }
+}
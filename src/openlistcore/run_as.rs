@@ -0,0 +1,272 @@
+use std::{
+    ffi::{CStr, CString},
+    io, mem,
+    os::{raw::c_char, unix::process::CommandExt},
+    process::Command,
+    ptr,
+};
+
+const LOOKUP_BUFFER_SIZE: usize = 16 * 1024;
+const MAX_GROUPS: usize = 65536;
+
+/// The unprivileged identity a child is switched to right before exec.
+#[derive(Debug, Clone)]
+pub struct RunAsIdentity {
+    pub uid: u32,
+    pub gid: u32,
+    pub groups: Vec<u32>,
+    pub description: String,
+}
+
+struct PasswdEntry {
+    name: String,
+    uid: u32,
+    gid: u32,
+}
+
+fn lookup_user(user: &str) -> io::Result<Option<PasswdEntry>> {
+    let mut pwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut buf = vec![0 as c_char; LOOKUP_BUFFER_SIZE];
+    let mut result: *mut libc::passwd = ptr::null_mut();
+
+    let rc = if let Ok(uid) = user.parse::<u32>() {
+        unsafe { libc::getpwuid_r(uid, &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) }
+    } else {
+        let name = CString::new(user)
+            .map_err(|_| io::Error::other(format!("Invalid user name: {user}")))?;
+        unsafe {
+            libc::getpwnam_r(
+                name.as_ptr(),
+                &mut pwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut result,
+            )
+        }
+    };
+    if rc != 0 {
+        return Err(io::Error::from_raw_os_error(rc));
+    }
+    if result.is_null() {
+        return Ok(None);
+    }
+
+    let name = unsafe { CStr::from_ptr(pwd.pw_name) }
+        .to_string_lossy()
+        .into_owned();
+    Ok(Some(PasswdEntry {
+        name,
+        uid: pwd.pw_uid,
+        gid: pwd.pw_gid,
+    }))
+}
+
+fn lookup_group(group: &str) -> io::Result<Option<u32>> {
+    if let Ok(gid) = group.parse::<u32>() {
+        return Ok(Some(gid));
+    }
+
+    let name = CString::new(group)
+        .map_err(|_| io::Error::other(format!("Invalid group name: {group}")))?;
+    let mut grp: libc::group = unsafe { mem::zeroed() };
+    let mut buf = vec![0 as c_char; LOOKUP_BUFFER_SIZE];
+    let mut result: *mut libc::group = ptr::null_mut();
+
+    let rc = unsafe {
+        libc::getgrnam_r(
+            name.as_ptr(),
+            &mut grp,
+            buf.as_mut_ptr(),
+            buf.len(),
+            &mut result,
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::from_raw_os_error(rc));
+    }
+    Ok((!result.is_null()).then_some(grp.gr_gid))
+}
+
+/// Supplementary groups `user` belongs to, including `gid`.
+fn supplementary_groups(user: &str, gid: u32) -> io::Result<Vec<u32>> {
+    let name =
+        CString::new(user).map_err(|_| io::Error::other(format!("Invalid user name: {user}")))?;
+    let mut capacity = 64;
+
+    while capacity <= MAX_GROUPS {
+        let mut count = capacity as libc::c_int;
+        #[cfg(target_os = "linux")]
+        let (rc, groups) = {
+            let mut groups = vec![0 as libc::gid_t; capacity];
+            let rc =
+                unsafe { libc::getgrouplist(name.as_ptr(), gid, groups.as_mut_ptr(), &mut count) };
+            (rc, groups)
+        };
+        // macOS declares the group list as plain ints
+        #[cfg(target_os = "macos")]
+        let (rc, groups) = {
+            let mut groups = vec![0 as libc::c_int; capacity];
+            let rc = unsafe {
+                libc::getgrouplist(
+                    name.as_ptr(),
+                    gid as libc::c_int,
+                    groups.as_mut_ptr(),
+                    &mut count,
+                )
+            };
+            let groups: Vec<u32> = groups.into_iter().map(|g| g as u32).collect();
+            (rc, groups)
+        };
+
+        if rc >= 0 {
+            return Ok(groups[..count as usize].to_vec());
+        }
+        capacity *= 4;
+    }
+
+    Err(io::Error::other(format!(
+        "User {user} belongs to too many groups"
+    )))
+}
+
+/// Resolves `run_as_user`/`run_as_group` to numeric ids. Either may be a name
+/// or a number; a user alone runs with their primary group. Fails if the
+/// user or group does not exist or the service cannot switch to them.
+pub fn resolve(user: Option<&str>, group: Option<&str>) -> io::Result<Option<RunAsIdentity>> {
+    if user.is_none() && group.is_none() {
+        return Ok(None);
+    }
+
+    let entry = match user {
+        Some(user) => Some(
+            lookup_user(user)?
+                .ok_or_else(|| io::Error::other(format!("User does not exist: {user}")))?,
+        ),
+        None => None,
+    };
+    let explicit_gid = match group {
+        Some(group) => Some(
+            lookup_group(group)?
+                .ok_or_else(|| io::Error::other(format!("Group does not exist: {group}")))?,
+        ),
+        None => None,
+    };
+
+    let current_uid = unsafe { libc::geteuid() };
+    let current_gid = unsafe { libc::getegid() };
+    let uid = entry.as_ref().map_or(current_uid, |e| e.uid);
+    let gid = explicit_gid
+        .or(entry.as_ref().map(|e| e.gid))
+        .unwrap_or(current_gid);
+    let groups = match &entry {
+        Some(entry) => supplementary_groups(&entry.name, gid)?,
+        None => vec![gid],
+    };
+
+    if current_uid != 0 {
+        if uid == current_uid && gid == current_gid {
+            // Already running as the requested identity, nothing to drop
+            return Ok(None);
+        }
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "The service runs as uid {current_uid}, switching to uid {uid} / gid {gid} requires root"
+            ),
+        ));
+    }
+
+    let description = match (&entry, group) {
+        (Some(entry), Some(group)) => format!("{}:{group} (uid {uid}, gid {gid})", entry.name),
+        (Some(entry), None) => format!("{} (uid {uid}, gid {gid})", entry.name),
+        (None, Some(group)) => format!(":{group} (gid {gid})"),
+        (None, None) => unreachable!(),
+    };
+
+    Ok(Some(RunAsIdentity {
+        uid,
+        gid,
+        groups,
+        description,
+    }))
+}
+
+/// Makes `command` drop to `identity` in the forked child before exec.
+/// Groups go first, since changing them is no longer allowed after `setuid`.
+pub fn apply(command: &mut Command, identity: &RunAsIdentity) {
+    let RunAsIdentity {
+        uid, gid, groups, ..
+    } = identity.clone();
+    let groups: Vec<libc::gid_t> = groups.into_iter().map(|g| g as libc::gid_t).collect();
+
+    unsafe {
+        command.pre_exec(move || {
+            if libc::setgroups(groups.len() as _, groups.as_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::setgid(gid) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            if libc::setuid(uid) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    /// `id -u`, `id -g` and `id -G` as seen by a child started with `identity`.
+    fn ids_seen_by_child(identity: &RunAsIdentity) -> Vec<String> {
+        let mut command = Command::new("sh");
+        command.args(["-c", "id -u; id -g; id -G"]);
+        apply(&mut command, identity);
+        let output = command.output().unwrap();
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn children_run_with_the_dropped_identity() {
+        if unsafe { libc::geteuid() } != 0 {
+            eprintln!("Skipping: dropping privileges needs root");
+            return;
+        }
+        let Some(nobody) = lookup_user("nobody").unwrap() else {
+            eprintln!("Skipping: no nobody user");
+            return;
+        };
+
+        let identity = resolve(Some("nobody"), None).unwrap().unwrap();
+        assert_eq!((identity.uid, identity.gid), (nobody.uid, nobody.gid));
+        let seen = ids_seen_by_child(&identity);
+        assert_eq!(seen[0], nobody.uid.to_string());
+        assert_eq!(seen[1], nobody.gid.to_string());
+        assert!(
+            !seen[2].split(' ').any(|gid| gid == "0"),
+            "root group kept: {seen:?}"
+        );
+
+        // A group alone keeps the user
+        let identity = resolve(None, Some(&nobody.gid.to_string()))
+            .unwrap()
+            .unwrap();
+        let seen = ids_seen_by_child(&identity);
+        assert_eq!(seen[..2], ["0".to_string(), nobody.gid.to_string()]);
+    }
+
+    #[test]
+    fn unknown_users_and_groups_are_rejected() {
+        let err = resolve(Some("no-such-user-openlist"), None).unwrap_err();
+        assert!(err.to_string().contains("User does not exist"), "{err}");
+        let err = resolve(None, Some("no-such-group-openlist")).unwrap_err();
+        assert!(err.to_string().contains("Group does not exist"), "{err}");
+        assert!(resolve(None, None).unwrap().is_none());
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+
+/// Environment variables and extra arguments for a single run, see
+/// `CoreManager::start_process_with_overrides`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RunOverrides {
+    pub env: HashMap<String, String>,
+    pub args: Vec<String>,
+}
+
+/// Parses overrides the way they are typed on a command line: `KEY=VALUE`
+/// sets an environment variable, anything else, `--flag=value` included, is
+/// appended to the arguments in order. A later `KEY` wins.
+pub fn parse(overrides: &[String]) -> RunOverrides {
+    let mut parsed = RunOverrides::default();
+    for item in overrides {
+        match item.split_once('=') {
+            Some((key, value)) if is_env_name(key) => {
+                parsed.env.insert(key.to_string(), value.to_string());
+            }
+            _ => parsed.args.push(item.clone()),
+        }
+    }
+    parsed
+}
+
+fn is_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assignments_become_env_and_everything_else_args() {
+        let overrides: Vec<String> = [
+            "RUST_LOG=debug",
+            "--verbose",
+            "--port=5245",
+            "EMPTY=",
+            "RUST_LOG=trace",
+            "=oops",
+            "1ST=no",
+            "serve",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+        let parsed = parse(&overrides);
+        assert_eq!(
+            parsed.env,
+            HashMap::from([
+                ("RUST_LOG".to_string(), "trace".to_string()),
+                ("EMPTY".to_string(), String::new()),
+            ])
+        );
+        assert_eq!(
+            parsed.args,
+            ["--verbose", "--port=5245", "=oops", "1ST=no", "serve"]
+        );
+    }
+}
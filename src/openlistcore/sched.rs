@@ -0,0 +1,63 @@
+use super::data::{IoPriority, IoPriorityClass, SchedPolicy};
+use std::{io, os::unix::process::CommandExt, process::Command};
+
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+fn policy_value(policy: SchedPolicy) -> libc::c_int {
+    match policy {
+        SchedPolicy::Other => libc::SCHED_OTHER,
+        SchedPolicy::Batch => libc::SCHED_BATCH,
+        SchedPolicy::Idle => libc::SCHED_IDLE,
+    }
+}
+
+/// `io_priority` encoded the way ioprio_set(2) takes it.
+fn ioprio_value(io_priority: IoPriority) -> libc::c_int {
+    let (class, level) = match io_priority.class {
+        IoPriorityClass::Realtime => (1, io_priority.level),
+        IoPriorityClass::BestEffort => (2, io_priority.level),
+        IoPriorityClass::Idle => (3, 0),
+    };
+    (class << IOPRIO_CLASS_SHIFT) | libc::c_int::from(level)
+}
+
+/// Fails for settings the kernel would refuse the service, up front, since a
+/// failure in the forked child only comes back as a bare errno.
+pub fn check_privileges(io_priority: Option<IoPriority>) -> io::Result<()> {
+    let uid = unsafe { libc::geteuid() };
+    if io_priority.is_some_and(|p| p.class == IoPriorityClass::Realtime) && uid != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("The service runs as uid {uid}, the realtime io_priority class requires root"),
+        ));
+    }
+    Ok(())
+}
+
+/// Makes `command` switch to `policy` and `io_priority` in the forked child
+/// before exec, so the process never runs with the service's own.
+pub fn apply(command: &mut Command, policy: Option<SchedPolicy>, io_priority: Option<IoPriority>) {
+    if policy.is_none() && io_priority.is_none() {
+        return;
+    }
+    let policy = policy.map(policy_value);
+    let ioprio = io_priority.map(ioprio_value);
+
+    unsafe {
+        command.pre_exec(move || {
+            if let Some(policy) = policy {
+                let param = libc::sched_param { sched_priority: 0 };
+                if libc::sched_setscheduler(0, policy, &param) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            if let Some(ioprio) = ioprio
+                && libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
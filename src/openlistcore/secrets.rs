@@ -0,0 +1,106 @@
+use anyhow::{Result, anyhow};
+use std::collections::HashMap;
+
+/// Marks an `env_vars` value as a reference to a keyring entry,
+/// `keyring:<service>/<account>`, rather than the value itself.
+const KEYRING_PREFIX: &str = "keyring:";
+
+/// Where the secrets referenced from `env_vars` are looked up.
+pub trait SecretStore: Send + Sync {
+    fn get(&self, service: &str, account: &str) -> Result<String>;
+}
+
+/// The keychain of the operating system: Keychain on macOS, Credential
+/// Manager on Windows and the kernel keyring on Linux.
+pub struct OsKeyring;
+
+impl SecretStore for OsKeyring {
+    fn get(&self, service: &str, account: &str) -> Result<String> {
+        Ok(keyring::Entry::new(service, account)?.get_password()?)
+    }
+}
+
+/// `vars` with every keyring reference replaced by the secret it points to.
+/// Fails naming the variable if a reference is malformed or its entry cannot
+/// be read.
+pub fn resolve_env_vars(
+    vars: &HashMap<String, String>,
+    store: &dyn SecretStore,
+) -> Result<HashMap<String, String>> {
+    vars.iter()
+        .map(|(name, value)| {
+            let Some(reference) = value.strip_prefix(KEYRING_PREFIX) else {
+                return Ok((name.clone(), value.clone()));
+            };
+            // Service names may contain slashes, account names rarely do
+            let (service, account) = reference
+                .rsplit_once('/')
+                .filter(|(service, account)| !service.is_empty() && !account.is_empty())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Environment variable {name} has an invalid keyring reference \
+                         {value:?}, expected keyring:<service>/<account>"
+                    )
+                })?;
+            let secret = store.get(service, account).map_err(|e| {
+                anyhow!(
+                    "Environment variable {name} references keyring entry {reference} \
+                     that could not be read: {e}"
+                )
+            })?;
+            Ok((name.clone(), secret))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openlistcore::test_support::MockKeyring;
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn keyring_references_resolve_and_plain_values_pass_through() {
+        let store = MockKeyring::with(&[
+            ("openlist", "admin", "s3cret"),
+            ("corp/openlist", "bot", "t0ken"),
+        ]);
+        let resolved = resolve_env_vars(
+            &vars(&[
+                ("PASSWORD", "keyring:openlist/admin"),
+                ("TOKEN", "keyring:corp/openlist/bot"),
+                ("PORT", "5244"),
+            ]),
+            &store,
+        )
+        .unwrap();
+        assert_eq!(
+            resolved,
+            vars(&[("PASSWORD", "s3cret"), ("TOKEN", "t0ken"), ("PORT", "5244")])
+        );
+    }
+
+    #[test]
+    fn unresolvable_references_name_the_variable() {
+        let store = MockKeyring::with(&[]);
+        let missing = resolve_env_vars(&vars(&[("TOKEN", "keyring:openlist/bot")]), &store)
+            .unwrap_err()
+            .to_string();
+        assert!(missing.contains("TOKEN"), "{missing}");
+        assert!(missing.contains("openlist/bot"), "{missing}");
+
+        for malformed in ["keyring:openlist", "keyring:/bot", "keyring:openlist/"] {
+            let err = resolve_env_vars(&vars(&[("TOKEN", malformed)]), &store).unwrap_err();
+            assert!(
+                err.to_string().contains("invalid keyring reference"),
+                "{err}"
+            );
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use super::data::{CheckResult, CheckStatus, ProcessConfig};
+use std::{
+    fs::{self, OpenOptions},
+    path::Path,
+    process::Command,
+    time::{Duration, SystemTime},
+};
+
+// 2024-01-01T00:00:00Z, anything earlier means the system clock was never set
+const MIN_PLAUSIBLE_TIMESTAMP: u64 = 1_704_067_200;
+const MAX_FUTURE_MTIME: Duration = Duration::from_secs(300);
+
+pub fn run_self_check(
+    config_dir: &Path,
+    config_file: &Path,
+    configs: &[ProcessConfig],
+) -> Vec<CheckResult> {
+    let mut results = vec![
+        check_config_dir_writable(config_dir),
+        check_elevation(),
+        check_clock(config_file),
+        check_shell(),
+    ];
+
+    for config in configs {
+        results.push(check_binary(config));
+    }
+
+    results
+}
+
+fn result(name: &str, status: CheckStatus, message: impl Into<String>) -> CheckResult {
+    CheckResult {
+        name: name.to_string(),
+        status,
+        message: message.into(),
+    }
+}
+
+fn check_config_dir_writable(config_dir: &Path) -> CheckResult {
+    const NAME: &str = "config_dir_writable";
+
+    if let Err(e) = fs::create_dir_all(config_dir) {
+        return result(
+            NAME,
+            CheckStatus::Fail,
+            format!("Cannot create config directory {config_dir:?}: {e}"),
+        );
+    }
+
+    let probe_path = config_dir.join(".self_check_probe");
+    let probe = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&probe_path);
+    match probe {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe_path);
+            result(
+                NAME,
+                CheckStatus::Pass,
+                format!("Config directory {config_dir:?} is writable"),
+            )
+        }
+        Err(e) => result(
+            NAME,
+            CheckStatus::Fail,
+            format!("Config directory {config_dir:?} is not writable: {e}"),
+        ),
+    }
+}
+
+fn command_succeeds(cmd: &str, args: &[&str]) -> bool {
+    Command::new(cmd)
+        .args(args)
+        .output()
+        .is_ok_and(|o| o.status.success())
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn check_elevation() -> CheckResult {
+    const NAME: &str = "elevation";
+
+    if unsafe { libc::geteuid() } == 0 {
+        return result(NAME, CheckStatus::Pass, "Service is running as root");
+    }
+    if !command_succeeds("which", &["sudo"]) {
+        return result(
+            NAME,
+            CheckStatus::Fail,
+            "sudo is not available, processes cannot be elevated or force-stopped",
+        );
+    }
+    if command_succeeds("sudo", &["-n", "true"]) {
+        result(NAME, CheckStatus::Pass, "Passwordless sudo is available")
+    } else {
+        result(
+            NAME,
+            CheckStatus::Warn,
+            "sudo requires a password, elevated start/stop may hang or fail",
+        )
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn check_elevation() -> CheckResult {
+    const NAME: &str = "elevation";
+
+    if command_succeeds("powershell", &["-Command", "exit 0"]) {
+        result(
+            NAME,
+            CheckStatus::Pass,
+            "PowerShell is available for elevated start/stop",
+        )
+    } else {
+        result(
+            NAME,
+            CheckStatus::Fail,
+            "PowerShell is not available, processes cannot be elevated or force-stopped",
+        )
+    }
+}
+
+fn check_clock(config_file: &Path) -> CheckResult {
+    const NAME: &str = "clock";
+
+    let now = SystemTime::now();
+    let now_secs = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    if now_secs < MIN_PLAUSIBLE_TIMESTAMP {
+        return result(
+            NAME,
+            CheckStatus::Fail,
+            format!("System clock looks unset (timestamp {now_secs})"),
+        );
+    }
+
+    let modified = fs::metadata(config_file).and_then(|m| m.modified());
+    if let Ok(modified) = modified
+        && let Ok(ahead) = modified.duration_since(now)
+        && ahead > MAX_FUTURE_MTIME
+    {
+        return result(
+            NAME,
+            CheckStatus::Warn,
+            format!(
+                "Config file was modified {}s in the future, the clock may have jumped backwards",
+                ahead.as_secs()
+            ),
+        );
+    }
+
+    result(NAME, CheckStatus::Pass, "System clock looks sane")
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn check_shell() -> CheckResult {
+    const NAME: &str = "shell";
+
+    if Path::new("/bin/sh").exists() {
+        result(NAME, CheckStatus::Pass, "/bin/sh is available")
+    } else {
+        result(
+            NAME,
+            CheckStatus::Warn,
+            "/bin/sh not found, shell commands cannot be run",
+        )
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn check_shell() -> CheckResult {
+    const NAME: &str = "shell";
+
+    let comspec = std::env::var("COMSPEC").unwrap_or_else(|_| "cmd.exe".to_string());
+    if command_succeeds(&comspec, &["/C", "exit 0"]) {
+        result(NAME, CheckStatus::Pass, format!("{comspec} is available"))
+    } else {
+        result(
+            NAME,
+            CheckStatus::Warn,
+            format!("{comspec} not found, shell commands cannot be run"),
+        )
+    }
+}
+
+fn check_binary(config: &ProcessConfig) -> CheckResult {
+    let name = format!("binary:{}", config.id);
+
+    if Path::new(&config.bin_path).exists() {
+        result(
+            &name,
+            CheckStatus::Pass,
+            format!("Binary for {} found at {}", config.name, config.bin_path),
+        )
+    } else if let Some(url) = &config.bin_url {
+        result(
+            &name,
+            CheckStatus::Pass,
+            format!(
+                "Binary for {} will be downloaded from {url} on first start",
+                config.name
+            ),
+        )
+    } else {
+        result(
+            &name,
+            CheckStatus::Warn,
+            format!(
+                "Binary for {} not found at {}",
+                config.name, config.bin_path
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::openlistcore::test_support::scratch_dir;
+
+    #[test]
+    fn writable_config_dir_passes_and_leaves_no_probe() {
+        let dir = scratch_dir("self-check").join("config");
+        let check = check_config_dir_writable(&dir);
+        assert_eq!(check.status, CheckStatus::Pass, "{}", check.message);
+        assert!(!dir.join(".self_check_probe").exists());
+    }
+
+    #[test]
+    fn config_dir_that_cannot_be_created_fails() {
+        let file = scratch_dir("self-check").join("not-a-dir");
+        fs::write(&file, b"").unwrap();
+        let check = check_config_dir_writable(&file.join("config"));
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(check.message.contains("Cannot create"), "{}", check.message);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn read_only_config_dir_fails() {
+        // /proc rejects new files even for root, unlike a chmod-ed directory
+        let check = check_config_dir_writable(Path::new("/proc"));
+        assert_eq!(check.status, CheckStatus::Fail);
+        assert!(check.message.contains("not writable"), "{}", check.message);
+    }
+}
@@ -0,0 +1,151 @@
+use std::{
+    collections::VecDeque,
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{
+    data::{CoreManager, ManagerHandle, ProcessState},
+    monitor::MonitorThread,
+    readiness,
+};
+use anyhow::Result;
+use log::{error, info};
+
+const QUEUE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How a queued process is started, e.g. `CoreManager::auto_start_process`.
+pub type StartFn = fn(&mut CoreManager, &str) -> Result<()>;
+
+/// Starts `ids` in order in the background with `start`, keeping at most
+/// `limit` of them in the starting state at once. A slot frees up when its
+/// process becomes ready, exits, is stopped, or runs out its readiness
+/// timeout.
+pub fn spawn_queued_starts(manager: ManagerHandle, ids: Vec<String>, limit: usize, start: StartFn) {
+    let spawned = thread::Builder::new()
+        .name("start-queue".to_string())
+        .spawn(move || {
+            let _active = MonitorThread::enter();
+            run_queue(manager, ids.into(), limit, start)
+        });
+    if let Err(e) = spawned {
+        error!("Failed to start the start queue: {e}");
+    }
+}
+
+fn run_queue(manager: ManagerHandle, mut pending: VecDeque<String>, limit: usize, start: StartFn) {
+    let mut in_flight: Vec<(String, Instant)> = Vec::new();
+
+    while !pending.is_empty() {
+        {
+            let Some(manager) = manager.upgrade() else {
+                info!("Manager is gone, dropping {} queued starts", pending.len());
+                return;
+            };
+            let mut core_manager = manager.lock();
+
+            in_flight.retain(|(id, deadline)| {
+                Instant::now() < *deadline
+                    && core_manager
+                        .get_process(id)
+                        .is_ok_and(|status| status.state == ProcessState::Starting)
+            });
+
+            while in_flight.len() < limit {
+                let Some(id) = pending.pop_front() else {
+                    break;
+                };
+                match start(&mut core_manager, &id) {
+                    Ok(_) => {
+                        info!("Successfully started queued process {id}");
+                        let timeout = core_manager
+                            .get_process(&id)
+                            .map(|status| readiness::readiness_timeout(&status.config))
+                            .unwrap_or_default();
+                        in_flight.push((id, Instant::now() + timeout));
+                    }
+                    Err(e) => error!("Failed to start queued process {id}: {e}"),
+                }
+            }
+        }
+        thread::sleep(QUEUE_POLL_INTERVAL);
+    }
+
+    info!("All queued starts have been issued");
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::openlistcore::{
+        data::UpdateServiceSettingsRequest,
+        test_support::{self, shell_request, wait_for},
+    };
+    use serde_json::json;
+    use std::net::TcpListener;
+
+    #[test]
+    fn start_all_keeps_at_most_the_limit_starting() {
+        let _serial = test_support::serial();
+        let manager = CoreManager::new_without_autostart();
+        // Nothing listens here, so every process stays starting until its
+        // readiness timeout
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let ids: Vec<String> = {
+            let mut core_manager = manager.lock();
+            let settings: UpdateServiceSettingsRequest =
+                serde_json::from_value(json!({ "max_concurrent_starts": 2 })).unwrap();
+            core_manager.update_settings(settings).unwrap();
+            (0..5)
+                .map(|i| {
+                    let request = shell_request(
+                        &format!("slow-{i}"),
+                        "exec sleep 30",
+                        json!({
+                            "readiness_check": { "type": "tcp", "address": closed.to_string() },
+                            "readiness_timeout_secs": 1,
+                        }),
+                    );
+                    core_manager.create_process(request).unwrap().id
+                })
+                .collect()
+        };
+
+        let result = manager.lock().start_all_processes().unwrap();
+        assert_eq!(result.queued.len(), 5);
+        assert!(result.succeeded.is_empty());
+
+        let mut most_starting = 0;
+        let all_running = wait_for(Duration::from_secs(10), || {
+            let statuses = manager.lock().list_processes().unwrap();
+            let starting = statuses
+                .iter()
+                .filter(|s| s.state == ProcessState::Starting)
+                .count();
+            most_starting = most_starting.max(starting);
+            statuses
+                .iter()
+                .all(|s| s.is_running && s.state == ProcessState::Running)
+        });
+        assert!(all_running);
+        assert_eq!(most_starting, 2);
+
+        let mut core_manager = manager.lock();
+        for id in &ids {
+            core_manager.stop_process(id).unwrap();
+        }
+        // Below the limit everything is started right away
+        let settings: UpdateServiceSettingsRequest =
+            serde_json::from_value(json!({ "max_concurrent_starts": 5 })).unwrap();
+        core_manager.update_settings(settings).unwrap();
+        let result = core_manager.start_all_processes().unwrap();
+        assert_eq!(result.succeeded.len(), 5);
+        assert!(result.queued.is_empty());
+        for id in &ids {
+            core_manager.stop_process(id).unwrap();
+        }
+    }
+}
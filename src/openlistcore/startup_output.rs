@@ -0,0 +1,254 @@
+use super::{
+    data::{ProcessRuntime, StartupOutput},
+    logs::LineSplitter,
+    monitor::MonitorThread,
+    process::is_process_running,
+    readiness::is_current_run,
+};
+use log::error;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+/// How many lines are kept from the start of each run.
+pub const STARTUP_OUTPUT_LINES: usize = 50;
+const MAX_LINE_BYTES: usize = 4096;
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+/// Runs that stay quiet for this long are not waited on any further.
+const CAPTURE_WINDOW: Duration = Duration::from_secs(60);
+const READ_CHUNK_BYTES: usize = 64 * 1024;
+
+static SHARED: Lazy<Mutex<SharedCaptures>> = Lazy::new(|| {
+    Mutex::new(SharedCaptures {
+        captures: Vec::new(),
+        polling: false,
+    })
+});
+
+/// Collects the first `STARTUP_OUTPUT_LINES` lines logged for the run
+/// identified by `generation` into `runtime.startup_output`, starting with the
+/// service's line naming the command. `logs` are the run's log files with the
+/// offsets it starts writing at. Whatever the run wrote before it exited is
+/// still picked up afterwards. With `shared`, one thread polls the captures
+/// of all runs instead of a thread per run.
+pub fn spawn_capture(
+    runtime: ProcessRuntime,
+    generation: u64,
+    logs: Vec<(String, u64)>,
+    shared: bool,
+) {
+    *runtime.startup_output.lock() = StartupOutput {
+        generation,
+        lines: Vec::new(),
+    };
+    let mut capture = Capture::new(runtime, generation, logs);
+
+    if shared {
+        let mut shared = SHARED.lock();
+        shared.captures.push(capture);
+        if shared.polling {
+            return;
+        }
+        match std::thread::Builder::new()
+            .name("startup-output".to_string())
+            .spawn(poll_shared)
+        {
+            Ok(_) => shared.polling = true,
+            Err(e) => {
+                error!("Failed to start capturing startup output: {e}");
+                shared.captures.clear();
+            }
+        }
+        return;
+    }
+
+    let spawned = std::thread::Builder::new()
+        .name(format!("startup-output-{generation}"))
+        .spawn(move || {
+            let _active = MonitorThread::enter();
+            while !capture.step() {
+                std::thread::sleep(POLL_INTERVAL);
+            }
+        });
+    if let Err(e) = spawned {
+        error!("Failed to start capturing startup output: {e}");
+    }
+}
+
+struct SharedCaptures {
+    captures: Vec<Capture>,
+    /// Whether the thread polling `captures` runs; it ends once they are done.
+    polling: bool,
+}
+
+fn poll_shared() {
+    let _active = MonitorThread::enter();
+    loop {
+        {
+            let mut shared = SHARED.lock();
+            shared.captures.retain_mut(|capture| !capture.step());
+            if shared.captures.is_empty() {
+                shared.polling = false;
+                return;
+            }
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+struct Capture {
+    runtime: ProcessRuntime,
+    generation: u64,
+    tails: Vec<LogTail>,
+    deadline: Instant,
+}
+
+impl Capture {
+    fn new(runtime: ProcessRuntime, generation: u64, logs: Vec<(String, u64)>) -> Self {
+        let tails = logs
+            .into_iter()
+            .map(|(path, offset)| LogTail {
+                path,
+                offset,
+                splitter: LineSplitter::new(MAX_LINE_BYTES),
+            })
+            .collect();
+        Self {
+            runtime,
+            generation,
+            tails,
+            deadline: Instant::now() + CAPTURE_WINDOW,
+        }
+    }
+
+    /// Picks up what the run logged since the last call. Returns whether the
+    /// capture is done.
+    fn step(&mut self) -> bool {
+        // Checked before reading, so nothing written before the exit is missed
+        let ended = !is_current_run(&self.runtime, self.generation)
+            || !is_process_running(self.runtime.running_pid.load(Ordering::Relaxed))
+            || Instant::now() >= self.deadline;
+
+        let mut lines = Vec::new();
+        for tail in &mut self.tails {
+            lines.extend(tail.read());
+            if ended {
+                lines.extend(tail.splitter.finish());
+            }
+        }
+
+        let mut output = self.runtime.startup_output.lock();
+        if output.generation != self.generation {
+            return true;
+        }
+        let room = STARTUP_OUTPUT_LINES.saturating_sub(output.lines.len());
+        output.lines.extend(lines.into_iter().take(room));
+        ended || output.lines.len() >= STARTUP_OUTPUT_LINES
+    }
+}
+
+struct LogTail {
+    path: String,
+    offset: u64,
+    splitter: LineSplitter,
+}
+
+impl LogTail {
+    /// The complete lines logged since the last call. A rotated or truncated
+    /// file is left alone, its start is no longer this run's.
+    fn read(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let Ok(mut file) = File::open(&self.path) else {
+            return lines;
+        };
+        if file.metadata().map_or(true, |m| m.len() < self.offset)
+            || file.seek(SeekFrom::Start(self.offset)).is_err()
+        {
+            return lines;
+        }
+
+        let mut buf = vec![0u8; READ_CHUNK_BYTES];
+        while let Ok(n) = file.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            self.offset += n as u64;
+            lines.extend(self.splitter.push(&buf[..n]));
+            if lines.len() >= STARTUP_OUTPUT_LINES {
+                break;
+            }
+        }
+        lines
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::STARTUP_OUTPUT_LINES;
+    use crate::openlistcore::{
+        data::CoreManager,
+        test_support::{self, shell_request, wait_for},
+    };
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn the_start_of_a_run_is_kept_after_it_exits() {
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let id = manager
+            .create_process(shell_request(
+                "instant-failure",
+                "echo 'openlist v1.2.3'; echo 'loading config' >&2; printf 'fatal: no config'; exit 1",
+                json!({}),
+            ))
+            .unwrap()
+            .id;
+        assert!(manager.get_process_startup_output(&id).unwrap().is_empty());
+
+        // After the service's own line naming the command
+        let expected = ["openlist v1.2.3", "loading config", "fatal: no config"];
+        for _ in 0..2 {
+            manager.start_process(&id).unwrap();
+            assert!(wait_for(Duration::from_secs(5), || {
+                manager.get_process_startup_output(&id).unwrap().len() == 4
+            }));
+            let output = manager.get_process_startup_output(&id).unwrap();
+            assert!(
+                output[0].starts_with("Spawning process: /bin/sh"),
+                "{output:?}"
+            );
+            assert_eq!(output[1..], expected);
+        }
+        assert!(manager.get_process_startup_output("missing").is_err());
+    }
+
+    #[test]
+    fn only_the_first_lines_are_kept() {
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let script = "i=0; while [ $i -lt 200 ]; do echo line $i; i=$((i+1)); done; exec sleep 30";
+        let id = manager
+            .create_process(shell_request("chatty", script, json!({})))
+            .unwrap()
+            .id;
+
+        manager.start_process(&id).unwrap();
+        assert!(wait_for(Duration::from_secs(5), || {
+            manager.get_process_startup_output(&id).unwrap().len() == STARTUP_OUTPUT_LINES
+        }));
+        let expected: Vec<String> = (0..STARTUP_OUTPUT_LINES - 1)
+            .map(|i| format!("line {i}"))
+            .collect();
+        assert_eq!(
+            manager.get_process_startup_output(&id).unwrap()[1..],
+            expected
+        );
+        manager.stop_process(&id).unwrap();
+    }
+}
@@ -0,0 +1,189 @@
+use super::{
+    data::{ProcessConfig, ProcessRuntime, ProcessState, StartupProbe, StopCause},
+    health::sleep_while_current,
+    hooks, process,
+};
+use log::{error, info, warn};
+use std::{fs::File, path::Path, sync::atomic::Ordering, time::Duration};
+
+/// How long a single attempt of the probe command may take.
+const PROBE_COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub enum ProbeOutcome {
+    Passed,
+    /// Every attempt failed, for this reason.
+    Failed(String),
+    /// The run exited or was replaced while being probed.
+    Abandoned,
+}
+
+/// Longest a probe can keep a run starting: every attempt timing out, with
+/// the delays in between.
+pub fn budget(config: &ProcessConfig) -> Duration {
+    let Some(probe) = &config.startup_probe else {
+        return Duration::ZERO;
+    };
+    (PROBE_COMMAND_TIMEOUT + Duration::from_secs(probe.delay_secs)) * (probe.retries + 1)
+}
+
+/// Runs `probe` for the run identified by `generation` until it succeeds,
+/// waiting `delay_secs` between attempts and giving up after `retries`
+/// failed retries. Output goes to the process log.
+pub fn run(
+    config: &ProcessConfig,
+    probe: &StartupProbe,
+    runtime: &ProcessRuntime,
+    generation: u64,
+) -> ProbeOutcome {
+    let working_dir = config.working_dir.as_deref().map(Path::new);
+    let attempts = probe.retries + 1;
+    for attempt in 1..=attempts {
+        if attempt > 1
+            && !sleep_while_current(runtime, generation, Duration::from_secs(probe.delay_secs))
+        {
+            return ProbeOutcome::Abandoned;
+        }
+        let log = File::options()
+            .create(true)
+            .append(true)
+            .open(&config.log_file)
+            .ok();
+        match hooks::run_shell_command(&probe.command, PROBE_COMMAND_TIMEOUT, working_dir, log) {
+            Ok(outcome) if outcome.success() => {
+                info!(
+                    "Startup probe for {} ({}) passed on attempt {attempt}",
+                    config.name, config.id
+                );
+                return ProbeOutcome::Passed;
+            }
+            Ok(outcome) => warn!(
+                "Startup probe for {} ({}) failed ({attempt}/{attempts}): {outcome:?}",
+                config.name, config.id
+            ),
+            Err(e) => error!("Failed to run startup probe for {}: {e}", config.name),
+        }
+    }
+    ProbeOutcome::Failed(format!("Startup probe failed {attempts} times"))
+}
+
+/// Stops the run identified by `generation` after its probe failed and marks
+/// it failed with `message`. Unlike a crash this is never auto-restarted.
+pub fn fail_start(
+    config: &ProcessConfig,
+    runtime: &ProcessRuntime,
+    generation: u64,
+    message: &str,
+) {
+    // Claim the run, so that neither a stop nor the exit monitor handles it too
+    if runtime
+        .start_generation
+        .compare_exchange(
+            generation,
+            generation + 1,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        )
+        .is_err()
+    {
+        return;
+    }
+    error!(
+        "Process {} ({}): {message}, stopping it",
+        config.name, config.id
+    );
+    *runtime.last_error.lock() = Some(message.to_string());
+    *runtime.stop_cause.lock() = Some(StopCause::StartFailed(message.to_string()));
+    *runtime.state.lock() = ProcessState::Failed;
+    runtime.is_running.store(false, Ordering::Relaxed);
+    *runtime.started_at.lock() = None;
+    let pid = runtime.running_pid.swap(-1, Ordering::Relaxed);
+    if pid > 0 {
+        if let Err(e) = process::kill_process(pid as u32, config.kill_sequence.as_deref()) {
+            error!("Failed to stop process {}: {e}", config.name);
+        }
+        process::try_reap(pid);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::openlistcore::{
+        data::{CoreManager, ProcessState},
+        process::is_process_running,
+        test_support::{self, scratch_dir, shell_request, wait_for},
+    };
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn probe_passing_on_the_third_attempt_lets_the_process_run() {
+        let _serial = test_support::serial();
+        let attempts = scratch_dir("probe-third").join("attempts");
+        let mut manager = CoreManager::new();
+        let request = shell_request(
+            "probe-third",
+            "exec sleep 30",
+            json!({
+                "startup_probe": {
+                    "command": format!(
+                        "echo attempt >> {0}; [ $(wc -l < {0}) -ge 3 ]",
+                        attempts.display()
+                    ),
+                    "retries": 3,
+                    "delay_secs": 0,
+                },
+            }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+
+        manager.start_process(&id).unwrap();
+        assert!(wait_for(Duration::from_secs(10), || {
+            manager.get_process(&id).unwrap().state != ProcessState::Starting
+        }));
+        let status = manager.get_process(&id).unwrap();
+        assert_eq!(status.state, ProcessState::Running);
+        assert!(status.is_running);
+        assert_eq!(status.last_error, None);
+        let count = std::fs::read_to_string(&attempts).unwrap().lines().count();
+        assert_eq!(count, 3);
+        manager.stop_process(&id).unwrap();
+    }
+
+    #[test]
+    fn probe_that_never_passes_fails_the_start() {
+        let _serial = test_support::serial();
+        let mut manager = CoreManager::new();
+        let request = shell_request(
+            "probe-never",
+            "exec sleep 30",
+            json!({
+                "auto_restart": true,
+                "startup_probe": { "command": "exit 1", "retries": 2, "delay_secs": 0 },
+            }),
+        );
+        let id = manager.create_process(request).unwrap().id;
+
+        manager.start_process(&id).unwrap();
+        let pid = manager.get_process(&id).unwrap().pid.unwrap();
+        assert!(wait_for(Duration::from_secs(10), || {
+            manager.get_process(&id).unwrap().state != ProcessState::Starting
+        }));
+        let status = manager.get_process(&id).unwrap();
+        assert_eq!(status.state, ProcessState::Failed);
+        assert!(!status.is_running);
+        assert_eq!(
+            status.last_error.as_deref(),
+            Some("Startup probe failed 3 times")
+        );
+        let exited = || !is_process_running(pid as i32);
+        assert!(wait_for(Duration::from_secs(5), exited));
+
+        // A failed start is not a crash to auto-restart
+        manager.supervise_processes();
+        let status = manager.get_process(&id).unwrap();
+        assert_eq!(
+            (status.state, status.restart_count),
+            (ProcessState::Failed, 0)
+        );
+    }
+}
@@ -0,0 +1,28 @@
+use super::data::ProcessStats;
+use anyhow::{Context, Result};
+use std::{collections::BTreeMap, path::Path};
+
+pub fn load(path: &Path) -> Result<BTreeMap<String, ProcessStats>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to open stats file: {path:?}"))?;
+    serde_json::from_slice(&bytes).with_context(|| format!("Failed to parse stats file: {path:?}"))
+}
+
+/// Writes to a temporary file first, so that a crash halfway through leaves
+/// the previous stats intact rather than a truncated file.
+pub fn save(path: &Path, stats: &BTreeMap<String, ProcessStats>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create config directory: {parent:?}"))?;
+    }
+    let bytes = serde_json::to_vec_pretty(stats)
+        .with_context(|| format!("Failed to serialize stats file: {path:?}"))?;
+    let tmp_path = path.with_extension("json.tmp");
+    std::fs::write(&tmp_path, bytes)
+        .with_context(|| format!("Failed to write stats file: {tmp_path:?}"))?;
+    std::fs::rename(&tmp_path, path)
+        .with_context(|| format!("Failed to replace stats file: {path:?}"))
+}
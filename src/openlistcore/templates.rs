@@ -0,0 +1,46 @@
+use super::data::{CreateProcessRequest, ProcessTemplate, UpdateProcessRequest};
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, path::Path};
+
+pub fn load(path: &Path) -> Result<BTreeMap<String, ProcessTemplate>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to open templates file: {path:?}"))?;
+    serde_json::from_slice(&bytes)
+        .with_context(|| format!("Failed to parse templates file: {path:?}"))
+}
+
+pub fn save(path: &Path, templates: &BTreeMap<String, ProcessTemplate>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create config directory: {parent:?}"))?;
+    }
+    let bytes = serde_json::to_vec_pretty(templates)
+        .with_context(|| format!("Failed to serialize templates file: {path:?}"))?;
+    std::fs::write(path, bytes).with_context(|| format!("Failed to write templates file: {path:?}"))
+}
+
+/// The fields `partial` actually sets, so unset ones neither clutter the
+/// stored template nor overwrite the template when used as overrides.
+pub fn set_fields(partial: UpdateProcessRequest) -> Result<ProcessTemplate> {
+    match serde_json::to_value(partial)? {
+        Value::Object(fields) => Ok(fields.into_iter().filter(|(_, v)| !v.is_null()).collect()),
+        _ => Err(anyhow!("A process template must be an object")),
+    }
+}
+
+/// The create request for `template` with `overrides` applied on top.
+/// Together they have to provide at least `name`, and `bin_path` unless the
+/// settings have a `default_bin_path`.
+pub fn instantiate(
+    template: &ProcessTemplate,
+    overrides: UpdateProcessRequest,
+) -> Result<CreateProcessRequest> {
+    let mut fields: Map<String, Value> = template.clone();
+    fields.extend(set_fields(overrides)?);
+    serde_json::from_value(Value::Object(fields))
+        .map_err(|e| anyhow!("Template and overrides do not describe a complete process: {e}"))
+}
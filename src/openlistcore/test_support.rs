@@ -0,0 +1,131 @@
+//! Helpers shared by the unit tests. The config directory is shared by the
+//! whole test binary, so every test that touches it or `CORE_MANAGER` holds
+//! `serial()` for its duration.
+
+use super::{
+    core::CORE_MANAGER,
+    data::{CoreManager, CreateProcessRequest},
+    secrets::SecretStore,
+};
+use anyhow::{Result, anyhow};
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, MutexGuard};
+use serde_json::{Value, json};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+static TEST_ROOT: Lazy<PathBuf> = Lazy::new(|| {
+    let root = std::env::temp_dir().join(format!("openlist-service-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&root).expect("Failed to create test directory");
+    root
+});
+static SERIAL: Mutex<()> = Mutex::new(());
+static NEXT_SCRATCH: AtomicUsize = AtomicUsize::new(0);
+
+/// Stands in for the real config directory while testing.
+pub fn config_dir() -> PathBuf {
+    TEST_ROOT.join("config")
+}
+
+/// Serialises the tests sharing the config directory and `CORE_MANAGER`, and
+/// hands each of them an empty config directory and a fresh global manager.
+pub fn serial() -> MutexGuard<'static, ()> {
+    let guard = SERIAL.lock();
+    let _ = std::fs::remove_dir_all(config_dir());
+    std::fs::create_dir_all(config_dir()).expect("Failed to create test config directory");
+    let mut manager = CORE_MANAGER.lock();
+    let handle = std::mem::take(&mut manager.handle);
+    *manager = CoreManager::new();
+    manager.handle = handle;
+    drop(manager);
+    guard
+}
+
+/// A new, empty directory for one test's own files.
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let n = NEXT_SCRATCH.fetch_add(1, Ordering::Relaxed);
+    let dir = TEST_ROOT.join("scratch").join(format!("{name}-{n}"));
+    std::fs::create_dir_all(&dir).expect("Failed to create scratch directory");
+    dir
+}
+
+/// A binary that is guaranteed to exist, for processes that are never started.
+pub fn existing_binary() -> String {
+    std::env::current_exe()
+        .expect("Failed to locate the test binary")
+        .to_string_lossy()
+        .to_string()
+}
+
+/// A create request for `bin_path` with the fields in `extra` set on top.
+pub fn create_request(name: &str, bin_path: &str, extra: Value) -> CreateProcessRequest {
+    let mut request = json!({ "name": name, "bin_path": bin_path });
+    if let (Some(request), Value::Object(extra)) = (request.as_object_mut(), extra) {
+        request.extend(extra);
+    }
+    serde_json::from_value(request).expect("Invalid create request")
+}
+
+/// A create request for a process running `script` with `/bin/sh -c`. Going
+/// through the shell avoids writing executables while other tests fork.
+#[cfg(unix)]
+pub fn shell_request(name: &str, script: &str, extra: Value) -> CreateProcessRequest {
+    let mut request = create_request(name, "/bin/sh", extra);
+    request.args = Some(vec!["-c".to_string(), script.to_string()]);
+    if request.log_file.is_none() {
+        request.log_file = Some(
+            scratch_dir(name)
+                .join("process.log")
+                .to_string_lossy()
+                .to_string(),
+        );
+    }
+    request
+}
+
+/// Polls `condition` until it holds or `timeout` passes.
+pub fn wait_for(timeout: Duration, mut condition: impl FnMut() -> bool) -> bool {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if condition() {
+            return true;
+        }
+        if Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    }
+}
+
+/// A keyring holding just the given `(service, account, secret)` entries.
+pub struct MockKeyring(HashMap<(String, String), String>);
+
+impl MockKeyring {
+    pub fn with(entries: &[(&str, &str, &str)]) -> Self {
+        Self(
+            entries
+                .iter()
+                .map(|(service, account, secret)| {
+                    (
+                        (service.to_string(), account.to_string()),
+                        secret.to_string(),
+                    )
+                })
+                .collect(),
+        )
+    }
+}
+
+impl SecretStore for MockKeyring {
+    fn get(&self, service: &str, account: &str) -> Result<String> {
+        self.0
+            .get(&(service.to_string(), account.to_string()))
+            .cloned()
+            .ok_or_else(|| anyhow!("No matching entry found in secure storage"))
+    }
+}
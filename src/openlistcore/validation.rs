@@ -0,0 +1,421 @@
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use super::run_as;
+use super::{
+    data::{IoPriority, KillStep, MAX_IO_PRIORITY_LEVEL, ProcessConfig, ValidationIssue},
+    dependencies, download, listen_probe, logs, process, readiness,
+};
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Upper bound on the waits of a whole kill sequence. Stopping blocks every
+/// other request to the service, so a sequence may not hold it for long.
+pub const MAX_KILL_SEQUENCE_WAIT_MS: u64 = 30_000;
+
+pub fn validate_kill_sequence(steps: &[KillStep]) -> Result<()> {
+    for step in steps {
+        if process::normalize_signal(&step.signal).is_none() {
+            return Err(anyhow!("Unknown signal in kill sequence: {}", step.signal));
+        }
+    }
+    let total_wait_ms = steps
+        .iter()
+        .fold(0u64, |total, step| total.saturating_add(step.wait_ms));
+    if total_wait_ms > MAX_KILL_SEQUENCE_WAIT_MS {
+        return Err(anyhow!(
+            "Kill sequence waits {total_wait_ms}ms in total, at most {MAX_KILL_SEQUENCE_WAIT_MS}ms is allowed"
+        ));
+    }
+    Ok(())
+}
+
+/// `run_as_admin` elevates through sudo, `run_as_user`/`run_as_group` drop
+/// privileges, so a process can only ask for one of them.
+pub fn validate_run_as(config: &ProcessConfig) -> Result<()> {
+    if config.run_as_admin && (config.run_as_user.is_some() || config.run_as_group.is_some()) {
+        return Err(anyhow!(
+            "run_as_admin cannot be combined with run_as_user or run_as_group"
+        ));
+    }
+    Ok(())
+}
+
+pub fn validate_watch_paths(paths: &[String]) -> Result<()> {
+    for path in paths {
+        if !Path::new(path).is_absolute() {
+            return Err(anyhow!("restart_on_change paths must be absolute: {path}"));
+        }
+    }
+    Ok(())
+}
+
+pub fn validate_log_patterns(patterns: &[String]) -> Result<()> {
+    for pattern in patterns {
+        Regex::new(pattern)
+            .map_err(|e| anyhow!("Invalid restart_on_log_pattern {pattern:?}: {e}"))?;
+    }
+    Ok(())
+}
+
+pub fn validate_launch_wrapper(wrapper: &[String]) -> Result<()> {
+    if wrapper
+        .first()
+        .is_some_and(|program| program.trim().is_empty())
+    {
+        return Err(anyhow!("launch_wrapper must start with a program"));
+    }
+    Ok(())
+}
+
+pub fn validate_io_priority(io_priority: &IoPriority) -> Result<()> {
+    if io_priority.level > MAX_IO_PRIORITY_LEVEL {
+        return Err(anyhow!(
+            "io_priority level must be between 0 and {MAX_IO_PRIORITY_LEVEL}, got {}",
+            io_priority.level
+        ));
+    }
+    Ok(())
+}
+
+fn issue(process_id: Option<&str>, field: &str, message: impl Into<String>) -> ValidationIssue {
+    ValidationIssue {
+        process_id: process_id.map(str::to_string),
+        field: field.to_string(),
+        message: message.into(),
+    }
+}
+
+/// Validates each configuration on its own, then looks for conflicts between
+/// them. Nothing is started or modified.
+pub fn validate_configs(configs: &[ProcessConfig]) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+
+    let ids: HashSet<&str> = configs.iter().map(|config| config.id.as_str()).collect();
+    for config in configs {
+        validate_config(config, &mut issues);
+        for dependency in &config.depends_on {
+            if !ids.contains(dependency.as_str()) {
+                issues.push(issue(
+                    Some(&config.id),
+                    "depends_on",
+                    format!("Depends on unknown process {dependency}"),
+                ));
+            }
+        }
+    }
+
+    let mut by_name: HashMap<String, Vec<&str>> = HashMap::new();
+    for config in configs {
+        by_name
+            .entry(config.name.trim().to_lowercase())
+            .or_default()
+            .push(&config.id);
+    }
+    let mut by_port: HashMap<u16, Vec<&str>> = HashMap::new();
+    for config in configs {
+        if let Some(port) = config.readiness_check.as_ref().and_then(readiness::port) {
+            by_port.entry(port).or_default().push(&config.id);
+        }
+    }
+
+    let mut duplicate_names: Vec<_> = by_name
+        .into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .collect();
+    duplicate_names.sort();
+    for (name, ids) in duplicate_names {
+        issues.push(issue(
+            None,
+            "name",
+            format!("Processes {} share the name \"{name}\"", ids.join(", ")),
+        ));
+    }
+
+    let mut port_conflicts: Vec<_> = by_port
+        .into_iter()
+        .filter(|(_, ids)| ids.len() > 1)
+        .collect();
+    port_conflicts.sort();
+    for (port, ids) in port_conflicts {
+        issues.push(issue(
+            None,
+            "readiness_check",
+            format!(
+                "Processes {} all expect to listen on port {port}",
+                ids.join(", ")
+            ),
+        ));
+    }
+
+    let all: Vec<&ProcessConfig> = configs.iter().collect();
+    if let Err(cycle) = dependencies::start_order(&all) {
+        issues.push(issue(None, "depends_on", cycle));
+    }
+
+    issues
+}
+
+fn validate_config(config: &ProcessConfig, issues: &mut Vec<ValidationIssue>) {
+    let id = Some(config.id.as_str());
+
+    if config.name.trim().is_empty() {
+        issues.push(issue(id, "name", "Name is empty"));
+    }
+
+    let bin_path = Path::new(&config.bin_path);
+    if let Some(url) = &config.bin_url
+        && let Err(e) = download::validate_source(url, config.bin_sha256.as_deref())
+    {
+        issues.push(issue(id, "bin_url", e.to_string()));
+    }
+    if !bin_path.exists() {
+        if config.bin_url.is_none() {
+            issues.push(issue(
+                id,
+                "bin_path",
+                format!("Binary not found at: {}", config.bin_path),
+            ));
+        }
+    } else if !bin_path.is_file() {
+        issues.push(issue(
+            id,
+            "bin_path",
+            format!("Binary path is not a file: {}", config.bin_path),
+        ));
+    } else if !is_executable(bin_path) {
+        issues.push(issue(
+            id,
+            "bin_path",
+            format!("Binary is not executable: {}", config.bin_path),
+        ));
+    }
+
+    if let Some(working_dir) = &config.working_dir
+        && !Path::new(working_dir).is_dir()
+    {
+        issues.push(issue(
+            id,
+            "working_dir",
+            format!("Working directory does not exist: {working_dir}"),
+        ));
+    }
+
+    let log_file = logs::resolve_log_path(
+        &config.log_file,
+        &config.name,
+        &config.id,
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+    );
+    if let Some(parent) = Path::new(&log_file).parent()
+        && !parent.as_os_str().is_empty()
+        && !parent.is_dir()
+    {
+        issues.push(issue(
+            id,
+            "log_file",
+            format!("Log directory does not exist: {}", parent.display()),
+        ));
+    }
+
+    if let Some(steps) = &config.kill_sequence
+        && let Err(e) = validate_kill_sequence(steps)
+    {
+        issues.push(issue(id, "kill_sequence", e.to_string()));
+    }
+
+    if let Some(check) = &config.readiness_check
+        && let Err(e) = readiness::validate(check)
+    {
+        issues.push(issue(id, "readiness_check", e.to_string()));
+    }
+
+    if let Some(probe) = &config.listen_address_probe
+        && let Err(e) = listen_probe::validate(probe)
+    {
+        issues.push(issue(id, "listen_address_probe", e.to_string()));
+    }
+
+    if let Err(e) = validate_watch_paths(&config.restart_on_change) {
+        issues.push(issue(id, "restart_on_change", e.to_string()));
+    }
+    for path in &config.restart_on_change {
+        if !Path::new(path).exists() {
+            issues.push(issue(
+                id,
+                "restart_on_change",
+                format!("Watched path does not exist: {path}"),
+            ));
+        }
+    }
+
+    if let Err(e) = validate_log_patterns(&config.restart_on_log_pattern) {
+        issues.push(issue(id, "restart_on_log_pattern", e.to_string()));
+    }
+
+    if let Err(e) = validate_launch_wrapper(&config.launch_wrapper) {
+        issues.push(issue(id, "launch_wrapper", e.to_string()));
+    }
+
+    if let Some(io_priority) = &config.io_priority
+        && let Err(e) = validate_io_priority(io_priority)
+    {
+        issues.push(issue(id, "io_priority", e.to_string()));
+    }
+
+    if let Err(e) = validate_run_as(config) {
+        issues.push(issue(id, "run_as_user", e.to_string()));
+    }
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    if let Err(e) = run_as::resolve(
+        config.run_as_user.as_deref(),
+        config.run_as_group.as_deref(),
+    ) {
+        issues.push(issue(id, "run_as_user", e.to_string()));
+    }
+    #[cfg(target_os = "windows")]
+    if config.run_as_user.is_some() || config.run_as_group.is_some() {
+        issues.push(issue(
+            id,
+            "run_as_user",
+            "run_as_user and run_as_group are only supported on Linux and macOS",
+        ));
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|metadata| metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(target_os = "windows")]
+fn is_executable(_path: &Path) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(signal: &str, wait_ms: u64) -> KillStep {
+        KillStep {
+            signal: signal.to_string(),
+            wait_ms,
+        }
+    }
+
+    #[test]
+    fn kill_sequence_waits_are_capped_in_total() {
+        assert!(validate_kill_sequence(&[step("TERM", 20_000), step("KILL", 10_000)]).is_ok());
+
+        let err = validate_kill_sequence(&[step("TERM", 20_000), step("KILL", 10_001)])
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("30001ms"), "{err}");
+        assert!(validate_kill_sequence(&[step("TERM", u64::MAX), step("KILL", 1)]).is_err());
+    }
+
+    fn config(id: &str, name: &str, extra: serde_json::Value) -> ProcessConfig {
+        let bin_path = crate::openlistcore::test_support::existing_binary();
+        let mut config = serde_json::json!({
+            "id": id,
+            "name": name,
+            "bin_path": bin_path,
+            "args": [],
+            "log_file": "process.log",
+            "working_dir": null,
+            "env_vars": null,
+            "auto_restart": false,
+            "auto_start": false,
+            "run_as_admin": false,
+            "created_at": 0,
+            "updated_at": 0,
+        });
+        if let (Some(config), serde_json::Value::Object(extra)) = (config.as_object_mut(), extra) {
+            config.extend(extra);
+        }
+        serde_json::from_value(config).unwrap()
+    }
+
+    #[test]
+    fn every_problem_is_reported_with_its_process_and_field() {
+        let configs = [
+            config("ok", "fine", serde_json::json!({})),
+            config(
+                "a",
+                "web",
+                serde_json::json!({
+                    "bin_path": "/definitely/missing/openlist",
+                    "working_dir": "/definitely/missing/dir",
+                    "kill_sequence": [{ "signal": "NOPE", "wait_ms": 10 }],
+                    "readiness_check": { "type": "tcp", "address": "127.0.0.1:5244" },
+                    "depends_on": ["ok", "missing"],
+                }),
+            ),
+            config(
+                "b",
+                " Web ",
+                serde_json::json!({
+                    "readiness_check": { "type": "http", "url": "http://127.0.0.1:5244/ping" },
+                    "log_file": "/definitely/missing/dir/{name}.log",
+                    "depends_on": ["c"],
+                }),
+            ),
+            config(
+                "c",
+                "",
+                serde_json::json!({
+                    "readiness_check": { "type": "http", "url": "https://example.com" },
+                    "depends_on": ["b"],
+                }),
+            ),
+        ];
+
+        let issues = validate_configs(&configs);
+        let found: Vec<(Option<&str>, &str)> = issues
+            .iter()
+            .map(|i| (i.process_id.as_deref(), i.field.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (Some("a"), "bin_path"),
+                (Some("a"), "working_dir"),
+                (Some("a"), "kill_sequence"),
+                (Some("a"), "depends_on"),
+                (Some("b"), "log_file"),
+                (Some("c"), "name"),
+                (Some("c"), "readiness_check"),
+                (None, "name"),
+                (None, "readiness_check"),
+                (None, "depends_on"),
+            ],
+            "{issues:#?}"
+        );
+        assert!(
+            issues[3].message.contains("missing"),
+            "{}",
+            issues[3].message
+        );
+        assert!(issues[7].message.contains("a, b"), "{}", issues[7].message);
+        assert!(
+            issues[8].message.contains("port 5244"),
+            "{}",
+            issues[8].message
+        );
+        assert!(
+            issues[9].message.contains("b -> c -> b"),
+            "{}",
+            issues[9].message
+        );
+        assert!(validate_configs(&configs[..1]).is_empty());
+    }
+}